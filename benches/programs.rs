@@ -0,0 +1,27 @@
+//! Lexer, parser and evaluator timings for the programs in `arc_compiler::bench`.
+//! `rust-compiler bench` runs the same programs without Criterion.
+
+use arc_compiler::bench::{self, programs};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+fn pipeline(c: &mut Criterion) {
+    for program in programs() {
+        let mut group = c.benchmark_group(program.name);
+        group.bench_function("lex", |b| b.iter(|| bench::lex(black_box(&program.source))));
+
+        let tokens = bench::lex(&program.source);
+        group.bench_function("parse", |b| {
+            b.iter_batched(|| tokens.clone(), bench::parse, BatchSize::SmallInput)
+        });
+        group.bench_function("evaluate", |b| {
+            b.iter_batched(|| bench::parse(tokens.clone()), |ast| bench::evaluate(ast, false), BatchSize::SmallInput)
+        });
+        group.bench_function("evaluate_optimized", |b| {
+            b.iter_batched(|| bench::parse(tokens.clone()), |ast| bench::evaluate(ast, true), BatchSize::SmallInput)
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,50 @@
+//! Compares the `Value` enum with the NaN-boxed `PackedValue` on a VM-style workload:
+//! filling a value stack, cloning it, and summing its numbers.
+
+use arc_compiler::ast::packed::PackedValue;
+use arc_compiler::ast::types::Value;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const STACK_SIZE: usize = 10_000;
+
+fn sample(i: usize) -> Value {
+    match i % 4 {
+        0 => Value::Integer(i as i64),
+        1 => Value::Float(i as f64 * 0.5),
+        2 => Value::Boolean(i % 8 == 2),
+        _ => Value::Integer(-(i as i64)),
+    }
+}
+
+fn sum_enum(stack: &[Value]) -> f64 {
+    stack
+        .iter()
+        .map(|value| match value {
+            Value::Integer(i) => *i as f64,
+            Value::Float(f) => *f,
+            _ => 0.0,
+        })
+        .sum()
+}
+
+fn sum_packed(stack: &[PackedValue]) -> f64 {
+    stack
+        .iter()
+        .map(|value| value.as_float().or_else(|| value.as_integer().map(|i| i as f64)).unwrap_or(0.0))
+        .sum()
+}
+
+fn value_repr(c: &mut Criterion) {
+    let values: Vec<Value> = (0..STACK_SIZE).map(sample).collect();
+    let packed: Vec<PackedValue> = values.iter().cloned().map(PackedValue::from).collect();
+
+    let mut group = c.benchmark_group("value_repr");
+    group.bench_function("enum/clone_stack", |b| b.iter(|| black_box(&values).clone()));
+    group.bench_function("packed/clone_stack", |b| b.iter(|| black_box(&packed).clone()));
+    group.bench_function("enum/sum", |b| b.iter(|| sum_enum(black_box(&values))));
+    group.bench_function("packed/sum", |b| b.iter(|| sum_packed(black_box(&packed))));
+    group.finish();
+}
+
+criterion_group!(benches, value_repr);
+criterion_main!(benches);
@@ -0,0 +1,34 @@
+/* C interface to the Arc interpreter. Build with: cargo build --release --features capi */
+#ifndef ARC_H
+#define ARC_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ARC_OK 0
+#define ARC_ERROR 1
+#define ARC_INVALID_ARGUMENT (-1)
+
+typedef struct ArcInterpreter ArcInterpreter;
+
+ArcInterpreter *arc_new(void);
+void arc_free(ArcInterpreter *handle);
+
+/* Evaluates a UTF-8 program; variables persist between calls. */
+int arc_eval(ArcInterpreter *handle, const char *source);
+
+/* Returned strings are owned by the handle and valid until the next arc_eval or arc_free. */
+const char *arc_get_string_result(const ArcInterpreter *handle);
+const char *arc_last_error(const ArcInterpreter *handle);
+
+int arc_get_int_result(const ArcInterpreter *handle, int64_t *out);
+int arc_get_float_result(const ArcInterpreter *handle, double *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ARC_H */
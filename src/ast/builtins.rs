@@ -0,0 +1,281 @@
+//! Builtin signatures - the arguments each builtin accepts
+//!
+//! The evaluator checks a call against its builtin's signature before calling it, so a
+//! wrong call reports what was expected next to what was passed, e.g. `sqrt expects 1
+//! numeric argument, got 2 arguments (String, Integer)`. The lint checks calls whose
+//! argument types are known without running them. Host functions check their own
+//! arguments.
+
+use crate::ast::types::{DataType, Value};
+
+/// What one argument may be
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Param {
+    Any,
+    /// An Integer or a Float
+    Number,
+    /// Anything `for` can loop over
+    Iterable,
+    Is(DataType),
+    Either(DataType, DataType),
+}
+
+impl Param {
+    pub fn accepts(&self, data_type: &DataType) -> bool {
+        match self {
+            Param::Any => true,
+            Param::Number => matches!(data_type, DataType::Integer | DataType::Float),
+            Param::Iterable => matches!(
+                data_type,
+                DataType::Array | DataType::String | DataType::Bytes | DataType::Map | DataType::Set | DataType::Range
+            ),
+            Param::Is(expected) => data_type == expected,
+            Param::Either(first, second) => data_type == first || data_type == second,
+        }
+    }
+
+    /// How the parameter reads in a list of parameters
+    fn name(&self) -> String {
+        match self {
+            Param::Any => "any".to_string(),
+            Param::Number => "number".to_string(),
+            Param::Iterable => "iterable".to_string(),
+            Param::Is(data_type) => data_type.to_string(),
+            Param::Either(first, second) => format!("{} or {}", first, second),
+        }
+    }
+
+    /// How the parameter reads before "argument", when every argument is alike
+    fn adjective(&self) -> String {
+        match self {
+            Param::Number => "numeric".to_string(),
+            other => other.name(),
+        }
+    }
+}
+
+/// The arguments a builtin accepts: `required` ones, then `optional` ones, then any number
+/// of `rest`
+#[derive(Debug, PartialEq)]
+pub struct Signature {
+    pub name: &'static str,
+    pub required: &'static [Param],
+    pub optional: &'static [Param],
+    pub rest: Option<Param>,
+}
+
+const fn signature(name: &'static str, required: &'static [Param]) -> Signature {
+    Signature { name, required, optional: &[], rest: None }
+}
+
+const fn with_optional(name: &'static str, required: &'static [Param], optional: &'static [Param]) -> Signature {
+    Signature { name, required, optional, rest: None }
+}
+
+const fn variadic(name: &'static str, required: &'static [Param], rest: Param) -> Signature {
+    Signature { name, required, optional: &[], rest: Some(rest) }
+}
+
+const NUMBER: Param = Param::Number;
+const INTEGER: Param = Param::Is(DataType::Integer);
+const STRING: Param = Param::Is(DataType::String);
+const ITERABLE: Param = Param::Iterable;
+const CHANNEL: Param = Param::Is(DataType::Channel);
+const MAP: Param = Param::Is(DataType::Map);
+const FUNCTION: Param = Param::Is(DataType::Function);
+
+/// Every builtin, by the module that implements it
+pub const SIGNATURES: &[Signature] = &[
+    // ast::math
+    signature("abs", &[NUMBER]),
+    signature("sqrt", &[NUMBER]),
+    signature("pow", &[NUMBER, NUMBER]),
+    signature("floor", &[NUMBER]),
+    signature("ceil", &[NUMBER]),
+    signature("round", &[NUMBER]),
+    variadic("min", &[NUMBER], NUMBER),
+    variadic("max", &[NUMBER], NUMBER),
+    signature("sin", &[NUMBER]),
+    signature("cos", &[NUMBER]),
+    signature("approx_eq", &[NUMBER, NUMBER, NUMBER]),
+    signature("popcount", &[INTEGER]),
+    signature("leading_zeros", &[INTEGER]),
+    signature("trailing_zeros", &[INTEGER]),
+    signature("rotate_left", &[INTEGER, INTEGER]),
+    signature("rotate_right", &[INTEGER, INTEGER]),
+    signature("bit", &[INTEGER, INTEGER]),
+    signature("to_base", &[INTEGER, INTEGER]),
+    signature("from_base", &[STRING, INTEGER]),
+    // ast::collections
+    signature("copy", &[Param::Any]),
+    signature("freeze", &[Param::Any]),
+    with_optional("set", &[], &[ITERABLE]),
+    signature("sort", &[ITERABLE]),
+    signature("sort_by", &[ITERABLE, FUNCTION]),
+    signature("cmp", &[Param::Any, Param::Any]),
+    signature("reverse", &[ITERABLE]),
+    signature("enumerate", &[ITERABLE]),
+    variadic("zip", &[ITERABLE], ITERABLE),
+    signature("sum", &[ITERABLE]),
+    signature("min_of", &[ITERABLE]),
+    signature("max_of", &[ITERABLE]),
+    // ast::strings
+    signature("join", &[ITERABLE, STRING]),
+    signature("concat_all", &[ITERABLE]),
+    with_optional("parse_int", &[STRING], &[INTEGER]),
+    signature("parse_float", &[STRING]),
+    signature("to_fixed", &[NUMBER, INTEGER]),
+    signature("format", &[Param::Any, STRING]),
+    signature("repr", &[Param::Any]),
+    // ast::encoding
+    signature("bytes", &[Param::Either(DataType::Array, DataType::Bytes)]),
+    with_optional("encode", &[STRING], &[STRING]),
+    with_optional("decode", &[Param::Is(DataType::Bytes)], &[STRING]),
+    // ast::functions
+    variadic("bind", &[FUNCTION], Param::Any),
+    signature("compose", &[FUNCTION, FUNCTION]),
+    signature("identity", &[Param::Any]),
+    signature("constantly", &[Param::Any]),
+    signature("apply", &[FUNCTION, Param::Is(DataType::Array)]),
+    signature("arity", &[FUNCTION]),
+    // ast::json
+    signature("json_parse", &[STRING]),
+    with_optional("json_stringify", &[Param::Any], &[Param::Is(DataType::Boolean)]),
+    // ast::channels
+    with_optional("channel", &[], &[INTEGER]),
+    signature("send", &[CHANNEL, Param::Any]),
+    with_optional("recv", &[CHANNEL], &[NUMBER]),
+    signature("close", &[CHANNEL]),
+    // ast::net
+    with_optional("http_get", &[STRING], &[MAP]),
+    with_optional("http_post", &[STRING, STRING], &[MAP]),
+    // The system builtins in ast::evaluator
+    variadic("print", &[], Param::Any),
+    signature("flush", &[]),
+    signature("env", &[STRING]),
+    signature("read_file", &[STRING]),
+    signature("clock", &[]),
+    with_optional("exec", &[STRING], &[Param::Is(DataType::Array)]),
+];
+
+/// The signature of a builtin, if `name` is one
+pub fn signature_of(name: &str) -> Option<&'static Signature> {
+    SIGNATURES.iter().find(|signature| signature.name == name)
+}
+
+impl Signature {
+    /// What the argument at `index` may be, if there can be one there
+    fn param(&self, index: usize) -> Option<&Param> {
+        self.required.iter().chain(self.optional).nth(index).or(self.rest.as_ref())
+    }
+
+    /// Checks the types of a call's arguments, returning the runtime error for a call the
+    /// builtin cannot take
+    pub fn check(&self, types: &[DataType]) -> Result<(), String> {
+        let count_ok = self.check_count(types.len()).is_ok();
+        let types_ok = types.iter().enumerate().all(|(i, data_type)| self.param(i).is_some_and(|param| param.accepts(data_type)));
+        if count_ok && types_ok {
+            return Ok(());
+        }
+        let found = match types {
+            [] => "no arguments".to_string(),
+            _ => format!(
+                "{} ({})",
+                arguments(types.len()),
+                types.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        Err(format!("{} expects {}, got {}", self.name, self.expected(), found))
+    }
+
+    /// Checks only the number of arguments, for calls whose argument types are not known
+    pub fn check_count(&self, count: usize) -> Result<(), String> {
+        if count >= self.required.len() && (self.rest.is_some() || count <= self.required.len() + self.optional.len()) {
+            return Ok(());
+        }
+        let found = if count == 0 { "no arguments".to_string() } else { arguments(count) };
+        Err(format!("{} expects {}, got {}", self.name, self.expected(), found))
+    }
+
+    /// Checks the arguments a call was given
+    pub fn check_values(&self, args: &[Value]) -> Result<(), String> {
+        self.check(&args.iter().map(Value::get_type).collect::<Vec<_>>())
+    }
+
+    /// The arguments the builtin accepts, e.g. `1 numeric argument` or `1 or 2 arguments
+    /// (String, Integer)`
+    fn expected(&self) -> String {
+        let (required, optional) = (self.required.len(), self.optional.len());
+        let params: Vec<&Param> = self.required.iter().chain(self.optional).chain(&self.rest).collect();
+        let Some(first) = params.first() else {
+            return "no arguments".to_string();
+        };
+        let count = match (required, optional, self.rest) {
+            (_, _, Some(_)) => format!("at least {}", required),
+            (_, 0, None) => required.to_string(),
+            (0, 1, None) => "0 or 1".to_string(),
+            _ if optional == 1 => format!("{} or {}", required, required + 1),
+            _ => format!("{} to {}", required, required + optional),
+        };
+        let plural = optional > 0 || required != 1;
+        let noun = if plural { "arguments" } else { "argument" };
+        if params.iter().all(|param| param == first) && **first != Param::Any {
+            format!("{} {} {}", count, first.adjective(), noun)
+        } else if params.iter().all(|param| **param == Param::Any) {
+            format!("{} {}", count, noun)
+        } else {
+            let names: Vec<String> = params.iter().map(|param| param.name()).collect();
+            let rest = if self.rest.is_some() { ", ..." } else { "" };
+            format!("{} {} ({}{})", count, noun, names.join(", "), rest)
+        }
+    }
+}
+
+/// "1 argument" or "n arguments"
+fn arguments(count: usize) -> String {
+    if count == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::channels::CHANNEL_BUILTINS;
+    use crate::ast::collections::COLLECTION_BUILTINS;
+    use crate::ast::encoding::ENCODING_BUILTINS;
+    use crate::ast::functions::FUNCTION_BUILTINS;
+    use crate::ast::evaluator::SYSTEM_BUILTINS;
+    use crate::ast::json::JSON_BUILTINS;
+    use crate::ast::math::MATH_BUILTINS;
+    use crate::ast::net::NET_BUILTINS;
+    use crate::ast::strings::STRING_BUILTINS;
+
+    #[test]
+    fn test_signatures_describe_bad_calls() {
+        let check = |name: &str, types: &[DataType]| signature_of(name).unwrap().check(types);
+        assert_eq!(
+            check("sqrt", &[DataType::String, DataType::Integer]),
+            Err("sqrt expects 1 numeric argument, got 2 arguments (String, Integer)".to_string())
+        );
+        assert_eq!(check("sqrt", &[DataType::Float]), Ok(()));
+        assert_eq!(
+            check("parse_int", &[DataType::String, DataType::Float]),
+            Err("parse_int expects 1 or 2 arguments (String, Integer), got 2 arguments (String, Float)".to_string())
+        );
+        assert_eq!(check("min", &[]), Err("min expects at least 1 numeric argument, got no arguments".to_string()));
+        assert_eq!(check("flush", &[DataType::Integer]), Err("flush expects no arguments, got 1 argument (Integer)".to_string()));
+        assert_eq!(check("print", &[DataType::Map, DataType::Channel]), Ok(()));
+        assert_eq!(signature_of("pow").unwrap().check_count(1), Err("pow expects 2 numeric arguments, got 1 argument".to_string()));
+        assert!(SIGNATURES.iter().all(|signature| signature_of(signature.name) == Some(signature)));
+        let builtins = [MATH_BUILTINS, COLLECTION_BUILTINS, STRING_BUILTINS, ENCODING_BUILTINS, JSON_BUILTINS]
+            .into_iter()
+            .chain([FUNCTION_BUILTINS, CHANNEL_BUILTINS, NET_BUILTINS, SYSTEM_BUILTINS])
+            .flatten();
+        for name in builtins {
+            assert!(signature_of(name).is_some(), "{} has no signature", name);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Channels - queues for passing values between interpreters on different threads
+//!
+//! A channel is a handle: copies of a channel value, including copies given to other
+//! interpreters with `Interpreter::set`, all refer to the same queue. Values sent through a
+//! channel are copies, like every other value, so receivers never share mutable state
+//! with senders.
+//!
+//! A script's `send` and `recv` wait at most `SizeLimits::max_channel_wait`, so one that
+//! waits on a channel nothing else will ever use, such as `recv(channel())`, fails instead
+//! of hanging the REPL or the host.
+
+use crate::ast::types::Value;
+use crate::limits::SizeLimits;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Names of the channel builtins
+pub const CHANNEL_BUILTINS: &[&str] = &["channel", "send", "recv", "close"];
+
+pub fn is_channel_builtin(name: &str) -> bool {
+    CHANNEL_BUILTINS.contains(&name)
+}
+
+/// A first-in, first-out queue that `send` appends to and `recv` takes from. A bounded
+/// channel makes `send` wait while it is full; an unbounded one never does.
+pub struct Channel {
+    state: Mutex<ChannelState>,
+    /// Signalled when an item is added or the channel closes
+    readable: Condvar,
+    /// Signalled when an item is taken or the channel closes
+    writable: Condvar,
+    capacity: Option<usize>,
+}
+
+struct ChannelState {
+    items: VecDeque<Value>,
+    closed: bool,
+}
+
+/// Why a wait on a channel ended without a value or room
+enum WaitError {
+    Closed(&'static str),
+    TimedOut,
+}
+
+impl Channel {
+    /// A channel holding at most `capacity` items, or any number for `None`
+    pub fn new(capacity: Option<usize>) -> Self {
+        Channel {
+            state: Mutex::new(ChannelState { items: VecDeque::new(), closed: false }),
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+            capacity,
+        }
+    }
+
+    /// The queue, even if a thread panicked while holding it; its items are still whole
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Queues a value, waiting up to `timeout` (forever for `None`) for room in a full
+    /// bounded channel
+    pub fn send(&self, value: Value, timeout: Option<Duration>) -> Result<(), String> {
+        self.send_within(value, timeout).map_err(|e| match e {
+            WaitError::Closed(message) => message.to_string(),
+            WaitError::TimedOut => "timed out waiting for room".to_string(),
+        })
+    }
+
+    /// Takes the oldest value, waiting up to `timeout` (forever for `None`) for one to arrive.
+    /// Values sent before the channel closed can still be received after.
+    pub fn recv(&self, timeout: Option<Duration>) -> Result<Value, String> {
+        self.recv_within(timeout).map_err(|e| match e {
+            WaitError::Closed(message) => message.to_string(),
+            WaitError::TimedOut => "timed out waiting for a value".to_string(),
+        })
+    }
+
+    fn send_within(&self, value: Value, timeout: Option<Duration>) -> Result<(), WaitError> {
+        let deadline = deadline(timeout);
+        let mut state = self.lock();
+        while !state.closed && self.capacity.is_some_and(|capacity| state.items.len() >= capacity) {
+            state = Self::wait(&self.writable, state, deadline)?;
+        }
+        if state.closed {
+            return Err(WaitError::Closed("cannot send on a closed channel"));
+        }
+        state.items.push_back(value);
+        self.readable.notify_one();
+        Ok(())
+    }
+
+    fn recv_within(&self, timeout: Option<Duration>) -> Result<Value, WaitError> {
+        let deadline = deadline(timeout);
+        let mut state = self.lock();
+        loop {
+            if let Some(value) = state.items.pop_front() {
+                self.writable.notify_one();
+                return Ok(value);
+            }
+            if state.closed {
+                return Err(WaitError::Closed("channel is closed and empty"));
+            }
+            state = Self::wait(&self.readable, state, deadline)?;
+        }
+    }
+
+    /// Waits for `condvar` to be signalled, or fails once `deadline` (if any) has passed
+    fn wait<'a>(
+        condvar: &Condvar,
+        state: MutexGuard<'a, ChannelState>,
+        deadline: Option<Instant>,
+    ) -> Result<MutexGuard<'a, ChannelState>, WaitError> {
+        let Some(deadline) = deadline else {
+            return Ok(condvar.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner()));
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(WaitError::TimedOut);
+        }
+        Ok(condvar
+            .wait_timeout(state, remaining)
+            .map(|(state, _)| state)
+            .unwrap_or_else(|poisoned| poisoned.into_inner().0))
+    }
+
+    /// Stops further sends and wakes every waiting thread
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.readable.notify_all();
+        self.writable.notify_all();
+    }
+}
+
+/// When a wait of `timeout` started now ends; `None` for no timeout or one too long to
+/// represent, which waits forever
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.and_then(|timeout| Instant::now().checked_add(timeout))
+}
+
+impl fmt::Debug for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("Channel")
+            .field("items", &state.items.len())
+            .field("capacity", &self.capacity)
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+/// Channels are equal only to themselves
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl From<Channel> for Value {
+    fn from(channel: Channel) -> Self {
+        Value::Channel(Arc::new(channel))
+    }
+}
+
+fn expect_channel(args: &[Value], count: usize) -> Result<&Channel, String> {
+    if args.len() != count {
+        return Err(format!("expected {} argument(s), found {}", count, args.len()));
+    }
+    match &args[0] {
+        Value::Channel(channel) => Ok(channel),
+        other => Err(format!("expected a Channel, found {}", other.get_type())),
+    }
+}
+
+/// Runs a script's wait, which asked for `requested` (no limit for `None`), for at most
+/// `max_channel_wait`. Reaching that limit is its own error, so it is not mistaken for a
+/// timeout the script chose.
+fn within_limit<T>(
+    limits: &SizeLimits,
+    operation: &str,
+    requested: Option<Duration>,
+    wait: impl FnOnce(Option<Duration>) -> Result<T, WaitError>,
+) -> Result<T, String> {
+    let limited = requested.is_none_or(|timeout| timeout > limits.max_channel_wait);
+    let timeout = if limited { Some(limits.max_channel_wait) } else { requested };
+    wait(timeout).map_err(|e| match e {
+        WaitError::Closed(message) => message.to_string(),
+        WaitError::TimedOut if limited => limits.channel_wait_error(operation),
+        WaitError::TimedOut if operation == "send" => "timed out waiting for room".to_string(),
+        WaitError::TimedOut => "timed out waiting for a value".to_string(),
+    })
+}
+
+/// Calls a channel builtin. `send` and `recv` block the calling thread while they wait,
+/// for no longer than `limits` allow.
+pub fn call(name: &str, args: &[Value], limits: &SizeLimits) -> Result<Option<Value>, String> {
+    let value = match (name, args) {
+        ("channel", []) => Value::from(Channel::new(None)),
+        ("channel", [Value::Integer(capacity)]) => match usize::try_from(*capacity) {
+            Ok(capacity) if capacity > 0 => Value::from(Channel::new(Some(capacity))),
+            _ => return Err(format!("capacity must be at least 1, found {}", capacity)),
+        },
+        ("channel", _) => return Err("expected no arguments, or an Integer capacity".to_string()),
+        ("send", _) => {
+            let channel = expect_channel(args, 2)?;
+            within_limit(limits, "send", None, |timeout| channel.send_within(args[1].clone(), timeout))?;
+            return Ok(None);
+        }
+        ("recv", [_]) => {
+            let channel = expect_channel(args, 1)?;
+            within_limit(limits, "recv", None, |timeout| channel.recv_within(timeout))?
+        }
+        ("recv", [_, timeout]) => {
+            let seconds = f64::try_from(timeout.clone())?;
+            let timeout = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| format!("timeout must be a non-negative number of seconds, found {}", seconds))?;
+            let channel = expect_channel(args, 2)?;
+            within_limit(limits, "recv", Some(timeout), |timeout| channel.recv_within(timeout))?
+        }
+        ("recv", _) => return Err(format!("expected 1 or 2 argument(s), found {}", args.len())),
+        ("close", _) => {
+            expect_channel(args, 1)?.close();
+            return Ok(None);
+        }
+        _ => return Err(format!("unknown channel function '{}'", name)),
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_channel_between_threads() {
+        let call = |name: &str, args: &[Value]| call(name, args, &SizeLimits::default());
+        let Ok(Some(channel)) = call("channel", &[Value::Integer(1)]) else {
+            panic!("expected a channel");
+        };
+        let producer = {
+            let channel = channel.clone();
+            std::thread::spawn(move || {
+                for i in 0..3 {
+                    call("send", &[channel.clone(), Value::Integer(i)]).unwrap();
+                }
+                call("close", &[channel]).unwrap();
+            })
+        };
+
+        let received: Vec<_> = (0..3).map(|_| call("recv", std::slice::from_ref(&channel))).collect();
+        producer.join().unwrap();
+        assert_eq!(received, (0..3).map(|i| Ok(Some(Value::Integer(i)))).collect::<Vec<_>>());
+        assert_eq!(call("recv", std::slice::from_ref(&channel)), Err("channel is closed and empty".to_string()));
+        assert_eq!(call("send", &[channel, Value::Integer(9)]), Err("cannot send on a closed channel".to_string()));
+
+        let idle = Value::from(Channel::new(None));
+        assert_eq!(call("recv", &[idle, Value::Float(0.01)]), Err("timed out waiting for a value".to_string()));
+        assert_eq!(call("channel", &[Value::Integer(0)]), Err("capacity must be at least 1, found 0".to_string()));
+    }
+
+    #[test]
+    fn test_waits_stop_at_the_limit() {
+        let limits = SizeLimits { max_channel_wait: Duration::from_millis(20), ..SizeLimits::default() };
+        let Ok(Some(channel)) = call("channel", &[Value::Integer(1)], &limits) else {
+            panic!("expected a channel");
+        };
+        // One interpreter alone would otherwise wait forever
+        assert_eq!(
+            call("recv", std::slice::from_ref(&channel), &limits),
+            Err("waited the limit of 0.02 seconds and nothing was sent".to_string())
+        );
+        assert_eq!(call("recv", &[channel.clone(), Value::Integer(60)], &limits), call("recv", std::slice::from_ref(&channel), &limits));
+        assert_eq!(call("recv", &[channel.clone(), Value::Float(0.001)], &limits), Err("timed out waiting for a value".to_string()));
+
+        assert_eq!(call("send", &[channel.clone(), Value::Integer(1)], &limits), Ok(None));
+        assert_eq!(
+            call("send", &[channel.clone(), Value::Integer(2)], &limits),
+            Err("waited the limit of 0.02 seconds and the channel stayed full".to_string())
+        );
+        assert_eq!(call("recv", &[channel, Value::Float(1e12)], &SizeLimits::unlimited()), Ok(Some(Value::Integer(1))));
+    }
+}
@@ -0,0 +1,233 @@
+//! Collection builtins - functions over arrays, maps and sets
+//!
+//! Like the math builtins these only look at their arguments, so they need no capability.
+//! Those that walk a collection accept any iterable (see `ast::iteration`) and return arrays.
+//! Those that build a collection from an iterable check its length against the
+//! `SizeLimits` before collecting it, so `reverse(0..1000000000)` fails without allocating.
+
+use crate::ast::iteration::ValueIter;
+use crate::ast::types::{Function, SetElement, Value};
+use crate::limits::SizeLimits;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+/// Names of the collection builtins
+pub const COLLECTION_BUILTINS: &[&str] =
+    &["copy", "freeze", "set", "sort", "sort_by", "cmp", "reverse", "enumerate", "zip", "sum", "min_of", "max_of"];
+
+pub fn is_collection_builtin(name: &str) -> bool {
+    COLLECTION_BUILTINS.contains(&name)
+}
+
+fn expect_args(args: &[Value], count: usize) -> Result<(), String> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        Err(format!("expected {} argument(s), found {}", count, args.len()))
+    }
+}
+
+/// Starts iterating a value whose items are about to be collected, failing if there are
+/// more than `limits` allow
+fn bounded(value: &Value, limits: &SizeLimits) -> Result<ValueIter, String> {
+    let iter = ValueIter::new(value)?;
+    limits.check_collection(iter.size_hint().0)?;
+    Ok(iter)
+}
+
+/// The items of an iterable value
+fn items(value: &Value, limits: &SizeLimits) -> Result<Vec<Value>, String> {
+    bounded(value, limits).map(Iterator::collect)
+}
+
+/// Orders two values the way `<` does, failing where `<` would be false both ways
+fn order(a: &Value, b: &Value) -> Result<Ordering, String> {
+    a.compare(b)?.ok_or_else(|| format!("cannot order {} and {}", a, b))
+}
+
+/// Calls a collection builtin other than `sort_by`, which calls back into the evaluator
+pub fn call(name: &str, args: &[Value], limits: &SizeLimits) -> Result<Option<Value>, String> {
+    let value = match name {
+        // Values other than arrays and maps have nothing to copy or freeze and come back as is
+        "copy" => {
+            expect_args(args, 1)?;
+            args[0].deep_copy()
+        }
+        "freeze" => {
+            expect_args(args, 1)?;
+            args[0].frozen()
+        }
+        "set" => match args {
+            [] => Value::from(BTreeSet::new()),
+            [iterable] => {
+                let elements = bounded(iterable, limits)?.map(|item| SetElement::try_from(&item));
+                Value::from(elements.collect::<Result<BTreeSet<_>, _>>()?)
+            }
+            _ => return Err(format!("expected 0 or 1 argument(s), found {}", args.len())),
+        },
+        "sort" => {
+            expect_args(args, 1)?;
+            Value::from(merge_sort(items(&args[0], limits)?, &mut order)?)
+        }
+        // -1, 0 or 1, the ordering `sort` uses, so `sort_by(items, cmp)` sorts like `sort`
+        "cmp" => {
+            expect_args(args, 2)?;
+            Value::Integer(order(&args[0], &args[1])? as i64)
+        }
+        "reverse" => {
+            expect_args(args, 1)?;
+            match &args[0] {
+                Value::String(text) => Value::from(text.chars().rev().collect::<String>()),
+                Value::Bytes(bytes) => Value::Bytes(bytes.iter().rev().copied().collect()),
+                other => Value::from(items(other, limits)?.into_iter().rev().collect::<Vec<_>>()),
+            }
+        }
+        "enumerate" => {
+            expect_args(args, 1)?;
+            let pairs = bounded(&args[0], limits)?.enumerate();
+            Value::from(pairs.map(|(i, item)| Value::from(vec![Value::Integer(i as i64), item])).collect::<Vec<_>>())
+        }
+        "zip" => {
+            if args.is_empty() {
+                return Err("expected at least 1 argument".to_string());
+            }
+            let mut iters = args.iter().map(ValueIter::new).collect::<Result<Vec<_>, _>>()?;
+            // Stops with the shortest argument, so only the shortest must fit
+            limits.check_collection(iters.iter().map(|iter| iter.size_hint().0).min().unwrap_or(0))?;
+            let mut rows = Vec::new();
+            while let Some(row) = iters.iter_mut().map(Iterator::next).collect::<Option<Vec<_>>>() {
+                rows.push(Value::from(row));
+            }
+            Value::from(rows)
+        }
+        "sum" => {
+            expect_args(args, 1)?;
+            sum(ValueIter::new(&args[0])?)?
+        }
+        "min_of" | "max_of" => {
+            expect_args(args, 1)?;
+            let mut items = ValueIter::new(&args[0])?;
+            let mut best = items.next().ok_or("expected at least 1 item")?;
+            let wanted = if name == "min_of" { Ordering::Less } else { Ordering::Greater };
+            for item in items {
+                if order(&item, &best)? == wanted {
+                    best = item;
+                }
+            }
+            best
+        }
+        _ => return Err(format!("unknown collection function '{}'", name)),
+    };
+    Ok(Some(value))
+}
+
+/// Adds up numbers, staying an integer until a float is seen. An empty collection sums to 0.
+fn sum(items: ValueIter) -> Result<Value, String> {
+    let mut total = Value::Integer(0);
+    for item in items {
+        total = match (&total, &item) {
+            (Value::Integer(a), Value::Integer(b)) => Value::Integer(a.checked_add(*b).ok_or("integer overflow")?),
+            (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+                Value::Float(f64::try_from(total)? + f64::try_from(item)?)
+            }
+            _ => return Err(format!("expected numbers, found {}", item.get_type())),
+        };
+    }
+    Ok(total)
+}
+
+/// `sort_by(items, compare)`: sorts with a function returning a negative integer, zero or a
+/// positive integer as its first argument belongs before, with or after its second.
+/// `call` runs the function; its errors end the sort.
+pub fn sort_by(args: &[Value], limits: &SizeLimits, mut call: impl FnMut(&Function, Vec<Value>) -> Result<Option<Value>, String>) -> Result<Option<Value>, String> {
+    let [iterable, compare] = args else {
+        return Err(format!("expected 2 argument(s), found {}", args.len()));
+    };
+    let Value::Function(compare) = compare else {
+        return Err(format!("expected a Function to compare with, found {}", compare.get_type()));
+    };
+    let sorted = merge_sort(items(iterable, limits)?, &mut |a, b| match call(compare, vec![a.clone(), b.clone()])? {
+        Some(Value::Integer(i)) => Ok(i.cmp(&0)),
+        Some(other) => Err(format!("{} must return an Integer, found {}", compare, other.get_type())),
+        None => Err(format!("{} returned no value", compare)),
+    })?;
+    Ok(Some(Value::from(sorted)))
+}
+
+/// Stable merge sort that stops at the first failed comparison. The standard library's sorts
+/// may panic when comparisons are inconsistent, which a script's comparator can be.
+fn merge_sort(
+    mut items: Vec<Value>,
+    compare: &mut impl FnMut(&Value, &Value) -> Result<Ordering, String>,
+) -> Result<Vec<Value>, String> {
+    if items.len() < 2 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = merge_sort(items, compare)?.into_iter().peekable();
+    let mut right = merge_sort(right, compare)?.into_iter().peekable();
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Ties take from the left, which keeps equal items in their original order
+        let next = if compare(b, a)? == Ordering::Less { right.next() } else { left.next() };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::interner::Atom;
+
+    #[test]
+    fn test_collection_utilities() {
+        let call = |name: &str, args: &[Value]| call(name, args, &SizeLimits::default());
+        let array = |items: &[i64]| Value::from(items.iter().map(|&i| Value::Integer(i)).collect::<Vec<_>>());
+        let show = |result: Result<Option<Value>, String>| result.map(|value| value.map(|value| value.to_string()).unwrap_or_default());
+
+        assert_eq!(show(call("sort", &[array(&[3, 1, 2])])), Ok("[1, 2, 3]".to_string()));
+        assert_eq!(show(call("sort", &[Value::from("cab")])), Ok("[\"a\", \"b\", \"c\"]".to_string()));
+        assert_eq!(show(call("reverse", &[array(&[1, 2])])), Ok("[2, 1]".to_string()));
+        assert_eq!(show(call("enumerate", &[Value::from("ab")])), Ok("[[0, \"a\"], [1, \"b\"]]".to_string()));
+        assert_eq!(show(call("zip", &[array(&[1, 2, 3]), Value::from("ab")])), Ok("[[1, \"a\"], [2, \"b\"]]".to_string()));
+        assert_eq!(call("sum", &[Value::Range(1, 5)]), Ok(Some(Value::Integer(10))));
+        assert_eq!(call("sum", &[Value::from(vec![Value::Integer(1), Value::Float(0.5)])]), Ok(Some(Value::Float(1.5))));
+        assert_eq!(call("max_of", &[array(&[4, 9, 2])]), Ok(Some(Value::Integer(9))));
+        assert_eq!(call("min_of", &[array(&[])]), Err("expected at least 1 item".to_string()));
+        assert_eq!(call("sort", &[Value::from(vec![Value::Integer(1), Value::from("a")])]), Err("Cannot compare String and Integer".to_string()));
+        assert_eq!(call("cmp", &[Value::from("Zoo"), Value::from("apple")]), Ok(Some(Value::Integer(-1))));
+        assert_eq!(call("cmp", &[array(&[1, 2]), array(&[1])]), Ok(Some(Value::Integer(1))));
+        assert_eq!(call("cmp", &[Value::Float(2.0), Value::Integer(2)]), Ok(Some(Value::Integer(0))));
+        assert_eq!(call("cmp", &[Value::Float(f64::NAN), Value::Integer(2)]), Err("cannot order NaN and 2".to_string()));
+        assert_eq!(show(call("sort", &[Value::from(vec![array(&[2]), array(&[1, 5]), array(&[1])])])), Ok("[[1], [1, 5], [2]]".to_string()));
+
+        // Descending by comparator, with equal keys kept in order
+        let pairs = Value::from(vec![array(&[1, 0]), array(&[2, 1]), array(&[1, 2])]);
+        let descending = |_: &Function, args: Vec<Value>| match (&args[0], &args[1]) {
+            (Value::Array(a), Value::Array(b)) => Ok(Some(Value::Integer(b[0].to_integer()? - a[0].to_integer()?))),
+            _ => Err("expected arrays".to_string()),
+        };
+        assert_eq!(show(sort_by(&[pairs, Value::Function(Function::Named(Atom::intern("desc")))], &SizeLimits::default(), descending)), Ok("[[2, 1], [1, 0], [1, 2]]".to_string()));
+    }
+
+    #[test]
+    fn test_limits_are_checked_before_collecting() {
+        // Collecting first would allocate hundreds of megabytes before failing
+        let limits = SizeLimits { max_collection_len: 10, ..SizeLimits::default() };
+        let huge = Value::Range(0, 30_000_000);
+        let expected = Err("Collection of 30000000 items exceeds the size limit of 10".to_string());
+        for name in ["reverse", "sort", "enumerate", "set"] {
+            assert_eq!(call(name, std::slice::from_ref(&huge), &limits), expected, "{}", name);
+        }
+        assert_eq!(call("zip", &[huge.clone(), Value::Range(0, i64::MAX)], &limits), expected);
+        assert_eq!(sort_by(&[huge.clone(), Value::Function(Function::Named(Atom::intern("cmp")))], &limits, |_, _| Ok(None)), expected);
+
+        // Only the shortest argument of zip is collected, and strings reverse to strings
+        assert!(call("zip", &[Value::Range(0, 3), huge], &limits).is_ok());
+        assert!(call("reverse", &[Value::from("x".repeat(100))], &limits).is_ok());
+    }
+}
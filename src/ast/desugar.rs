@@ -0,0 +1,88 @@
+//! Desugaring - lowers surface syntax to the core forms the later passes handle
+//!
+//! The parser keeps `guard` and `|>` as their own nodes, so tools that work on the source
+//! (the formatter, lints) see what was written. `ASTEvaluator::resolve` runs this pass
+//! first, leaving only core statements and expressions for the optimizer, resolver and
+//! evaluator.
+
+use crate::ast::transform::{replace_expression, walk_expression, walk_statement, AstTransformer};
+use crate::ast::{
+    Ast, ASTExpression, ASTExpressionKind, ASTIfArm, ASTIfStatement, ASTStatement, ASTStatementKind, ASTUnaryOperator,
+    ASTUnaryOperatorKind,
+};
+use std::mem;
+
+/// Lowers every `guard` and `|>` in `ast`
+pub fn desugar(ast: &mut Ast) {
+    Desugar.transform(ast);
+}
+
+struct Desugar;
+
+impl AstTransformer for Desugar {
+    fn transform_statement(&mut self, statement: &mut ASTStatement) {
+        // `guard cond else { body }` is `if !cond { body }`
+        if let ASTStatementKind::Guard(guard) = &mut statement.kind {
+            let condition = mem::replace(&mut *guard.condition, ASTExpression::boolean(false));
+            let body = mem::take(&mut guard.body);
+            let not = ASTUnaryOperator::new(ASTUnaryOperatorKind::LogicalNot, guard.keyword.clone());
+            let arm = ASTIfArm::new(ASTExpression::unary(not, condition), body);
+            statement.kind = ASTStatementKind::If(ASTIfStatement::new(vec![arm], None));
+        }
+        walk_statement(self, statement);
+    }
+
+    fn transform_expression(&mut self, expression: &mut ASTExpression) {
+        // Bottom-up, so the value of `x |> f |> g` is already a call when `g` gets it
+        walk_expression(self, expression);
+        if matches!(expression.kind, ASTExpressionKind::Pipe(_)) {
+            replace_expression(expression, |pipe| match pipe.kind {
+                ASTExpressionKind::Pipe(pipe) => call(*pipe.value, *pipe.target),
+                kind => ASTExpression::new(kind),
+            });
+        }
+    }
+}
+
+/// A call on the right gets `value` as its first argument, so `x |> f |> g(2)` is
+/// `g(f(x), 2)`; anything else is called with `value` alone
+fn call(value: ASTExpression, target: ASTExpression) -> ASTExpression {
+    match target.kind {
+        ASTExpressionKind::FunctionCall(mut call) => {
+            call.arguments.insert(0, value);
+            ASTExpression::new(ASTExpressionKind::FunctionCall(call))
+        }
+        kind => ASTExpression::function_call(ASTExpression::new(kind), vec![value]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+
+    fn lowered(source: &str) -> String {
+        let mut ast = Ast::new();
+        ast.statements = Parser::from_lexer(Lexer::new(source)).parse_all();
+        desugar(&mut ast);
+        ast.to_source()
+    }
+
+    #[test]
+    fn test_guard_becomes_negated_if() {
+        assert_eq!(lowered("for x in xs {\n    guard x > 0 else { continue }\n}"), "for x in xs {\n    if !(x > 0) {\n        continue\n    }\n}\n");
+    }
+
+    #[test]
+    fn test_pipe_becomes_nested_calls() {
+        assert_eq!(lowered("x |> f |> g(2)"), "g(f(x), 2)\n");
+        assert_eq!(lowered("[3, 1] |> sort_by(cmp) |> first"), "first(sort_by([3, 1], cmp))\n");
+    }
+
+    #[test]
+    fn test_sugar_nested_in_core_forms() {
+        assert_eq!(lowered("let y = [x |> f, -(1 |> g)]"), "let y = [f(x), -(g(1))]\n");
+        assert_eq!(lowered("while true {\n    guard ok |> check else { break }\n}"), "while true {\n    if !check(ok) {\n        break\n    }\n}\n");
+    }
+}
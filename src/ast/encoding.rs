@@ -0,0 +1,126 @@
+//! Encoding builtins - conversions between strings, bytes and arrays of byte values
+//!
+//! `encode` and `decode` take an optional encoding name, defaulting to UTF-8. Like the math
+//! builtins these only look at their arguments, so they need no capability.
+
+use crate::ast::types::Value;
+
+/// Names of the encoding builtins
+pub const ENCODING_BUILTINS: &[&str] = &["bytes", "encode", "decode"];
+
+pub fn is_encoding_builtin(name: &str) -> bool {
+    ENCODING_BUILTINS.contains(&name)
+}
+
+/// Text encodings `encode` and `decode` understand
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    /// ISO-8859-1: each byte is the code point of the same value
+    Latin1,
+    Ascii,
+}
+
+impl Encoding {
+    /// The encoding named by the optional argument after the value
+    fn from_args(args: &[Value]) -> Result<Self, String> {
+        match args {
+            [_] => Ok(Encoding::Utf8),
+            [_, Value::String(name)] => match name.to_ascii_lowercase().as_str() {
+                "utf-8" | "utf8" => Ok(Encoding::Utf8),
+                "latin-1" | "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+                "ascii" => Ok(Encoding::Ascii),
+                _ => Err(format!("unknown encoding '{}'; expected utf-8, latin-1 or ascii", name)),
+            },
+            [_, other] => Err(format!("encoding must be a String, found {}", other.get_type())),
+            _ => Err(format!("expected 1 or 2 argument(s), found {}", args.len())),
+        }
+    }
+
+    /// The highest code point the encoding can hold
+    fn max_char(self) -> char {
+        match self {
+            Encoding::Utf8 => char::MAX,
+            Encoding::Latin1 => '\u{ff}',
+            Encoding::Ascii => '\u{7f}',
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Latin1 => "latin-1",
+            Encoding::Ascii => "ascii",
+        }
+    }
+
+    fn encode(self, text: &str) -> Result<Vec<u8>, String> {
+        if self == Encoding::Utf8 {
+            return Ok(text.as_bytes().to_vec());
+        }
+        text.chars()
+            .map(|c| {
+                if c <= self.max_char() {
+                    Ok(c as u8)
+                } else {
+                    Err(format!("'{}' cannot be encoded as {}", c, self.name()))
+                }
+            })
+            .collect()
+    }
+
+    fn decode(self, bytes: &[u8]) -> Result<String, String> {
+        match self {
+            Encoding::Utf8 => std::str::from_utf8(bytes)
+                .map(str::to_string)
+                .map_err(|e| format!("invalid utf-8 at byte {}", e.valid_up_to())),
+            Encoding::Latin1 => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+            Encoding::Ascii => match bytes.iter().position(|byte| !byte.is_ascii()) {
+                Some(position) => Err(format!("invalid ascii at byte {}", position)),
+                None => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+            },
+        }
+    }
+}
+
+/// Calls an encoding builtin
+pub fn call(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    let value = match (name, args.first()) {
+        ("bytes", Some(Value::Array(items))) if args.len() == 1 => {
+            let bytes = items
+                .iter()
+                .map(|item| match item {
+                    Value::Integer(i) => u8::try_from(*i).map_err(|_| format!("byte values must be from 0 to 255, found {}", i)),
+                    other => Err(format!("byte values must be integers, found {}", other.get_type())),
+                })
+                .collect::<Result<Vec<u8>, String>>()?;
+            Value::Bytes(bytes.into())
+        }
+        ("bytes", Some(Value::Bytes(bytes))) if args.len() == 1 => Value::Bytes(bytes.clone()),
+        ("bytes", _) => return Err("expected 1 Array or Bytes argument".to_string()),
+        ("encode", Some(Value::String(text))) => Value::Bytes(Encoding::from_args(args)?.encode(text)?.into()),
+        ("encode", _) => return Err("expected a String to encode".to_string()),
+        ("decode", Some(Value::Bytes(bytes))) => Value::from(Encoding::from_args(args)?.decode(bytes)?),
+        ("decode", _) => return Err("expected Bytes to decode".to_string()),
+        _ => return Err(format!("unknown encoding function '{}'", name)),
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_encode_and_decode() {
+        let text = Value::from("é!");
+        assert_eq!(call("encode", &[Value::from("é!")]), Ok(Some(Value::Bytes(Arc::from([0xc3, 0xa9, b'!'])))));
+        assert_eq!(call("encode", &[text.clone(), Value::from("latin-1")]), Ok(Some(Value::Bytes(Arc::from([0xe9, b'!'])))));
+        assert_eq!(call("encode", &[text, Value::from("ascii")]), Err("'é' cannot be encoded as ascii".to_string()));
+        assert_eq!(call("decode", &[Value::Bytes(Arc::from([0xe9])), Value::from("latin1")]), Ok(Some(Value::from("é"))));
+        assert_eq!(call("decode", &[Value::Bytes(Arc::from([b'a', 0xff]))]), Err("invalid utf-8 at byte 1".to_string()));
+        assert_eq!(call("decode", &[Value::Bytes(Arc::from([])), Value::from("ebcdic")]).map(|_| ()), Err("unknown encoding 'ebcdic'; expected utf-8, latin-1 or ascii".to_string()));
+        assert_eq!(call("bytes", &[Value::from(vec![Value::Integer(1), Value::Integer(256)])]), Err("byte values must be from 0 to 255, found 256".to_string()));
+    }
+}
@@ -1,14 +1,102 @@
 //! Evaluator - executes AST and produces values
 
-use crate::ast::{ASTVisitor, ASTBinaryExpression, ASTNumberExpression, ASTBinaryOperatorKind, ASTUnaryExpression, ASTUnaryOperatorKind, ASTVariableDeclaration, ASTAssignment, ASTIdentifierExpression, ASTFunctionCallExpression};
-use crate::ast::types::Value;
-use crate::ast::symbol_table::SymbolTable;
+use crate::ast::{Ast, ASTStatement, ASTVisitor, ExpressionEvaluator, StatementEvaluator, ASTExpression, ASTExpressionKind, ASTBinaryExpression, ASTNumberExpression, ASTBinaryOperatorKind, ASTUnaryExpression, ASTUnaryOperatorKind, ASTVariableDeclaration, ASTAssignment, ASTIdentifierExpression, ASTFunctionCallExpression, ASTMemberAccessExpression, ASTMethodCallExpression, ASTImportStatement, ASTArrayExpression, ASTMapExpression, ASTSetExpression, ASTIndexExpression, ASTForStatement, ASTForPattern, ASTWhileStatement, ASTSpreadExpression, ASTBlockExpression, ASTLoopControl, LoopControlKind, ASTIfStatement, ASTStatementKind, ASTWithStatement, ASTMatchStatement, ASTPattern, ASTGuardStatement, ASTPipeExpression};
+use crate::ast::lexer::Lexer;
+use crate::ast::parser::Parser;
+use crate::ast::types::{Function, SetElement, Value};
+use crate::ast::builtins;
+use crate::ast::channels;
+use crate::ast::diagnostics::line_column;
+use crate::ast::collections;
+use crate::ast::encoding;
+use crate::ast::functions;
+use crate::ast::json;
+use crate::ast::net;
+use crate::ast::strings;
+use crate::ast::suggest;
+use crate::ast::unparse::{string_literal, value_source};
+use crate::ast::interner::Atom;
+use crate::ast::iteration::ValueIter;
+use crate::ast::math;
+use crate::ast::number_format::NumberFormat;
+use crate::ast::desugar::desugar;
+use crate::ast::optimizer::{self, PureCallCache};
+use crate::ast::resolver::Resolver;
+use crate::ast::symbol_table::{Slot, SymbolTable};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::io::{self, Write as _};
+use std::sync::Arc;
+use crate::capabilities::EvalCapabilities;
+use crate::globals::SharedGlobals;
+use crate::limits::SizeLimits;
+use crate::prelude;
+use crate::async_host::{AsyncBridge, AsyncHostFunction};
+use crate::output::OutputSink;
+use crate::host::HostFunction;
+use crate::package::ModuleLoader;
+use crate::profiler::Profiler;
 
 /// Evaluates AST nodes and maintains execution state
 pub struct ASTEvaluator {
     pub last_value: Option<Value>,
     pub errors: Vec<String>,
     pub symbol_table: SymbolTable,
+    pub profiler: Option<Profiler>,
+    pub modules: ModuleLoader,
+    /// When set, program output is appended here instead of going to stdout
+    pub captured_output: Option<String>,
+    /// Receives program output instead of stdout, unless `captured_output` is set
+    pub output_sink: Option<OutputSink>,
+    /// Receives each runtime error, followed by a newline, as it is raised. Errors are
+    /// still collected in `errors`.
+    pub diagnostic_sink: Option<OutputSink>,
+    /// Printed text not yet written to the output sink or stdout
+    pending_output: String,
+    /// Functions provided by the embedding application
+    pub host_functions: HashMap<String, HostFunction>,
+    /// Host functions returning futures, callable only under `Interpreter::eval_async`
+    pub async_functions: HashMap<String, AsyncHostFunction>,
+    /// Set while running under `Interpreter::eval_async`; hands futures to the host to poll
+    pub(crate) async_bridge: Option<AsyncBridge>,
+    /// Host resources builtins are allowed to use
+    pub capabilities: EvalCapabilities,
+    /// The largest strings and collections a script may build
+    pub size_limits: SizeLimits,
+    /// Read-only scope shared with other interpreters, searched after local variables
+    pub globals: Option<Arc<SharedGlobals>>,
+    /// Fall back to the constants of the standard prelude (see `crate::prelude`) for names
+    /// that neither the script nor the shared globals define
+    pub use_prelude: bool,
+    /// How `print` writes numbers, including those inside arrays and maps
+    pub number_format: NumberFormat,
+    /// Fold constant pure builtin calls and memoize repeated pure calls within a statement (`-O`)
+    pub optimize: bool,
+    pure_calls: PureCallCache,
+    /// Set by `break` or `continue`; statements are skipped until the loop it targets takes it
+    loop_control: Option<ASTLoopControl>,
+    /// Save the scopes as they were at the first runtime error, for post-mortem inspection
+    pub keep_failure_scopes: bool,
+    /// The scopes, block locals included, when the first runtime error was raised
+    pub failure_scopes: Option<SymbolTable>,
+    /// Names the top-level program in traces of errors raised inside imports, e.g. `<repl>`
+    pub source_name: Option<String>,
+    /// Modules being imported, outermost first
+    import_stack: Vec<ImportFrame>,
+    /// Errors before this index already carry a trace
+    traced_errors: usize,
+}
+
+/// A module being imported and the line of the statement it is running
+struct ImportFrame {
+    module: String,
+    line: usize,
+}
+
+impl Default for ASTEvaluator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ASTEvaluator {
@@ -17,98 +105,704 @@ impl ASTEvaluator {
             last_value: None,
             errors: Vec::new(),
             symbol_table: SymbolTable::new(),
+            profiler: None,
+            modules: ModuleLoader::new(),
+            captured_output: None,
+            output_sink: None,
+            diagnostic_sink: None,
+            pending_output: String::new(),
+            host_functions: HashMap::new(),
+            async_functions: HashMap::new(),
+            async_bridge: None,
+            capabilities: EvalCapabilities::default(),
+            size_limits: SizeLimits::default(),
+            globals: None,
+            use_prelude: true,
+            optimize: false,
+            number_format: NumberFormat::default(),
+            pure_calls: PureCallCache::default(),
+            loop_control: None,
+            keep_failure_scopes: false,
+            failure_scopes: None,
+            source_name: None,
+            import_stack: Vec::new(),
+            traced_errors: 0,
+        }
+    }
+
+    /// Appends program output to the capture buffer, or buffers it for the output sink or
+    /// stdout until the next flush
+    fn write_output(&mut self, text: &str) {
+        if let Some(buffer) = self.captured_output.as_mut() {
+            buffer.push_str(text);
+            return;
+        }
+        self.pending_output.push_str(text);
+        if self.pending_output.len() >= OUTPUT_BUFFER_SIZE {
+            self.flush_pending_output();
+        }
+    }
+
+    /// Writes buffered output to the output sink or stdout and flushes it
+    pub fn flush_output(&mut self) -> io::Result<()> {
+        let text = std::mem::take(&mut self.pending_output);
+        match self.output_sink.as_mut() {
+            Some(sink) => sink.write_all(text.as_bytes()).and_then(|_| sink.flush()),
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(text.as_bytes()).and_then(|_| stdout.flush())
+            }
+        }
+    }
+
+    /// Flushes buffered output, reporting a failure as an error of the `print` that wrote it
+    fn flush_pending_output(&mut self) {
+        if self.pending_output.is_empty() {
+            return;
+        }
+        if let Err(e) = self.flush_output() {
+            self.add_error(format!("print: {}", e));
         }
     }
 
     fn add_error(&mut self, error: String) {
+        if self.keep_failure_scopes && self.failure_scopes.is_none() {
+            self.failure_scopes = Some(self.symbol_table.clone());
+        }
+        // Output printed before the error reaches its sink first. An error that cannot be
+        // written is still collected below.
+        if self.diagnostic_sink.is_some() {
+            self.flush_pending_output();
+        }
+        if let Some(sink) = self.diagnostic_sink.as_mut() {
+            let _ = sink.write_all(format!("{}\n", error).as_bytes());
+        }
         self.errors.push(error);
     }
+
+    /// Wraps the result of a checked integer operation, reporting overflow as a runtime error
+    fn checked_integer(&mut self, result: Option<i64>, operation: &str) -> Option<Value> {
+        match result {
+            Some(value) => Some(Value::Integer(value)),
+            None => {
+                self.add_error(format!("Integer overflow in {}", operation));
+                None
+            }
+        }
+    }
+
+    /// Finds the function a call refers to. A bare name calls the builtin or host function
+    /// of that name; any other callee must evaluate to a function value.
+    fn resolve_callee(&mut self, callee: &ASTExpression) -> Option<Function> {
+        if let ASTExpressionKind::Identifier(ident) = &callee.kind {
+            if self.is_function_name(ident.name.as_str()) {
+                return Some(Function::Named(ident.name));
+            }
+            if self.read_variable(ident.name, ident.slot).is_err() {
+                let hint = self.did_you_mean(ident.name.as_str(), true);
+                self.add_error(format!("Unknown function: '{}'{}", ident.name, hint));
+                return None;
+            }
+        }
+        match self.evaluate_expression(callee)? {
+            Value::Function(function) => Some(function),
+            other => {
+                self.add_error(format!("{} is not callable", other.get_type()));
+                None
+            }
+        }
+    }
+
+    /// True if `name` is a builtin or a registered host function
+    fn is_function_name(&self, name: &str) -> bool {
+        SYSTEM_BUILTINS.contains(&name)
+            || math::is_math_builtin(name)
+            || collections::is_collection_builtin(name)
+            || channels::is_channel_builtin(name)
+            || encoding::is_encoding_builtin(name)
+            || functions::is_function_builtin(name)
+            || json::is_json_builtin(name)
+            || net::is_net_builtin(name)
+            || strings::is_string_builtin(name)
+            || self.lookup_host_function(name).is_some()
+            || self.async_functions.contains_key(name)
+    }
+
+    /// Evaluates a call's arguments and dispatches it to the built-in or host function it
+    /// names, or to the function value it evaluated to
+    fn call_function(&mut self, function: Function, func_call: &ASTFunctionCallExpression) -> Option<Value> {
+        let Function::Named(name) = function else {
+            let values = self.evaluate_arguments(&func_call.arguments, Vec::new())?;
+            return self.call_function_value(&function, values);
+        };
+        if !self.check_call_capability(name.as_str()) {
+            return None;
+        }
+
+        let values = if name.as_str() == "print" {
+            // print() shows whatever arguments evaluated, even if some failed
+            let mut values = Vec::new();
+            for arg in &func_call.arguments {
+                self.push_argument(arg, &mut values);
+            }
+            values
+        } else {
+            self.evaluate_arguments(&func_call.arguments, Vec::new())?
+        };
+        self.call_with_values(name, values, func_call.memoize)
+    }
+
+    /// Calls `name(receiver, arguments)`, so every function can also be called as a method
+    fn call_method(&mut self, method_call: &ASTMethodCallExpression) -> Option<Value> {
+        if !self.check_call_capability(method_call.name.as_str()) {
+            return None;
+        }
+        let error_count = self.errors.len();
+        let receiver = self.evaluate_expression(&method_call.receiver);
+        if self.errors.len() > error_count {
+            return None;
+        }
+        let values = self.evaluate_arguments(&method_call.arguments, receiver.into_iter().collect())?;
+        self.call_with_values(method_call.name, values, false)
+    }
+
+    /// Reports a missing capability for a builtin, returning whether the call may go ahead
+    fn check_call_capability(&mut self, name: &str) -> bool {
+        if let Some(capability) = required_capability(name) {
+            if let Err(e) = self.capabilities.check(capability, &format!("{}()", name)) {
+                self.add_error(e);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Calls a built-in or host function with evaluated arguments
+    fn call_with_values(&mut self, name: Atom, values: Vec<Value>, memoize: bool) -> Option<Value> {
+        if let Some(signature) = builtins::signature_of(name.as_str()) {
+            if let Err(e) = signature.check_values(&values) {
+                self.add_error(e);
+                return None;
+            }
+        }
+        match name.as_str() {
+            "print" => {
+                // Print the values
+                let mut line = String::new();
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        line.push(' ');
+                    }
+                    let _ = write!(line, "{}", value.display_with(&self.number_format));
+                }
+                line.push('\n');
+                self.write_output(&line);
+                
+                // print() doesn't return a value
+                None
+            }
+            "flush" => {
+                let result = match values.len() {
+                    0 => self.flush_output().map(|_| None).map_err(|e| e.to_string()),
+                    n => Err(format!("expected 0 argument(s), found {}", n)),
+                };
+                self.finish_call("flush", result)
+            }
+            name @ ("env" | "read_file" | "clock" | "exec") => self.finish_call(name, call_system_builtin(name, &values)),
+            "apply" => match &values[..] {
+                [Value::Function(function), Value::Array(args)] => {
+                    let (function, args) = (function.clone(), args.iter().cloned().collect());
+                    self.call_function_value(&function, args)
+                }
+                _ => self.finish_call("apply", Err("expected a Function and an Array of arguments".to_string())),
+            },
+            "sort_by" => {
+                let limits = self.size_limits;
+                let result = collections::sort_by(&values, &limits, |compare, args| self.call_value(compare, args));
+                self.finish_call("sort_by", result)
+            }
+            name if collections::is_collection_builtin(name) => self.finish_call(name, collections::call(name, &values, &self.size_limits)),
+            name if channels::is_channel_builtin(name) => self.finish_call(name, channels::call(name, &values, &self.size_limits)),
+            name if encoding::is_encoding_builtin(name) => self.finish_call(name, encoding::call(name, &values)),
+            name if functions::is_function_builtin(name) => self.finish_call(name, functions::call(name, &values)),
+            name if json::is_json_builtin(name) => self.finish_call(name, json::call(name, &values)),
+            name if net::is_net_builtin(name) => self.finish_call(name, net::call(name, &values)),
+            name if strings::is_string_builtin(name) => self.finish_call(name, strings::call(name, &values)),
+            name if math::is_math_builtin(name) => {
+                let result = if memoize {
+                    self.pure_calls.get_or_call(name.into(), &values, |args| math::call(name, args))
+                } else {
+                    math::call(name, &values)
+                };
+                self.finish_call(name, result)
+            }
+            name if self.async_functions.contains_key(name) => {
+                let result = match (&self.async_bridge, self.async_functions.get(name)) {
+                    (Some(bridge), Some(function)) => bridge.call(function(values)),
+                    _ => Err("async functions can only be called under Interpreter::eval_async".to_string()),
+                };
+                self.finish_call(name, result)
+            }
+            name => match self.lookup_host_function(name) {
+                Some(function) => self.finish_call(name, function(&values)),
+                None => {
+                    let hint = self.did_you_mean(name, true);
+                    self.add_error(format!("Unknown function: '{}'{}", name, hint));
+                    None
+                }
+            },
+        }
+    }
+
+    /// Calls a function value with evaluated arguments
+    fn call_function_value(&mut self, function: &Function, mut args: Vec<Value>) -> Option<Value> {
+        match function {
+            Function::Named(name) => {
+                if !self.check_call_capability(name.as_str()) {
+                    return None;
+                }
+                self.call_with_values(*name, args, false)
+            }
+            Function::Bound(bound) => {
+                args.splice(0..0, bound.args.iter().cloned());
+                self.call_function_value(&bound.function, args)
+            }
+            Function::Composed(pair) => {
+                let (outer, inner) = &**pair;
+                let error_count = self.errors.len();
+                match self.call_function_value(inner, args) {
+                    Some(value) => self.call_function_value(outer, vec![value]),
+                    None if self.errors.len() == error_count => {
+                        self.add_error(format!("{} returned no value to pass to {}", inner, outer));
+                        None
+                    }
+                    None => None,
+                }
+            }
+            Function::Constant(value) => Some((**value).clone()),
+        }
+    }
+
+    /// Calls a function value on behalf of a builtin, returning its first error instead of
+    /// reporting it
+    fn call_value(&mut self, function: &Function, args: Vec<Value>) -> Result<Option<Value>, String> {
+        let error_count = self.errors.len();
+        let result = self.call_function_value(function, args);
+        match self.errors.drain(error_count..).next() {
+            Some(error) => Err(error),
+            None => Ok(result),
+        }
+    }
+
+    /// Finds a host function registered on this evaluator or in the shared globals
+    fn lookup_host_function(&self, name: &str) -> Option<HostFunction> {
+        self.host_functions
+            .get(name)
+            .or_else(|| self.globals.as_ref()?.function(name))
+            .cloned()
+    }
+
+    /// The prelude's constants, unless it is turned off
+    fn prelude(&self) -> Option<&'static SharedGlobals> {
+        self.use_prelude.then(prelude::prelude)
+    }
+
+    /// Reads a variable, falling back to the shared globals and then the prelude
+    pub fn lookup_variable(&self, name: impl Into<Atom>) -> Result<Value, String> {
+        self.read_variable(name.into(), None)
+    }
+
+    /// Reads a variable through its resolved slot when it is still valid
+    fn read_variable(&self, name: Atom, slot: Option<Slot>) -> Result<Value, String> {
+        if let Some(symbol) = self.symbol_table.locate(name, slot).and_then(|slot| self.symbol_table.get(slot)) {
+            return Ok(symbol.value.clone());
+        }
+        self.globals
+            .as_ref()
+            .and_then(|globals| globals.get(name.as_str()).cloned())
+            .or_else(|| self.prelude()?.get(name.as_str()).cloned())
+            .ok_or_else(|| format!("Variable '{}' not found{}", name, self.did_you_mean(name.as_str(), true)))
+    }
+
+    /// A hint naming the variable, or also the function, that `name` is probably a typo of
+    fn did_you_mean(&self, name: &str, include_functions: bool) -> String {
+        let mut candidates: Vec<&str> = self.symbol_table.iter_symbols().map(|symbol| symbol.name.as_str()).collect();
+        candidates.extend(self.globals.iter().flat_map(|globals| globals.names()));
+        candidates.extend(self.prelude().into_iter().flat_map(|prelude| prelude.names()));
+        if include_functions {
+            candidates.extend(
+                [
+                    SYSTEM_BUILTINS,
+                    math::MATH_BUILTINS,
+                    collections::COLLECTION_BUILTINS,
+                    channels::CHANNEL_BUILTINS,
+                    encoding::ENCODING_BUILTINS,
+                    functions::FUNCTION_BUILTINS,
+                    json::JSON_BUILTINS,
+                    net::NET_BUILTINS,
+                    strings::STRING_BUILTINS,
+                ]
+                .concat(),
+            );
+            candidates.extend(self.host_functions.keys().chain(self.async_functions.keys()).map(String::as_str));
+        }
+        suggest::did_you_mean(name, candidates)
+    }
+
+    /// Lowers `guard` and `|>`, then assigns variable slots in freshly parsed statements
+    /// against the current scopes, after folding constant pure calls when `optimize` is set.
+    ///
+    /// Call before evaluating them: statements that were not desugared cannot run.
+    pub fn resolve(&self, ast: &mut Ast) {
+        desugar(ast);
+        if self.optimize {
+            optimizer::optimize(ast);
+        }
+        let mut resolver = Resolver::new(&self.symbol_table);
+        for statement in &mut ast.statements {
+            resolver.resolve_statement(statement);
+        }
+    }
+
+    /// Evaluates call arguments (or array elements) after any already in `values`,
+    /// stopping at the first one that fails
+    fn evaluate_arguments(&mut self, arguments: &[ASTExpression], mut values: Vec<Value>) -> Option<Vec<Value>> {
+        let error_count = self.errors.len();
+        for arg in arguments {
+            self.push_argument(arg, &mut values);
+            if self.errors.len() > error_count {
+                return None;
+            }
+        }
+        Some(values)
+    }
+
+    /// Evaluates one argument onto `values`, flattening it in place if it is spread
+    fn push_argument(&mut self, arg: &ASTExpression, values: &mut Vec<Value>) {
+        let ASTExpressionKind::Spread(spread) = &arg.kind else {
+            if let Some(value) = self.evaluate_expression(arg) {
+                match self.size_limits.check_collection(values.len() + 1) {
+                    Ok(()) => values.push(value),
+                    Err(e) => self.add_error(e),
+                }
+            }
+            return;
+        };
+        match self.evaluate_expression(&spread.expression) {
+            Some(Value::Array(items)) => match self.size_limits.check_collection(values.len() + items.len()) {
+                Ok(()) => values.extend(items.iter().cloned()),
+                Err(e) => self.add_error(e),
+            },
+            Some(other) => self.add_error(format!("Cannot spread {}; only arrays can be spread", other.get_type())),
+            None => {}
+        }
+    }
+
+    /// Runs a block's statements in the current scope, stopping at the first runtime error
+    /// or at a `break` or `continue`. However the block ends, the expressions of the `defer`
+    /// statements it reached are then evaluated, last deferred first, while its variables
+    /// are still in scope. Returns the last statement's value if every statement ran.
+    fn run_block(&mut self, statements: &[ASTStatement]) -> Option<Value> {
+        let error_count = self.errors.len();
+        let mut deferred = Vec::new();
+        let mut value = None;
+        for statement in statements {
+            if let ASTStatementKind::Defer(expr) = &statement.kind {
+                deferred.push(expr);
+                continue;
+            }
+            value = self.run_statement(statement);
+            if self.errors.len() > error_count || self.loop_control.is_some() {
+                value = None;
+                break;
+            }
+        }
+        for expr in deferred.into_iter().rev() {
+            self.pure_calls.clear();
+            let _ = self.evaluate_expression(expr);
+        }
+        value
+    }
+
+    fn run_statement(&mut self, statement: &ASTStatement) -> Option<Value> {
+        // Memoized results only live for one statement, so repeated calls are never hoisted
+        // across statements
+        self.pure_calls.clear();
+        self.evaluate_statement(statement)
+    }
+
+    /// Runs `f` in a new scope, which is exited afterwards however `f` returns
+    fn scoped<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        let depth = self.symbol_table.scope_depth();
+        self.symbol_table.enter_scope();
+        let result = f(self);
+        self.symbol_table.exit_to_depth(depth);
+        result
+    }
+
+    /// Defines a loop's variables for one item, unpacking it for a `(a, b)` pattern
+    fn bind_loop_variables(&mut self, pattern: &ASTForPattern, item: Value) -> bool {
+        let result = match (pattern, &item) {
+            (ASTForPattern::Name(name), _) => self.symbol_table.define(*name, item, true),
+            (ASTForPattern::Tuple(names), Value::Array(items)) if items.len() == names.len() => names
+                .iter()
+                .zip(items.iter())
+                .try_for_each(|(name, value)| self.symbol_table.define(*name, value.clone(), true)),
+            (ASTForPattern::Tuple(names), Value::Array(items)) => Err(format!(
+                "Cannot unpack an array of length {} into {} variables",
+                items.len(),
+                names.len()
+            )),
+            (ASTForPattern::Tuple(names), other) => {
+                Err(format!("Cannot unpack {} into {} variables", other.get_type(), names.len()))
+            }
+        };
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                self.add_error(e);
+                false
+            }
+        }
+    }
+
+    /// Appends the import chain, innermost frame first, to the first error a statement in
+    /// an imported module raised since `error_count`; the errors it caused are not repeated
+    fn trace_errors(&mut self, error_count: usize) {
+        let first = error_count.max(self.traced_errors);
+        if first >= self.errors.len() {
+            return;
+        }
+        let mut trace = String::new();
+        for frame in self.import_stack.iter().rev() {
+            let _ = write!(trace, "\n  at module '{}', line {}", frame.module, frame.line);
+        }
+        if let Some(source_name) = &self.source_name {
+            let _ = write!(trace, "\n  at {}", source_name);
+        }
+        self.errors[first].push_str(&trace);
+        self.traced_errors = self.errors.len();
+    }
+
+    /// Runs a module's statements in the global scope. Errors they raise are traced back
+    /// through the modules importing this one.
+    pub(crate) fn run_module(&mut self, name: &str, source: &str) {
+        let mut parser = Parser::from_lexer(Lexer::new(source));
+        let mut module = Ast::new();
+        module.statements = parser.parse_all();
+        for diagnostic in &parser.diagnostics {
+            self.add_error(format!("In module '{}': {}", name, diagnostic.message));
+        }
+        self.resolve(&mut module);
+        if self.import_stack.is_empty() {
+            self.traced_errors = self.errors.len();
+        }
+        self.import_stack.push(ImportFrame { module: name.to_string(), line: 0 });
+        for statement in &module.statements {
+            let line = statement.range.as_ref().map_or(0, |range| line_column(source, range.start).0);
+            if let Some(frame) = self.import_stack.last_mut() {
+                frame.line = line;
+            }
+            let error_count = self.errors.len();
+            self.visit_statement(statement);
+            self.trace_errors(error_count);
+        }
+        self.import_stack.pop();
+    }
+
+    /// Unwraps a function's result, reporting failures as runtime errors
+    fn finish_call(&mut self, name: &str, result: Result<Option<Value>, String>) -> Option<Value> {
+        let limits = self.size_limits;
+        let result = result.and_then(|value| match &value {
+            Some(built) => limits.check(built).map(|_| value),
+            None => Ok(value),
+        });
+        match result {
+            Ok(value) => value,
+            Err(e) => {
+                self.add_error(format!("{}: {}", name, e));
+                None
+            }
+        }
+    }
 }
 
-impl ASTVisitor for ASTEvaluator {
-    fn visit_number(&mut self, number: &ASTNumberExpression) {
-        self.last_value = Some(number.value.clone());
+/// Bytes of printed text buffered before they are written out without waiting for the
+/// statement to end
+const OUTPUT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Builtins that reach outside the interpreter, besides the network ones
+pub(crate) const SYSTEM_BUILTINS: &[&str] = &["print", "flush", "env", "read_file", "clock", "exec"];
+
+/// The capability a builtin needs, if any
+fn required_capability(name: &str) -> Option<&'static str> {
+    match name {
+        "print" | "flush" => Some("stdout"),
+        "env" => Some("env"),
+        "read_file" => Some("filesystem"),
+        "clock" => Some("time"),
+        "exec" => Some("process"),
+        "http_get" | "http_post" => Some("network"),
+        _ => None,
+    }
+}
+
+/// Builtins that read from or act on the host environment
+fn call_system_builtin(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    match (name, args) {
+        ("env", [Value::String(key)]) => std::env::var(&**key)
+            .map(|value| Some(Value::from(value)))
+            .map_err(|_| format!("environment variable '{}' is not set", key)),
+        ("read_file", [Value::String(path)]) => std::fs::read_to_string(&**path)
+            .map(|contents| Some(Value::from(contents)))
+            .map_err(|e| format!("cannot read '{}': {}", path, e)),
+        ("clock", []) => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| Some(Value::Float(elapsed.as_secs_f64())))
+            .map_err(|e| e.to_string()),
+        ("clock", _) => Err(format!("expected 0 argument(s), found {}", args.len())),
+        ("exec", [Value::String(command)]) => exec(command, &[]).map(Some),
+        ("exec", [Value::String(command), Value::Array(arguments)]) => exec(command, arguments).map(Some),
+        ("exec", _) => Err("expected a String command and an optional Array of arguments".to_string()),
+        _ => Err("expected 1 String argument".to_string()),
+    }
+}
+
+/// Runs a program directly, without a shell, and waits for it. Returns a map of its exit
+/// `code` (-1 if it was ended by a signal) and its `stdout` and `stderr` text.
+fn exec(command: &str, arguments: &[Value]) -> Result<Value, String> {
+    let arguments = arguments
+        .iter()
+        .map(|argument| match argument {
+            Value::String(argument) => Ok(&**argument),
+            other => Err(format!("arguments must be Strings, found {}", other.get_type())),
+        })
+        .collect::<Result<Vec<&str>, String>>()?;
+    let output = std::process::Command::new(command)
+        .args(arguments)
+        .output()
+        .map_err(|e| format!("cannot run '{}': {}", command, e))?;
+    let result = BTreeMap::from([
+        (Arc::from("code"), Value::Integer(output.status.code().map_or(-1, i64::from))),
+        (Arc::from("stdout"), Value::from(String::from_utf8_lossy(&output.stdout).into_owned())),
+        (Arc::from("stderr"), Value::from(String::from_utf8_lossy(&output.stderr).into_owned())),
+    ]);
+    Ok(Value::from(result))
+}
+
+/// `|`, `&` and `-` on two sets: union, intersection and difference
+fn combine_sets(operator: &ASTBinaryOperatorKind, left: &Value, right: &Value) -> Option<Value> {
+    let (Value::Set(a), Value::Set(b)) = (left, right) else {
+        return None;
+    };
+    let elements: BTreeSet<SetElement> = match operator {
+        ASTBinaryOperatorKind::BitwiseOr => a.union(b).cloned().collect(),
+        ASTBinaryOperatorKind::BitwiseAnd => a.intersection(b).cloned().collect(),
+        ASTBinaryOperatorKind::Minus => a.difference(b).cloned().collect(),
+        _ => return None,
+    };
+    Some(Value::from(elements))
+}
+
+/// Whether `value` matches `pattern`, collecting the values of the names it binds. Literals
+/// match equal values; a literal of a type the value cannot be compared with does not match.
+fn match_pattern(pattern: &ASTPattern, value: &Value, bindings: &mut Vec<(Atom, Value)>) -> bool {
+    match (pattern, value) {
+        (ASTPattern::Wildcard, _) => true,
+        (ASTPattern::Binding(name), _) => {
+            bindings.push((*name, value.clone()));
+            true
+        }
+        (ASTPattern::Literal(literal), _) => literal.equals(value) == Ok(true),
+        (ASTPattern::Tuple(patterns), Value::Array(items)) => {
+            patterns.len() == items.len()
+                && patterns.iter().zip(items.iter()).all(|(pattern, item)| match_pattern(pattern, item, bindings))
+        }
+        (ASTPattern::Tuple(_), _) => false,
+    }
+}
+
+/// Positions `start..end` of a sequence of `length` items, if all of them exist
+fn slice_bounds(start: i64, end: i64, length: usize) -> Result<std::ops::Range<usize>, String> {
+    match (usize::try_from(start), usize::try_from(end)) {
+        (Ok(first), Ok(last)) if first <= last && last <= length => Ok(first..last),
+        _ => Err(format!("Slice {}..{} is out of bounds for length {}", start, end, length)),
+    }
+}
+
+impl ExpressionEvaluator for ASTEvaluator {
+    type Output = Option<Value>;
+
+    fn evaluate_number(&mut self, number: &ASTNumberExpression) -> Option<Value> {
+        Some(number.value.clone())
     }
 
     /// Evaluates binary operations with short-circuit logic for && and ||
-    fn visit_binary_expression(&mut self, expr: &ASTBinaryExpression) {
+    fn evaluate_binary_expression(&mut self, expr: &ASTBinaryExpression) -> Option<Value> {
         // Handle short-circuit evaluation for logical operators (optimization + correctness)
         match expr.operator.kind {
             ASTBinaryOperatorKind::LogicalAnd => {
                 // Evaluate left operand first
-                self.visit_expression(&expr.left);
-                let left = match &self.last_value {
-                    Some(v) => v.clone(),
-                    None => return,
-                };
+                let left = self.evaluate_expression(&expr.left)?;
                 
                 // If left is false, result is false without evaluating right
                 if !left.to_boolean() {
-                    self.last_value = Some(Value::Boolean(false));
-                    return;
+                    return Some(Value::Boolean(false));
                 }
                 
-                self.visit_expression(&expr.right);
-                let right = match &self.last_value {
-                    Some(v) => v.clone(),
-                    None => return,
-                };
-                
-                self.last_value = Some(Value::Boolean(right.to_boolean()));
-                return;
+                let right = self.evaluate_expression(&expr.right)?;
+                return Some(Value::Boolean(right.to_boolean()));
             },
             ASTBinaryOperatorKind::LogicalOr => {
                 // Short-circuit: if left is true, don't evaluate right
-                self.visit_expression(&expr.left);
-                let left = match &self.last_value {
-                    Some(v) => v.clone(),
-                    None => return,
-                };
+                let left = self.evaluate_expression(&expr.left)?;
                 
                 if left.to_boolean() {
-                    self.last_value = Some(Value::Boolean(true));
-                    return;
+                    return Some(Value::Boolean(true));
                 }
                 
-                self.visit_expression(&expr.right);
-                let right = match &self.last_value {
-                    Some(v) => v.clone(),
-                    None => return,
-                };
-                
-                self.last_value = Some(Value::Boolean(right.to_boolean()));
-                return;
+                let right = self.evaluate_expression(&expr.right)?;
+                return Some(Value::Boolean(right.to_boolean()));
             },
             _ => {}, // Continue with normal evaluation
         }
 
         // Normal evaluation for non-short-circuit operators
-        self.visit_expression(&expr.left);
-        let left = match &self.last_value {
-            Some(v) => v.clone(),
-            None => {
-                self.add_error("Left operand evaluation failed".to_string());
-                return;
-            }
+        let Some(left) = self.evaluate_expression(&expr.left) else {
+            self.add_error("Left operand evaluation failed".to_string());
+            return None;
         };
         
-        self.visit_expression(&expr.right);
-        let right = match &self.last_value {
-            Some(v) => v.clone(),
-            None => {
-                self.add_error("Right operand evaluation failed".to_string());
-                return;
-            }
+        let Some(right) = self.evaluate_expression(&expr.right) else {
+            self.add_error("Right operand evaluation failed".to_string());
+            return None;
         };
 
-        self.last_value = match expr.operator.kind {
+        if let Some(combined) = combine_sets(&expr.operator.kind, &left, &right) {
+            if let Err(e) = self.size_limits.check(&combined) {
+                self.add_error(e);
+                return None;
+            }
+            return Some(combined);
+        }
+
+        match expr.operator.kind {
             ASTBinaryOperatorKind::Plus => {
                 // Try to coerce operands to compatible types (e.g., int + float -> float + float)
                 match Value::coerce_to_common_type(&left, &right) {
                     Ok((l, r)) => match (l, r) {
-                        (Value::Integer(a), Value::Integer(b)) => Some(Value::Integer(a + b)),
+                        (Value::Integer(a), Value::Integer(b)) => self.checked_integer(a.checked_add(b), "addition"),
                         (Value::Float(a), Value::Float(b)) => Some(Value::Float(a + b)),
-                        (Value::String(a), Value::String(b)) => Some(Value::String(format!("{}{}", a, b))),
+                        (Value::String(a), Value::String(b)) => {
+                            if let Err(e) = self.size_limits.check_string(a.len() + b.len()) {
+                                self.add_error(e);
+                                return None;
+                            }
+                            let mut joined = String::with_capacity(a.len() + b.len());
+                            joined.push_str(&a);
+                            joined.push_str(&b);
+                            Some(Value::from(joined))
+                        }
                         _ => {
                             self.add_error(format!("Cannot add {:?} and {:?}", left.get_type(), right.get_type()));
                             None
@@ -123,7 +817,7 @@ impl ASTVisitor for ASTEvaluator {
             ASTBinaryOperatorKind::Minus => {
                 match Value::coerce_to_common_type(&left, &right) {
                     Ok((l, r)) => match (l, r) {
-                        (Value::Integer(a), Value::Integer(b)) => Some(Value::Integer(a - b)),
+                        (Value::Integer(a), Value::Integer(b)) => self.checked_integer(a.checked_sub(b), "subtraction"),
                         (Value::Float(a), Value::Float(b)) => Some(Value::Float(a - b)),
                         _ => {
                             self.add_error(format!("Cannot subtract {:?} from {:?}", right.get_type(), left.get_type()));
@@ -139,7 +833,7 @@ impl ASTVisitor for ASTEvaluator {
             ASTBinaryOperatorKind::Multiply => {
                 match Value::coerce_to_common_type(&left, &right) {
                     Ok((l, r)) => match (l, r) {
-                        (Value::Integer(a), Value::Integer(b)) => Some(Value::Integer(a * b)),
+                        (Value::Integer(a), Value::Integer(b)) => self.checked_integer(a.checked_mul(b), "multiplication"),
                         (Value::Float(a), Value::Float(b)) => Some(Value::Float(a * b)),
                         _ => {
                             self.add_error(format!("Cannot multiply {:?} and {:?}", left.get_type(), right.get_type()));
@@ -161,7 +855,7 @@ impl ASTVisitor for ASTEvaluator {
                                 self.add_error("Division by zero".to_string());
                                 None
                             } else {
-                                Some(Value::Integer(a / b))
+                                self.checked_integer(a.checked_div(b), "division")
                             }
                         },
                         (Value::Float(a), Value::Float(b)) => {
@@ -192,7 +886,7 @@ impl ASTVisitor for ASTEvaluator {
                                 self.add_error("Modulo by zero".to_string());
                                 None
                             } else {
-                                Some(Value::Integer(a % b))
+                                self.checked_integer(a.checked_rem(b), "modulo")
                             }
                         },
                         (Value::Float(a), Value::Float(b)) => Some(Value::Float(a % b)),
@@ -215,7 +909,8 @@ impl ASTVisitor for ASTEvaluator {
                             if b < 0 {
                                 Some(Value::Float((a as f64).powf(b as f64)))
                             } else {
-                                Some(Value::Integer(a.pow(b as u32)))
+                                let result = u32::try_from(b).ok().and_then(|exp| a.checked_pow(exp));
+                                self.checked_integer(result, "exponentiation")
                             }
                         },
                         (Value::Float(a), Value::Float(b)) => Some(Value::Float(a.powf(b))),
@@ -260,7 +955,13 @@ impl ASTVisitor for ASTEvaluator {
             },
             ASTBinaryOperatorKind::LeftShift => {
                 match (left.to_integer(), right.to_integer()) {
-                    (Ok(l), Ok(r)) => Some(Value::Integer(l << r)),
+                    (Ok(l), Ok(r)) => match u32::try_from(r).ok().and_then(|r| l.checked_shl(r)) {
+                        Some(result) => Some(Value::Integer(result)),
+                        None => {
+                            self.add_error(format!("Shift amount {} is out of range (0..64)", r));
+                            None
+                        }
+                    },
                     _ => {
                         self.add_error("Left shift requires integer operands".to_string());
                         None
@@ -269,7 +970,13 @@ impl ASTVisitor for ASTEvaluator {
             },
             ASTBinaryOperatorKind::RightShift => {
                 match (left.to_integer(), right.to_integer()) {
-                    (Ok(l), Ok(r)) => Some(Value::Integer(l >> r)),
+                    (Ok(l), Ok(r)) => match u32::try_from(r).ok().and_then(|r| l.checked_shr(r)) {
+                        Some(result) => Some(Value::Integer(result)),
+                        None => {
+                            self.add_error(format!("Shift amount {} is out of range (0..64)", r));
+                            None
+                        }
+                    },
                     _ => {
                         self.add_error("Right shift requires integer operands".to_string());
                         None
@@ -297,7 +1004,7 @@ impl ASTVisitor for ASTEvaluator {
             },
             ASTBinaryOperatorKind::Less => {
                 match left.compare(&right) {
-                    Ok(ordering) => Some(Value::Boolean(ordering == std::cmp::Ordering::Less)),
+                    Ok(ordering) => Some(Value::Boolean(ordering == Some(std::cmp::Ordering::Less))),
                     Err(e) => {
                         self.add_error(e);
                         None
@@ -306,7 +1013,7 @@ impl ASTVisitor for ASTEvaluator {
             },
             ASTBinaryOperatorKind::Greater => {
                 match left.compare(&right) {
-                    Ok(ordering) => Some(Value::Boolean(ordering == std::cmp::Ordering::Greater)),
+                    Ok(ordering) => Some(Value::Boolean(ordering == Some(std::cmp::Ordering::Greater))),
                     Err(e) => {
                         self.add_error(e);
                         None
@@ -315,7 +1022,7 @@ impl ASTVisitor for ASTEvaluator {
             },
             ASTBinaryOperatorKind::LessEqual => {
                 match left.compare(&right) {
-                    Ok(ordering) => Some(Value::Boolean(ordering != std::cmp::Ordering::Greater)),
+                    Ok(ordering) => Some(Value::Boolean(matches!(ordering, Some(std::cmp::Ordering::Less | std::cmp::Ordering::Equal)))),
                     Err(e) => {
                         self.add_error(e);
                         None
@@ -324,34 +1031,44 @@ impl ASTVisitor for ASTEvaluator {
             },
             ASTBinaryOperatorKind::GreaterEqual => {
                 match left.compare(&right) {
-                    Ok(ordering) => Some(Value::Boolean(ordering != std::cmp::Ordering::Less)),
+                    Ok(ordering) => Some(Value::Boolean(matches!(ordering, Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal)))),
                     Err(e) => {
                         self.add_error(e);
                         None
                     }
                 }
             },
+            ASTBinaryOperatorKind::In => match right.contains(&left) {
+                Ok(found) => Some(Value::Boolean(found)),
+                Err(e) => {
+                    self.add_error(e);
+                    None
+                }
+            },
+            ASTBinaryOperatorKind::Range => match (left, right) {
+                (Value::Integer(start), Value::Integer(end)) => Some(Value::Range(start, end)),
+                (left, right) => {
+                    self.add_error(format!("Range bounds must be integers, found {} and {}", left.get_type(), right.get_type()));
+                    None
+                }
+            },
             // Logical operators are handled at the beginning with short-circuit
             ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
                 unreachable!("Logical operators should be handled by short-circuit evaluation")
             },
-        };
+        }
     }
 
-    fn visit_unary_expression(&mut self, unary_expr: &ASTUnaryExpression) {
-        self.visit_expression(&unary_expr.operand);
-        let operand = match &self.last_value {
-            Some(v) => v.clone(),
-            None => {
-                self.add_error("Operand evaluation failed".to_string());
-                return;
-            }
+    fn evaluate_unary_expression(&mut self, unary_expr: &ASTUnaryExpression) -> Option<Value> {
+        let Some(operand) = self.evaluate_expression(&unary_expr.operand) else {
+            self.add_error("Operand evaluation failed".to_string());
+            return None;
         };
         
-        self.last_value = match unary_expr.operator.kind {
+        match unary_expr.operator.kind {
             ASTUnaryOperatorKind::Plus => Some(operand),
             ASTUnaryOperatorKind::Minus => match operand {
-                Value::Integer(i) => Some(Value::Integer(-i)),
+                Value::Integer(i) => self.checked_integer(i.checked_neg(), "negation"),
                 Value::Float(f) => Some(Value::Float(-f)),
                 _ => {
                     self.add_error(format!("Cannot negate {:?}", operand.get_type()));
@@ -361,89 +1078,399 @@ impl ASTVisitor for ASTEvaluator {
             ASTUnaryOperatorKind::LogicalNot => {
                 Some(Value::Boolean(!operand.to_boolean()))
             },
-        };
+        }
     }
 
-    fn visit_identifier(&mut self, ident: &ASTIdentifierExpression) {
-        match self.symbol_table.get_value(&ident.name) {
-            Ok(value) => self.last_value = Some(value),
+    /// Reads a variable; a name that is not a variable but names a function evaluates to
+    /// that function, so `let f = sqrt` works
+    fn evaluate_identifier(&mut self, ident: &ASTIdentifierExpression) -> Option<Value> {
+        match self.read_variable(ident.name, ident.slot) {
+            Ok(value) => Some(value),
+            Err(_) if self.is_function_name(ident.name.as_str()) => Some(Value::Function(Function::Named(ident.name))),
             Err(e) => {
                 self.add_error(e);
-                self.last_value = None;
+                None
             }
         }
     }
 
-    fn visit_variable_declaration(&mut self, decl: &ASTVariableDeclaration) {
-        // Evaluate the initializer
-        self.visit_expression(&decl.initializer);
-        
-        match &self.last_value {
-            Some(value) => {
-                if let Err(e) = self.symbol_table.define(
-                    decl.name.clone(),
-                    value.clone(),
-                    decl.is_mutable
-                ) {
-                    self.add_error(e);
+    /// Calls a function, charging its time to the profiler when one is attached
+    fn evaluate_function_call(&mut self, func_call: &ASTFunctionCallExpression) -> Option<Value> {
+        let function = self.resolve_callee(&func_call.callee)?;
+        let name = function.name();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.enter_function(name.as_str());
+        }
+        let result = self.call_function(function, func_call);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.exit_function(name.as_str());
+        }
+        result
+    }
+
+    /// No value has fields yet, so any member access is an error once the object evaluates
+    fn evaluate_member_access(&mut self, member: &ASTMemberAccessExpression) -> Option<Value> {
+        let object = self.evaluate_expression(&member.object)?;
+        self.add_error(format!("{:?} has no field '{}'", object.get_type(), member.name));
+        None
+    }
+
+    fn evaluate_array(&mut self, array: &ASTArrayExpression) -> Option<Value> {
+        let elements = self.evaluate_arguments(&array.elements, Vec::new())?;
+        Some(Value::from(elements))
+    }
+
+    fn evaluate_map(&mut self, map: &ASTMapExpression) -> Option<Value> {
+        let mut entries = BTreeMap::new();
+        for (key, value) in &map.entries {
+            let key = match self.evaluate_expression(key)? {
+                Value::String(key) => key,
+                other => {
+                    self.add_error(format!("Map keys must be strings, found {}", other.get_type()));
+                    return None;
                 }
+            };
+            let value = self.evaluate_expression(value)?;
+            entries.insert(key, value);
+        }
+        Some(Value::from(entries))
+    }
+
+    fn evaluate_set(&mut self, set: &ASTSetExpression) -> Option<Value> {
+        let elements = self.evaluate_arguments(&set.elements, Vec::new())?;
+        match elements.iter().map(SetElement::try_from).collect::<Result<BTreeSet<_>, _>>() {
+            Ok(elements) => Some(Value::from(elements)),
+            Err(e) => {
+                self.add_error(e);
+                None
             }
-            None => {
-                self.add_error(format!("Failed to evaluate initializer for variable '{}'", decl.name));
+        }
+    }
+
+    /// Indexes arrays, strings (by character), bytes and ranges by position, and maps by
+    /// key. Indexing an array, string or bytes with a range slices it.
+    fn evaluate_index(&mut self, index: &ASTIndexExpression) -> Option<Value> {
+        let object = self.evaluate_expression(&index.object)?;
+        let position = self.evaluate_expression(&index.index)?;
+        let out_of_bounds = |i: i64, length: usize| format!("Index {} is out of bounds for length {}", i, length);
+        let result = match (&object, &position) {
+            (Value::Array(items), Value::Integer(i)) => usize::try_from(*i)
+                .ok()
+                .and_then(|i| items.get(i).cloned())
+                .ok_or_else(|| out_of_bounds(*i, items.len())),
+            (Value::String(text), Value::Integer(i)) => usize::try_from(*i)
+                .ok()
+                .and_then(|i| text.chars().nth(i))
+                .map(|c| Value::from(c.to_string()))
+                .ok_or_else(|| out_of_bounds(*i, text.chars().count())),
+            (Value::Bytes(bytes), Value::Integer(i)) => usize::try_from(*i)
+                .ok()
+                .and_then(|i| bytes.get(i))
+                .map(|byte| Value::Integer((*byte).into()))
+                .ok_or_else(|| out_of_bounds(*i, bytes.len())),
+            (Value::Array(items), Value::Range(start, end)) => {
+                slice_bounds(*start, *end, items.len()).map(|range| Value::from(items[range].to_vec()))
+            }
+            (Value::String(text), Value::Range(start, end)) => {
+                slice_bounds(*start, *end, text.chars().count()).map(|range| {
+                    Value::from(text.chars().skip(range.start).take(range.len()).collect::<String>())
+                })
+            }
+            (Value::Bytes(bytes), Value::Range(start, end)) => {
+                slice_bounds(*start, *end, bytes.len()).map(|range| Value::Bytes(bytes[range].into()))
+            }
+            (Value::Range(start, end), Value::Integer(i)) => start
+                .checked_add(*i)
+                .filter(|item| *i >= 0 && item < end)
+                .map(Value::Integer)
+                .ok_or_else(|| out_of_bounds(*i, end.saturating_sub(*start).max(0) as usize)),
+            (Value::Map(entries), Value::String(key)) => {
+                entries.get(key).cloned().ok_or_else(|| format!("Key {} not found in map", string_literal(key)))
+            }
+            _ => Err(format!("Cannot index {} with {}", object.get_type(), position.get_type())),
+        };
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.add_error(e);
+                None
             }
         }
     }
 
-    fn visit_assignment(&mut self, assign: &ASTAssignment) {
-        // Evaluate the value expression
-        self.visit_expression(&assign.value);
-        
-        match &self.last_value {
-            Some(value) => {
-                if let Err(e) = self.symbol_table.assign(&assign.name, value.clone()) {
-                    self.add_error(e);
-                }
+    /// Spreads are flattened by `push_argument`; the parser produces them nowhere else
+    fn evaluate_spread(&mut self, _spread: &ASTSpreadExpression) -> Option<Value> {
+        self.add_error("'...' can only spread into an array literal or call arguments".to_string());
+        None
+    }
+
+    /// Runs the block in a fresh scope; its value is its last expression's, taken before
+    /// its `defer` statements run
+    fn evaluate_block(&mut self, block: &ASTBlockExpression) -> Option<Value> {
+        self.scoped(|this| this.run_block(&block.statements))
+    }
+
+    fn evaluate_pipe(&mut self, _pipe: &ASTPipeExpression) -> Option<Value> {
+        self.add_error("'|>' was not desugared; call resolve before evaluating".to_string());
+        None
+    }
+
+    /// Calls a method, charging its time to the profiler like a function call
+    fn evaluate_method_call(&mut self, method_call: &ASTMethodCallExpression) -> Option<Value> {
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.enter_function(method_call.name.as_str());
+        }
+        let result = self.call_method(method_call);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.exit_function(method_call.name.as_str());
+        }
+        result
+    }
+}
+
+/// Statements run through the visitor, which stores each statement's value in `last_value`
+/// for callers that only hold a visitor
+impl ASTVisitor for ASTEvaluator {
+    fn visit_statement(&mut self, statement: &ASTStatement) {
+        self.last_value = self.run_statement(statement);
+        self.flush_pending_output();
+    }
+
+    fn visit_expression(&mut self, expression: &ASTExpression) {
+        self.last_value = self.evaluate_expression(expression);
+    }
+
+    fn visit_number(&mut self, number: &ASTNumberExpression) {
+        self.last_value = self.evaluate_number(number);
+    }
+}
+
+/// Declarations and assignments produce the value stored; control flow and imports produce none
+impl StatementEvaluator for ASTEvaluator {
+    fn evaluate_variable_declaration(&mut self, decl: &ASTVariableDeclaration) -> Option<Value> {
+        let Some(value) = self.evaluate_expression(&decl.initializer) else {
+            self.add_error(format!("Failed to evaluate initializer for variable '{}'", decl.name));
+            return None;
+        };
+        if let Err(e) = self.symbol_table.define(decl.name, value.clone(), decl.is_mutable) {
+            self.add_error(e);
+        }
+        Some(value)
+    }
+
+    fn evaluate_assignment(&mut self, assign: &ASTAssignment) -> Option<Value> {
+        // Element assignments evaluate their indices first, left to right
+        let mut path = Vec::with_capacity(assign.indices.len());
+        for index in &assign.indices {
+            path.push(self.evaluate_expression(index)?);
+        }
+
+        let Some(value) = self.evaluate_expression(&assign.value) else {
+            self.add_error(format!("Failed to evaluate value for assignment to '{}'", assign.name));
+            return None;
+        };
+        let result = match self.symbol_table.locate(assign.name, assign.slot) {
+            Some(slot) if !path.is_empty() => self
+                .symbol_table
+                .value_mut(slot)
+                .and_then(|target| target.set_element(&path, value.clone())),
+            Some(slot) => self.symbol_table.assign_slot(slot, value.clone()),
+            None if self.globals.as_ref().is_some_and(|globals| globals.get(assign.name.as_str()).is_some()) => {
+                Err(format!("Cannot assign to shared global '{}'", assign.name))
             }
-            None => {
-                self.add_error(format!("Failed to evaluate value for assignment to '{}'", assign.name));
+            None if self.prelude().is_some_and(|prelude| prelude.get(assign.name.as_str()).is_some()) => {
+                Err(format!("Cannot assign to prelude constant '{}'", assign.name))
             }
+            None => Err(format!("Variable '{}' not found{}", assign.name, self.did_you_mean(assign.name.as_str(), false))),
+        };
+        if let Err(e) = result {
+            self.add_error(e);
         }
+        Some(value)
     }
 
-    fn visit_function_call(&mut self, func_call: &ASTFunctionCallExpression) {
-        match func_call.name.as_str() {
-            "print" => {
-                // Evaluate all arguments and print them
-                let mut values = Vec::new();
-                for arg in &func_call.arguments {
-                    self.visit_expression(arg);
-                    if let Some(value) = &self.last_value {
-                        values.push(value.clone());
-                    }
+    /// Runs the body once per item, each time in a fresh scope holding the loop variables.
+    /// The first runtime error stops the loop, as does a `break` aimed at it; a `continue`
+    /// aimed at it skips to the next item. Either aimed at an outer loop ends this one and
+    /// is left for the outer loop to take.
+    fn evaluate_for_statement(&mut self, for_statement: &ASTForStatement) -> Option<Value> {
+        let iterable = self.evaluate_expression(&for_statement.iterable)?;
+        let items = match ValueIter::new(&iterable) {
+            Ok(items) => items,
+            Err(e) => {
+                self.add_error(e);
+                return None;
+            }
+        };
+
+        let error_count = self.errors.len();
+        for item in items {
+            self.scoped(|this| {
+                if this.bind_loop_variables(&for_statement.pattern, item) {
+                    this.run_block(&for_statement.body);
                 }
-                
-                // Print the values
-                for (i, value) in values.iter().enumerate() {
-                    if i > 0 {
-                        print!(" ");
+            });
+            if self.errors.len() > error_count {
+                break;
+            }
+            match self.loop_control.take() {
+                None => {}
+                Some(control) if control.targets(for_statement.label) => {
+                    if control.kind == LoopControlKind::Break {
+                        break;
                     }
-                    match value {
-                        Value::Integer(n) => print!("{}", n),
-                        Value::Float(f) => print!("{}", f),
-                        Value::Boolean(b) => print!("{}", b),
-                        Value::String(s) => print!("{}", s),
+                }
+                outer => {
+                    self.loop_control = outer;
+                    break;
+                }
+            }
+        }
+        None
+    }
+
+    /// Runs the body in a fresh scope for as long as the condition is true; conditions are
+    /// truthy like those of `if`. `break` and `continue` work as in `for`. A loop that runs
+    /// more often than `size_limits` allows is stopped with a runtime error, since its
+    /// condition is most likely never going to be false.
+    fn evaluate_while_statement(&mut self, while_statement: &ASTWhileStatement) -> Option<Value> {
+        let error_count = self.errors.len();
+        let mut iterations = 0;
+        while self.evaluate_expression(&while_statement.condition)?.to_boolean() {
+            iterations += 1;
+            if let Err(e) = self.size_limits.check_iterations(iterations) {
+                self.add_error(e);
+                break;
+            }
+            self.scoped(|this| this.run_block(&while_statement.body));
+            if self.errors.len() > error_count {
+                break;
+            }
+            match self.loop_control.take() {
+                None => {}
+                Some(control) if control.targets(while_statement.label) => {
+                    if control.kind == LoopControlKind::Break {
+                        break;
                     }
                 }
-                println!();
-                
-                // print() doesn't return a value
-                self.last_value = None;
+                outer => {
+                    self.loop_control = outer;
+                    break;
+                }
+            }
+        }
+        None
+    }
+
+    fn evaluate_loop_control(&mut self, control: &ASTLoopControl) -> Option<Value> {
+        self.loop_control = Some(*control);
+        None
+    }
+
+    /// Blocks run their `defer` statements themselves, so one reached here is outside any block
+    fn evaluate_defer(&mut self, _expr: &ASTExpression) -> Option<Value> {
+        self.add_error("'defer' can only be used inside a block".to_string());
+        None
+    }
+
+    /// Runs the body in a fresh scope where the resource is bound to a constant, then closes
+    /// the resource. It is closed however the body ends, like a `defer` that runs last.
+    /// Channels are the only values that can be closed.
+    fn evaluate_with(&mut self, with: &ASTWithStatement) -> Option<Value> {
+        let resource = self.evaluate_expression(&with.resource)?;
+        let Value::Channel(channel) = &resource else {
+            self.add_error(format!("'with' needs a resource that can be closed, found {}", resource.get_type()));
+            return None;
+        };
+        let channel = channel.clone();
+        self.scoped(|this| match this.symbol_table.define(with.name, resource, false) {
+            Ok(()) => {
+                this.run_block(&with.body);
             }
-            _ => {
-                self.add_error(format!("Unknown function: '{}'", func_call.name));
-                self.last_value = None;
+            Err(e) => this.add_error(e),
+        });
+        channel.close();
+        None
+    }
+
+    /// Runs the block of the first arm whose condition is true, or else the `else` block,
+    /// in a fresh scope. Conditions are truthy like the operands of `&&`.
+    fn evaluate_if_statement(&mut self, if_statement: &ASTIfStatement) -> Option<Value> {
+        let mut chosen = if_statement.else_body.as_deref();
+        for arm in &if_statement.arms {
+            if self.evaluate_expression(&arm.condition)?.to_boolean() {
+                chosen = Some(&arm.body);
+                break;
             }
         }
+        if let Some(body) = chosen {
+            self.scoped(|this| this.run_block(body));
+        }
+        None
     }
-}
 
+    /// Runs the body of the first arm whose pattern matches the value and whose guard is
+    /// true, in a fresh scope holding the names the pattern binds; the guard sees them too.
+    /// A value that no arm matches is a runtime error.
+    fn evaluate_guard(&mut self, _guard: &ASTGuardStatement) -> Option<Value> {
+        self.add_error("'guard' was not desugared; call resolve before evaluating".to_string());
+        None
+    }
+
+    fn evaluate_match_statement(&mut self, match_statement: &ASTMatchStatement) -> Option<Value> {
+        let subject = self.evaluate_expression(&match_statement.subject)?;
+        let error_count = self.errors.len();
+        for arm in &match_statement.arms {
+            let mut bindings = Vec::new();
+            if !match_pattern(&arm.pattern, &subject, &mut bindings) {
+                continue;
+            }
+            let chosen = self.scoped(|this| {
+                for (name, value) in bindings {
+                    if let Err(e) = this.symbol_table.define(name, value, true) {
+                        this.add_error(e);
+                        return false;
+                    }
+                }
+                let passed = match &arm.guard {
+                    Some(guard) => this.evaluate_expression(guard).is_some_and(|value| value.to_boolean()),
+                    None => true,
+                };
+                if passed && this.errors.len() == error_count {
+                    this.run_block(&arm.body);
+                }
+                passed
+            });
+            if chosen || self.errors.len() > error_count {
+                return None;
+            }
+        }
+        self.add_error(format!("No 'match' arm matches {}", value_source(&subject)));
+        None
+    }
+
+    /// Runs an imported module once, sharing the importer's global scope
+    fn evaluate_import(&mut self, import: &ASTImportStatement) -> Option<Value> {
+        if let Err(e) = self.capabilities.check("filesystem", "import") {
+            self.add_error(e);
+            return None;
+        }
+        let path = match self.modules.resolve(&import.path) {
+            Ok(path) => path,
+            Err(e) => {
+                self.add_error(e);
+                return None;
+            }
+        };
+        if !self.modules.begin_module(&path) {
+            return None; // Already imported
+        }
+
+        match std::fs::read_to_string(&path) {
+            Ok(source) => self.run_module(&import.path, &source),
+            Err(e) => self.add_error(format!("Error reading module '{}': {}", path.display(), e)),
+        }
+        self.modules.end_module();
+        None
+    }
+}
\ No newline at end of file
@@ -0,0 +1,87 @@
+//! Function builtins - building functions out of other functions
+//!
+//! `bind(f, a, b)` returns a function that calls `f` with `a` and `b` before the arguments
+//! it is given, so `bind(pow, 2)(3)` is `pow(2, 3)`. Arc has no closures, so binding,
+//! `compose` and `constantly` are how a script makes a new function value. `apply` calls a
+//! function, so the evaluator runs it (see `ASTEvaluator::call_with_values`).
+
+use crate::ast::builtins;
+use crate::ast::types::{Function, Value};
+use std::sync::Arc;
+
+/// Names of the function builtins
+pub const FUNCTION_BUILTINS: &[&str] = &["bind", "compose", "identity", "constantly", "apply", "arity"];
+
+pub fn is_function_builtin(name: &str) -> bool {
+    FUNCTION_BUILTINS.contains(&name)
+}
+
+/// Calls a function builtin
+pub fn call(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    match (name, args) {
+        ("bind", [Value::Function(function), bound @ ..]) => {
+            Ok(Some(Value::Function(Function::bind(function.clone(), bound.to_vec()))))
+        }
+        ("bind", _) => Err("expected a Function and the arguments to bind to it".to_string()),
+        ("compose", [Value::Function(outer), Value::Function(inner)]) => {
+            Ok(Some(Value::Function(Function::Composed(Arc::new((outer.clone(), inner.clone()))))))
+        }
+        ("compose", _) => Err("expected 2 Function arguments".to_string()),
+        ("identity", [value]) => Ok(Some(value.clone())),
+        ("identity", _) => Err(format!("expected 1 argument, found {}", args.len())),
+        ("constantly", [value]) => Ok(Some(Value::Function(Function::Constant(Arc::new(value.clone()))))),
+        ("constantly", _) => Err(format!("expected 1 argument, found {}", args.len())),
+        ("arity", [Value::Function(function)]) => arity(function).map(|n| Some(Value::Integer(n as i64))),
+        ("arity", _) => Err("expected 1 Function argument".to_string()),
+        _ => Err(format!("unknown function builtin '{}'", name)),
+    }
+}
+
+/// How many arguments a call to `function` needs: a builtin's required arguments, less any
+/// already bound. Host functions do not declare theirs.
+pub fn arity(function: &Function) -> Result<usize, String> {
+    match function {
+        Function::Named(name) => builtins::signature_of(name.as_str())
+            .map(|signature| signature.required.len())
+            .ok_or_else(|| format!("the arguments of '{}' are not known; only builtins declare theirs", name)),
+        Function::Bound(bound) => Ok(arity(&bound.function)?.saturating_sub(bound.args.len())),
+        Function::Composed(pair) => arity(&pair.1),
+        Function::Constant(_) => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_supplies_leading_arguments() {
+        let pow = Value::Function(Function::Named("pow".into()));
+        let Ok(Some(Value::Function(powers_of_two))) = call("bind", &[pow, Value::Integer(2)]) else {
+            panic!("bind did not return a function");
+        };
+        assert_eq!(powers_of_two.to_string(), "bind(pow, 2)");
+        let Ok(Some(Value::Function(both))) = call("bind", &[Value::Function(powers_of_two), Value::from("x")]) else {
+            panic!("bind did not return a function");
+        };
+        // Binding again adds to the arguments instead of nesting
+        assert_eq!(both.to_string(), "bind(pow, 2, \"x\")");
+        assert_eq!(both.name().as_str(), "pow");
+        assert!(call("bind", &[Value::Integer(1)]).is_err());
+    }
+
+    #[test]
+    fn test_arity_counts_required_arguments() {
+        let named = |name: &str| Function::Named(name.into());
+        let composed = Function::Composed(Arc::new((named("sqrt"), named("pow"))));
+        assert_eq!(composed.to_string(), "compose(sqrt, pow)");
+        assert_eq!(arity(&composed), Ok(2));
+        assert_eq!(arity(&Function::bind(named("pow"), vec![Value::Integer(2)])), Ok(1));
+        assert_eq!(arity(&named("parse_int")), Ok(1));
+        assert_eq!(arity(&Function::Constant(Arc::new(Value::from("x")))), Ok(0));
+        assert_eq!(
+            arity(&named("greet")),
+            Err("the arguments of 'greet' are not known; only builtins declare theirs".to_string())
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! String interner - identifiers become small integer handles
+//!
+//! The lexer interns every identifier once; tokens, AST nodes and symbol-table keys then
+//! carry a `Copy` handle, so variable lookups hash a `u32` instead of a string.
+//!
+//! The table is process-wide and never shrinks. Each distinct name is leaked once, which
+//! lets `as_str` hand out `&'static str` and lets an `Atom` print without a reference to
+//! any evaluator. The cost is memory that grows with the number of distinct identifiers
+//! ever seen, not with program size or run count: a server that parses untrusted code
+//! full of fresh names keeps them all. Interning takes the write lock only for a new
+//! name; `as_str`, and so every `Display` of an `Atom`, takes the read lock, which is
+//! uncontended unless another thread is interning a new name at that moment.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{OnceLock, RwLock};
+
+/// An interned identifier (a `Symbol(u32)`-style handle)
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Atom(u32);
+
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, Atom>,
+    names: Vec<&'static str>,
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| RwLock::new(Interner::default()))
+}
+
+impl Atom {
+    /// Returns the handle for `name`, interning it on first use
+    pub fn intern(name: &str) -> Atom {
+        // A poisoned lock still holds a consistent table: entries are only ever appended
+        if let Some(atom) = interner().read().unwrap_or_else(|e| e.into_inner()).ids.get(name) {
+            return *atom;
+        }
+        let mut interner = interner().write().unwrap_or_else(|e| e.into_inner());
+        if let Some(atom) = interner.ids.get(name) {
+            return *atom;
+        }
+        let atom = Atom(interner.names.len() as u32);
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        interner.names.push(name);
+        interner.ids.insert(name, atom);
+        atom
+    }
+
+    /// The identifier text; this takes the interner's read lock, so hot loops that compare
+    /// names should compare atoms instead
+    pub fn as_str(self) -> &'static str {
+        interner()
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .names
+            .get(self.0 as usize)
+            .copied()
+            .unwrap_or("")
+    }
+}
+
+impl From<&str> for Atom {
+    fn from(name: &str) -> Self {
+        Atom::intern(name)
+    }
+}
+
+impl From<String> for Atom {
+    fn from(name: String) -> Self {
+        Atom::intern(&name)
+    }
+}
+
+impl From<&String> for Atom {
+    fn from(name: &String) -> Self {
+        Atom::intern(name)
+    }
+}
+
+impl PartialEq<str> for Atom {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Atom {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/// Atoms serialize as their text, since handle numbers differ between processes
+#[cfg(feature = "serde")]
+impl serde::Serialize for Atom {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Atom {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Atom::intern(&name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interning_is_stable() {
+        let a = Atom::intern("counter");
+        assert_eq!(a, Atom::from("counter".to_string()));
+        assert_ne!(a, Atom::intern("counter2"));
+        assert_eq!(a.as_str(), "counter");
+    }
+}
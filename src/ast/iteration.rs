@@ -0,0 +1,135 @@
+//! Iteration - the one protocol `for` loops use to walk any iterable value
+//!
+//! Arrays yield their elements, strings their characters (as one-character strings), bytes
+//! their values as integers, maps `[key, value]` pairs in key order, sets their elements
+//! in order, and ranges their integers. Items are produced one at a time from the shared value, so
+//! `for i in 0..1000000` never builds a list and iterating an array never copies it.
+
+use crate::ast::types::{Aggregate, SetElement, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// A cursor over an iterable value
+pub enum ValueIter {
+    Array { items: Arc<Aggregate<Vec<Value>>>, next: usize },
+    Chars { text: Arc<str>, offset: usize },
+    Bytes { bytes: Arc<[u8]>, next: usize },
+    /// Resumes after the last key yielded, since the map cannot be borrowed across items
+    Map { entries: Arc<Aggregate<BTreeMap<Arc<str>, Value>>>, after: Option<Arc<str>> },
+    Set { elements: Arc<Aggregate<BTreeSet<SetElement>>>, after: Option<SetElement> },
+    Range { next: i64, end: i64 },
+}
+
+impl ValueIter {
+    /// Starts iterating `value`, or explains why it cannot be iterated
+    pub fn new(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Array(items) => Ok(ValueIter::Array { items: items.clone(), next: 0 }),
+            Value::String(text) => Ok(ValueIter::Chars { text: text.clone(), offset: 0 }),
+            Value::Bytes(bytes) => Ok(ValueIter::Bytes { bytes: bytes.clone(), next: 0 }),
+            Value::Map(entries) => Ok(ValueIter::Map { entries: entries.clone(), after: None }),
+            Value::Set(elements) => Ok(ValueIter::Set { elements: elements.clone(), after: None }),
+            Value::Range(start, end) => Ok(ValueIter::Range { next: *start, end: *end }),
+            other => Err(format!("{} is not iterable", other.get_type())),
+        }
+    }
+}
+
+impl Iterator for ValueIter {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        match self {
+            ValueIter::Array { items, next } => {
+                let item = items.get(*next)?.clone();
+                *next += 1;
+                Some(item)
+            }
+            ValueIter::Chars { text, offset } => {
+                let c = text[*offset..].chars().next()?;
+                *offset += c.len_utf8();
+                Some(Value::from(c.to_string()))
+            }
+            ValueIter::Bytes { bytes, next } => {
+                let byte = *bytes.get(*next)?;
+                *next += 1;
+                Some(Value::Integer(byte.into()))
+            }
+            ValueIter::Map { entries, after } => {
+                let lower = match after {
+                    Some(key) => Bound::Excluded(key.clone()),
+                    None => Bound::Unbounded,
+                };
+                let (key, value) = entries.range((lower, Bound::Unbounded)).next()?;
+                *after = Some(key.clone());
+                Some(Value::from(vec![Value::String(key.clone()), value.clone()]))
+            }
+            ValueIter::Set { elements, after } => {
+                let lower = match after {
+                    Some(element) => Bound::Excluded(element.clone()),
+                    None => Bound::Unbounded,
+                };
+                let element = elements.range((lower, Bound::Unbounded)).next()?.clone();
+                *after = Some(element.clone());
+                Some(Value::from(element))
+            }
+            ValueIter::Range { next, end } => {
+                if *next >= *end {
+                    return None;
+                }
+                let item = *next;
+                *next += 1;
+                Some(Value::Integer(item))
+            }
+        }
+    }
+
+    /// Exact for arrays, bytes and ranges and for maps and sets not yet started, so a
+    /// builtin can check the length of what it is about to collect before allocating it
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let exact = |len: usize| (len, Some(len));
+        match self {
+            ValueIter::Array { items, next } => exact(items.len().saturating_sub(*next)),
+            // Each character is 1 to 4 bytes
+            ValueIter::Chars { text, offset } => {
+                let remaining = text.len() - *offset;
+                (remaining.div_ceil(4), Some(remaining))
+            }
+            ValueIter::Bytes { bytes, next } => exact(bytes.len().saturating_sub(*next)),
+            ValueIter::Map { entries, after: None } => exact(entries.len()),
+            ValueIter::Map { entries, .. } => (0, Some(entries.len())),
+            ValueIter::Set { elements, after: None } => exact(elements.len()),
+            ValueIter::Set { elements, .. } => (0, Some(elements.len())),
+            ValueIter::Range { next, end } => {
+                let len = (*end as i128 - *next as i128).max(0);
+                let len = usize::try_from(len).ok();
+                (len.unwrap_or(usize::MAX), len)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iterates_every_iterable_type() {
+        let items = |value: Value| ValueIter::new(&value).map(|iter| iter.map(|item| item.to_string()).collect::<Vec<_>>());
+
+        assert_eq!(items(Value::from("hé")), Ok(vec!["h".to_string(), "é".to_string()]));
+        assert_eq!(items(Value::Range(2, 5)), Ok(vec!["2".to_string(), "3".to_string(), "4".to_string()]));
+        assert_eq!(items(Value::Range(5, 2)), Ok(Vec::new()));
+        assert_eq!(items(Value::from(vec![Value::Integer(1), Value::from("a")])), Ok(vec!["1".to_string(), "a".to_string()]));
+
+        let map = BTreeMap::from([(Arc::from("b"), Value::Integer(2)), (Arc::from("a"), Value::Integer(1))]);
+        assert_eq!(items(Value::from(map)), Ok(vec!["[\"a\", 1]".to_string(), "[\"b\", 2]".to_string()]));
+        assert_eq!(items(Value::Integer(3)), Err("Integer is not iterable".to_string()));
+
+        let hint = |value: Value| ValueIter::new(&value).map(|iter| iter.size_hint());
+        assert_eq!(hint(Value::Range(i64::MIN, i64::MAX)), Ok((usize::MAX, Some(usize::MAX))));
+        assert_eq!(hint(Value::Range(5, 2)), Ok((0, Some(0))));
+        assert_eq!(hint(Value::from("hé")), Ok((1, Some(3))));
+    }
+}
@@ -0,0 +1,361 @@
+//! JSON builtins - `json_parse` and `json_stringify`
+//!
+//! Objects become maps, arrays arrays, and numbers integers when they are whole and fit in
+//! an `i64`, floats otherwise. Arc has no null, so `null` is a parse error. Sets stringify
+//! as arrays; values JSON cannot hold (bytes, functions, ranges, NaN and infinities) cannot
+//! be stringified.
+
+use crate::ast::types::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Names of the JSON builtins
+pub const JSON_BUILTINS: &[&str] = &["json_parse", "json_stringify"];
+
+/// Deepest nesting of arrays and objects `json_parse` accepts, so hostile input cannot
+/// overflow the stack
+const MAX_DEPTH: usize = 256;
+
+pub fn is_json_builtin(name: &str) -> bool {
+    JSON_BUILTINS.contains(&name)
+}
+
+/// Calls a JSON builtin
+pub fn call(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    match (name, args) {
+        ("json_parse", [Value::String(text)]) => parse(text).map(Some),
+        ("json_parse", _) => Err("expected 1 String argument".to_string()),
+        ("json_stringify", [value]) => stringify(value, false).map(|json| Some(Value::from(json))),
+        ("json_stringify", [value, Value::Boolean(pretty)]) => stringify(value, *pretty).map(|json| Some(Value::from(json))),
+        ("json_stringify", _) => Err("expected a value and an optional Boolean 'pretty'".to_string()),
+        _ => Err(format!("unknown JSON function '{}'", name)),
+    }
+}
+
+/// Parses one JSON document, which may be surrounded by whitespace
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = JsonParser { text, pos: 0, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.pos)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Consumes `word` if the input continues with it
+    fn keyword(&mut self, word: &str) -> bool {
+        let matched = self.text[self.pos..].starts_with(word);
+        if matched {
+            self.pos += word.len();
+        }
+        matched
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.nested(Self::object),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.keyword("true") => Ok(Value::Boolean(true)),
+            _ if self.keyword("false") => Ok(Value::Boolean(false)),
+            _ if self.text[self.pos..].starts_with("null") => Err(self.error("null has no Arc value")),
+            Some(_) => Err(self.error("expected a JSON value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nested too deeply (limit is {})", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        self.pos += 1; // consume '[' or '{'
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Value::from(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Value::from(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        let mut entries = BTreeMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Value::from(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            entries.insert(key, self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Value::from(entries));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<Arc<str>, String> {
+        self.pos += 1; // consume opening quote
+        let mut string = String::new();
+        loop {
+            let c = self.text[self.pos..].chars().next().ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(Arc::from(string)),
+                '\\' => string.push(self.escape()?),
+                c if c < ' ' => return Err(self.error("control character in string")),
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let escaped = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += 1;
+        match escaped {
+            b'"' => Ok('"'),
+            b'\\' => Ok('\\'),
+            b'/' => Ok('/'),
+            b'b' => Ok('\u{8}'),
+            b'f' => Ok('\u{c}'),
+            b'n' => Ok('\n'),
+            b'r' => Ok('\r'),
+            b't' => Ok('\t'),
+            b'u' => {
+                let high = self.hex4()?;
+                if !(0xD800..0xDC00).contains(&high) {
+                    return char::from_u32(high).ok_or_else(|| self.error("unpaired surrogate"));
+                }
+                // A high surrogate must be followed by an escaped low one
+                if !self.keyword("\\u") {
+                    return Err(self.error("unpaired surrogate"));
+                }
+                let low = self.hex4()?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return Err(self.error("unpaired surrogate"));
+                }
+                char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or_else(|| self.error("invalid escape"))
+            }
+            _ => Err(self.error("invalid escape")),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()));
+        let value = digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()).ok_or_else(|| self.error("expected 4 hex digits"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let mut whole = true;
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("expected a digit")),
+        }
+        if self.peek() == Some(b'.') {
+            whole = false;
+            self.pos += 1;
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+            self.digits();
+        }
+        if matches!(self.peek(), Some(b'e' | b'E')) {
+            whole = false;
+            self.pos += 1;
+            if matches!(self.peek(), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                return Err(self.error("expected a digit"));
+            }
+            self.digits();
+        }
+        let literal = &self.text[start..self.pos];
+        match literal.parse::<i64>() {
+            Ok(i) if whole => Ok(Value::Integer(i)),
+            // Fractions, exponents and integers too large for an i64
+            _ => match literal.parse::<f64>() {
+                Ok(f) if f.is_finite() => Ok(Value::Float(f)),
+                _ => Err(self.error("number out of range")),
+            },
+        }
+    }
+
+    fn digits(&mut self) {
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+    }
+}
+
+/// Renders `value` as JSON, on one line or indented by two spaces per level
+pub fn stringify(value: &Value, pretty: bool) -> Result<String, String> {
+    let mut json = String::new();
+    write_value(&mut json, value, pretty, 0)?;
+    Ok(json)
+}
+
+fn write_value(json: &mut String, value: &Value, pretty: bool, depth: usize) -> Result<(), String> {
+    match value {
+        Value::Integer(i) => json.push_str(&i.to_string()),
+        Value::Float(f) if !f.is_finite() => return Err(format!("{} cannot be represented in JSON", f)),
+        // Keep a fraction on whole floats so they parse back as floats
+        Value::Float(f) if f.fract() == 0.0 && f.abs() < 1e16 => json.push_str(&format!("{:.1}", f)),
+        Value::Float(f) => json.push_str(&f.to_string()),
+        Value::Boolean(b) => json.push_str(&b.to_string()),
+        Value::String(s) => write_string(json, s),
+        Value::Set(elements) => {
+            let items: Vec<Value> = elements.iter().cloned().map(Value::from).collect();
+            write_value(json, &Value::from(items), pretty, depth)?;
+        }
+        Value::Array(items) => {
+            json.push('[');
+            for (i, item) in items.iter().enumerate() {
+                write_separator(json, i, pretty, depth + 1);
+                write_value(json, item, pretty, depth + 1)?;
+            }
+            write_close(json, ']', !items.is_empty() && pretty, depth);
+        }
+        Value::Map(entries) => {
+            json.push('{');
+            for (i, (key, item)) in entries.iter().enumerate() {
+                write_separator(json, i, pretty, depth + 1);
+                write_string(json, key);
+                json.push_str(if pretty { ": " } else { ":" });
+                write_value(json, item, pretty, depth + 1)?;
+            }
+            write_close(json, '}', !entries.is_empty() && pretty, depth);
+        }
+        other => return Err(format!("{} cannot be represented in JSON", other.get_type())),
+    }
+    Ok(())
+}
+
+fn write_separator(json: &mut String, index: usize, pretty: bool, depth: usize) {
+    if index > 0 {
+        json.push(',');
+    }
+    if pretty {
+        json.push('\n');
+        json.push_str(&"  ".repeat(depth));
+    }
+}
+
+fn write_close(json: &mut String, close: char, newline: bool, depth: usize) {
+    if newline {
+        json.push('\n');
+        json.push_str(&"  ".repeat(depth));
+    }
+    json.push(close);
+}
+
+fn write_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_stringify_round_trip() {
+        let text = r#" {"name": "arc", "tags": ["a\n", "\u00e9\ud83d\ude00"], "n": 3, "x": -1.5e2, "ok": true, "empty": {}} "#;
+        assert_eq!(
+            stringify(&parse(text).unwrap(), false),
+            Ok(r#"{"empty":{},"n":3,"name":"arc","ok":true,"tags":["a\n","é😀"],"x":-150.0}"#.to_string())
+        );
+        assert_eq!(parse(&stringify(&parse("[1, 2.0, [\"\\u0001\"]]").unwrap(), false).unwrap()), parse("[1, 2.0, [\"\\u0001\"]]"));
+        assert_eq!(stringify(&parse(r#"{"a": [1, 2], "b": {}}"#).unwrap(), true), Ok("{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {}\n}".to_string()));
+
+        assert_eq!(parse("[1, null]"), Err("null has no Arc value at byte 4".to_string()));
+        assert_eq!(parse("[1,]"), Err("expected a JSON value at byte 3".to_string()));
+        assert_eq!(parse("01"), Err("unexpected trailing characters at byte 1".to_string()));
+        assert_eq!(parse("1e400"), Err("number out of range at byte 5".to_string()));
+        assert!(parse(&"[".repeat(MAX_DEPTH + 1)).unwrap_err().starts_with("nested too deeply"));
+        assert_eq!(stringify(&Value::Range(0, 2), false), Err("Range cannot be represented in JSON".to_string()));
+    }
+}
@@ -1,5 +1,10 @@
 //! Lexical analyzer - converts source code into tokens
 
+use crate::ast::diagnostics::Diagnostic;
+use crate::ast::interner::Atom;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
 /// Represents different token types in Arc language
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
@@ -7,6 +12,7 @@ pub enum TokenKind {
     Float(f64),
     Boolean(bool),
     String(String),
+    Bytes(Vec<u8>),
     Plus,
     Minus,
     Asterisk,
@@ -15,6 +21,8 @@ pub enum TokenKind {
     DoubleStar,
     Ampersand,
     Pipe,
+    /// `|>`, which passes the value on its left to the call on its right
+    PipeGreater,
     Caret,
     LeftShift,
     RightShift,
@@ -32,19 +40,170 @@ pub enum TokenKind {
     LeftParen,
     RightParen,
     Comma,
+    Dot,
+    DotDot,
+    Ellipsis,
+    Colon,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     // Assignment and keywords
     Equal,
+    /// `=>`, between a `match` arm's pattern and its body
+    FatArrow,
     Let,
     Const,
+    Import,
+    For,
+    While,
+    In,
+    Break,
+    Continue,
+    If,
+    Else,
+    Defer,
+    Guard,
+    With,
+    As,
+    Match,
     Semicolon,
+    DocComment(String),
     Bad,
     EOF,
     Whitespace,
-    Identifier(String),
+    Identifier(Atom),
 }  
 
+/// The broad class of a token, so diagnostics and tools such as the highlighter can treat
+/// like tokens alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenCategory {
+    /// Numbers, booleans, strings and bytes
+    Literal,
+    Identifier,
+    Keyword,
+    /// Arithmetic, bitwise, comparison, logical, range, spread, pipeline and `=`
+    Operator,
+    /// Brackets, separators, `.`, `:` and `=>`
+    Punctuation,
+    /// Whitespace and comments, including doc comments
+    Trivia,
+    /// A character the lexer does not recognize
+    Invalid,
+    End,
+}
+
+/// The keyword tokens; an identifier spelled like one of their names is that keyword
+pub const KEYWORDS: &[TokenKind] = &[
+    TokenKind::Let, TokenKind::Const, TokenKind::Import, TokenKind::For, TokenKind::While, TokenKind::In, TokenKind::Break,
+    TokenKind::Continue, TokenKind::If, TokenKind::Else, TokenKind::Defer, TokenKind::Guard, TokenKind::With,
+    TokenKind::As, TokenKind::Match,
+];
+
+impl TokenKind {
+    pub fn category(&self) -> TokenCategory {
+        match self {
+            TokenKind::Number(_) | TokenKind::Float(_) | TokenKind::Boolean(_) | TokenKind::String(_)
+            | TokenKind::Bytes(_) => TokenCategory::Literal,
+            TokenKind::Identifier(_) => TokenCategory::Identifier,
+            _ if KEYWORDS.contains(self) => TokenCategory::Keyword,
+            TokenKind::LeftParen | TokenKind::RightParen | TokenKind::LeftBrace | TokenKind::RightBrace
+            | TokenKind::LeftBracket | TokenKind::RightBracket | TokenKind::Comma | TokenKind::Dot | TokenKind::Colon
+            | TokenKind::Semicolon | TokenKind::FatArrow => TokenCategory::Punctuation,
+            TokenKind::Whitespace | TokenKind::DocComment(_) => TokenCategory::Trivia,
+            TokenKind::Bad => TokenCategory::Invalid,
+            TokenKind::EOF => TokenCategory::End,
+            _ => TokenCategory::Operator,
+        }
+    }
+
+    /// A name for the kind that does not change between releases: the source text of
+    /// keywords, operators and punctuation, and a word such as "number" for the rest
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Number(_) | TokenKind::Float(_) => "number",
+            TokenKind::Boolean(_) => "boolean",
+            TokenKind::String(_) => "string",
+            TokenKind::Bytes(_) => "bytes",
+            TokenKind::Identifier(_) => "identifier",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Asterisk => "*",
+            TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::DoubleStar => "**",
+            TokenKind::Ampersand => "&",
+            TokenKind::Pipe => "|",
+            TokenKind::PipeGreater => "|>",
+            TokenKind::Caret => "^",
+            TokenKind::LeftShift => "<<",
+            TokenKind::RightShift => ">>",
+            TokenKind::EqualEqual => "==",
+            TokenKind::BangEqual => "!=",
+            TokenKind::Less => "<",
+            TokenKind::Greater => ">",
+            TokenKind::LessEqual => "<=",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::DoubleAmpersand => "&&",
+            TokenKind::DoublePipe => "||",
+            TokenKind::Bang => "!",
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::Comma => ",",
+            TokenKind::Dot => ".",
+            TokenKind::DotDot => "..",
+            TokenKind::Ellipsis => "...",
+            TokenKind::Colon => ":",
+            TokenKind::LeftBrace => "{",
+            TokenKind::RightBrace => "}",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Equal => "=",
+            TokenKind::FatArrow => "=>",
+            TokenKind::Let => "let",
+            TokenKind::Const => "const",
+            TokenKind::Import => "import",
+            TokenKind::For => "for",
+            TokenKind::While => "while",
+            TokenKind::In => "in",
+            TokenKind::Break => "break",
+            TokenKind::Continue => "continue",
+            TokenKind::If => "if",
+            TokenKind::Else => "else",
+            TokenKind::Defer => "defer",
+            TokenKind::Guard => "guard",
+            TokenKind::With => "with",
+            TokenKind::As => "as",
+            TokenKind::Match => "match",
+            TokenKind::Semicolon => ";",
+            TokenKind::DocComment(_) => "doc comment",
+            TokenKind::Bad => "invalid character",
+            TokenKind::EOF => "end of input",
+            TokenKind::Whitespace => "whitespace",
+        }
+    }
+}
+
+/// Describes the token for diagnostics: "keyword 'let'", "operator '+'", "'('",
+/// "number 42", "identifier 'total'" or "end of input"
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TokenKind::Number(n) => write!(f, "number {}", n),
+            TokenKind::Float(n) => write!(f, "number {:?}", n),
+            TokenKind::Boolean(b) => write!(f, "boolean {}", b),
+            TokenKind::Identifier(name) => write!(f, "identifier '{}'", name),
+            _ => match self.category() {
+                TokenCategory::Keyword => write!(f, "keyword '{}'", self.name()),
+                TokenCategory::Operator => write!(f, "operator '{}'", self.name()),
+                TokenCategory::Punctuation => write!(f, "'{}'", self.name()),
+                _ => f.write_str(self.name()),
+            },
+        }
+    }
+}
+
 /// Tracks location and content of a token in source code
 #[derive(Debug, PartialEq, Clone)]
 pub struct TextSpan {
@@ -61,6 +220,11 @@ impl TextSpan {
     pub fn length(&self) -> usize {
         self.end - self.start
     }
+
+    /// Byte offset where the span starts
+    pub fn start(&self) -> usize {
+        self.start
+    }
 }
 
 /// A token with its type and source location
@@ -68,18 +232,34 @@ impl TextSpan {
 pub struct Token {
      pub(crate) kind: TokenKind,
      pub(crate) span: TextSpan,
+     /// Whether a line break separates this token from the previous non-trivia token
+     pub(crate) newline_before: bool,
 } 
 
 impl Token {
     pub fn new(kind: TokenKind, span: TextSpan) -> Self {
-        Self { kind, span }
+        Self { kind, span, newline_before: false }
     }
 }
 
-/// Tokenizes Arc source code into a stream of tokens
+/// Tokenizes Arc source code into a stream of tokens.
+///
+/// By default whitespace and ordinary comments are skipped, which is what the parser
+/// wants; `Lexer::raw` keeps them as `Whitespace` tokens so that tools working on the
+/// source text (the highlighter, a formatter) see every byte.
 pub struct Lexer<'o> {
     pub input: &'o str,
     pub current_pos: usize,
+    keep_trivia: bool,
+    /// Set when trivia since the last significant token contained a line break
+    pending_newline: bool,
+    /// Report unknown or malformed escape sequences instead of keeping them as written
+    strict: bool,
+    /// Set once the `EOF` token has been returned; the lexer returns nothing after it
+    emitted_eof: bool,
+    /// Problems found while lexing: numbers too large for their type, and in strict mode
+    /// bad escapes
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl <'o> Lexer<'o> {
@@ -87,41 +267,76 @@ impl <'o> Lexer<'o> {
         Self {
             input,
             current_pos: 0,
+            keep_trivia: false,
+            pending_newline: false,
+            strict: false,
+            emitted_eof: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Turns on strict mode, where unknown or malformed escapes are diagnostics
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// A lexer that also emits whitespace and comments as `Whitespace` tokens
+    pub fn raw(input: &'o str) -> Self {
+        Self {
+            keep_trivia: true,
+            ..Self::new(input)
         }
     }
 
     /// Returns the next token from input stream
     pub fn next_token(&mut self) -> Option<Token> {
-        if self.current_pos == self.input.len() {
-            self.current_pos += 1;
-            return Some(Token::new(
-                TokenKind::EOF,
-                TextSpan::new(0,0,'\u{0000}'.to_string())
-            ))
-        }
-        let c: Option<char> = self.current_char();
-        return c.map(|c: char| {
-            let start = self.current_pos;
-            let mut kind = TokenKind::Bad;
-
-            if Self::is_number_start(&c) {
-                kind = self.consume_number_or_float();
-            } else if Self::is_whitespace(&c) {
-                self.consume();
-                kind = TokenKind::Whitespace;
-            } else if c == '"' {
-                kind = self.consume_string();
-            } else if Self::is_identifier_start(&c) {
-                kind = self.consume_identifier();
+        loop {
+            let mut token = self.next_raw_token()?;
+            if token.kind == TokenKind::Whitespace {
+                self.pending_newline |= token.span.literal.contains('\n');
+                if self.keep_trivia {
+                    return Some(token);
+                }
             } else {
-                kind = self.consume_punctuation();
+                token.newline_before = std::mem::take(&mut self.pending_newline);
+                return Some(token);
             }
+        }
+    }
 
-            let end = self.current_pos;
-            let literal = self.input[start..end].to_string();
-            let span = TextSpan::new(start, end, literal);
-            Token::new(kind, span)
-        });
+    /// Returns the next token, including whitespace and comments
+    fn next_raw_token(&mut self) -> Option<Token> {
+        if self.emitted_eof {
+            return None;
+        }
+        let Some(c) = self.current_char() else {
+            // An empty span at the end of the input, after any trailing whitespace
+            self.emitted_eof = true;
+            let end = self.input.len();
+            return Some(Token::new(TokenKind::EOF, TextSpan::new(end, end, String::new())));
+        };
+        let start = self.current_pos;
+
+        let kind = if Self::is_number_start(&c) {
+            self.consume_number_or_float()
+        } else if Self::is_whitespace(&c) {
+            self.consume();
+            TokenKind::Whitespace
+        } else if c == '"' {
+            self.consume_string()
+        } else if c == 'b' && self.peek_char(1) == Some('"') {
+            self.consume_bytes()
+        } else if Self::is_identifier_start(&c) {
+            self.consume_identifier()
+        } else {
+            self.consume_punctuation()
+        };
+
+        let end = self.current_pos;
+        let literal = self.input.get(start..end).unwrap_or("").to_string();
+        let span = TextSpan::new(start, end, literal);
+        Some(Token::new(kind, span))
     }
 
     pub fn is_whitespace(c :&char) -> bool {
@@ -130,7 +345,10 @@ impl <'o> Lexer<'o> {
 
     /// Handles operators and punctuation, including multi-character operators
     pub fn consume_punctuation(&mut self) -> TokenKind {
-        let c: char = self.consume().unwrap();
+        let c: char = match self.consume() {
+            Some(c) => c,
+            None => return TokenKind::Bad,
+        };
         match c {
             '+' => TokenKind::Plus,
             '-' => TokenKind::Minus,
@@ -144,8 +362,12 @@ impl <'o> Lexer<'o> {
                 }
             },
             '/' => {
-                // Check for // (single-line comment) or /* (multi-line comment)
-                if self.current_char() == Some('/') {
+                // Check for /// (doc comment), // (single-line comment) or /* (multi-line comment)
+                if self.current_char() == Some('/') && self.peek_char(1) == Some('/') && self.peek_char(2) != Some('/') {
+                    self.consume(); // consume second /
+                    self.consume(); // consume third /
+                    self.consume_doc_comment()
+                } else if self.current_char() == Some('/') {
                     self.consume(); // consume second /
                     self.consume_single_line_comment();
                     TokenKind::Whitespace
@@ -168,12 +390,17 @@ impl <'o> Lexer<'o> {
                 }
             },
             '|' => {
-                // Check for || (logical OR)
-                if self.current_char() == Some('|') {
-                    self.consume();
-                    TokenKind::DoublePipe
-                } else {
-                    TokenKind::Pipe
+                // Check for || (logical OR) and |> (pipeline)
+                match self.current_char() {
+                    Some('|') => {
+                        self.consume();
+                        TokenKind::DoublePipe
+                    }
+                    Some('>') => {
+                        self.consume();
+                        TokenKind::PipeGreater
+                    }
+                    _ => TokenKind::Pipe,
                 }
             },
             '^' => TokenKind::Caret,
@@ -187,12 +414,17 @@ impl <'o> Lexer<'o> {
                 }
             },
             '=' => {
-                // Check for == (equal)
-                if self.current_char() == Some('=') {
-                    self.consume();
-                    TokenKind::EqualEqual
-                } else {
-                    TokenKind::Equal
+                // Check for == (equal) and => (match arm)
+                match self.current_char() {
+                    Some('=') => {
+                        self.consume();
+                        TokenKind::EqualEqual
+                    }
+                    Some('>') => {
+                        self.consume();
+                        TokenKind::FatArrow
+                    }
+                    _ => TokenKind::Equal,
                 }
             },
             ';' => TokenKind::Semicolon,
@@ -223,65 +455,85 @@ impl <'o> Lexer<'o> {
             '(' => TokenKind::LeftParen,
             ')' => TokenKind::RightParen,
             ',' => TokenKind::Comma,
+            '.' => {
+                // Lookahead for ... (spread), .. (range) or single . (member access)
+                if self.current_char() == Some('.') {
+                    self.consume();
+                    if self.current_char() == Some('.') {
+                        self.consume();
+                        TokenKind::Ellipsis
+                    } else {
+                        TokenKind::DotDot
+                    }
+                } else {
+                    TokenKind::Dot
+                }
+            },
+            ':' => TokenKind::Colon,
             '{' => TokenKind::LeftBrace,
             '}' => TokenKind::RightBrace,
+            '[' => TokenKind::LeftBracket,
+            ']' => TokenKind::RightBracket,
             _ => TokenKind::Bad,
         }
     }
 
     pub fn is_number_start(c: &char) -> bool {
-        c.is_digit(10)
+        c.is_ascii_digit()
     }
 
     pub fn is_identifier_start(c: &char) -> bool {
         c.is_alphabetic() || *c == '_'
     }
 
+    /// Combining marks are allowed so that decomposed names like `e\u{301}` lex whole
     pub fn is_identifier_continue(c: &char) -> bool {
-        c.is_alphanumeric() || *c == '_'
+        c.is_alphanumeric() || *c == '_' || is_combining_mark(*c)
     }
 
+    /// Character at the current byte offset (None at or past end of input)
     pub fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.current_pos) 
+        self.input.get(self.current_pos..)?.chars().next()
     }
 
-
+    /// Advances past the current character, keeping `current_pos` on a char boundary
     pub fn consume(&mut self) -> Option<char> {
-        if self.current_pos > self.input.len() {
-            return None;
-        }
-        let c: Option<char> = self.current_char();
-        self.current_pos += 1;
+        let c: char = self.current_char()?;
+        self.current_pos += c.len_utf8();
 
-        c
+        Some(c)
     }
     
     pub fn consume_number(&mut self) -> i64 {
         let mut number :i64= 0;
         while let Some(c) = self.current_char() {
-            if !c.is_digit(10) {
+            if !c.is_ascii_digit() {
                 break;
             }
-            self.consume().unwrap();
-            number = number * 10 + c.to_digit(10).unwrap() as i64;
+            self.consume();
+            let digit = c.to_digit(10).unwrap_or(0) as i64;
+            number = number.wrapping_mul(10).wrapping_add(digit);
         }
         number
     }
 
-    /// Parses numeric literals (integers or floats)
+    /// Parses numeric literals (integers or floats). A literal too large for its type is
+    /// reported in `diagnostics` and lexes as the largest value the type holds, so it is
+    /// never silently read as some other number.
     pub fn consume_number_or_float(&mut self) -> TokenKind {
+        let start = self.current_pos;
         let mut number_str = String::new();
         let mut is_float = false;
         
         // Consume integer part
         while let Some(c) = self.current_char() {
-            if c.is_digit(10) {
+            if c.is_ascii_digit() {
                 number_str.push(c);
                 self.consume();
             } else if c == '.' && !is_float {
                 // Lookahead to distinguish float (3.14) from method call (obj.method)
                 if let Some(next_c) = self.peek_char(1) {
-                    if next_c.is_digit(10) {
+                    if next_c.is_ascii_digit() {
                         is_float = true;
                         number_str.push(c);
                         self.consume();
@@ -296,47 +548,142 @@ impl <'o> Lexer<'o> {
             }
         }
         
-        if is_float {
-            TokenKind::Float(number_str.parse().unwrap_or(0.0))
+        let (kind, problem) = if is_float {
+            // Digits with one '.' always parse; only the magnitude can be out of range
+            let number: f64 = number_str.parse().unwrap_or(f64::INFINITY);
+            if number.is_finite() {
+                (TokenKind::Float(number), None)
+            } else {
+                (TokenKind::Float(f64::MAX), Some(format!("Float literal {} is too large for a Float", number_str)))
+            }
         } else {
-            TokenKind::Number(number_str.parse().unwrap_or(0))
+            match number_str.parse() {
+                Ok(number) => (TokenKind::Number(number), None),
+                Err(_) => (
+                    TokenKind::Number(i64::MAX),
+                    Some(format!(
+                        "Integer literal {} is too large for an Integer (the largest is {}); write {}.0 for a Float",
+                        number_str,
+                        i64::MAX,
+                        number_str
+                    )),
+                ),
+            }
+        };
+        if let Some(message) = problem {
+            let span = TextSpan::new(start, self.current_pos, number_str);
+            self.diagnostics.push(Diagnostic::error(message, Some(span)));
         }
+        kind
     }
 
     /// Parses string literals with escape sequence support
     pub fn consume_string(&mut self) -> TokenKind {
+        // Only whole characters are pushed, so the contents are always valid UTF-8
+        TokenKind::String(String::from_utf8(self.consume_quoted(false)).unwrap_or_default())
+    }
+
+    /// Parses `b"..."` byte-string literals. Escapes are the same as in strings, except
+    /// that `\xNN` is a single byte of any value rather than a code point.
+    pub fn consume_bytes(&mut self) -> TokenKind {
+        self.consume(); // consume 'b'
+        TokenKind::Bytes(self.consume_quoted(true))
+    }
+
+    /// Consumes a quoted literal and returns its contents as UTF-8, except for `\xNN`
+    /// escapes in byte strings. A literal the input ends inside is reported, and keeps
+    /// what it holds up to there.
+    fn consume_quoted(&mut self, bytes: bool) -> Vec<u8> {
+        let start = self.current_pos;
         self.consume(); // consume opening quote
-        let mut string = String::new();
+        let mut string = Vec::new();
+        let mut buffer = [0; 4];
         
-        while let Some(c) = self.current_char() {
+        loop {
+            let Some(c) = self.current_char() else {
+                let text = self.input.get(start..self.current_pos).unwrap_or_default();
+                let span = TextSpan::new(start, self.current_pos, text.to_string());
+                let kind = if bytes { "byte string" } else { "string" };
+                self.diagnostics.push(Diagnostic::error(format!("unterminated {} literal", kind), Some(span)));
+                break;
+            };
             if c == '"' {
                 self.consume(); // consume closing quote
                 break;
             } else if c == '\\' {
+                let escape_start = self.current_pos;
                 self.consume();
-                // Process escape sequences (\n, \t, etc.)
-                if let Some(escaped) = self.current_char() {
-                    self.consume();
-                    match escaped {
-                        'n' => string.push('\n'),
-                        't' => string.push('\t'),
-                        'r' => string.push('\r'),
-                        '\\' => string.push('\\'),
-                        '"' => string.push('"'),
-                        _ => {
-                            // Unknown escape: keep backslash and character
-                            string.push('\\');
-                            string.push(escaped);
+                match self.consume_escape() {
+                    Ok(escaped) if bytes && self.input[escape_start..].starts_with("\\x") => string.push(escaped as u8),
+                    Ok(escaped) => string.extend_from_slice(escaped.encode_utf8(&mut buffer).as_bytes()),
+                    Err(problem) => {
+                        // Keep the escape's text as written; strict mode also reports it
+                        let text = self.input.get(escape_start..self.current_pos).unwrap_or("\\");
+                        if self.strict {
+                            let span = TextSpan::new(escape_start, self.current_pos, text.to_string());
+                            self.diagnostics.push(Diagnostic::error(format!("{} escape sequence '{}'", problem, text), Some(span)));
                         }
+                        string.extend_from_slice(text.as_bytes());
                     }
                 }
             } else {
-                string.push(c);
+                string.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
                 self.consume();
             }
         }
         
-        TokenKind::String(string)
+        string
+    }
+
+    /// Decodes the escape after a backslash: `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\xNN` (a
+    /// code point up to `FF`) or `\u{N..}` (1-6 hex digits). On failure returns whether the
+    /// escape was "unknown" or "invalid", having consumed the characters it looked at.
+    fn consume_escape(&mut self) -> Result<char, &'static str> {
+        let escaped = self.consume().ok_or("unknown")?;
+        match escaped {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            'x' => {
+                let digits = self.consume_hex_digits(2);
+                if digits.len() != 2 {
+                    return Err("invalid");
+                }
+                u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32).ok_or("invalid")
+            }
+            'u' => {
+                if self.current_char() != Some('{') {
+                    return Err("invalid");
+                }
+                self.consume();
+                let digits = self.consume_hex_digits(6);
+                if self.current_char() != Some('}') {
+                    return Err("invalid");
+                }
+                self.consume();
+                // Rejects empty braces, surrogates and values past U+10FFFF
+                u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32).ok_or("invalid")
+            }
+            _ => Err("unknown"),
+        }
+    }
+
+    /// Consumes up to `max` hex digits
+    fn consume_hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max {
+            match self.current_char() {
+                Some(c) if c.is_ascii_hexdigit() => {
+                    digits.push(c);
+                    self.consume();
+                }
+                _ => break,
+            }
+        }
+        digits
     }
 
     /// Parses identifiers and keywords (let, const, true, false)
@@ -352,18 +699,25 @@ impl <'o> Lexer<'o> {
             }
         }
         
+        // Normalize to NFC so that composed and decomposed spellings of a name are one name
+        if !identifier.is_ascii() {
+            identifier = identifier.nfc().collect();
+        }
+
         // Distinguish reserved keywords from user-defined identifiers
         match identifier.as_str() {
             "true" => TokenKind::Boolean(true),
             "false" => TokenKind::Boolean(false),
-            "let" => TokenKind::Let,
-            "const" => TokenKind::Const,
-            _ => TokenKind::Identifier(identifier), // User-defined name
+            name => match KEYWORDS.iter().find(|keyword| keyword.name() == name) {
+                Some(keyword) => keyword.clone(),
+                None => TokenKind::Identifier(Atom::intern(name)), // User-defined name
+            },
         }
     }
 
+    /// Character `offset` characters after the current one
     pub fn peek_char(&self, offset: usize) -> Option<char> {
-        self.input.chars().nth(self.current_pos + offset)
+        self.input.get(self.current_pos..)?.chars().nth(offset)
     }
 
     pub fn consume_single_line_comment(&mut self) {
@@ -376,6 +730,20 @@ impl <'o> Lexer<'o> {
         }
     }
 
+    /// Collects the text of a `///` doc comment, dropping one leading space
+    pub fn consume_doc_comment(&mut self) -> TokenKind {
+        let mut text = String::new();
+        while let Some(c) = self.current_char() {
+            if c == '\n' {
+                break;
+            }
+            text.push(c);
+            self.consume();
+        }
+        let text = text.strip_prefix(' ').unwrap_or(&text);
+        TokenKind::DocComment(text.trim_end().to_string())
+    }
+
     pub fn consume_multi_line_comment(&mut self) {
         // Consume until */ or end of input
         while let Some(c) = self.current_char() {
@@ -388,3 +756,124 @@ impl <'o> Lexer<'o> {
         }
     }
 }
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.next_token()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trivia_modes() {
+        let source = "let x = 1 // one\n";
+        let kinds = |lexer: Lexer| lexer.map(|token| token.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds(Lexer::new(source)),
+            [TokenKind::Let, TokenKind::Identifier(Atom::intern("x")), TokenKind::Equal, TokenKind::Number(1), TokenKind::EOF]
+        );
+
+        let raw: Vec<Token> = Lexer::raw(source).collect();
+        let text: String = raw.iter().map(|token| token.span.literal.as_str()).collect();
+        assert_eq!(text, source);
+        assert_eq!(raw.last().map(|token| (token.span.start, token.span.end)), Some((source.len(), source.len())));
+    }
+
+    #[test]
+    fn test_token_categories_and_names() {
+        let described: Vec<(TokenCategory, String)> = Lexer::new("match x |> f(2.5) => {")
+            .map(|token| (token.kind.category(), token.kind.to_string()))
+            .collect();
+        let expected = [
+            (TokenCategory::Keyword, "keyword 'match'"),
+            (TokenCategory::Identifier, "identifier 'x'"),
+            (TokenCategory::Operator, "operator '|>'"),
+            (TokenCategory::Identifier, "identifier 'f'"),
+            (TokenCategory::Punctuation, "'('"),
+            (TokenCategory::Literal, "number 2.5"),
+            (TokenCategory::Punctuation, "')'"),
+            (TokenCategory::Punctuation, "'=>'"),
+            (TokenCategory::Punctuation, "'{'"),
+            (TokenCategory::End, "end of input"),
+        ];
+        assert_eq!(described, expected.map(|(category, text)| (category, text.to_string())));
+        // Every keyword lexes back to itself from its name
+        for keyword in KEYWORDS {
+            assert_eq!(Lexer::new(keyword.name()).next().map(|token| token.kind).as_ref(), Some(keyword));
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_numbers() {
+        let mut lexer = Lexer::new("9223372036854775807 99999999999999999999");
+        assert_eq!(lexer.next_token().map(|token| token.kind), Some(TokenKind::Number(i64::MAX)));
+        assert!(lexer.diagnostics.is_empty());
+        assert_eq!(lexer.next_token().map(|token| token.kind), Some(TokenKind::Number(i64::MAX)));
+        let spans: Vec<_> = lexer.diagnostics.iter().map(|d| (d.message.as_str(), d.span.as_ref().map(|s| s.start..s.end))).collect();
+        assert_eq!(
+            spans,
+            [(
+                "Integer literal 99999999999999999999 is too large for an Integer (the largest is 9223372036854775807); write 99999999999999999999.0 for a Float",
+                Some(20..40)
+            )]
+        );
+
+        let huge = format!("{}.5", "9".repeat(400));
+        let mut lexer = Lexer::new(&huge);
+        assert_eq!(lexer.next_token().map(|token| token.kind), Some(TokenKind::Float(f64::MAX)));
+        assert_eq!(lexer.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_identifiers_are_nfc_normalized() {
+        let composed: Vec<TokenKind> = Lexer::new("let \u{e9}t\u{e9} = \u{3c0}").map(|token| token.kind).collect();
+        let decomposed: Vec<TokenKind> = Lexer::new("let e\u{301}te\u{301} = \u{3c0}").map(|token| token.kind).collect();
+        assert_eq!(composed, decomposed);
+        assert_eq!(composed[1], TokenKind::Identifier(Atom::intern("\u{e9}t\u{e9}")));
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        let mut lexer = Lexer::new(r#""a\0\x41\u{1F600}\q""#).strict();
+        assert_eq!(lexer.next_token().map(|token| token.kind), Some(TokenKind::String("a\0A\u{1F600}\\q".to_string())));
+        let spans: Vec<_> = lexer.diagnostics.iter().map(|d| (d.message.as_str(), d.span.as_ref().map(|s| s.start..s.end))).collect();
+        assert_eq!(spans, [("unknown escape sequence '\\q'", Some(17..19))]);
+
+        let mut lexer = Lexer::new(r#""\x4" "\u{D800}""#).strict();
+        lexer.by_ref().for_each(drop);
+        assert_eq!(lexer.diagnostics.len(), 2);
+
+        let mut lexer = Lexer::new(r#""\q""#);
+        assert_eq!(lexer.next_token().map(|token| token.kind), Some(TokenKind::String("\\q".to_string())));
+        assert!(lexer.diagnostics.is_empty());
+
+        // In byte strings `\xNN` is a raw byte, and other characters are UTF-8 encoded
+        let mut lexer = Lexer::new(r#"b"\xffé\n" b"#);
+        assert_eq!(lexer.next_token().map(|token| token.kind), Some(TokenKind::Bytes(vec![0xff, 0xc3, 0xa9, b'\n'])));
+        assert_eq!(lexer.next_token().map(|token| token.kind), Some(TokenKind::Identifier(Atom::intern("b"))));
+    }
+
+    #[test]
+    fn test_unterminated_literals_are_reported() {
+        let mut lexer = Lexer::new("let s = \"abc");
+        let kinds: Vec<TokenKind> = lexer.by_ref().map(|token| token.kind).collect();
+        assert_eq!(kinds[3], TokenKind::String("abc".to_string()));
+        let spans: Vec<_> = lexer.diagnostics.iter().map(|d| (d.message.as_str(), d.span.as_ref().map(|s| s.start..s.end))).collect();
+        assert_eq!(spans, [("unterminated string literal", Some(8..12))]);
+
+        let mut lexer = Lexer::new("x = b\"\\x01");
+        lexer.by_ref().for_each(drop);
+        let spans: Vec<_> = lexer.diagnostics.iter().map(|d| (d.message.as_str(), d.span.as_ref().map(|s| s.start..s.end))).collect();
+        assert_eq!(spans, [("unterminated byte string literal", Some(5..10))]);
+
+        // A backslash right before the end does not hide it
+        let mut lexer = Lexer::new("\"a\\");
+        lexer.by_ref().for_each(drop);
+        assert_eq!(lexer.diagnostics.len(), 1);
+        assert_eq!(lexer.diagnostics[0].message, "unterminated string literal");
+    }
+}
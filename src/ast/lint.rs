@@ -0,0 +1,806 @@
+//! Lints - warnings about source that runs but probably does not mean what it says
+//!
+//! Six lints always run:
+//! - confusable identifiers: names whose letters look like ASCII letters from another
+//!   script, such as a Cyrillic `а` inside `pаy`
+//! - operator mixes that are commonly misread, such as `a & b == c`, whose grouping differs
+//!   from C's, or `-a ** b`, which negates `a` before raising it
+//! - discarded values: an expression statement such as `x + 1` that computes a value with
+//!   no side effects and drops it, usually a missing assignment or `print`
+//! - decided conditions: an `if` condition that folds to a constant, with the branches it
+//!   makes unreachable, a `while` loop whose body never runs, and comparisons whose operand
+//!   types already decide the outcome
+//! - builtin calls with arguments the builtin cannot take, judged by the signatures in
+//!   `ast::builtins` from the argument count and whatever argument types are known
+//! - `match` arms that the arms above them leave unreachable. A `match` on a Boolean that
+//!   handles only one of `true` and `false` and has no catch-all arm is an error instead.
+//!
+//! Naming conventions and trailing commas are a matter of house style, so those lints
+//! are off unless a package's `arc.toml` turns them on (see `LintConfig`).
+
+use crate::ast::builtins::signature_of;
+use crate::ast::desugar::desugar;
+use crate::ast::diagnostics::Diagnostic;
+use crate::ast::lexer::{Lexer, TextSpan, Token, TokenKind};
+use crate::ast::interner::Atom;
+use crate::ast::optimizer::{constant_value, is_effect_free_builtin};
+use crate::ast::types::DataType;
+use crate::ast::parser::Parser;
+use crate::ast::transform::{children, statement_expressions};
+use crate::ast::types::Value;
+use crate::ast::unparse::{expression_source, pattern_source};
+use crate::ast::{
+    Ast, ASTBinaryOperatorKind, ASTExpression, ASTExpressionKind, ASTMatchStatement, ASTPattern, ASTStatement,
+    ASTStatementKind, ASTUnaryOperatorKind, OperatorFamily,
+};
+use crate::package::{find_manifest, Manifest};
+use std::collections::HashMap;
+use std::mem;
+use std::path::Path;
+
+/// The optional lints, set in the `[lints]` table of `arc.toml`:
+///
+/// ```toml
+/// [lints]
+/// snake_case = true
+/// screaming_case_constants = true
+/// no_trailing_commas = true
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LintConfig {
+    /// Warn about `let` and loop variables that are not snake_case
+    pub snake_case: bool,
+    /// Warn about `const` names that are not SCREAMING_CASE
+    pub screaming_case_constants: bool,
+    /// Warn about a comma after the last item of a call's arguments or a literal
+    pub no_trailing_commas: bool,
+}
+
+impl LintConfig {
+    /// Reads the `[lints]` table of a manifest. Unknown lints are errors, so a misspelled
+    /// name does not silently leave a lint off.
+    pub fn from_manifest(manifest: &Manifest) -> Result<Self, String> {
+        let mut config = LintConfig::default();
+        for (key, value) in &manifest.extra {
+            let Some(name) = key.strip_prefix("lints.") else {
+                continue;
+            };
+            let enabled = match value.as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(format!("arc.toml: lints.{} must be true or false", name)),
+            };
+            match name {
+                "snake_case" => config.snake_case = enabled,
+                "screaming_case_constants" => config.screaming_case_constants = enabled,
+                "no_trailing_commas" => config.no_trailing_commas = enabled,
+                _ => return Err(format!("arc.toml: unknown lint '{}'", name)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The lints for a script, from the nearest manifest; the defaults outside a package
+    pub fn for_script(script: &Path) -> Result<Self, String> {
+        let dir = script.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        match find_manifest(dir) {
+            Some(manifest_path) => LintConfig::from_manifest(&Manifest::load(&manifest_path)?),
+            None => Ok(LintConfig::default()),
+        }
+    }
+}
+
+/// Non-Latin letters that render like an ASCII letter, with that letter
+const CONFUSABLES: &[(char, char)] = &[
+    // Cyrillic
+    ('\u{430}', 'a'), ('\u{435}', 'e'), ('\u{43E}', 'o'), ('\u{440}', 'p'), ('\u{441}', 'c'),
+    ('\u{443}', 'y'), ('\u{445}', 'x'), ('\u{455}', 's'), ('\u{456}', 'i'), ('\u{458}', 'j'),
+    ('\u{4BB}', 'h'), ('\u{501}', 'd'), ('\u{410}', 'A'), ('\u{412}', 'B'), ('\u{415}', 'E'),
+    ('\u{41A}', 'K'), ('\u{41C}', 'M'), ('\u{41D}', 'H'), ('\u{41E}', 'O'), ('\u{420}', 'P'),
+    ('\u{421}', 'C'), ('\u{422}', 'T'), ('\u{425}', 'X'),
+    // Greek
+    ('\u{3BF}', 'o'), ('\u{3BD}', 'v'), ('\u{391}', 'A'), ('\u{392}', 'B'), ('\u{395}', 'E'),
+    ('\u{396}', 'Z'), ('\u{397}', 'H'), ('\u{399}', 'I'), ('\u{39A}', 'K'), ('\u{39C}', 'M'),
+    ('\u{39D}', 'N'), ('\u{39F}', 'O'), ('\u{3A1}', 'P'), ('\u{3A4}', 'T'), ('\u{3A5}', 'Y'),
+    ('\u{3A7}', 'X'),
+];
+
+fn ascii_lookalike(c: char) -> Option<char> {
+    CONFUSABLES.iter().find(|(confusable, _)| *confusable == c).map(|(_, ascii)| *ascii)
+}
+
+/// The name with every confusable letter replaced by its ASCII lookalike
+fn skeleton(name: &str) -> String {
+    name.chars().map(|c| ascii_lookalike(c).unwrap_or(c)).collect()
+}
+
+/// Runs the lints that are always on over `source`
+pub fn lint(source: &str) -> Vec<Diagnostic> {
+    lint_with(source, &LintConfig::default())
+}
+
+/// Runs the lints that are always on and those `config` enables over `source`
+pub fn lint_with(source: &str, config: &LintConfig) -> Vec<Diagnostic> {
+    let tokens: Vec<Token> = Lexer::new(source).collect();
+    // Parse errors are left to the parser to report. The lints check what will run, so
+    // `guard` and `|>` are lowered first.
+    let mut ast = Ast::new();
+    ast.statements = Parser::from_lexer(Lexer::new(source)).parse_all();
+    desugar(&mut ast);
+    let statements = ast.statements;
+    let mut diagnostics = check_confusables(&tokens);
+    diagnostics.extend(check_precedence(&statements));
+    check_discarded_values(source, &statements, false, &mut diagnostics);
+    check_decided_conditions(source, &statements, &mut diagnostics);
+    diagnostics.extend(check_naming(&tokens, config));
+    if config.no_trailing_commas {
+        diagnostics.extend(check_trailing_commas(&tokens));
+    }
+    // In source order; the sort is stable, so warnings on one token keep their order
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.as_ref().map_or(0, |span| span.start));
+    diagnostics
+}
+
+/// Warns once per identifier that mixes ASCII letters with confusable ones, or that
+/// differs from another identifier in the source only by confusable letters
+fn check_confusables(tokens: &[Token]) -> Vec<Diagnostic> {
+    // First spelling seen for each skeleton
+    let mut by_skeleton: HashMap<String, &str> = HashMap::new();
+    let mut reported: Vec<&str> = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    for token in tokens {
+        let TokenKind::Identifier(atom) = &token.kind else {
+            continue;
+        };
+        let name = atom.as_str();
+        let skeleton = skeleton(name);
+        let twin = *by_skeleton.entry(skeleton.clone()).or_insert(name);
+        if reported.contains(&name) {
+            continue;
+        }
+
+        let confusable = name.chars().find_map(|c| Some((c, ascii_lookalike(c)?)));
+        let message = match confusable {
+            Some((c, ascii)) if name.chars().any(|c| c.is_ascii_alphabetic()) => format!(
+                "identifier '{}' mixes scripts: '{}' (U+{:04X}) looks like the ASCII letter '{}'",
+                name, c, c as u32, ascii
+            ),
+            _ if twin != name => format!("identifier '{}' looks like '{}' but is a different name", name, twin),
+            _ => continue,
+        };
+        reported.push(name);
+        diagnostics.push(Diagnostic::warning(message, Some(token.span.clone())));
+    }
+    diagnostics
+}
+
+/// Warns about names declared with `let`, `const` or `for` that break the conventions
+/// `config` enables, suggesting the conventional spelling
+fn check_naming(tokens: &[Token], config: &LintConfig) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        let is_constant = match token.kind {
+            TokenKind::Let | TokenKind::For => false,
+            TokenKind::Const => true,
+            _ => continue,
+        };
+        // The declared name, or the names of a loop pattern such as `(key, value)`
+        let pattern = tokens[i + 1..].iter().take_while(|token| {
+            matches!(token.kind, TokenKind::Identifier(_) | TokenKind::LeftParen | TokenKind::Comma | TokenKind::RightParen)
+        });
+        for token in pattern {
+            let TokenKind::Identifier(atom) = &token.kind else {
+                continue;
+            };
+            let name = atom.as_str();
+            let message = if is_constant {
+                if !config.screaming_case_constants || !name.chars().any(char::is_lowercase) {
+                    continue;
+                }
+                format!("constant '{}' should be SCREAMING_CASE: '{}'", name, split_words(name).to_uppercase())
+            } else {
+                if !config.snake_case || !name.chars().any(char::is_uppercase) {
+                    continue;
+                }
+                format!("variable '{}' should be snake_case: '{}'", name, split_words(name).to_lowercase())
+            };
+            diagnostics.push(Diagnostic::warning(message, Some(token.span.clone())));
+        }
+    }
+    diagnostics
+}
+
+/// Warns about each comma directly before a closing bracket
+fn check_trailing_commas(tokens: &[Token]) -> Vec<Diagnostic> {
+    tokens
+        .windows(2)
+        .filter(|pair| pair[0].kind == TokenKind::Comma)
+        .filter(|pair| matches!(pair[1].kind, TokenKind::RightParen | TokenKind::RightBracket | TokenKind::RightBrace))
+        .map(|pair| {
+            let message = format!("trailing comma before '{}'", pair[1].kind.name());
+            Diagnostic::warning(message, Some(pair[0].span.clone()))
+        })
+        .collect()
+}
+
+/// Puts an underscore wherever a new word starts with a capital: `maxSize` becomes
+/// `max_Size` and `HTTPServer` becomes `HTTP_Server`
+fn split_words(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 && c.is_uppercase() {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase() || previous.is_numeric() || (previous.is_uppercase() && next_is_lower) {
+                words.push('_');
+            }
+        }
+        words.push(c);
+    }
+    words
+}
+
+/// Warns about each operator applied without parentheses to an operand whose grouping is
+/// commonly misread
+fn check_precedence(statements: &[ASTStatement]) -> Vec<Diagnostic> {
+    let mut pending: Vec<&ASTExpression> = statements.iter().flat_map(statement_expressions).collect();
+    let mut diagnostics = Vec::new();
+    while let Some(expression) = pending.pop() {
+        pending.extend(children(expression));
+        let ASTExpressionKind::Binary(binary) = &expression.kind else {
+            continue;
+        };
+        let (left, right) = (expression_source(&binary.left), expression_source(&binary.right));
+        let grouping = match (&binary.left.kind, &binary.right.kind) {
+            (ASTExpressionKind::Unary(unary), _)
+                if matches!(binary.operator.kind, ASTBinaryOperatorKind::Exponentiation)
+                    && matches!(unary.operator.kind, ASTUnaryOperatorKind::Minus) =>
+            {
+                Some(format!("({}) {} {}", left, binary.operator.kind, right))
+            }
+            (ASTExpressionKind::Binary(inner), _) if is_misread(&binary.operator.kind, &inner.operator.kind) => {
+                Some(format!("({}) {} {}", left, binary.operator.kind, right))
+            }
+            (_, ASTExpressionKind::Binary(inner)) if is_misread(&binary.operator.kind, &inner.operator.kind) => {
+                Some(format!("{} {} ({})", left, binary.operator.kind, right))
+            }
+            _ => None,
+        };
+        if let Some(grouping) = grouping {
+            let message = format!(
+                "'{}' groups as '{}'; add parentheses to make the grouping explicit",
+                expression_source(expression),
+                grouping
+            );
+            diagnostics.push(Diagnostic::warning(message, Some(binary.operator.token.span.clone())));
+        }
+    }
+    diagnostics
+}
+
+/// Warns about expression statements whose effect-free value is dropped. When
+/// `value_used` is set the last statement is the value of an enclosing block, so it is
+/// kept.
+fn check_discarded_values(source: &str, statements: &[ASTStatement], value_used: bool, diagnostics: &mut Vec<Diagnostic>) {
+    for (i, statement) in statements.iter().enumerate() {
+        let is_value = value_used && i + 1 == statements.len();
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) if !is_value && is_effect_free(expression) => {
+                let span = statement.range.clone().map(|range| TextSpan::new(range.start, range.end, source[range].to_string()));
+                let message = format!(
+                    "the value of '{}' is discarded; assign it to a variable or print it",
+                    expression_source(expression)
+                );
+                diagnostics.push(Diagnostic::warning(message, span));
+            }
+            ASTStatementKind::If(if_statement) => {
+                for body in if_statement.arms.iter().map(|arm| &arm.body).chain(&if_statement.else_body) {
+                    check_discarded_values(source, body, is_value, diagnostics);
+                }
+            }
+            ASTStatementKind::For(for_statement) => check_discarded_values(source, &for_statement.body, false, diagnostics),
+            ASTStatementKind::While(while_statement) => {
+                check_discarded_values(source, &while_statement.body, false, diagnostics)
+            }
+            ASTStatementKind::With(with) => check_discarded_values(source, &with.body, is_value, diagnostics),
+            ASTStatementKind::Match(match_statement) => {
+                for arm in &match_statement.arms {
+                    check_discarded_values(source, &arm.body, is_value, diagnostics);
+                }
+            }
+            _ => {}
+        }
+        // Blocks inside expressions give their last statement's value to the expression
+        let mut pending = own_expressions(statement);
+        while let Some(expression) = pending.pop() {
+            match &expression.kind {
+                ASTExpressionKind::Block(block) => check_discarded_values(source, &block.statements, true, diagnostics),
+                _ => pending.extend(children(expression)),
+            }
+        }
+    }
+}
+
+/// Warns about `if` conditions that fold to a constant, marking each branch they make
+/// unreachable, about `while` conditions that are always false, and about comparisons
+/// that the types of their operands decide: mixing types that cannot be compared is
+/// always a runtime error, and a range never contains anything but Integers
+fn check_decided_conditions(source: &str, statements: &[ASTStatement], diagnostics: &mut Vec<Diagnostic>) {
+    let mut all = Vec::new();
+    collect_statements(statements, &mut all);
+    let types = declared_types(&all);
+    let span = |statements: &[ASTStatement]| body_span(source, statements);
+
+    for statement in &all {
+        let statement_span = statement.range.clone().map(|range| TextSpan::new(range.start, range.end, source[range].to_string()));
+        for expression in own_expressions(statement) {
+            check_typed_comparisons(expression, &types, diagnostics);
+            check_builtin_calls(expression, &types, &statement_span, diagnostics);
+        }
+        if let ASTStatementKind::Match(match_statement) = &statement.kind {
+            check_match_arms(source, match_statement, &types, &statement_span, diagnostics);
+        }
+        // `while true` is how a loop that ends with `break` is written, so only false is news
+        if let ASTStatementKind::While(while_statement) = &statement.kind {
+            if constant_value(&while_statement.condition).is_some_and(|value| !value.to_boolean()) {
+                let condition = expression_source(&while_statement.condition);
+                let message = format!("unreachable loop body: condition '{}' is always false", condition);
+                diagnostics.push(Diagnostic::warning(message, span(&while_statement.body)));
+            }
+        }
+        let ASTStatementKind::If(if_statement) = &statement.kind else {
+            continue;
+        };
+        // The condition that is always true, once one is found
+        let mut decided: Option<String> = None;
+        for arm in &if_statement.arms {
+            if let Some(condition) = &decided {
+                let message = format!("unreachable branch: condition '{}' above is always true", condition);
+                diagnostics.push(Diagnostic::warning(message, span(&arm.body)));
+                continue;
+            }
+            let Some(value) = constant_value(&arm.condition) else {
+                continue;
+            };
+            let condition = expression_source(&arm.condition);
+            let always = value.to_boolean();
+            diagnostics.push(Diagnostic::warning(format!("condition '{}' is always {}", condition, always), statement_span.clone()));
+            if always {
+                decided = Some(condition);
+            } else {
+                let message = format!("unreachable branch: condition '{}' is always false", condition);
+                diagnostics.push(Diagnostic::warning(message, span(&arm.body)));
+            }
+        }
+        if let (Some(condition), Some(else_body)) = (&decided, &if_statement.else_body) {
+            let message = format!("unreachable branch: condition '{}' above is always true", condition);
+            diagnostics.push(Diagnostic::warning(message, span(else_body)));
+        }
+    }
+}
+
+/// The span of a body, from its first statement to its last
+fn body_span(source: &str, statements: &[ASTStatement]) -> Option<TextSpan> {
+    let start = statements.first()?.range.as_ref()?.start;
+    let end = statements.last()?.range.as_ref()?.end;
+    Some(TextSpan::new(start, end, source[start..end].to_string()))
+}
+
+/// Warns about `match` arms that no value can reach, because an unguarded arm above matches
+/// everything they match, or because the arms above handle both `true` and `false` of a
+/// Boolean subject. A `match` on a Boolean that leaves `true` or `false` unhandled and has
+/// no catch-all arm is an error, since the value it misses fails at runtime. The subject
+/// counts as a Boolean when its type is known to be, or when every literal pattern is one.
+fn check_match_arms(
+    source: &str,
+    match_statement: &ASTMatchStatement,
+    types: &HashMap<Atom, Option<DataType>>,
+    statement_span: &Option<TextSpan>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let subject_is_boolean = static_type(&match_statement.subject, types) == Some(DataType::Boolean);
+    // The patterns of the unguarded arms seen so far, which match whenever their pattern does
+    let mut covering: Vec<&ASTPattern> = Vec::new();
+    for arm in &match_statement.arms {
+        let handled = |b: bool| covering.iter().any(|pattern| **pattern == ASTPattern::Literal(Value::Boolean(b)));
+        let message = if let Some(earlier) = covering.iter().find(|earlier| covers(earlier, &arm.pattern)) {
+            Some(format!(
+                "unreachable arm: '{}' is already matched by '{}' above",
+                pattern_source(&arm.pattern),
+                pattern_source(earlier)
+            ))
+        } else if subject_is_boolean && handled(true) && handled(false) {
+            Some("unreachable arm: 'true' and 'false' are both matched above".to_string())
+        } else {
+            None
+        };
+        if let Some(message) = message {
+            diagnostics.push(Diagnostic::warning(message, body_span(source, &arm.body)));
+        }
+        if arm.guard.is_none() {
+            covering.push(&arm.pattern);
+        }
+    }
+
+    let mut literals = match_statement.arms.iter().filter_map(|arm| match &arm.pattern {
+        ASTPattern::Literal(value) => Some(value),
+        _ => None,
+    });
+    let is_boolean = subject_is_boolean
+        || (literals.clone().next().is_some() && literals.all(|value| matches!(value, Value::Boolean(_))));
+    if !is_boolean || covering.iter().any(|pattern| matches!(pattern, ASTPattern::Wildcard | ASTPattern::Binding(_))) {
+        return;
+    }
+    let missing: Vec<&str> = [(true, "true"), (false, "false")]
+        .into_iter()
+        .filter(|(b, _)| !covering.contains(&&ASTPattern::Literal(Value::Boolean(*b))))
+        .map(|(_, name)| name)
+        .collect();
+    if !missing.is_empty() {
+        let message = format!(
+            "'match' on a Boolean does not handle {}; add {} or a '_' arm",
+            missing.join(" or "),
+            if missing.len() == 1 { "an arm for it" } else { "arms for them" }
+        );
+        diagnostics.push(Diagnostic::error(message, statement_span.clone()));
+    }
+}
+
+/// Whether every value `inner` matches is also matched by `outer`
+fn covers(outer: &ASTPattern, inner: &ASTPattern) -> bool {
+    match (outer, inner) {
+        (ASTPattern::Wildcard | ASTPattern::Binding(_), _) => true,
+        (ASTPattern::Literal(outer), ASTPattern::Literal(inner)) => outer.equals(inner) == Ok(true),
+        (ASTPattern::Tuple(outer), ASTPattern::Tuple(inner)) => {
+            outer.len() == inner.len() && outer.iter().zip(inner).all(|(outer, inner)| covers(outer, inner))
+        }
+        _ => false,
+    }
+}
+
+/// Every statement, including those in loop, `if`, `with` and `match` bodies and in blocks
+fn collect_statements<'a>(statements: &'a [ASTStatement], all: &mut Vec<&'a ASTStatement>) {
+    for statement in statements {
+        all.push(statement);
+        match &statement.kind {
+            ASTStatementKind::If(if_statement) => {
+                for body in if_statement.arms.iter().map(|arm| &arm.body).chain(&if_statement.else_body) {
+                    collect_statements(body, all);
+                }
+            }
+            ASTStatementKind::For(for_statement) => collect_statements(&for_statement.body, all),
+            ASTStatementKind::While(while_statement) => collect_statements(&while_statement.body, all),
+            ASTStatementKind::With(with) => collect_statements(&with.body, all),
+            ASTStatementKind::Match(match_statement) => {
+                for arm in &match_statement.arms {
+                    collect_statements(&arm.body, all);
+                }
+            }
+            _ => {}
+        }
+        let mut pending = own_expressions(statement);
+        while let Some(expression) = pending.pop() {
+            match &expression.kind {
+                ASTExpressionKind::Block(block) => collect_statements(&block.statements, all),
+                _ => pending.extend(children(expression)),
+            }
+        }
+    }
+}
+
+/// The type of each variable whose declarations all have an initializer of one known
+/// type. Assignments cannot change a variable's type, so it holds wherever the name is used.
+fn declared_types(statements: &[&ASTStatement]) -> HashMap<Atom, Option<DataType>> {
+    let mut types: HashMap<Atom, Option<DataType>> = HashMap::new();
+    for statement in statements {
+        let ASTStatementKind::VariableDeclaration(decl) = &statement.kind else {
+            continue;
+        };
+        let declared = static_type(&decl.initializer, &types);
+        let entry = types.entry(decl.name).or_insert(declared);
+        if *entry != declared {
+            *entry = None;
+        }
+    }
+    types
+}
+
+/// The type an expression always has, when that is known without running it
+fn static_type(expression: &ASTExpression, types: &HashMap<Atom, Option<DataType>>) -> Option<DataType> {
+    if let Some(value) = constant_value(expression) {
+        return Some(value.get_type());
+    }
+    match &expression.kind {
+        ASTExpressionKind::Identifier(ident) => types.get(&ident.name).copied().flatten(),
+        ASTExpressionKind::Paranthesized(paren_expr) => static_type(&paren_expr.expression, types),
+        ASTExpressionKind::Binary(binary) => match binary.operator.kind.family() {
+            OperatorFamily::Comparison | OperatorFamily::Logical => Some(DataType::Boolean),
+            OperatorFamily::Range => Some(DataType::Range),
+            _ => None,
+        },
+        ASTExpressionKind::Array(_) => Some(DataType::Array),
+        ASTExpressionKind::Map(_) => Some(DataType::Map),
+        ASTExpressionKind::Set(_) => Some(DataType::Set),
+        _ => None,
+    }
+}
+
+/// Warns about the comparisons within `expression` that its operand types decide
+fn check_typed_comparisons(
+    expression: &ASTExpression,
+    types: &HashMap<Atom, Option<DataType>>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut pending = vec![expression];
+    while let Some(expression) = pending.pop() {
+        match &expression.kind {
+            // Checked as statements of their own
+            ASTExpressionKind::Block(_) => continue,
+            ASTExpressionKind::Binary(binary) if binary.operator.kind.family() == OperatorFamily::Comparison => {
+                let operand_types = (static_type(&binary.left, types), static_type(&binary.right, types));
+                let message = match (&binary.operator.kind, operand_types) {
+                    (ASTBinaryOperatorKind::In, (Some(item), Some(DataType::Range))) if item != DataType::Integer => {
+                        Some(format!("'{}' is always false: a range only holds Integers", expression_source(expression)))
+                    }
+                    (ASTBinaryOperatorKind::In, (Some(key), Some(DataType::Map))) if key != DataType::String => {
+                        Some(format!("'{}' is always false: map keys are Strings", expression_source(expression)))
+                    }
+                    (ASTBinaryOperatorKind::In, _) => None,
+                    (_, (Some(left), Some(right))) if !comparable(&left, &right) => Some(format!(
+                        "'{}' compares {} with {}, which is always a runtime error",
+                        expression_source(expression),
+                        left,
+                        right
+                    )),
+                    _ => None,
+                };
+                if let Some(message) = message {
+                    diagnostics.push(Diagnostic::warning(message, Some(binary.operator.token.span.clone())));
+                }
+            }
+            _ => {}
+        }
+        pending.extend(children(expression));
+    }
+}
+
+/// Warns about the builtin calls within `expression` that would fail their signature check.
+/// Calls with spread arguments are skipped, since their argument count is not known.
+/// Expressions carry no spans, so the warnings point at the enclosing statement.
+fn check_builtin_calls(
+    expression: &ASTExpression,
+    types: &HashMap<Atom, Option<DataType>>,
+    statement_span: &Option<TextSpan>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut pending = vec![expression];
+    while let Some(expression) = pending.pop() {
+        let call = match &expression.kind {
+            ASTExpressionKind::Block(_) => continue,
+            ASTExpressionKind::FunctionCall(func_call) => {
+                func_call.name().map(|name| (name, func_call.arguments.iter().collect::<Vec<_>>()))
+            }
+            // The receiver is the first argument
+            ASTExpressionKind::MethodCall(method_call) => Some((
+                method_call.name,
+                std::iter::once(&*method_call.receiver).chain(&method_call.arguments).collect(),
+            )),
+            _ => None,
+        };
+        pending.extend(children(expression));
+        let Some((name, arguments)) = call else {
+            continue;
+        };
+        let Some(signature) = signature_of(name.as_str()) else {
+            continue;
+        };
+        if arguments.iter().any(|argument| matches!(argument.kind, ASTExpressionKind::Spread(_))) {
+            continue;
+        }
+        let known: Option<Vec<DataType>> = arguments.iter().map(|argument| static_type(argument, types)).collect();
+        let result = match known {
+            Some(argument_types) => signature.check(&argument_types),
+            None => signature.check_count(arguments.len()),
+        };
+        if let Err(message) = result {
+            diagnostics.push(Diagnostic::warning(message, statement_span.clone()));
+        }
+    }
+}
+
+/// Whether values of the two types can be compared at all; integers and floats compare
+/// with each other
+fn comparable(left: &DataType, right: &DataType) -> bool {
+    let numeric = |data_type: &DataType| matches!(data_type, DataType::Integer | DataType::Float);
+    left == right || (numeric(left) && numeric(right))
+}
+
+/// The expressions of a statement, leaving out those of the statements in its bodies
+fn own_expressions(statement: &ASTStatement) -> Vec<&ASTExpression> {
+    match &statement.kind {
+        ASTStatementKind::For(for_statement) => vec![&for_statement.iterable],
+        ASTStatementKind::While(while_statement) => vec![&while_statement.condition],
+        ASTStatementKind::With(with) => vec![&with.resource],
+        ASTStatementKind::If(if_statement) => if_statement.arms.iter().map(|arm| &arm.condition).collect(),
+        ASTStatementKind::Match(match_statement) => std::iter::once(&*match_statement.subject)
+            .chain(match_statement.arms.iter().filter_map(|arm| arm.guard.as_ref()))
+            .collect(),
+        _ => statement_expressions(statement),
+    }
+}
+
+/// True if evaluating the expression can do nothing but compute its value or fail.
+/// Blocks are assumed to have effects, since their statements are checked on their own.
+fn is_effect_free(expression: &ASTExpression) -> bool {
+    let callee_is_effect_free = match &expression.kind {
+        ASTExpressionKind::Block(_) => false,
+        ASTExpressionKind::FunctionCall(func_call) => {
+            func_call.name().is_some_and(|name| is_effect_free_builtin(name.as_str()))
+        }
+        ASTExpressionKind::MethodCall(method_call) => is_effect_free_builtin(method_call.name.as_str()),
+        _ => true,
+    };
+    callee_is_effect_free && children(expression).into_iter().all(is_effect_free)
+}
+
+/// Whether `outer` applied directly to an operand built with `inner` is easy to misread:
+/// bitwise operators bind looser than comparisons in C but tighter in Arc, shifts and
+/// bitwise operators are often expected to bind tighter than arithmetic, and C compilers
+/// warn about mixing `&` with `|` or `&&` with `||`
+fn is_misread(outer: &ASTBinaryOperatorKind, inner: &ASTBinaryOperatorKind) -> bool {
+    match (outer.family(), inner.family()) {
+        (OperatorFamily::Comparison, OperatorFamily::Bitwise)
+        | (OperatorFamily::Shift, OperatorFamily::Arithmetic)
+        | (OperatorFamily::Bitwise, OperatorFamily::Arithmetic) => true,
+        (OperatorFamily::Bitwise, OperatorFamily::Bitwise) | (OperatorFamily::Logical, OperatorFamily::Logical) => {
+            mem::discriminant(outer) != mem::discriminant(inner)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confusable_identifiers() {
+        let warnings = lint("let cost = 1\nlet p\u{430}y = 2\nlet \u{441}ost = 3\nlet \u{3c0} = 3.14\nprint(p\u{430}y + p\u{430}y)\nlet cop = 4\nlet \u{441}\u{43e}\u{440} = 5");
+        let messages: Vec<&str> = warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "identifier 'p\u{430}y' mixes scripts: '\u{430}' (U+0430) looks like the ASCII letter 'a'",
+                "identifier '\u{441}ost' mixes scripts: '\u{441}' (U+0441) looks like the ASCII letter 'c'",
+                "identifier '\u{441}\u{43e}\u{440}' looks like 'cop' but is a different name",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_misread_operator_mixes() {
+        let warnings = lint("let a = 1\nlet b = 2\nlet c = 3\nprint(a & b == c)\nprint(a << b + c)\nlet d = -a ** b\nprint(a || b && c)\nprint((a & b) == c)\nprint(a + b * c)");
+        let messages: Vec<&str> = warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "'a & b == c' groups as '(a & b) == c'; add parentheses to make the grouping explicit",
+                "'a << b + c' groups as 'a << (b + c)'; add parentheses to make the grouping explicit",
+                "'-a ** b' groups as '(-a) ** b'; add parentheses to make the grouping explicit",
+                "'a || b && c' groups as 'a || (b && c)'; add parentheses to make the grouping explicit",
+            ]
+        );
+        assert_eq!(warnings[0].span.as_ref().map(|span| span.start), Some(42));
+    }
+
+    #[test]
+    fn test_naming_conventions() {
+        let source = "let maxSize = 1\nconst limit = 2\nconst HTTPServer = 3\nfor (rowIndex, row) in [[1, 2]] { print(row) }\nconst PI_2 = 6.28\nlet _tmp = 4";
+        assert!(lint(source).is_empty());
+
+        let config = LintConfig { snake_case: true, screaming_case_constants: true, ..LintConfig::default() };
+        let messages: Vec<String> = lint_with(source, &config).into_iter().map(|warning| warning.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "variable 'maxSize' should be snake_case: 'max_size'",
+                "constant 'limit' should be SCREAMING_CASE: 'LIMIT'",
+                "constant 'HTTPServer' should be SCREAMING_CASE: 'HTTP_SERVER'",
+                "variable 'rowIndex' should be snake_case: 'row_index'",
+            ]
+        );
+
+        let manifest = Manifest::parse("[package]\nname = \"demo\"\n\n[lints]\nsnake_case = true\n").unwrap();
+        assert_eq!(LintConfig::from_manifest(&manifest), Ok(LintConfig { snake_case: true, ..LintConfig::default() }));
+        let manifest = Manifest::parse("[package]\nname = \"demo\"\n\n[lints]\nsnakecase = true\n").unwrap();
+        assert_eq!(LintConfig::from_manifest(&manifest), Err("arc.toml: unknown lint 'snakecase'".to_string()));
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let source = "print(1, 2,)\nlet m = {\n    \"a\": [1,],\n    \"b\": {3, 4},\n}";
+        assert!(lint(source).is_empty());
+        let config = LintConfig { no_trailing_commas: true, ..LintConfig::default() };
+        let warnings: Vec<(String, Option<usize>)> = lint_with(source, &config)
+            .into_iter()
+            .map(|warning| (warning.message, warning.span.map(|span| span.start)))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                ("trailing comma before ')'".to_string(), Some(10)),
+                ("trailing comma before ']'".to_string(), Some(34)),
+                ("trailing comma before '}'".to_string(), Some(53)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_discarded_values() {
+        let source = "let x = 1\nx + 1\nprint(x)\nsqrt(16)\nx == 2\nfor i in 0..2 { i * 2 }\nlet y = { x; x + 1 }\nif x > 0 { x }\nsend(channel(), x)";
+        let messages: Vec<String> = lint(source).into_iter().map(|warning| warning.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "the value of 'x + 1' is discarded; assign it to a variable or print it",
+                "the value of 'sqrt(16)' is discarded; assign it to a variable or print it",
+                "the value of 'x == 2' is discarded; assign it to a variable or print it",
+                "the value of 'i * 2' is discarded; assign it to a variable or print it",
+                "the value of 'x' is discarded; assign it to a variable or print it",
+                "the value of 'x' is discarded; assign it to a variable or print it",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decided_conditions() {
+        let source = "let flag = true\nlet n = 3\nif true {\n    print(1)\n} else if n > 2 {\n    print(2)\n} else {\n    print(3)\n}\nif 1 > 2 {\n    print(4)\n}\nwhile 1 > 2 {\n    print(5)\n}\nwhile true {\n    break\n}\nprint(flag == 1, \"a\" in 0..n, n < 2.5)";
+        let messages: Vec<String> = lint(source).into_iter().map(|warning| warning.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "condition 'true' is always true",
+                "unreachable branch: condition 'true' above is always true",
+                "unreachable branch: condition 'true' above is always true",
+                "condition '1 > 2' is always false",
+                "unreachable branch: condition '1 > 2' is always false",
+                "unreachable loop body: condition '1 > 2' is always false",
+                "'flag == 1' compares Boolean with Integer, which is always a runtime error",
+                "'\"a\" in 0 .. n' is always false: a range only holds Integers",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_builtin_calls() {
+        let source = "let n = 4\nlet name = \"x\"\nprint(sqrt(n, 2))\nprint(sqrt(name))\nprint(pow(missing))\nprint(\"ff\".from_base(16), max(...[1, 2]))";
+        let messages: Vec<String> = lint(source).into_iter().map(|warning| warning.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "sqrt expects 1 numeric argument, got 2 arguments (Integer, Integer)",
+                "sqrt expects 1 numeric argument, got 1 argument (String)",
+                "pow expects 2 numeric arguments, got 1 argument",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_arms() {
+        let source = "let done = n > 2\nmatch item {\n    x if x > 0 => print(x)\n    (a, 1) => print(a)\n    (_, 1.0) => print(1)\n    other => print(other)\n    _ => print(0)\n}\nmatch done {\n    true => print(1)\n    false => print(2)\n    _ => print(3)\n}\nmatch done {\n    false if n > 9 => print(4)\n    true => print(5)\n}";
+        let diagnostics = lint(source);
+        let messages: Vec<(bool, &str)> =
+            diagnostics.iter().map(|diagnostic| (diagnostic.is_error(), diagnostic.message.as_str())).collect();
+        assert_eq!(
+            messages,
+            [
+                (false, "unreachable arm: '(_, 1.0)' is already matched by '(a, 1)' above"),
+                (false, "unreachable arm: '_' is already matched by 'other' above"),
+                (false, "unreachable arm: 'true' and 'false' are both matched above"),
+                (true, "'match' on a Boolean does not handle false; add an arm for it or a '_' arm"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Literal pool - one shared allocation per distinct string or bytes literal
+//!
+//! Generated code often repeats the same literal thousands of times. The parser passes
+//! every string and bytes literal through a `LiteralPool`, so repeats share one `Arc`
+//! instead of each holding its own copy. Numbers and booleans live inline in `Value` and
+//! need no pooling. Expressions hold the shared value itself rather than an index into the
+//! pool, so statements stay valid when they are moved to another `Ast`.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The distinct literals seen by one parser
+#[derive(Debug, Default)]
+pub struct LiteralPool {
+    strings: HashSet<Arc<str>>,
+    bytes: HashSet<Arc<[u8]>>,
+}
+
+impl LiteralPool {
+    pub fn new() -> Self {
+        LiteralPool::default()
+    }
+
+    /// The pooled copy of a string literal
+    pub fn string(&mut self, text: String) -> Arc<str> {
+        if let Some(pooled) = self.strings.get(text.as_str()) {
+            return pooled.clone();
+        }
+        let pooled: Arc<str> = text.into();
+        self.strings.insert(pooled.clone());
+        pooled
+    }
+
+    /// The pooled copy of a bytes literal
+    pub fn bytes(&mut self, bytes: Vec<u8>) -> Arc<[u8]> {
+        if let Some(pooled) = self.bytes.get(bytes.as_slice()) {
+            return pooled.clone();
+        }
+        let pooled: Arc<[u8]> = bytes.into();
+        self.bytes.insert(pooled.clone());
+        pooled
+    }
+
+    /// Number of distinct literals held
+    pub fn len(&self) -> usize {
+        self.strings.len() + self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,220 @@
+//! Math builtins - pure numeric functions
+//!
+//! None of these touch the host, so they need no capability and the optimizer may fold or
+//! memoize them (see `ast::optimizer`).
+
+use crate::ast::types::Value;
+
+/// Names of the math builtins
+pub const MATH_BUILTINS: &[&str] = &[
+    "abs", "sqrt", "pow", "floor", "ceil", "round", "min", "max", "sin", "cos", "approx_eq",
+    "popcount", "leading_zeros", "trailing_zeros", "rotate_left", "rotate_right", "bit",
+    "to_base", "from_base",
+];
+
+pub fn is_math_builtin(name: &str) -> bool {
+    MATH_BUILTINS.contains(&name)
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    f64::try_from(value.clone())
+}
+
+/// Bit builtins work on the 64-bit two's complement form of integers only
+fn integer(value: &Value) -> Result<i64, String> {
+    i64::try_from(value.clone())
+}
+
+/// A base for `to_base` and `from_base`, which use the digits 0-9 then a-z
+fn base(value: &Value) -> Result<u32, String> {
+    let base = integer(value)?;
+    u32::try_from(base)
+        .ok()
+        .filter(|base| (2..=36).contains(base))
+        .ok_or_else(|| format!("base must be between 2 and 36, found {}", base))
+}
+
+/// `n` written in `base`, in lowercase, with a leading '-' if it is negative
+fn to_base(n: i64, base: u32) -> String {
+    let mut magnitude = n.unsigned_abs();
+    let mut digits = Vec::new();
+    loop {
+        digits.push(char::from_digit((magnitude % u64::from(base)) as u32, base).unwrap_or('?'));
+        magnitude /= u64::from(base);
+        if magnitude == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        digits.push('-');
+    }
+    digits.into_iter().rev().collect()
+}
+
+fn expect_args(args: &[Value], count: usize) -> Result<(), String> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        Err(format!("expected {} argument(s), found {}", count, args.len()))
+    }
+}
+
+/// Calls a math builtin. Integer arguments stay integers where the result allows it
+/// (`abs`, `min`, `max`), the bit builtins and `from_base` return integers, `to_base` a
+/// string, `approx_eq` a boolean and everything else a float.
+pub fn call(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    let value = match name {
+        "abs" => {
+            expect_args(args, 1)?;
+            match &args[0] {
+                Value::Integer(i) => Value::Integer(i.checked_abs().ok_or("integer overflow")?),
+                other => Value::Float(number(other)?.abs()),
+            }
+        }
+        "min" | "max" => {
+            if args.is_empty() {
+                return Err("expected at least 1 argument".to_string());
+            }
+            let pick_min = name == "min";
+            if args.iter().all(|arg| matches!(arg, Value::Integer(_))) {
+                let ints = args.iter().filter_map(|arg| match arg {
+                    Value::Integer(i) => Some(*i),
+                    _ => None,
+                });
+                Value::Integer(if pick_min { ints.min() } else { ints.max() }.unwrap_or_default())
+            } else {
+                let mut result = number(&args[0])?;
+                for arg in &args[1..] {
+                    let n = number(arg)?;
+                    result = if pick_min { result.min(n) } else { result.max(n) };
+                }
+                Value::Float(result)
+            }
+        }
+        "approx_eq" => {
+            // Tolerance is absolute near zero and relative to the larger magnitude above 1
+            expect_args(args, 3)?;
+            let (a, b, tolerance) = (number(&args[0])?, number(&args[1])?, number(&args[2])?);
+            if tolerance.is_nan() || tolerance < 0.0 {
+                return Err("tolerance must be a non-negative number".to_string());
+            }
+            Value::Boolean((a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0))
+        }
+        "popcount" | "leading_zeros" | "trailing_zeros" => {
+            expect_args(args, 1)?;
+            let x = integer(&args[0])?;
+            Value::Integer(match name {
+                "popcount" => x.count_ones(),
+                "leading_zeros" => x.leading_zeros(),
+                _ => x.trailing_zeros(),
+            }
+            .into())
+        }
+        "rotate_left" | "rotate_right" => {
+            // Rotating by n is rotating by n mod 64, so negative amounts rotate the other way
+            expect_args(args, 2)?;
+            let (x, n) = (integer(&args[0])?, integer(&args[1])?.rem_euclid(64) as u32);
+            Value::Integer(if name == "rotate_left" { x.rotate_left(n) } else { x.rotate_right(n) })
+        }
+        "bit" => {
+            expect_args(args, 2)?;
+            let (x, i) = (integer(&args[0])?, integer(&args[1])?);
+            match u32::try_from(i).ok().and_then(|i| x.checked_shr(i)) {
+                Some(shifted) => Value::Integer(shifted & 1),
+                None => return Err(format!("bit index {} is out of range (0..64)", i)),
+            }
+        }
+        "to_base" => {
+            expect_args(args, 2)?;
+            Value::from(to_base(integer(&args[0])?, base(&args[1])?))
+        }
+        "from_base" => {
+            // Digits in either case, after an optional sign; no prefix such as "0x"
+            expect_args(args, 2)?;
+            let base = base(&args[1])?;
+            let Value::String(text) = &args[0] else {
+                return Err(format!("expected a String, found {}", args[0].get_type()));
+            };
+            match i64::from_str_radix(text, base) {
+                Ok(n) => Value::Integer(n),
+                Err(e) if matches!(e.kind(), std::num::IntErrorKind::PosOverflow | std::num::IntErrorKind::NegOverflow) => {
+                    return Err(format!("\"{}\" does not fit in an Integer", text));
+                }
+                Err(_) => return Err(format!("\"{}\" is not a base-{} integer", text, base)),
+            }
+        }
+        "pow" => {
+            expect_args(args, 2)?;
+            Value::Float(number(&args[0])?.powf(number(&args[1])?))
+        }
+        _ => {
+            expect_args(args, 1)?;
+            let n = number(&args[0])?;
+            Value::Float(match name {
+                "sqrt" => n.sqrt(),
+                "floor" => n.floor(),
+                "ceil" => n.ceil(),
+                "round" => n.round(),
+                "sin" => n.sin(),
+                "cos" => n.cos(),
+                _ => return Err(format!("unknown math function '{}'", name)),
+            })
+        }
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_math_builtins() {
+        assert_eq!(call("abs", &[Value::Integer(-3)]), Ok(Some(Value::Integer(3))));
+        assert_eq!(call("sqrt", &[Value::Integer(16)]), Ok(Some(Value::Float(4.0))));
+        assert_eq!(call("max", &[Value::Integer(2), Value::Integer(7)]), Ok(Some(Value::Integer(7))));
+        assert_eq!(call("min", &[Value::Integer(2), Value::Float(1.5)]), Ok(Some(Value::Float(1.5))));
+        assert_eq!(call("approx_eq", &[Value::Float(0.1 + 0.2), Value::Float(0.3), Value::Float(1e-9)]), Ok(Some(Value::Boolean(true))));
+        assert_eq!(call("approx_eq", &[Value::Float(1e20), Value::Float(1.0001e20), Value::Float(1e-9)]), Ok(Some(Value::Boolean(false))));
+        assert!(call("abs", &[Value::Integer(i64::MIN)]).is_err());
+        assert!(call("pow", &[Value::Integer(2)]).is_err());
+        assert!(call("floor", &[Value::from("x")]).is_err());
+    }
+
+    #[test]
+    fn test_bit_builtins() {
+        let int = |i: i64| Value::Integer(i);
+        assert_eq!(call("popcount", &[int(0b1011)]), Ok(Some(int(3))));
+        assert_eq!(call("popcount", &[int(-1)]), Ok(Some(int(64))));
+        assert_eq!(call("leading_zeros", &[int(1)]), Ok(Some(int(63))));
+        assert_eq!(call("trailing_zeros", &[int(8)]), Ok(Some(int(3))));
+        assert_eq!(call("rotate_left", &[int(i64::MIN), int(1)]), Ok(Some(int(1))));
+        assert_eq!(call("rotate_right", &[int(1), int(-1)]), Ok(Some(int(2))));
+        assert_eq!(call("bit", &[int(0b100), int(2)]), Ok(Some(int(1))));
+        assert_eq!(call("bit", &[int(1), int(64)]), Err("bit index 64 is out of range (0..64)".to_string()));
+        assert_eq!(call("popcount", &[Value::Float(1.0)]), Err("Expected Integer value, found Float".to_string()));
+    }
+
+    #[test]
+    fn test_base_conversion() {
+        let int = |i: i64| Value::Integer(i);
+        assert_eq!(call("to_base", &[int(255), int(16)]), Ok(Some(Value::from("ff"))));
+        assert_eq!(call("to_base", &[int(-5), int(2)]), Ok(Some(Value::from("-101"))));
+        assert_eq!(call("to_base", &[int(0), int(36)]), Ok(Some(Value::from("0"))));
+        assert_eq!(call("to_base", &[int(i64::MIN), int(16)]), Ok(Some(Value::from("-8000000000000000"))));
+        assert_eq!(call("from_base", &[Value::from("FF"), int(16)]), Ok(Some(int(255))));
+        assert_eq!(call("from_base", &[Value::from("-zz"), int(36)]), Ok(Some(int(-1295))));
+        assert_eq!(call("from_base", &[Value::from("102"), int(2)]), Err("\"102\" is not a base-2 integer".to_string()));
+        assert_eq!(call("from_base", &[Value::from("1".repeat(64)), int(2)]), Err(format!("\"{}\" does not fit in an Integer", "1".repeat(64))));
+        assert_eq!(call("to_base", &[int(1), int(37)]), Err("base must be between 2 and 36, found 37".to_string()));
+
+        for n in [0, 1, -1, 12345, i64::MAX, i64::MIN] {
+            for b in [2, 8, 10, 36] {
+                let Ok(Some(text)) = call("to_base", &[int(n), int(b)]) else {
+                    panic!("to_base failed");
+                };
+                assert_eq!(call("from_base", &[text, int(b)]), Ok(Some(int(n))));
+            }
+        }
+    }
+}
@@ -5,15 +5,47 @@ pub mod parser;
 pub mod evaluator;
 pub mod types;
 pub mod symbol_table;
+pub mod builtins;
+pub mod desugar;
+pub mod channels;
+pub mod collections;
+pub mod diagnostics;
+pub mod encoding;
+pub mod functions;
+pub mod interner;
+pub mod iteration;
+pub mod json;
+pub mod lint;
+pub mod literals;
+pub mod math;
+pub mod net;
+pub mod number_format;
+pub mod optimizer;
+pub mod packed;
+pub mod pretty;
+pub mod resolver;
+pub mod strings;
+pub mod suggest;
+pub mod transform;
+pub mod unparse;
 
+use crate::ast::interner::Atom;
 use crate::ast::lexer::Token;
+use crate::ast::symbol_table::Slot;
 use crate::ast::types::Value;
+use std::ops::Range;
 
 /// Root AST structure containing statements
 pub struct Ast {
     pub statements: Vec<ASTStatement>,
 }
 
+impl Default for Ast {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ast {
     pub fn new() -> Self {
         Ast {
@@ -31,7 +63,7 @@ impl Ast {
         }
     }
 
-    pub fn visualize(&self) -> () {
+    pub fn visualize(&self) {
         let mut printor = ASTPrintor { indent: 0 };
         self.visit(&mut printor);
     }
@@ -43,6 +75,15 @@ pub trait ASTVisitor {
             ASTStatementKind::Expression(expr) => self.visit_expression(expr),
             ASTStatementKind::VariableDeclaration(decl) => self.visit_variable_declaration(decl),
             ASTStatementKind::Assignment(assign) => self.visit_assignment(assign),
+            ASTStatementKind::Import(import) => self.visit_import(import),
+            ASTStatementKind::For(for_statement) => self.visit_for_statement(for_statement),
+            ASTStatementKind::While(while_statement) => self.visit_while_statement(while_statement),
+            ASTStatementKind::LoopControl(control) => self.visit_loop_control(control),
+            ASTStatementKind::If(if_statement) => self.visit_if_statement(if_statement),
+            ASTStatementKind::Defer(expr) => self.visit_defer(expr),
+            ASTStatementKind::With(with) => self.visit_with(with),
+            ASTStatementKind::Match(match_statement) => self.visit_match_statement(match_statement),
+            ASTStatementKind::Guard(guard) => self.visit_guard(guard),
         }
     }
     fn visit_statement(&mut self, statement: &ASTStatement){
@@ -67,6 +108,33 @@ pub trait ASTVisitor {
             ASTExpressionKind::FunctionCall(func_call) => {
                 self.visit_function_call(func_call);
             }
+            ASTExpressionKind::MemberAccess(member) => {
+                self.visit_member_access(member);
+            }
+            ASTExpressionKind::MethodCall(method_call) => {
+                self.visit_method_call(method_call);
+            }
+            ASTExpressionKind::Array(array) => {
+                self.visit_array(array);
+            }
+            ASTExpressionKind::Map(map) => {
+                self.visit_map(map);
+            }
+            ASTExpressionKind::Set(set) => {
+                self.visit_set(set);
+            }
+            ASTExpressionKind::Index(index) => {
+                self.visit_index(index);
+            }
+            ASTExpressionKind::Spread(spread) => {
+                self.visit_spread(spread);
+            }
+            ASTExpressionKind::Block(block) => {
+                self.visit_block(block);
+            }
+            ASTExpressionKind::Pipe(pipe) => {
+                self.visit_pipe(pipe);
+            }
         }
     }
     fn visit_expression(&mut self, expression: &ASTExpression){
@@ -92,18 +160,210 @@ pub trait ASTVisitor {
     }
 
     fn visit_function_call(&mut self, func_call: &ASTFunctionCallExpression) {
+        self.visit_expression(&func_call.callee);
         for arg in &func_call.arguments {
             self.visit_expression(arg);
         }
     }
 
+    fn visit_member_access(&mut self, member: &ASTMemberAccessExpression) {
+        self.visit_expression(&member.object);
+    }
+
+    fn visit_method_call(&mut self, method_call: &ASTMethodCallExpression) {
+        self.visit_expression(&method_call.receiver);
+        for arg in &method_call.arguments {
+            self.visit_expression(arg);
+        }
+    }
+
+    fn visit_array(&mut self, array: &ASTArrayExpression) {
+        for element in &array.elements {
+            self.visit_expression(element);
+        }
+    }
+
+    fn visit_set(&mut self, set: &ASTSetExpression) {
+        for element in &set.elements {
+            self.visit_expression(element);
+        }
+    }
+
+    fn visit_map(&mut self, map: &ASTMapExpression) {
+        for (key, value) in &map.entries {
+            self.visit_expression(key);
+            self.visit_expression(value);
+        }
+    }
+
+    fn visit_index(&mut self, index: &ASTIndexExpression) {
+        self.visit_expression(&index.object);
+        self.visit_expression(&index.index);
+    }
+
+    fn visit_spread(&mut self, spread: &ASTSpreadExpression) {
+        self.visit_expression(&spread.expression);
+    }
+
+    fn visit_block(&mut self, block: &ASTBlockExpression) {
+        for statement in &block.statements {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_pipe(&mut self, pipe: &ASTPipeExpression) {
+        self.visit_expression(&pipe.value);
+        self.visit_expression(&pipe.target);
+    }
+
     fn visit_variable_declaration(&mut self, decl: &ASTVariableDeclaration) {
         self.visit_expression(&decl.initializer);
     }
 
     fn visit_assignment(&mut self, assign: &ASTAssignment) {
+        for index in &assign.indices {
+            self.visit_expression(index);
+        }
         self.visit_expression(&assign.value);
     }
+
+    fn visit_import(&mut self, import: &ASTImportStatement) {
+        let _ = import; // Default implementation
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.visit_expression(&for_statement.iterable);
+        for statement in &for_statement.body {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.visit_expression(&while_statement.condition);
+        for statement in &while_statement.body {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_loop_control(&mut self, control: &ASTLoopControl) {
+        let _ = control; // Default implementation
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        for arm in &if_statement.arms {
+            self.visit_expression(&arm.condition);
+            for statement in &arm.body {
+                self.visit_statement(statement);
+            }
+        }
+        for statement in if_statement.else_body.iter().flatten() {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_defer(&mut self, expr: &ASTExpression) {
+        self.visit_expression(expr);
+    }
+
+    fn visit_with(&mut self, with: &ASTWithStatement) {
+        self.visit_expression(&with.resource);
+        for statement in &with.body {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_guard(&mut self, guard: &ASTGuardStatement) {
+        self.visit_expression(&guard.condition);
+        for statement in &guard.body {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_match_statement(&mut self, match_statement: &ASTMatchStatement) {
+        self.visit_expression(&match_statement.subject);
+        for arm in &match_statement.arms {
+            if let Some(guard) = &arm.guard {
+                self.visit_expression(guard);
+            }
+            for statement in &arm.body {
+                self.visit_statement(statement);
+            }
+        }
+    }
+}
+
+/// Expression visitor that returns each result instead of storing it on `self`,
+/// so evaluating a tree never clones values back out of shared state
+pub trait ExpressionEvaluator {
+    type Output;
+
+    fn evaluate_expression(&mut self, expression: &ASTExpression) -> Self::Output {
+        match &expression.kind {
+            ASTExpressionKind::Number(number) => self.evaluate_number(number),
+            ASTExpressionKind::Binary(expr) => self.evaluate_binary_expression(expr),
+            ASTExpressionKind::Paranthesized(paren_expr) => self.evaluate_expression(&paren_expr.expression),
+            ASTExpressionKind::Unary(unary_expr) => self.evaluate_unary_expression(unary_expr),
+            ASTExpressionKind::Identifier(ident) => self.evaluate_identifier(ident),
+            ASTExpressionKind::FunctionCall(func_call) => self.evaluate_function_call(func_call),
+            ASTExpressionKind::MemberAccess(member) => self.evaluate_member_access(member),
+            ASTExpressionKind::MethodCall(method_call) => self.evaluate_method_call(method_call),
+            ASTExpressionKind::Array(array) => self.evaluate_array(array),
+            ASTExpressionKind::Map(map) => self.evaluate_map(map),
+            ASTExpressionKind::Set(set) => self.evaluate_set(set),
+            ASTExpressionKind::Index(index) => self.evaluate_index(index),
+            ASTExpressionKind::Spread(spread) => self.evaluate_spread(spread),
+            ASTExpressionKind::Block(block) => self.evaluate_block(block),
+            ASTExpressionKind::Pipe(pipe) => self.evaluate_pipe(pipe),
+        }
+    }
+
+    fn evaluate_number(&mut self, number: &ASTNumberExpression) -> Self::Output;
+    fn evaluate_binary_expression(&mut self, expr: &ASTBinaryExpression) -> Self::Output;
+    fn evaluate_unary_expression(&mut self, unary_expr: &ASTUnaryExpression) -> Self::Output;
+    fn evaluate_identifier(&mut self, ident: &ASTIdentifierExpression) -> Self::Output;
+    fn evaluate_function_call(&mut self, func_call: &ASTFunctionCallExpression) -> Self::Output;
+    fn evaluate_member_access(&mut self, member: &ASTMemberAccessExpression) -> Self::Output;
+    fn evaluate_method_call(&mut self, method_call: &ASTMethodCallExpression) -> Self::Output;
+    fn evaluate_array(&mut self, array: &ASTArrayExpression) -> Self::Output;
+    fn evaluate_map(&mut self, map: &ASTMapExpression) -> Self::Output;
+    fn evaluate_set(&mut self, set: &ASTSetExpression) -> Self::Output;
+    fn evaluate_index(&mut self, index: &ASTIndexExpression) -> Self::Output;
+    fn evaluate_spread(&mut self, spread: &ASTSpreadExpression) -> Self::Output;
+    fn evaluate_block(&mut self, block: &ASTBlockExpression) -> Self::Output;
+    fn evaluate_pipe(&mut self, pipe: &ASTPipeExpression) -> Self::Output;
+}
+
+/// Statement counterpart of `ExpressionEvaluator`: each statement's result is returned
+/// rather than left in a field for the caller to pick up afterwards
+pub trait StatementEvaluator: ExpressionEvaluator {
+    fn evaluate_statement(&mut self, statement: &ASTStatement) -> Self::Output {
+        match &statement.kind {
+            ASTStatementKind::Expression(expr) => self.evaluate_expression(expr),
+            ASTStatementKind::VariableDeclaration(decl) => self.evaluate_variable_declaration(decl),
+            ASTStatementKind::Assignment(assign) => self.evaluate_assignment(assign),
+            ASTStatementKind::Import(import) => self.evaluate_import(import),
+            ASTStatementKind::For(for_statement) => self.evaluate_for_statement(for_statement),
+            ASTStatementKind::While(while_statement) => self.evaluate_while_statement(while_statement),
+            ASTStatementKind::LoopControl(control) => self.evaluate_loop_control(control),
+            ASTStatementKind::If(if_statement) => self.evaluate_if_statement(if_statement),
+            ASTStatementKind::Defer(expr) => self.evaluate_defer(expr),
+            ASTStatementKind::With(with) => self.evaluate_with(with),
+            ASTStatementKind::Match(match_statement) => self.evaluate_match_statement(match_statement),
+            ASTStatementKind::Guard(guard) => self.evaluate_guard(guard),
+        }
+    }
+
+    fn evaluate_variable_declaration(&mut self, decl: &ASTVariableDeclaration) -> Self::Output;
+    fn evaluate_assignment(&mut self, assign: &ASTAssignment) -> Self::Output;
+    fn evaluate_import(&mut self, import: &ASTImportStatement) -> Self::Output;
+    fn evaluate_for_statement(&mut self, for_statement: &ASTForStatement) -> Self::Output;
+    fn evaluate_while_statement(&mut self, while_statement: &ASTWhileStatement) -> Self::Output;
+    fn evaluate_loop_control(&mut self, control: &ASTLoopControl) -> Self::Output;
+    fn evaluate_if_statement(&mut self, if_statement: &ASTIfStatement) -> Self::Output;
+    fn evaluate_defer(&mut self, expr: &ASTExpression) -> Self::Output;
+    fn evaluate_with(&mut self, with: &ASTWithStatement) -> Self::Output;
+    fn evaluate_match_statement(&mut self, match_statement: &ASTMatchStatement) -> Self::Output;
+    fn evaluate_guard(&mut self, guard: &ASTGuardStatement) -> Self::Output;
 }
 
 /// Visitor implementation for pretty-printing AST structure
@@ -160,6 +420,93 @@ impl ASTVisitor for ASTPrintor {
         self.print_with_indent(&format!("Identifier: {}", ident.name));
     }
 
+    fn visit_function_call(&mut self, func_call: &ASTFunctionCallExpression) {
+        self.print_with_indent("Function Call");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&func_call.callee);
+        for arg in &func_call.arguments {
+            self.visit_expression(arg);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_member_access(&mut self, member: &ASTMemberAccessExpression) {
+        self.print_with_indent(&format!("Member Access: .{}", member.name));
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&member.object);
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_method_call(&mut self, method_call: &ASTMethodCallExpression) {
+        self.print_with_indent(&format!("Method Call: .{}()", method_call.name));
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&method_call.receiver);
+        for arg in &method_call.arguments {
+            self.visit_expression(arg);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_array(&mut self, array: &ASTArrayExpression) {
+        self.print_with_indent("Array");
+        self.indent += LEVEL_INDENT;
+        for element in &array.elements {
+            self.visit_expression(element);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_set(&mut self, set: &ASTSetExpression) {
+        self.print_with_indent("Set");
+        self.indent += LEVEL_INDENT;
+        for element in &set.elements {
+            self.visit_expression(element);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_map(&mut self, map: &ASTMapExpression) {
+        self.print_with_indent("Map");
+        self.indent += LEVEL_INDENT;
+        for (key, value) in &map.entries {
+            self.visit_expression(key);
+            self.visit_expression(value);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_index(&mut self, index: &ASTIndexExpression) {
+        self.print_with_indent("Index");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&index.object);
+        self.visit_expression(&index.index);
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_pipe(&mut self, pipe: &ASTPipeExpression) {
+        self.print_with_indent("Pipe");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&pipe.value);
+        self.visit_expression(&pipe.target);
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_spread(&mut self, spread: &ASTSpreadExpression) {
+        self.print_with_indent("Spread");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&spread.expression);
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_block(&mut self, block: &ASTBlockExpression) {
+        self.print_with_indent("Block");
+        self.indent += LEVEL_INDENT;
+        for statement in &block.statements {
+            self.visit_statement(statement);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
     fn visit_variable_declaration(&mut self, decl: &ASTVariableDeclaration) {
         self.print_with_indent(&format!(
             "Variable Declaration: {} {} {}",
@@ -173,11 +520,115 @@ impl ASTVisitor for ASTPrintor {
     }
 
     fn visit_assignment(&mut self, assign: &ASTAssignment) {
-        self.print_with_indent(&format!("Assignment: {} =", assign.name));
+        self.print_with_indent(&format!("Assignment: {}{} =", assign.name, "[]".repeat(assign.indices.len())));
         self.indent += LEVEL_INDENT;
+        for index in &assign.indices {
+            self.visit_expression(index);
+        }
         self.visit_expression(&assign.value);
         self.indent -= LEVEL_INDENT;
     }
+
+    fn visit_import(&mut self, import: &ASTImportStatement) {
+        self.print_with_indent(&format!("Import: \"{}\"", import.path));
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        match for_statement.label {
+            Some(label) => self.print_with_indent(&format!("For ({}): {} in", label, for_statement.pattern)),
+            None => self.print_with_indent(&format!("For: {} in", for_statement.pattern)),
+        }
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&for_statement.iterable);
+        for statement in &for_statement.body {
+            self.visit_statement(statement);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        match while_statement.label {
+            Some(label) => self.print_with_indent(&format!("While ({}):", label)),
+            None => self.print_with_indent("While:"),
+        }
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&while_statement.condition);
+        for statement in &while_statement.body {
+            self.visit_statement(statement);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_loop_control(&mut self, control: &ASTLoopControl) {
+        self.print_with_indent(&format!("LoopControl: {}", control));
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        for (i, arm) in if_statement.arms.iter().enumerate() {
+            self.print_with_indent(if i == 0 { "If:" } else { "Else If:" });
+            self.indent += LEVEL_INDENT;
+            self.visit_expression(&arm.condition);
+            for statement in &arm.body {
+                self.visit_statement(statement);
+            }
+            self.indent -= LEVEL_INDENT;
+        }
+        if let Some(else_body) = &if_statement.else_body {
+            self.print_with_indent("Else:");
+            self.indent += LEVEL_INDENT;
+            for statement in else_body {
+                self.visit_statement(statement);
+            }
+            self.indent -= LEVEL_INDENT;
+        }
+    }
+
+    fn visit_defer(&mut self, expr: &ASTExpression) {
+        self.print_with_indent("Defer:");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(expr);
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_with(&mut self, with: &ASTWithStatement) {
+        self.print_with_indent(&format!("With: {} =", with.name));
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&with.resource);
+        for statement in &with.body {
+            self.visit_statement(statement);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_guard(&mut self, guard: &ASTGuardStatement) {
+        self.print_with_indent("Guard:");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&guard.condition);
+        self.print_with_indent("Else:");
+        for statement in &guard.body {
+            self.visit_statement(statement);
+        }
+        self.indent -= LEVEL_INDENT;
+    }
+
+    fn visit_match_statement(&mut self, match_statement: &ASTMatchStatement) {
+        self.print_with_indent("Match:");
+        self.indent += LEVEL_INDENT;
+        self.visit_expression(&match_statement.subject);
+        for arm in &match_statement.arms {
+            let guard = if arm.guard.is_some() { " if" } else { "" };
+            self.print_with_indent(&format!("Arm: {}{}", unparse::pattern_source(&arm.pattern), guard));
+            self.indent += LEVEL_INDENT;
+            if let Some(guard) = &arm.guard {
+                self.visit_expression(guard);
+            }
+            for statement in &arm.body {
+                self.visit_statement(statement);
+            }
+            self.indent -= LEVEL_INDENT;
+        }
+        self.indent -= LEVEL_INDENT;
+    }
 }
 
 impl ASTPrintor {
@@ -191,15 +642,28 @@ pub enum  ASTStatementKind{
     Expression(ASTExpression),
     VariableDeclaration(ASTVariableDeclaration),
     Assignment(ASTAssignment),
+    Import(ASTImportStatement),
+    For(ASTForStatement),
+    While(ASTWhileStatement),
+    LoopControl(ASTLoopControl),
+    If(ASTIfStatement),
+    /// `defer expr`: evaluates `expr` when the enclosing block exits
+    Defer(ASTExpression),
+    With(ASTWithStatement),
+    Match(ASTMatchStatement),
+    /// `guard cond else { ... }`, which `desugar` lowers to an `if`
+    Guard(ASTGuardStatement),
 }
 
 pub struct ASTStatement {
     pub kind: ASTStatementKind,
+    /// Byte range of the statement in its source, when produced by the parser
+    pub range: Option<Range<usize>>,
 } 
 
 impl ASTStatement {
     pub fn new(kind: ASTStatementKind) -> Self {
-        ASTStatement { kind }
+        ASTStatement { kind, range: None }
     }
 
     pub fn expression(expr: ASTExpression) -> Self {
@@ -213,6 +677,42 @@ impl ASTStatement {
     pub fn assignment(assign: ASTAssignment) -> Self {
         ASTStatement::new(ASTStatementKind::Assignment(assign))
     }
+
+    pub fn import(import: ASTImportStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::Import(import))
+    }
+
+    pub fn for_statement(for_statement: ASTForStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::For(for_statement))
+    }
+
+    pub fn while_statement(while_statement: ASTWhileStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::While(while_statement))
+    }
+
+    pub fn loop_control(control: ASTLoopControl) -> Self {
+        ASTStatement::new(ASTStatementKind::LoopControl(control))
+    }
+
+    pub fn with_statement(with: ASTWithStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::With(with))
+    }
+
+    pub fn if_statement(if_statement: ASTIfStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::If(if_statement))
+    }
+
+    pub fn defer(expr: ASTExpression) -> Self {
+        ASTStatement::new(ASTStatementKind::Defer(expr))
+    }
+
+    pub fn match_statement(match_statement: ASTMatchStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::Match(match_statement))
+    }
+
+    pub fn guard(guard: ASTGuardStatement) -> Self {
+        ASTStatement::new(ASTStatementKind::Guard(guard))
+    }
 }
 
 /// Expression types in Arc language
@@ -223,6 +723,16 @@ pub enum ASTExpressionKind {
     Unary(ASTUnaryExpression),
     Identifier(ASTIdentifierExpression),
     FunctionCall(ASTFunctionCallExpression),
+    MemberAccess(ASTMemberAccessExpression),
+    MethodCall(ASTMethodCallExpression),
+    Array(ASTArrayExpression),
+    Map(ASTMapExpression),
+    Set(ASTSetExpression),
+    Index(ASTIndexExpression),
+    Spread(ASTSpreadExpression),
+    Block(ASTBlockExpression),
+    /// `value |> target`, which `desugar` lowers to a call
+    Pipe(ASTPipeExpression),
 }
 
 pub struct ASTBinaryExpression {
@@ -246,17 +756,25 @@ impl ASTBinaryOperator {
             ASTBinaryOperatorKind::LogicalOr => 1,
             ASTBinaryOperatorKind::LogicalAnd => 2,
             ASTBinaryOperatorKind::Equal | ASTBinaryOperatorKind::NotEqual => 3,
+            // Below the comparisons, so `a < b in flags` tests the comparison's result
+            ASTBinaryOperatorKind::In => 4,
             ASTBinaryOperatorKind::Less | ASTBinaryOperatorKind::Greater |
-            ASTBinaryOperatorKind::LessEqual | ASTBinaryOperatorKind::GreaterEqual => 4,
-            ASTBinaryOperatorKind::BitwiseOr => 5,
-            ASTBinaryOperatorKind::BitwiseXor => 6,
-            ASTBinaryOperatorKind::BitwiseAnd => 7,
-            ASTBinaryOperatorKind::LeftShift | ASTBinaryOperatorKind::RightShift => 8,
-            ASTBinaryOperatorKind::Plus | ASTBinaryOperatorKind::Minus => 9,
-            ASTBinaryOperatorKind::Multiply | ASTBinaryOperatorKind::Divide | ASTBinaryOperatorKind::Modulo => 10,
-            ASTBinaryOperatorKind::Exponentiation => 11,
+            ASTBinaryOperatorKind::LessEqual | ASTBinaryOperatorKind::GreaterEqual => 5,
+            ASTBinaryOperatorKind::Range => 6,
+            ASTBinaryOperatorKind::BitwiseOr => 7,
+            ASTBinaryOperatorKind::BitwiseXor => 8,
+            ASTBinaryOperatorKind::BitwiseAnd => 9,
+            ASTBinaryOperatorKind::LeftShift | ASTBinaryOperatorKind::RightShift => 10,
+            ASTBinaryOperatorKind::Plus | ASTBinaryOperatorKind::Minus => 11,
+            ASTBinaryOperatorKind::Multiply | ASTBinaryOperatorKind::Divide | ASTBinaryOperatorKind::Modulo => 12,
+            ASTBinaryOperatorKind::Exponentiation => 13,
         }
     }
+
+    /// `2 ** 3 ** 2` groups as `2 ** (3 ** 2)`; every other operator groups left to right
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self.kind, ASTBinaryOperatorKind::Exponentiation)
+    }
 }
 #[derive(Debug)]
 pub enum ASTBinaryOperatorKind {
@@ -278,9 +796,87 @@ pub enum ASTBinaryOperatorKind {
     Greater,
     LessEqual,
     GreaterEqual,
+    /// `item in collection`: set or array membership, a map key, a substring or a range's integer
+    In,
     // Logical operators
     LogicalAnd,
     LogicalOr,
+    /// `start..end`, the integers from `start` up to but not including `end`
+    Range,
+}
+
+/// Groups of binary operators, by what they compute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperatorFamily {
+    /// `+ - * / % **`
+    Arithmetic,
+    /// `<< >>`
+    Shift,
+    /// `& | ^`
+    Bitwise,
+    /// `== != < > <= >= in`
+    Comparison,
+    /// `&& ||`
+    Logical,
+    /// `..`
+    Range,
+}
+
+impl ASTBinaryOperatorKind {
+    /// The operator's family. How bitwise and shift operators rank against the others
+    /// differs between languages, so the precedence lint looks at mixed families.
+    pub fn family(&self) -> OperatorFamily {
+        match self {
+            ASTBinaryOperatorKind::Plus
+            | ASTBinaryOperatorKind::Minus
+            | ASTBinaryOperatorKind::Multiply
+            | ASTBinaryOperatorKind::Divide
+            | ASTBinaryOperatorKind::Modulo
+            | ASTBinaryOperatorKind::Exponentiation => OperatorFamily::Arithmetic,
+            ASTBinaryOperatorKind::LeftShift | ASTBinaryOperatorKind::RightShift => OperatorFamily::Shift,
+            ASTBinaryOperatorKind::BitwiseAnd | ASTBinaryOperatorKind::BitwiseOr | ASTBinaryOperatorKind::BitwiseXor => {
+                OperatorFamily::Bitwise
+            }
+            ASTBinaryOperatorKind::Equal
+            | ASTBinaryOperatorKind::NotEqual
+            | ASTBinaryOperatorKind::Less
+            | ASTBinaryOperatorKind::Greater
+            | ASTBinaryOperatorKind::LessEqual
+            | ASTBinaryOperatorKind::GreaterEqual
+            | ASTBinaryOperatorKind::In => OperatorFamily::Comparison,
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => OperatorFamily::Logical,
+            ASTBinaryOperatorKind::Range => OperatorFamily::Range,
+        }
+    }
+}
+
+impl std::fmt::Display for ASTBinaryOperatorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            ASTBinaryOperatorKind::Plus => "+",
+            ASTBinaryOperatorKind::Minus => "-",
+            ASTBinaryOperatorKind::Multiply => "*",
+            ASTBinaryOperatorKind::Divide => "/",
+            ASTBinaryOperatorKind::Modulo => "%",
+            ASTBinaryOperatorKind::Exponentiation => "**",
+            ASTBinaryOperatorKind::BitwiseAnd => "&",
+            ASTBinaryOperatorKind::BitwiseOr => "|",
+            ASTBinaryOperatorKind::BitwiseXor => "^",
+            ASTBinaryOperatorKind::LeftShift => "<<",
+            ASTBinaryOperatorKind::RightShift => ">>",
+            ASTBinaryOperatorKind::Equal => "==",
+            ASTBinaryOperatorKind::NotEqual => "!=",
+            ASTBinaryOperatorKind::Less => "<",
+            ASTBinaryOperatorKind::Greater => ">",
+            ASTBinaryOperatorKind::LessEqual => "<=",
+            ASTBinaryOperatorKind::GreaterEqual => ">=",
+            ASTBinaryOperatorKind::In => "in",
+            ASTBinaryOperatorKind::LogicalAnd => "&&",
+            ASTBinaryOperatorKind::LogicalOr => "||",
+            ASTBinaryOperatorKind::Range => "..",
+        };
+        write!(f, "{}", symbol)
+    }
 }
 
 #[derive(Debug)]
@@ -289,6 +885,17 @@ pub enum ASTUnaryOperatorKind {
     Minus,
     LogicalNot,
 }
+
+impl std::fmt::Display for ASTUnaryOperatorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let symbol = match self {
+            ASTUnaryOperatorKind::Plus => "+",
+            ASTUnaryOperatorKind::Minus => "-",
+            ASTUnaryOperatorKind::LogicalNot => "!",
+        };
+        write!(f, "{}", symbol)
+    }
+}
 pub struct ASTNumberExpression {
     pub value: Value,
 }
@@ -339,7 +946,7 @@ impl ASTExpression {
     }
 
     pub fn string(string: String) -> Self {
-        ASTExpression::literal(Value::String(string))
+        ASTExpression::literal(Value::from(string))
     }
 
     pub fn binary(operator: ASTBinaryOperator, left: ASTExpression, right: ASTExpression) -> Self {
@@ -354,62 +961,387 @@ impl ASTExpression {
         ASTExpression::new(ASTExpressionKind::Unary(ASTUnaryExpression { operator, operand: Box::new(operand) }))
     }
 
-    pub fn identifier(name: String) -> Self {
-        ASTExpression::new(ASTExpressionKind::Identifier(ASTIdentifierExpression { name }))
+    pub fn identifier(name: Atom) -> Self {
+        ASTExpression::new(ASTExpressionKind::Identifier(ASTIdentifierExpression::new(name)))
+    }
+
+    pub fn function_call(callee: ASTExpression, arguments: Vec<ASTExpression>) -> Self {
+        ASTExpression::new(ASTExpressionKind::FunctionCall(ASTFunctionCallExpression::new(callee, arguments)))
+    }
+
+    pub fn member_access(object: ASTExpression, name: Atom) -> Self {
+        ASTExpression::new(ASTExpressionKind::MemberAccess(ASTMemberAccessExpression { object: Box::new(object), name }))
+    }
+
+    pub fn method_call(receiver: ASTExpression, name: Atom, arguments: Vec<ASTExpression>) -> Self {
+        ASTExpression::new(ASTExpressionKind::MethodCall(ASTMethodCallExpression { receiver: Box::new(receiver), name, arguments }))
+    }
+
+    pub fn array(elements: Vec<ASTExpression>) -> Self {
+        ASTExpression::new(ASTExpressionKind::Array(ASTArrayExpression { elements }))
+    }
+
+    pub fn map(entries: Vec<(ASTExpression, ASTExpression)>) -> Self {
+        ASTExpression::new(ASTExpressionKind::Map(ASTMapExpression { entries }))
+    }
+
+    pub fn set(elements: Vec<ASTExpression>) -> Self {
+        ASTExpression::new(ASTExpressionKind::Set(ASTSetExpression { elements }))
+    }
+
+    pub fn index(object: ASTExpression, index: ASTExpression) -> Self {
+        ASTExpression::new(ASTExpressionKind::Index(ASTIndexExpression { object: Box::new(object), index: Box::new(index) }))
     }
 
-    pub fn function_call(name: String, arguments: Vec<ASTExpression>) -> Self {
-        ASTExpression::new(ASTExpressionKind::FunctionCall(ASTFunctionCallExpression { name, arguments }))
+    pub fn pipe(value: ASTExpression, target: ASTExpression) -> Self {
+        ASTExpression::new(ASTExpressionKind::Pipe(ASTPipeExpression { value: Box::new(value), target: Box::new(target) }))
+    }
+
+    pub fn spread(expression: ASTExpression) -> Self {
+        ASTExpression::new(ASTExpressionKind::Spread(ASTSpreadExpression { expression: Box::new(expression) }))
+    }
+
+    pub fn block(statements: Vec<ASTStatement>) -> Self {
+        ASTExpression::new(ASTExpressionKind::Block(ASTBlockExpression { statements }))
     }
 }
 
 // Variable-related AST nodes
 pub struct ASTVariableDeclaration {
-    pub name: String,
+    pub name: Atom,
     pub initializer: Box<ASTExpression>,
     pub is_mutable: bool, // true for 'let', false for 'const'
+    pub doc: Option<String>, // text of the `///` comments preceding the declaration
 }
 
 impl ASTVariableDeclaration {
-    pub fn new(name: String, initializer: ASTExpression, is_mutable: bool) -> Self {
+    pub fn new(name: Atom, initializer: ASTExpression, is_mutable: bool) -> Self {
         ASTVariableDeclaration {
             name,
             initializer: Box::new(initializer),
             is_mutable,
+            doc: None,
         }
     }
 }
 
+/// `name = value`, or `name[i][j] = value` to store into an array or map
 pub struct ASTAssignment {
-    pub name: String,
+    pub name: Atom,
+    pub indices: Vec<ASTExpression>,
     pub value: Box<ASTExpression>,
+    pub slot: Option<Slot>, // filled in by the resolver
 }
 
 impl ASTAssignment {
-    pub fn new(name: String, value: ASTExpression) -> Self {
+    pub fn new(name: Atom, value: ASTExpression) -> Self {
+        Self::element(name, Vec::new(), value)
+    }
+
+    pub fn element(name: Atom, indices: Vec<ASTExpression>, value: ASTExpression) -> Self {
         ASTAssignment {
             name,
+            indices,
             value: Box::new(value),
+            slot: None,
+        }
+    }
+}
+
+/// `import "path"` - runs another module's statements in the current global scope
+pub struct ASTImportStatement {
+    pub path: String,
+}
+
+impl ASTImportStatement {
+    pub fn new(path: String) -> Self {
+        ASTImportStatement { path }
+    }
+}
+
+/// `for pattern in iterable { body }`; the body runs in a fresh scope for each item
+pub struct ASTForStatement {
+    pub pattern: ASTForPattern,
+    pub iterable: Box<ASTExpression>,
+    pub body: Vec<ASTStatement>,
+    /// Set by `label: for ...`, so `break label` in a nested loop can end this one
+    pub label: Option<Atom>,
+}
+
+impl ASTForStatement {
+    pub fn new(pattern: ASTForPattern, iterable: ASTExpression, body: Vec<ASTStatement>) -> Self {
+        ASTForStatement { pattern, iterable: Box::new(iterable), body, label: None }
+    }
+}
+
+/// `while condition { body }`; the condition is checked before each run of the body, which
+/// gets a fresh scope every time
+pub struct ASTWhileStatement {
+    pub condition: Box<ASTExpression>,
+    pub body: Vec<ASTStatement>,
+    /// Set by `label: while ...`, so `break label` in a nested loop can end this one
+    pub label: Option<Atom>,
+}
+
+impl ASTWhileStatement {
+    pub fn new(condition: ASTExpression, body: Vec<ASTStatement>) -> Self {
+        ASTWhileStatement { condition: Box::new(condition), body, label: None }
+    }
+}
+
+/// `if c1 { ... } else if c2 { ... } else { ... }`. An `else if` chain is kept as a flat list
+/// of arms rather than nested statements, so long chains cost no extra depth.
+pub struct ASTIfStatement {
+    pub arms: Vec<ASTIfArm>,
+    pub else_body: Option<Vec<ASTStatement>>,
+}
+
+impl ASTIfStatement {
+    pub fn new(arms: Vec<ASTIfArm>, else_body: Option<Vec<ASTStatement>>) -> Self {
+        ASTIfStatement { arms, else_body }
+    }
+}
+
+/// One condition of an `if` chain and the block it guards
+pub struct ASTIfArm {
+    pub condition: ASTExpression,
+    pub body: Vec<ASTStatement>,
+}
+
+impl ASTIfArm {
+    pub fn new(condition: ASTExpression, body: Vec<ASTStatement>) -> Self {
+        ASTIfArm { condition, body }
+    }
+}
+
+/// `with resource as name { ... }`: runs the block with `name` bound to the resource, then
+/// closes the resource however the block exits
+pub struct ASTWithStatement {
+    pub resource: Box<ASTExpression>,
+    pub name: Atom,
+    pub body: Vec<ASTStatement>,
+}
+
+impl ASTWithStatement {
+    pub fn new(resource: ASTExpression, name: Atom, body: Vec<ASTStatement>) -> Self {
+        ASTWithStatement { resource: Box::new(resource), name, body }
+    }
+}
+
+/// `guard condition else { body }`: the body runs when the condition is false and must end
+/// in `break` or `continue`
+pub struct ASTGuardStatement {
+    pub condition: Box<ASTExpression>,
+    pub body: Vec<ASTStatement>,
+    /// The `guard` keyword, which becomes the token of the `!` it is lowered to
+    pub keyword: Token,
+}
+
+impl ASTGuardStatement {
+    pub fn new(condition: ASTExpression, body: Vec<ASTStatement>, keyword: Token) -> Self {
+        ASTGuardStatement { condition: Box::new(condition), body, keyword }
+    }
+}
+
+/// `match value { pattern if guard => body ... }`: runs the body of the first arm whose
+/// pattern matches the value and whose guard, if it has one, is true
+pub struct ASTMatchStatement {
+    pub subject: Box<ASTExpression>,
+    pub arms: Vec<ASTMatchArm>,
+}
+
+impl ASTMatchStatement {
+    pub fn new(subject: ASTExpression, arms: Vec<ASTMatchArm>) -> Self {
+        ASTMatchStatement { subject: Box::new(subject), arms }
+    }
+}
+
+/// One arm of a `match`. The guard and the body run in a fresh scope holding the names the
+/// pattern binds.
+pub struct ASTMatchArm {
+    pub pattern: ASTPattern,
+    pub guard: Option<ASTExpression>,
+    pub body: Vec<ASTStatement>,
+}
+
+impl ASTMatchArm {
+    pub fn new(pattern: ASTPattern, guard: Option<ASTExpression>, body: Vec<ASTStatement>) -> Self {
+        ASTMatchArm { pattern, guard, body }
+    }
+}
+
+/// The values a `match` arm accepts
+#[derive(Debug, Clone, PartialEq)]
+pub enum ASTPattern {
+    /// `_` matches anything and binds nothing
+    Wildcard,
+    /// A name matches anything and binds it
+    Binding(Atom),
+    /// A number, string, bytes or boolean literal matches values equal to it
+    Literal(Value),
+    /// `(p1, p2)` matches an array of exactly that many items, each matching its pattern
+    Tuple(Vec<ASTPattern>),
+}
+
+impl ASTPattern {
+    /// The names the pattern binds, in order
+    pub fn names(&self) -> Vec<Atom> {
+        match self {
+            ASTPattern::Wildcard | ASTPattern::Literal(_) => Vec::new(),
+            ASTPattern::Binding(name) => vec![*name],
+            ASTPattern::Tuple(items) => items.iter().flat_map(ASTPattern::names).collect(),
+        }
+    }
+}
+
+/// Whether a loop control statement leaves the loop or skips to its next item
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopControlKind {
+    Break,
+    Continue,
+}
+
+/// `break` or `continue`, optionally naming the labeled loop it applies to; without a
+/// label it applies to the innermost loop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ASTLoopControl {
+    pub kind: LoopControlKind,
+    pub label: Option<Atom>,
+}
+
+impl ASTLoopControl {
+    pub fn new(kind: LoopControlKind, label: Option<Atom>) -> Self {
+        ASTLoopControl { kind, label }
+    }
+
+    /// True if this statement applies to the loop labeled `label` (or unlabeled)
+    pub fn targets(&self, label: Option<Atom>) -> bool {
+        self.label.is_none() || self.label == label
+    }
+}
+
+impl std::fmt::Display for ASTLoopControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keyword = match self.kind {
+            LoopControlKind::Break => "break",
+            LoopControlKind::Continue => "continue",
+        };
+        match self.label {
+            Some(label) => write!(f, "{} {}", keyword, label),
+            None => write!(f, "{}", keyword),
+        }
+    }
+}
+
+/// The variables a `for` loop binds: `x` takes each item whole, `(k, v)` unpacks an
+/// array item (a map yields `[key, value]` pairs) into one variable per element
+pub enum ASTForPattern {
+    Name(Atom),
+    Tuple(Vec<Atom>),
+}
+
+impl ASTForPattern {
+    /// The bound names, in order
+    pub fn names(&self) -> &[Atom] {
+        match self {
+            ASTForPattern::Name(name) => std::slice::from_ref(name),
+            ASTForPattern::Tuple(names) => names,
+        }
+    }
+}
+
+impl std::fmt::Display for ASTForPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ASTForPattern::Name(name) => write!(f, "{}", name),
+            ASTForPattern::Tuple(names) => {
+                let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                write!(f, "({})", names.join(", "))
+            }
         }
     }
 }
 
 pub struct ASTIdentifierExpression {
-    pub name: String,
+    pub name: Atom,
+    pub slot: Option<Slot>, // filled in by the resolver
 }
 
 impl ASTIdentifierExpression {
-    pub fn new(name: String) -> Self {
-        ASTIdentifierExpression { name }
+    pub fn new(name: Atom) -> Self {
+        ASTIdentifierExpression { name, slot: None }
     }
 }
+/// `callee(arguments)`; the callee is usually a bare name but may be any expression
+/// that evaluates to a function
 pub struct ASTFunctionCallExpression {
-    pub name: String,
+    pub callee: Box<ASTExpression>,
     pub arguments: Vec<ASTExpression>,
+    pub memoize: bool, // set by the optimizer on pure calls repeated within a statement
 }
 
 impl ASTFunctionCallExpression {
-    pub fn new(name: String, arguments: Vec<ASTExpression>) -> Self {
-        ASTFunctionCallExpression { name, arguments }
+    pub fn new(callee: ASTExpression, arguments: Vec<ASTExpression>) -> Self {
+        ASTFunctionCallExpression { callee: Box::new(callee), arguments, memoize: false }
     }
+
+    /// The called name when the callee is a bare identifier, as in `sqrt(x)`
+    pub fn name(&self) -> Option<Atom> {
+        match &self.callee.kind {
+            ASTExpressionKind::Identifier(ident) => Some(ident.name),
+            _ => None,
+        }
+    }
+}
+
+/// `object.name`; no value has fields yet, but structs and modules will
+pub struct ASTMemberAccessExpression {
+    pub object: Box<ASTExpression>,
+    pub name: Atom,
+}
+
+/// `receiver.name(arguments)`, which calls `name(receiver, arguments)`
+pub struct ASTMethodCallExpression {
+    pub receiver: Box<ASTExpression>,
+    pub name: Atom,
+    pub arguments: Vec<ASTExpression>,
+}
+
+/// `[a, b, c]`
+pub struct ASTArrayExpression {
+    pub elements: Vec<ASTExpression>,
+}
+
+/// `{"key": value, ...}`; keys are expressions that must evaluate to strings
+pub struct ASTMapExpression {
+    pub entries: Vec<(ASTExpression, ASTExpression)>,
+}
+
+/// `{a, b, c}`; `{}` is an empty map, not a set
+pub struct ASTSetExpression {
+    pub elements: Vec<ASTExpression>,
+}
+
+/// `object[index]`
+pub struct ASTIndexExpression {
+    pub object: Box<ASTExpression>,
+    pub index: Box<ASTExpression>,
+}
+
+/// `...expression`, only valid as an array element or call argument, where the array it
+/// evaluates to is flattened in place
+pub struct ASTSpreadExpression {
+    pub expression: Box<ASTExpression>,
+}
+
+/// `value |> target`: `target` called with `value` before its own arguments
+pub struct ASTPipeExpression {
+    pub value: Box<ASTExpression>,
+    pub target: Box<ASTExpression>,
+}
+
+/// `{ statements; value }`, run in its own scope. The parser ensures the last statement
+/// is an expression, whose value is the block's value.
+pub struct ASTBlockExpression {
+    pub statements: Vec<ASTStatement>,
 }
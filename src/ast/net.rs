@@ -0,0 +1,141 @@
+//! Network builtins - blocking HTTP requests
+//!
+//! `http_get(url, headers?)` and `http_post(url, body, headers?)` return a map of the
+//! response's `status`, `body` and `headers` (names lowercased). Any status, 404 and 500
+//! included, is a response; only failing to get one at all is an error. They need the
+//! `network` capability, and are compiled in only with the `net` feature: without it they
+//! still exist but fail, so scripts get a clear error instead of an unknown function.
+
+use crate::ast::types::Value;
+
+/// Names of the network builtins
+pub const NET_BUILTINS: &[&str] = &["http_get", "http_post"];
+
+pub fn is_net_builtin(name: &str) -> bool {
+    NET_BUILTINS.contains(&name)
+}
+
+/// Calls a network builtin
+pub fn call(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    let (url, body, headers) = match (name, args) {
+        ("http_get", [Value::String(url)]) => (url, None, None),
+        ("http_get", [Value::String(url), headers]) => (url, None, Some(headers)),
+        ("http_post", [Value::String(url), Value::String(body)]) => (url, Some(body), None),
+        ("http_post", [Value::String(url), Value::String(body), headers]) => (url, Some(body), Some(headers)),
+        ("http_get", _) => return Err("expected a String url and an optional Map of headers".to_string()),
+        ("http_post", _) => return Err("expected a String url, a String body and an optional Map of headers".to_string()),
+        _ => return Err(format!("unknown network function '{}'", name)),
+    };
+    let headers = match headers {
+        Some(Value::Map(entries)) => entries
+            .iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => Ok((&**name, &**value)),
+                other => Err(format!("header '{}' must be a String, found {}", name, other.get_type())),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(other) => return Err(format!("headers must be a Map, found {}", other.get_type())),
+        None => Vec::new(),
+    };
+    request(url, body.map(|body| &**body), &headers).map(Some)
+}
+
+#[cfg(feature = "net")]
+fn request(url: &str, body: Option<&str>, headers: &[(&str, &str)]) -> Result<Value, String> {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Requests that take longer than this fail rather than hang the script
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let mut request = match body {
+        Some(_) => agent.post(url),
+        None => agent.get(url),
+    };
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    let response = match body {
+        Some(body) => request.send_string(body),
+        None => request.call(),
+    };
+    let response = match response {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(e) => return Err(format!("request to '{}' failed: {}", url, e)),
+    };
+
+    let status = Value::Integer(response.status().into());
+    let mut response_headers = BTreeMap::new();
+    for name in response.headers_names() {
+        if let Some(value) = response.header(&name) {
+            response_headers.insert(Arc::from(name.to_ascii_lowercase()), Value::from(value));
+        }
+    }
+    let body = response
+        .into_string()
+        .map_err(|e| format!("cannot read the response from '{}': {}", url, e))?;
+    Ok(Value::from(BTreeMap::from([
+        (Arc::from("status"), status),
+        (Arc::from("body"), Value::from(body)),
+        (Arc::from("headers"), Value::from(response_headers)),
+    ])))
+}
+
+#[cfg(not(feature = "net"))]
+fn request(_url: &str, _body: Option<&str>, _headers: &[(&str, &str)]) -> Result<Value, String> {
+    Err("Arc was built without the 'net' feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "net")]
+    fn test_http_requests_return_responses() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        // A one-shot server that echoes the request's method and body back in a 404
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/items", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let (mut request_line, mut length) = (String::new(), 0);
+            reader.read_line(&mut request_line).unwrap();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let reply = format!("{} {}", request_line.split(' ').next().unwrap(), String::from_utf8(body).unwrap());
+            let response = format!("HTTP/1.1 404 Not Found\r\nX-Test: yes\r\nContent-Length: {}\r\n\r\n{}", reply.len(), reply);
+            reader.into_inner().write_all(response.as_bytes()).unwrap();
+        });
+
+        let Ok(Some(Value::Map(response))) = call("http_post", &[Value::from(url), Value::from("hi")]) else {
+            panic!("expected a response map");
+        };
+        server.join().unwrap();
+        assert_eq!(response.get("status"), Some(&Value::Integer(404)));
+        assert_eq!(response.get("body"), Some(&Value::from("POST hi")));
+        assert_eq!(response["headers"].to_string(), "{\"content-length\": \"7\", \"x-test\": \"yes\"}");
+    }
+
+    #[test]
+    fn test_http_arguments_are_checked() {
+        assert_eq!(call("http_get", &[Value::Integer(1)]), Err("expected a String url and an optional Map of headers".to_string()));
+        let headers = Value::from(std::collections::BTreeMap::from([(std::sync::Arc::from("accept"), Value::Integer(1))]));
+        assert_eq!(call("http_get", &[Value::from("http://localhost"), headers]), Err("header 'accept' must be a String, found Integer".to_string()));
+    }
+}
@@ -0,0 +1,130 @@
+//! Number formatting - how integers and floats are written out
+//!
+//! A `NumberFormat` is parsed from a short spec, `[,][.precision][f|e]`: `,` groups the
+//! integer digits in thousands, `.N` fixes the digits after the point, and `e` switches to
+//! scientific notation (`f`, fixed, is the default). Output never depends on the host's
+//! locale: the separator is always `,` and the point always `.`.
+
+/// Fixed notation (`1234.5`) or scientific notation (`1.2345e3`)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Notation {
+    #[default]
+    Fixed,
+    Scientific,
+}
+
+/// Settings for writing numbers. The default writes them exactly as `print` always has:
+/// integers in full and floats with the fewest digits that read back as the same float.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct NumberFormat {
+    /// Digits after the point; `None` keeps every digit the float needs
+    pub precision: Option<usize>,
+    pub notation: Notation,
+    /// Group integer digits in thousands with `,`
+    pub thousands: bool,
+}
+
+/// Most digits a spec may ask for after the point
+pub const MAX_PRECISION: usize = 100;
+
+impl NumberFormat {
+    /// Parses a spec such as `",.2f"`, `".3e"` or `","`; the empty spec is the default format
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut format = NumberFormat::default();
+        let mut rest = spec;
+        if let Some(after) = rest.strip_prefix(',') {
+            format.thousands = true;
+            rest = after;
+        }
+        if let Some(after) = rest.strip_prefix('.') {
+            let digits = after.find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len());
+            let precision = after[..digits]
+                .parse::<usize>()
+                .ok()
+                .filter(|&precision| precision <= MAX_PRECISION)
+                .ok_or_else(|| format!("invalid format spec '{}': precision must be from 0 to {}", spec, MAX_PRECISION))?;
+            format.precision = Some(precision);
+            rest = &after[digits..];
+        }
+        match rest {
+            "" | "f" => {}
+            "e" => format.notation = Notation::Scientific,
+            _ => return Err(format!("invalid format spec '{}'; expected [,][.precision][f|e]", spec)),
+        }
+        if format.thousands && format.notation == Notation::Scientific {
+            return Err(format!("invalid format spec '{}': ',' cannot be used with scientific notation", spec));
+        }
+        Ok(format)
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == NumberFormat::default()
+    }
+
+    /// Writes an integer; a precision or scientific notation writes it as a float would be
+    pub fn format_integer(&self, value: i64) -> String {
+        if self.precision.is_some() || self.notation == Notation::Scientific {
+            return self.format_float(value as f64);
+        }
+        self.group(value.to_string())
+    }
+
+    pub fn format_float(&self, value: f64) -> String {
+        let text = match (self.notation, self.precision) {
+            (Notation::Fixed, None) => value.to_string(),
+            (Notation::Fixed, Some(precision)) => format!("{:.*}", precision, value),
+            (Notation::Scientific, None) => format!("{:e}", value),
+            (Notation::Scientific, Some(precision)) => format!("{:.*e}", precision, value),
+        };
+        if value.is_finite() {
+            self.group(text)
+        } else {
+            text
+        }
+    }
+
+    /// Inserts thousands separators into the integer digits of `text`, if enabled
+    fn group(&self, text: String) -> String {
+        if !self.thousands {
+            return text;
+        }
+        let (sign, unsigned) = match text.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", text.as_str()),
+        };
+        let digits = unsigned.find(|c: char| !c.is_ascii_digit()).unwrap_or(unsigned.len());
+        let (integer, fraction) = unsigned.split_at(digits);
+
+        let mut grouped = String::with_capacity(text.len() + digits / 3);
+        grouped.push_str(sign);
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (digits - i) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        grouped.push_str(fraction);
+        grouped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_format_specs() {
+        let format = |spec: &str, value: f64| NumberFormat::parse(spec).map(|format| format.format_float(value));
+
+        assert_eq!(format("", 0.1 + 0.2), Ok("0.30000000000000004".to_string()));
+        assert_eq!(format(".2", 0.1 + 0.2), Ok("0.30".to_string()));
+        assert_eq!(format(",.1f", -1234567.25), Ok("-1,234,567.2".to_string()));
+        assert_eq!(format(".3e", 1234.5), Ok("1.234e3".to_string()));
+        assert_eq!(format(",", f64::NAN), Ok("NaN".to_string()));
+        assert_eq!(NumberFormat::parse(",").map(|format| format.format_integer(-1000)), Ok("-1,000".to_string()));
+        assert_eq!(NumberFormat::parse(".1").map(|format| format.format_integer(3)), Ok("3.0".to_string()));
+        assert_eq!(format(",e", 1.0), Err("invalid format spec ',e': ',' cannot be used with scientific notation".to_string()));
+        assert_eq!(format("x", 1.0), Err("invalid format spec 'x'; expected [,][.precision][f|e]".to_string()));
+        assert_eq!(format(".", 1.0), Err("invalid format spec '.': precision must be from 0 to 100".to_string()));
+    }
+}
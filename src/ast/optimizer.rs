@@ -0,0 +1,316 @@
+//! Optimizer - pure builtin folding and memoization, enabled by `-O`
+//!
+//! Before a statement runs, calls to pure builtins whose arguments are constant are
+//! evaluated once and replaced by their result, and pure calls that appear more than once
+//! in the statement are marked for memoization. While it runs, marked calls are cached by
+//! argument values, so `sqrt(x * x) + sqrt(x * x)` computes the root once. Unrepeated
+//! calls skip the cache, which would only add hashing.
+
+use crate::ast::evaluator::ASTEvaluator;
+use crate::ast::interner::Atom;
+use crate::ast::{collections, encoding, functions, json, math, strings};
+use crate::ast::transform::{children, children_mut, statement_expressions_mut, walk_expression, AstTransformer};
+use crate::ast::types::{Function, SetElement, Value};
+use crate::ast::{Ast, ASTExpression, ASTExpressionKind, ExpressionEvaluator};
+use crate::capabilities::EvalCapabilities;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::Arc;
+
+/// True for builtins whose result depends only on their arguments
+pub fn is_pure_builtin(name: &str) -> bool {
+    math::is_math_builtin(name)
+}
+
+/// True for builtins that only compute a result, so calling one and dropping the result does
+/// nothing. Unlike the pure builtins these are not folded: their results can be large
+/// aggregates, and `sort_by` and `apply` are excluded because they call functions that may
+/// not be effect-free.
+pub fn is_effect_free_builtin(name: &str) -> bool {
+    is_pure_builtin(name)
+        || (collections::is_collection_builtin(name) && name != "sort_by")
+        || strings::is_string_builtin(name)
+        || encoding::is_encoding_builtin(name)
+        || (functions::is_function_builtin(name) && name != "apply")
+        || json::is_json_builtin(name)
+}
+
+/// True if the expression reads no variables and calls only pure builtins
+fn is_constant(expression: &ASTExpression) -> bool {
+    match &expression.kind {
+        ASTExpressionKind::Number(_) => true,
+        ASTExpressionKind::Binary(expr) => is_constant(&expr.left) && is_constant(&expr.right),
+        ASTExpressionKind::Paranthesized(paren_expr) => is_constant(&paren_expr.expression),
+        ASTExpressionKind::Unary(unary_expr) => is_constant(&unary_expr.operand),
+        ASTExpressionKind::Identifier(_) => false,
+        ASTExpressionKind::FunctionCall(func_call) => {
+            // The callee is a name, not a variable read, when it names a builtin
+            func_call.name().is_some_and(|name| is_pure_builtin(name.as_str()))
+                && func_call.arguments.iter().all(is_constant)
+        }
+        ASTExpressionKind::MemberAccess(_)
+        | ASTExpressionKind::Array(_)
+        | ASTExpressionKind::Map(_)
+        | ASTExpressionKind::Set(_)
+        | ASTExpressionKind::Index(_)
+        | ASTExpressionKind::Spread(_)
+        | ASTExpressionKind::Block(_)
+        | ASTExpressionKind::Pipe(_) => false,
+        ASTExpressionKind::MethodCall(method_call) => {
+            is_pure_builtin(method_call.name.as_str())
+                && is_constant(&method_call.receiver)
+                && method_call.arguments.iter().all(is_constant)
+        }
+    }
+}
+
+/// The value of an expression that reads no variables, computed the way folding would;
+/// `None` if it is not constant or raises an error
+pub fn constant_value(expression: &ASTExpression) -> Option<Value> {
+    if !is_constant(expression) {
+        return None;
+    }
+    let mut evaluator = ASTEvaluator::new();
+    evaluator.capabilities = EvalCapabilities::none();
+    let value = evaluator.evaluate_expression(expression)?;
+    evaluator.errors.is_empty().then_some(value)
+}
+
+/// Runs both passes: folds constant pure calls, then marks repeated ones for memoization.
+/// Returns how many calls were folded.
+pub fn optimize(ast: &mut Ast) -> usize {
+    let folded = fold_pure_calls(ast);
+    for expression in ast.statements.iter_mut().flat_map(statement_expressions_mut) {
+        let mut calls = Vec::new();
+        pure_signature(expression, &mut calls);
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        for signature in calls.iter().flatten() {
+            *counts.entry(*signature).or_insert(0) += 1;
+        }
+        let mut repeated = calls.iter().map(|signature| signature.is_some_and(|signature| counts[&signature] > 1));
+        mark_repeated_calls(expression, &mut repeated);
+    }
+    folded
+}
+
+/// Replaces pure builtin calls with constant arguments by their results, returning how
+/// many calls were folded. Calls that fail are left for the evaluator to report.
+pub fn fold_pure_calls(ast: &mut Ast) -> usize {
+    let mut folder = PureCallFolder { scratch: None, folded: 0 };
+    folder.transform(ast);
+    folder.folded
+}
+
+/// Folds bottom-up, so a call whose arguments fold to constants is folded in turn
+struct PureCallFolder {
+    /// Created on the first foldable call; most statements have none
+    scratch: Option<ASTEvaluator>,
+    folded: usize,
+}
+
+impl AstTransformer for PureCallFolder {
+    fn transform_expression(&mut self, expression: &mut ASTExpression) {
+        walk_expression(self, expression);
+
+        let is_call = matches!(expression.kind, ASTExpressionKind::FunctionCall(_) | ASTExpressionKind::MethodCall(_));
+        if is_call && is_constant(expression) {
+            let scratch = self.scratch.get_or_insert_with(|| {
+                let mut evaluator = ASTEvaluator::new();
+                evaluator.capabilities = EvalCapabilities::none();
+                evaluator
+            });
+            scratch.errors.clear();
+            if let Some(value) = scratch.evaluate_expression(expression) {
+                if scratch.errors.is_empty() {
+                    *expression = ASTExpression::literal(value);
+                    self.folded += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Hash of a pure expression's structure, used to spot repeated calls; `None` if it calls
+/// anything impure. Each call's hash is also pushed to `calls`, in post-order.
+///
+/// A collision only marks a call for memoization needlessly: cached results are keyed by
+/// argument values, never by this hash.
+fn pure_signature(expression: &ASTExpression, calls: &mut Vec<Option<u64>>) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    mem::discriminant(&expression.kind).hash(&mut hasher);
+    let mut pure = true;
+    match &expression.kind {
+        ASTExpressionKind::Number(number) => ArgKey::from(&number.value).hash(&mut hasher),
+        ASTExpressionKind::Identifier(ident) => ident.name.hash(&mut hasher),
+        ASTExpressionKind::Binary(expr) => mem::discriminant(&expr.operator.kind).hash(&mut hasher),
+        ASTExpressionKind::Unary(unary_expr) => mem::discriminant(&unary_expr.operator.kind).hash(&mut hasher),
+        ASTExpressionKind::Paranthesized(_)
+        | ASTExpressionKind::Array(_)
+        | ASTExpressionKind::Map(_)
+        | ASTExpressionKind::Set(_)
+        | ASTExpressionKind::Index(_)
+        | ASTExpressionKind::Spread(_)
+        | ASTExpressionKind::Block(_) => {}
+        ASTExpressionKind::FunctionCall(func_call) => {
+            func_call.name().hash(&mut hasher);
+            pure = func_call.name().is_some_and(|name| is_pure_builtin(name.as_str()));
+        }
+        ASTExpressionKind::MemberAccess(member) => member.name.hash(&mut hasher),
+        // Lowered to a call before optimizing; its callee is not known until then
+        ASTExpressionKind::Pipe(_) => pure = false,
+        ASTExpressionKind::MethodCall(method_call) => {
+            method_call.name.hash(&mut hasher);
+            pure = is_pure_builtin(method_call.name.as_str());
+        }
+    }
+    for child in children(expression) {
+        match pure_signature(child, calls) {
+            Some(signature) => signature.hash(&mut hasher),
+            None => pure = false,
+        }
+    }
+    let signature = pure.then(|| hasher.finish());
+    if matches!(expression.kind, ASTExpressionKind::FunctionCall(_)) {
+        calls.push(signature);
+    }
+    signature
+}
+
+/// Sets `memoize` on each call, visiting calls in the same post-order as `pure_signature`
+fn mark_repeated_calls(expression: &mut ASTExpression, repeated: &mut impl Iterator<Item = bool>) {
+    for child in children_mut(expression) {
+        mark_repeated_calls(child, repeated);
+    }
+    if let ASTExpressionKind::FunctionCall(func_call) = &mut expression.kind {
+        func_call.memoize = repeated.next().unwrap_or(false);
+    }
+}
+
+/// Hashable form of an argument; floats are keyed by their bits
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ArgKey {
+    Integer(i64),
+    Float(u64),
+    Boolean(bool),
+    String(Arc<str>),
+    Bytes(Arc<[u8]>),
+    Function(Atom),
+    Bound(Box<ArgKey>, Vec<ArgKey>),
+    Composed(Box<ArgKey>, Box<ArgKey>),
+    Constant(Box<ArgKey>),
+    Array(Vec<ArgKey>),
+    Map(Vec<(Arc<str>, ArgKey)>),
+    Set(Vec<SetElement>),
+    Range(i64, i64),
+    /// Channels are keyed by identity
+    Channel(usize),
+}
+
+impl From<&Value> for ArgKey {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Integer(i) => ArgKey::Integer(*i),
+            Value::Float(f) => ArgKey::Float(f.to_bits()),
+            Value::Boolean(b) => ArgKey::Boolean(*b),
+            Value::String(s) => ArgKey::String(s.clone()),
+            Value::Bytes(b) => ArgKey::Bytes(b.clone()),
+            Value::Function(Function::Named(name)) => ArgKey::Function(*name),
+            Value::Function(Function::Bound(bound)) => ArgKey::Bound(
+                Box::new(ArgKey::from(&Value::Function(bound.function.clone()))),
+                bound.args.iter().map(ArgKey::from).collect(),
+            ),
+            Value::Function(Function::Composed(pair)) => ArgKey::Composed(
+                Box::new(ArgKey::from(&Value::Function(pair.0.clone()))),
+                Box::new(ArgKey::from(&Value::Function(pair.1.clone()))),
+            ),
+            Value::Function(Function::Constant(value)) => ArgKey::Constant(Box::new(ArgKey::from(&**value))),
+            Value::Array(items) => ArgKey::Array(items.iter().map(ArgKey::from).collect()),
+            Value::Map(entries) => {
+                ArgKey::Map(entries.iter().map(|(key, value)| (key.clone(), ArgKey::from(value))).collect())
+            }
+            Value::Set(elements) => ArgKey::Set(elements.iter().cloned().collect()),
+            Value::Range(start, end) => ArgKey::Range(*start, *end),
+            Value::Channel(channel) => ArgKey::Channel(Arc::as_ptr(channel) as usize),
+        }
+    }
+}
+
+/// Results of pure calls made while evaluating the current statement
+#[derive(Debug, Default)]
+pub struct PureCallCache {
+    results: HashMap<(Atom, Vec<ArgKey>), Value>,
+}
+
+impl PureCallCache {
+    /// Returns the cached result of `name(args)`, computing and storing it on a miss
+    pub fn get_or_call(
+        &mut self,
+        name: Atom,
+        args: &[Value],
+        call: impl FnOnce(&[Value]) -> Result<Option<Value>, String>,
+    ) -> Result<Option<Value>, String> {
+        let key = (name, args.iter().map(ArgKey::from).collect());
+        if let Some(value) = self.results.get(&key) {
+            return Ok(Some(value.clone()));
+        }
+        let result = call(args)?;
+        if let Some(value) = &result {
+            self.results.insert(key, value.clone());
+        }
+        Ok(result)
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+    use crate::ast::ASTStatementKind;
+
+    #[test]
+    fn test_folds_constant_pure_calls_only() {
+        let mut ast = Ast::new();
+        ast.statements = Parser::from_lexer(Lexer::new("sqrt(abs(-16)) + sqrt(x)\nclock()\nsqrt(\"a\")")).parse_all();
+        assert_eq!(fold_pure_calls(&mut ast), 2);
+
+        let ASTStatementKind::Expression(sum) = &ast.statements[0].kind else {
+            panic!("expected an expression");
+        };
+        let ASTExpressionKind::Binary(sum) = &sum.kind else {
+            panic!("expected a binary expression");
+        };
+        assert!(matches!(&sum.left.kind, ASTExpressionKind::Number(n) if n.value == Value::Float(4.0)));
+        assert!(matches!(&sum.right.kind, ASTExpressionKind::FunctionCall(_)));
+    }
+
+    #[test]
+    fn test_marks_repeated_pure_calls() {
+        let mut ast = Ast::new();
+        ast.statements = Parser::from_lexer(Lexer::new("sqrt(x * x) + sqrt(x * x) + sqrt(x) + clock()")).parse_all();
+        optimize(&mut ast);
+
+        let mut marked = Vec::new();
+        let ASTStatementKind::Expression(expression) = &ast.statements[0].kind else {
+            panic!("expected an expression");
+        };
+        let mut pending = vec![expression];
+        while let Some(expression) = pending.pop() {
+            if let ASTExpressionKind::FunctionCall(func_call) = &expression.kind {
+                marked.push((func_call.name().expect("named call").to_string(), func_call.memoize));
+            }
+            pending.extend(children(expression));
+        }
+        marked.sort();
+        assert_eq!(
+            marked,
+            [("clock".to_string(), false), ("sqrt".to_string(), false), ("sqrt".to_string(), true), ("sqrt".to_string(), true)]
+        );
+    }
+}
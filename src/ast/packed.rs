@@ -0,0 +1,210 @@
+//! Packed values - a NaN-boxed 8-byte encoding of `Value`
+//!
+//! `Value` is 24 bytes (an `Arc<str>` fat pointer plus the tag). `PackedValue` fits any
+//! value in one `u64`, which is what a bytecode VM's stack and constant pool want:
+//!
+//! - a float is stored as its own bits; NaNs are canonicalized to one positive quiet NaN
+//! - everything else lives in the negative quiet-NaN space `0xFFF8_xxxx_xxxx_xxxx`, a
+//!   3-bit tag in bits 48-50 and a 48-bit payload below it:
+//!   - integers that fit in 48 bits, sign-extended on the way out
+//!   - booleans
+//!   - a pointer to a boxed `Value` for strings and integers too wide to inline
+//!
+//! Only boxed values allocate; `Clone` and `Drop` manage the box. Run
+//! `cargo bench --bench value_repr` to compare against the enum.
+
+use crate::ast::types::{DataType, Value};
+use std::fmt;
+
+const QUIET_NAN: u64 = 0x7FF8_0000_0000_0000;
+const BOXED_PREFIX: u64 = 0xFFF8_0000_0000_0000;
+const PAYLOAD_MASK: u64 = 0x0000_FFFF_FFFF_FFFF;
+const TAG_SHIFT: u32 = 48;
+
+const TAG_INTEGER: u64 = 1;
+const TAG_BOOLEAN: u64 = 2;
+const TAG_HEAP: u64 = 3;
+
+/// Smallest and largest integers stored inline
+const INLINE_MIN: i64 = -(1 << 47);
+const INLINE_MAX: i64 = (1 << 47) - 1;
+
+/// A `Value` packed into 64 bits
+pub struct PackedValue(u64);
+
+impl PackedValue {
+    fn tagged(tag: u64, payload: u64) -> Self {
+        PackedValue(BOXED_PREFIX | (tag << TAG_SHIFT) | (payload & PAYLOAD_MASK))
+    }
+
+    /// The tag, or `None` for a float
+    fn tag(&self) -> Option<u64> {
+        (self.0 & BOXED_PREFIX == BOXED_PREFIX).then_some((self.0 >> TAG_SHIFT) & 0x7)
+    }
+
+    fn payload(&self) -> u64 {
+        self.0 & PAYLOAD_MASK
+    }
+
+    pub fn integer(value: i64) -> Self {
+        if (INLINE_MIN..=INLINE_MAX).contains(&value) {
+            Self::tagged(TAG_INTEGER, value as u64)
+        } else {
+            Self::heap(Value::Integer(value))
+        }
+    }
+
+    pub fn float(value: f64) -> Self {
+        PackedValue(if value.is_nan() { QUIET_NAN } else { value.to_bits() })
+    }
+
+    pub fn boolean(value: bool) -> Self {
+        Self::tagged(TAG_BOOLEAN, value as u64)
+    }
+
+    fn heap(value: Value) -> Self {
+        let pointer = Box::into_raw(Box::new(value)) as usize as u64;
+        // User-space addresses fit in 48 bits on every supported platform
+        assert!(pointer & !PAYLOAD_MASK == 0, "heap pointer does not fit in a NaN box");
+        Self::tagged(TAG_HEAP, pointer)
+    }
+
+    fn heap_value(&self) -> Option<&Value> {
+        if self.tag() != Some(TAG_HEAP) {
+            return None;
+        }
+        // SAFETY: a heap tag is only ever written by `heap`, from a live `Box<Value>` that
+        // this value owns until it is dropped
+        Some(unsafe { &*(self.payload() as usize as *const Value) })
+    }
+
+    /// The integer, if this holds one
+    pub fn as_integer(&self) -> Option<i64> {
+        match self.tag() {
+            // Shift the 48-bit payload to the top and back to sign-extend it
+            Some(TAG_INTEGER) => Some(((self.payload() << 16) as i64) >> 16),
+            Some(TAG_HEAP) => match self.heap_value() {
+                Some(Value::Integer(i)) => Some(*i),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The float, if this holds one
+    pub fn as_float(&self) -> Option<f64> {
+        match self.tag() {
+            None => Some(f64::from_bits(self.0)),
+            Some(_) => None,
+        }
+    }
+
+    /// The boolean, if this holds one
+    pub fn as_boolean(&self) -> Option<bool> {
+        (self.tag() == Some(TAG_BOOLEAN)).then(|| self.payload() != 0)
+    }
+
+    pub fn get_type(&self) -> DataType {
+        match self.tag() {
+            None => DataType::Float,
+            Some(TAG_INTEGER) => DataType::Integer,
+            Some(TAG_BOOLEAN) => DataType::Boolean,
+            Some(_) => self.heap_value().map_or(DataType::String, Value::get_type),
+        }
+    }
+
+    /// Unpacks into a `Value`
+    pub fn to_value(&self) -> Value {
+        match self.tag() {
+            None => Value::Float(f64::from_bits(self.0)),
+            Some(TAG_INTEGER) => Value::Integer(self.as_integer().unwrap_or_default()),
+            Some(TAG_BOOLEAN) => Value::Boolean(self.payload() != 0),
+            // Floats are canonicalized, so no other bit pattern reaches the tagged space
+            Some(_) => match self.heap_value() {
+                Some(value) => value.clone(),
+                None => unreachable!("invalid NaN-box tag"),
+            },
+        }
+    }
+}
+
+impl From<Value> for PackedValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Integer(i) => PackedValue::integer(i),
+            Value::Float(f) => PackedValue::float(f),
+            Value::Boolean(b) => PackedValue::boolean(b),
+            other => PackedValue::heap(other),
+        }
+    }
+}
+
+impl From<&PackedValue> for Value {
+    fn from(packed: &PackedValue) -> Self {
+        packed.to_value()
+    }
+}
+
+impl Clone for PackedValue {
+    fn clone(&self) -> Self {
+        match self.heap_value() {
+            Some(value) => PackedValue::heap(value.clone()),
+            None => PackedValue(self.0),
+        }
+    }
+}
+
+impl Drop for PackedValue {
+    fn drop(&mut self) {
+        if self.tag() == Some(TAG_HEAP) {
+            // SAFETY: see `heap_value`; the box is freed exactly once, here
+            drop(unsafe { Box::from_raw(self.payload() as usize as *mut Value) });
+        }
+    }
+}
+
+impl PartialEq for PackedValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_value() == other.to_value()
+    }
+}
+
+impl fmt::Debug for PackedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Packed({:?})", self.to_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        assert_eq!(std::mem::size_of::<PackedValue>(), 8);
+        let values = [
+            Value::Integer(0),
+            Value::Integer(-1),
+            Value::Integer(INLINE_MAX),
+            Value::Integer(INLINE_MIN),
+            Value::Integer(i64::MAX),
+            Value::Integer(i64::MIN),
+            Value::Float(-0.0),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Float(1.5),
+            Value::Boolean(true),
+            Value::Boolean(false),
+            Value::from("hello"),
+        ];
+        for value in values {
+            let packed = PackedValue::from(value.clone());
+            assert_eq!(packed.get_type(), value.get_type());
+            assert_eq!(packed.clone().to_value(), value);
+        }
+
+        let nan = PackedValue::from(Value::Float(-f64::NAN));
+        assert!(nan.as_float().is_some_and(f64::is_nan));
+        assert_eq!(PackedValue::integer(1 << 50).as_integer(), Some(1 << 50));
+        assert_eq!(PackedValue::boolean(true).as_integer(), None);
+    }
+}
@@ -1,40 +1,466 @@
 //! Parser - converts tokens into Abstract Syntax Tree using recursive descent
 
-use crate::ast::lexer::Token;
+use crate::ast::interner::Atom;
+use crate::ast::lexer::{TextSpan, Token};
 use crate::ast::ASTBinaryOperator;
 use crate::ast::ASTBinaryOperatorKind;
 use crate::ast::ASTUnaryOperator;
 use crate::ast::ASTUnaryOperatorKind;
-use crate::ast::{ASTStatement, ASTExpression, ASTVariableDeclaration, ASTAssignment, ASTFunctionCallExpression};
+use crate::ast::{ASTStatement, ASTStatementKind, ASTExpression, ASTExpressionKind, ASTVariableDeclaration, ASTAssignment, ASTImportStatement, ASTForStatement, ASTForPattern, ASTWhileStatement, ASTLoopControl, LoopControlKind, ASTIfStatement, ASTIfArm, ASTWithStatement, ASTGuardStatement, ASTMatchStatement, ASTMatchArm, ASTPattern};
 use crate::ast::lexer::TokenKind;
+use crate::ast::diagnostics::{self, Diagnostic};
+use crate::ast::lexer::Lexer;
+use crate::ast::literals::LiteralPool;
+use crate::ast::transform::{walk_expression, walk_statement, AstTransformer};
+use crate::ast::Ast;
+use crate::ast::types::Value;
+use crate::stream::ReaderLexer;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::ops::Range;
 
-/// Converts token stream into AST using recursive descent with precedence climbing
-pub struct Parser {
-    tokens: Vec<crate::ast::lexer::Token>,
-    current: usize,
+/// Maximum nesting of sub-expressions before the parser gives up instead of overflowing the stack
+const MAX_NESTING_DEPTH: usize = 256;
+
+/// `|>` binds more loosely than every binary operator (see `ASTBinaryOperator::precedence`),
+/// so `a + b |> f` passes the sum to `f`
+const PIPE_PRECEDENCE: u8 = 0;
+
+/// Tokens the parser keeps ahead of the current position (assignments need one extra)
+const LOOKAHEAD: usize = 2;
+
+/// A token waiting to be parsed, with the text of any `///` comments before it
+#[derive(Clone)]
+struct BufferedToken {
+    token: Token,
+    doc: Option<String>,
+}
+
+/// Where the parser pulls tokens from. A lexer also supplies the source text, for
+/// locating bad characters, and its own diagnostics; a token list may come with its text.
+/// A reader is lexed as it is read, and locates offsets itself.
+enum TokenSource<'a> {
+    Lexer(Lexer<'a>),
+    Tokens(Box<dyn Iterator<Item = Token> + 'a>, &'a str),
+    Reader(ReaderLexer<'a>),
+}
+
+/// A saved parser position from `Parser::checkpoint`, to return to with
+/// `Parser::rollback` or to let go of with `Parser::commit`
+#[must_use]
+pub struct Checkpoint {
+    /// Number of tokens in `replay` when it was taken
+    consumed: usize,
+    /// Number of entries in `lexed` when it was taken
+    lexed: usize,
+    diagnostics: usize,
+    previous: Option<Token>,
+    depth: usize,
+    ran_out_of_input: bool,
+    loop_labels: usize,
+    block_depth: usize,
 }
 
-impl Parser {
+/// Moves every span in a reused statement by the bytes an earlier edit added (or, when
+/// negative, removed)
+struct ShiftSpans(isize);
+
+impl ShiftSpans {
+    fn shift(&self, offset: usize) -> usize {
+        offset.saturating_add_signed(self.0)
+    }
+}
+
+impl AstTransformer for ShiftSpans {
+    fn transform_statement(&mut self, statement: &mut ASTStatement) {
+        statement.range = statement.range.as_ref().map(|range| self.shift(range.start)..self.shift(range.end));
+        if let ASTStatementKind::Guard(guard) = &mut statement.kind {
+            guard.keyword.span.start = self.shift(guard.keyword.span.start);
+            guard.keyword.span.end = self.shift(guard.keyword.span.end);
+        }
+        walk_statement(self, statement);
+    }
+
+    fn transform_expression(&mut self, expression: &mut ASTExpression) {
+        let token = match &mut expression.kind {
+            ASTExpressionKind::Binary(binary) => Some(&mut binary.operator.token),
+            ASTExpressionKind::Unary(unary) => Some(&mut unary.operator.token),
+            _ => None,
+        };
+        if let Some(token) = token {
+            token.span.start = self.shift(token.span.start);
+            token.span.end = self.shift(token.span.end);
+        }
+        walk_expression(self, expression);
+    }
+}
+
+/// Converts token stream into AST using recursive descent with precedence climbing.
+///
+/// Tokens are pulled from the source on demand, so only a small window of them is held
+/// in memory at once. A rule that cannot tell what it is looking at from the next couple
+/// of tokens can parse speculatively: take a `checkpoint`, try one reading, and `rollback`
+/// to try another. Consumed tokens are kept only while a checkpoint is open.
+pub struct Parser<'a> {
+    source: TokenSource<'a>,
+    /// The current token followed by up to `LOOKAHEAD - 1` more
+    lookahead: VecDeque<BufferedToken>,
+    previous: Option<Token>,
+    depth: usize,
+    /// Set when a parse error happened because the tokens ran out
+    ran_out_of_input: bool,
+    /// Set once the `EOF` token is in `lookahead`; nothing after it is pulled
+    ended: bool,
+    /// Line number of the first line of the source
+    first_line: usize,
+    /// Labels of the loops enclosing the current statement, innermost last
+    loop_labels: Vec<Option<Atom>>,
+    /// Number of `{ }` blocks enclosing the current statement
+    block_depth: usize,
+    /// Shares one allocation between repeats of a string or bytes literal
+    literals: LiteralPool,
+    /// Number of checkpoints not yet rolled back or committed
+    checkpoints: usize,
+    /// Tokens consumed since the oldest open checkpoint, for a rollback to put back
+    replay: Vec<BufferedToken>,
+    /// Diagnostics about the tokens pulled since the oldest open checkpoint. They belong to
+    /// the tokens rather than to a reading of them, so a rollback keeps them.
+    lexed: Vec<Diagnostic>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses tokens produced by the lexer
+    pub fn from_lexer(lexer: Lexer<'a>) -> Self {
+        Self::from_lexer_at_line(lexer, 1)
+    }
+
+    /// Parses source that starts partway into a file, numbering lines in diagnostics from
+    /// `first_line`
+    pub fn from_lexer_at_line(lexer: Lexer<'a>, first_line: usize) -> Self {
+        Self::with_source(TokenSource::Lexer(lexer), first_line)
+    }
+
+    /// Parses a program as it is read, holding only the text of the lines not yet parsed
+    pub fn from_reader(reader: impl BufRead + 'a) -> Self {
+        Self::with_source(TokenSource::Reader(ReaderLexer::new(reader)), 1)
+    }
+
+    /// Parses an already collected token list
     pub fn new(
         tokens: Vec<Token>,
     ) -> Self {
-        Parser {
-            tokens: tokens.iter().filter(|token| token.kind != TokenKind::Whitespace).cloned().collect(),
-            current: 0,
+        Self::from_tokens(tokens)
+    }
+
+    pub fn from_tokens(tokens: impl IntoIterator<Item = Token> + 'a) -> Self {
+        Self::with_source(TokenSource::Tokens(Box::new(tokens.into_iter()), ""), 1)
+    }
+
+    /// Parses tokens already lexed from `input`, for callers that lex separately (to time
+    /// it, say). The lexer's own diagnostics are not included.
+    pub fn from_lexed(tokens: Vec<Token>, input: &'a str) -> Self {
+        Self::with_source(TokenSource::Tokens(Box::new(tokens.into_iter()), input), 1)
+    }
+
+    fn with_source(source: TokenSource<'a>, first_line: usize) -> Self {
+        let mut parser = Parser {
+            source,
+            lookahead: VecDeque::with_capacity(LOOKAHEAD),
+            previous: None,
+            depth: 0,
+            ran_out_of_input: false,
+            ended: false,
+            first_line,
+            loop_labels: Vec::new(),
+            block_depth: 0,
+            literals: LiteralPool::new(),
+            checkpoints: 0,
+            replay: Vec::new(),
+            lexed: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        parser.fill_lookahead();
+        parser
+    }
+
+    /// The source text, or `""` when parsing a bare token stream or a reader
+    fn text(&self) -> &'a str {
+        match &self.source {
+            TokenSource::Lexer(lexer) => lexer.input,
+            TokenSource::Tokens(_, input) => input,
+            TokenSource::Reader(_) => "",
+        }
+    }
+
+    /// The next token from the source, collecting any lexer diagnostics
+    fn next_token(&mut self) -> Option<Token> {
+        match &mut self.source {
+            TokenSource::Lexer(lexer) => {
+                let token = lexer.next_token();
+                self.diagnostics.append(&mut lexer.diagnostics);
+                token
+            }
+            TokenSource::Tokens(tokens, _) => tokens.next(),
+            TokenSource::Reader(reader) => {
+                let token = reader.next_token();
+                self.diagnostics.append(&mut reader.diagnostics);
+                token
+            }
+        }
+    }
+
+    /// Pulls tokens until the lookahead window is full or the `EOF` token is in it.
+    ///
+    /// Whitespace (present when tokens come from a raw lexer) is dropped, and doc comments
+    /// are lifted out of the stream (attached to the token they precede) so they never
+    /// interrupt expressions. A token list that stops without an `EOF` gets one where its
+    /// last token ended, so the parser always has a current token.
+    fn fill_lookahead(&mut self) {
+        let reported = self.diagnostics.len();
+        let mut doc: Vec<String> = Vec::new();
+        while self.lookahead.len() < LOOKAHEAD && !self.ended {
+            let token = match self.next_token() {
+                Some(token) => token,
+                None => {
+                    let end = self.lookahead.back().map(|buffered| &buffered.token).or(self.previous.as_ref());
+                    let end = end.map_or(self.text().len(), |token| token.span.end);
+                    Token::new(TokenKind::EOF, TextSpan::new(end, end, String::new()))
+                }
+            };
+            self.ended = token.kind == TokenKind::EOF;
+            match token.kind {
+                TokenKind::Whitespace => {}
+                TokenKind::DocComment(text) => doc.push(text),
+                TokenKind::Bad => {
+                    // Reported here, once; parse errors at the token itself are suppressed
+                    let message = format!("unexpected character '{}'{}", token.span.literal, self.location(token.span.start));
+                    self.diagnostics.push(Diagnostic::error(message, Some(token.span.clone())));
+                    self.lookahead.push_back(BufferedToken { token, doc: None });
+                }
+                _ => {
+                    // Doc comments at the very end of the input go on the EOF token, where
+                    // nothing reads them since they have nothing to document
+                    let doc = if doc.is_empty() { None } else { Some(doc.join("\n")) };
+                    self.lookahead.push_back(BufferedToken { token, doc });
+                }
+            }
+        }
+        // Lexer errors raised while a checkpoint is open are recorded as well, so a
+        // `rollback` that drops the parse errors since the checkpoint restores them
+        if self.checkpoints > 0 {
+            self.lexed.extend_from_slice(&self.diagnostics[reported..]);
+        }
+    }
+
+    /// Returns true once every token up to EOF has been consumed
+    pub fn is_at_end(&self) -> bool {
+        self.current().is_none_or(|token| token.kind == TokenKind::EOF)
+    }
+
+    /// " at line 2, column 7" for a byte offset into the source, or nothing when the source
+    /// text is unknown
+    fn location(&self, offset: usize) -> String {
+        let (line, column) = match &self.source {
+            TokenSource::Reader(reader) => reader.line_column(offset),
+            _ if self.text().is_empty() => return String::new(),
+            _ => diagnostics::line_column(self.text(), offset),
+        };
+        format!(" at line {}, column {}", line + self.first_line - 1, column)
+    }
+
+    /// Records a parse error at the current token
+    fn error(&mut self, message: String) {
+        if self.is_at_end() {
+            self.ran_out_of_input = true;
+        }
+        if self.current().is_some_and(|token| token.kind == TokenKind::Bad) {
+            return;
         }
+        let span = self.current().map(|token| token.span.clone());
+        self.diagnostics.push(Diagnostic::error(message, span));
+    }
+
+    /// Returns true if parsing failed only because the input ended mid-statement,
+    /// meaning more input could still complete it
+    pub fn is_incomplete(&self) -> bool {
+        self.ran_out_of_input
     }
 
-    pub fn from_tokens(tokens: Vec<Token>) -> Self {
-        Parser {
-            tokens,
-            current: 0,
+    /// Skips a single token, used to resynchronize after a parse error
+    pub fn skip_token(&mut self) {
+        if !self.is_at_end() {
+            self.consume();
         }
     }
 
     
 
+    /// Parses one statement and its terminator. A statement ends at a `;`, a line break or
+    /// the end of input; any other token after it is an error.
     pub fn next_statement(&mut self) -> Option<ASTStatement>{
-        return self.parse_statement();
+        self.next_statement_ending(Self::end_statement)
+    }
+
+    /// Parses a statement followed by the separator `end` checks for
+    fn next_statement_ending(&mut self, end: fn(&mut Self) -> bool) -> Option<ASTStatement> {
+        let start = self.current().map(|token| token.span.start);
+        let mut statement = self.parse_statement()?;
+        if !end(self) {
+            return None;
+        }
+        let end = self.previous().map(|token| token.span.end);
+        if let (Some(start), Some(end)) = (start, end) {
+            statement.range = Some(start..end);
+        }
+        Some(statement)
+    }
+
+    /// Re-parses only the statements touched by an edit, reusing every other node.
+    ///
+    /// `edit_range` is the replaced byte range in the old source, `new_text` what replaced
+    /// it, and `source` the full text after the edit. Parsing starts at the statement
+    /// before the edit (the edit may extend it) and stops at the first unchanged statement
+    /// that still parses with the same extent; later statements are kept, with their
+    /// ranges and every span inside them shifted. Offsets and line numbers in the
+    /// statements and diagnostics are the same as a full parse of `source` gives.
+    pub fn reparse(old_ast: Ast, edit_range: Range<usize>, new_text: &str, source: &str) -> (Ast, Vec<Diagnostic>) {
+        let mut old = old_ast.statements;
+        let ranges: Option<Vec<Range<usize>>> = old.iter().map(|statement| statement.range.clone()).collect();
+        let ranges = match ranges {
+            Some(ranges) => ranges,
+            None => return Self::parse_region(source, 0..source.len()),
+        };
+        let delta = new_text.len() as isize - edit_range.len() as isize;
+        let shift = |offset: usize| (offset as isize + delta) as usize;
+
+        // The first statement to re-parse is the one just before the edit
+        let first = ranges.iter().position(|range| range.end >= edit_range.start).unwrap_or(ranges.len()).saturating_sub(1);
+        let start = if first == 0 { 0 } else { ranges[first - 1].end };
+        let after = ranges.iter().position(|range| range.start > edit_range.end).unwrap_or(ranges.len());
+
+        // Grow the region one unchanged statement at a time until its boundary holds
+        for (resync, range) in ranges.iter().enumerate().skip(after) {
+            let expected = shift(range.start)..shift(range.end);
+            let (mut region, diagnostics) = Self::parse_region(source, start..expected.end);
+            if region.statements.last().and_then(|statement| statement.range.clone()) != Some(expected) {
+                continue;
+            }
+
+            let mut tail = old.split_off(resync + 1);
+            old.truncate(first);
+            old.append(&mut region.statements);
+            for statement in &mut tail {
+                ShiftSpans(delta).transform_statement(statement);
+            }
+            old.append(&mut tail);
+            return (Ast { statements: old }, diagnostics);
+        }
+
+        let (mut region, diagnostics) = Self::parse_region(source, start..source.len());
+        old.truncate(first);
+        old.append(&mut region.statements);
+        (Ast { statements: old }, diagnostics)
+    }
+
+    /// Parses a byte range of `source`. The lexer starts partway into the text, so spans
+    /// and the locations in diagnostics are those of the whole source.
+    fn parse_region(source: &str, range: Range<usize>) -> (Ast, Vec<Diagnostic>) {
+        let mut lexer = Lexer::new(source.get(..range.end).unwrap_or(source));
+        lexer.current_pos = range.start;
+        let mut parser = Parser::from_lexer(lexer);
+        let mut ast = Ast::new();
+        for statement in parser.parse_all() {
+            ast.add_statement(statement);
+        }
+        (ast, parser.diagnostics)
+    }
+
+    /// Consumes the `;` ending a statement, or checks that the next token starts a new line
+    fn end_statement(&mut self) -> bool {
+        let Some(token) = self.current() else {
+            return true;
+        };
+        match token.kind {
+            TokenKind::Semicolon => {
+                self.consume();
+                true
+            }
+            // The last statement of a block needs no separator before its '}'
+            TokenKind::EOF | TokenKind::RightBrace => true,
+            _ if token.newline_before => true,
+            _ => {
+                self.error(format!("Unexpected {} after statement, expected ';' or a new line", token.kind));
+                false
+            }
+        }
+    }
+
+    /// Like `end_statement`, but a ',' may also end a `match` arm
+    fn end_match_arm(&mut self) -> bool {
+        let Some(token) = self.current() else {
+            return true;
+        };
+        match token.kind {
+            TokenKind::Comma => {
+                self.consume();
+                true
+            }
+            TokenKind::Semicolon | TokenKind::EOF | TokenKind::RightBrace => self.end_statement(),
+            _ if token.newline_before => true,
+            _ => {
+                self.error(format!("Unexpected {} after the 'match' arm, expected ',', ';' or a new line", token.kind));
+                false
+            }
+        }
+    }
+
+    /// Parses statements until EOF, skipping to the next statement boundary after each
+    /// parse error so later statements are still found; errors are left in `diagnostics`
+    pub fn parse_all(&mut self) -> Vec<ASTStatement> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            statements.extend(self.next_statement_or_skip());
+        }
+        statements
+    }
+
+    /// Parses the next statement like `next_statement`, skipping past the rest of it if it
+    /// fails to parse so the parser is ready for the one after
+    pub fn next_statement_or_skip(&mut self) -> Option<ASTStatement> {
+        let start = self.current().map(|token| token.span.start);
+        let statement = self.next_statement();
+        if statement.is_none() {
+            self.synchronize(start);
+        }
+        statement
+    }
+
+    /// Lets go of the pooled literals, so statements already returned hold the only copies
+    pub(crate) fn release_literals(&mut self) {
+        self.literals = LiteralPool::new();
+    }
+
+    /// Skips past the rest of a statement that failed to parse: through the next `;`, or up
+    /// to the first token on a new line. `start` is where the statement began; a token is
+    /// always skipped if nothing was consumed since.
+    fn synchronize(&mut self, start: Option<usize>) {
+        if self.current().map(|token| token.span.start) == start {
+            self.skip_token();
+        }
+        while let Some(token) = self.current() {
+            match token.kind {
+                TokenKind::EOF => break,
+                TokenKind::Semicolon => {
+                    self.consume();
+                    break;
+                }
+                _ if token.newline_before => break,
+                _ => {
+                    self.consume();
+                }
+            }
+        }
     }
 
     /// Parses a statement (variable declaration, assignment, or expression)
@@ -48,173 +474,666 @@ impl Parser {
         if matches!(token.kind, TokenKind::Let | TokenKind::Const) {
             return self.parse_variable_declaration();
         }
+
+        if token.kind == TokenKind::Import {
+            return self.parse_import();
+        }
+
+        if token.kind == TokenKind::For {
+            return self.parse_for(None);
+        }
+
+        if token.kind == TokenKind::While {
+            return self.parse_while(None);
+        }
+
+        if matches!(token.kind, TokenKind::Break | TokenKind::Continue) {
+            return self.parse_loop_control();
+        }
+
+        if token.kind == TokenKind::If {
+            return self.parse_if();
+        }
+
+        if token.kind == TokenKind::Defer {
+            return self.parse_defer();
+        }
+
+        if token.kind == TokenKind::Guard {
+            return self.parse_guard();
+        }
+
+        if token.kind == TokenKind::With {
+            return self.parse_with();
+        }
+
+        if token.kind == TokenKind::Match {
+            return self.parse_match();
+        }
+
+        if token.kind == TokenKind::Else {
+            self.error("'else' without a preceding 'if'; put 'else' on the same line as the '}' closing the 'if' block".to_string());
+            return None;
+        }
         
         // Check for assignment - needs lookahead to distinguish from identifier expression
-        if let TokenKind::Identifier(_) = token.kind {
-            if self.peek(1).map(|t| &t.kind) == Some(&TokenKind::Equal) {
-                return self.parse_assignment();
+        if let TokenKind::Identifier(name) = token.kind {
+            match self.peek(1).map(|t| &t.kind) {
+                Some(TokenKind::Equal) => return self.parse_assignment(),
+                Some(TokenKind::Colon) => return self.parse_labeled_loop(name),
+                _ => {}
             }
         }
         
-        // Otherwise, parse as expression statement
-        let expr = self.parse_expression()?;
-        
-        // Consume optional semicolon
-        if self.current().map(|t| &t.kind) == Some(&TokenKind::Semicolon) {
-            self.consume();
+        // Otherwise, parse as expression statement, unless it turns out to be the target of
+        // an element assignment such as `grid[y][x] = 1`
+        let expr = self.parse_binary_expression(0)?;
+        if self.current().map(|t| &t.kind) == Some(&TokenKind::Equal) {
+            return self.parse_element_assignment(expr);
         }
         
-        return Some(ASTStatement::expression(expr));
+        Some(ASTStatement::expression(expr))
     }
 
     /// Parses 'let' or 'const' variable declarations
     pub fn parse_variable_declaration(&mut self) -> Option<ASTStatement> {
+        let doc = self.lookahead.front().and_then(|buffered| buffered.doc.clone());
         let keyword = self.consume()?;
         let is_mutable = keyword.kind == TokenKind::Let;
         
         // Expect identifier
-        let name_token = self.consume()?;
-        let name = match name_token.kind {
-            TokenKind::Identifier(ref n) => n.clone(),
+        let name = match self.current()?.kind {
+            TokenKind::Identifier(n) => n,
             _ => {
-                eprintln!("Expected identifier after '{}' keyword", 
-                    if is_mutable { "let" } else { "const" });
+                self.error(format!("Expected identifier after '{}' keyword",
+                    if is_mutable { "let" } else { "const" }));
                 return None;
             }
         };
+        self.consume();
         
         // Expect '='
-        if self.consume()?.kind != TokenKind::Equal {
-            eprintln!("Expected '=' after variable name");
+        if self.current()?.kind != TokenKind::Equal {
+            self.error("Expected '=' after variable name".to_string());
             return None;
         }
+        self.consume();
         
         // Parse initializer expression
         let initializer = self.parse_expression()?;
         
-        // Consume optional semicolon
-        if self.current().map(|t| &t.kind) == Some(&TokenKind::Semicolon) {
+        let mut declaration = ASTVariableDeclaration::new(name, initializer, is_mutable);
+        declaration.doc = doc;
+        Some(ASTStatement::variable_declaration(declaration))
+    }
+
+    /// Parses `import "path"` statements
+    pub fn parse_import(&mut self) -> Option<ASTStatement> {
+        self.consume()?; // consume 'import'
+
+        let path = match self.current().map(|t| t.kind.clone()) {
+            Some(TokenKind::String(path)) => path,
+            _ => {
+                self.error("Expected a string path after 'import'".to_string());
+                return None;
+            }
+        };
+        self.consume();
+
+        Some(ASTStatement::import(ASTImportStatement::new(path)))
+    }
+
+    /// Parses `if cond { ... }` followed by any number of `else if cond { ... }` and an
+    /// optional `else { ... }`. The chain is collected in a loop, not by recursion.
+    fn parse_if(&mut self) -> Option<ASTStatement> {
+        self.consume(); // consume 'if'
+        let mut arms = Vec::new();
+        loop {
+            let condition = self.parse_expression()?;
+            let body = self.parse_block()?;
+            arms.push(ASTIfArm::new(condition, body));
+
+            if self.current().map(|t| &t.kind) != Some(&TokenKind::Else) {
+                return Some(ASTStatement::if_statement(ASTIfStatement::new(arms, None)));
+            }
+            self.consume(); // consume 'else'
+            if self.current().map(|t| &t.kind) != Some(&TokenKind::If) {
+                let else_body = self.parse_block()?;
+                return Some(ASTStatement::if_statement(ASTIfStatement::new(arms, Some(else_body))));
+            }
+            self.consume(); // consume 'if'
+        }
+    }
+
+    /// Parses `guard cond else { ... }`, whose block runs when `cond` is false and must
+    /// leave the enclosing loop iteration. The desugar pass lowers it to an `if`.
+    fn parse_guard(&mut self) -> Option<ASTStatement> {
+        let keyword = self.consume()?.clone();
+        let condition = self.parse_expression()?;
+        if self.current().map(|t| &t.kind) != Some(&TokenKind::Else) {
+            self.error("Expected 'else' after the 'guard' condition".to_string());
+            return None;
+        }
+        self.consume(); // consume 'else'
+        let body = self.parse_block()?;
+        if !diverges(&body) {
+            self.error("The 'else' block of a 'guard' must end with 'break' or 'continue'".to_string());
+            return None;
+        }
+        Some(ASTStatement::guard(ASTGuardStatement::new(condition, body, keyword)))
+    }
+
+    /// Parses `with resource as name { ... }`
+    fn parse_with(&mut self) -> Option<ASTStatement> {
+        self.consume(); // consume 'with'
+        let resource = self.parse_expression()?;
+        if self.current().map(|t| &t.kind) != Some(&TokenKind::As) {
+            self.error("Expected 'as' after the 'with' resource".to_string());
+            return None;
+        }
+        self.consume(); // consume 'as'
+        let name = match self.current().map(|t| &t.kind) {
+            Some(TokenKind::Identifier(name)) => *name,
+            _ => {
+                self.error("Expected a variable name after 'as'".to_string());
+                return None;
+            }
+        };
+        self.consume();
+        let body = self.parse_block()?;
+        Some(ASTStatement::with_statement(ASTWithStatement::new(resource, name, body)))
+    }
+
+    /// Parses `match value { ... }`. Each arm is a pattern, an optional `if` guard, `=>` and
+    /// either a block or a single statement.
+    fn parse_match(&mut self) -> Option<ASTStatement> {
+        self.consume(); // consume 'match'
+        let subject = self.parse_expression()?;
+        if self.current().map(|t| &t.kind) != Some(&TokenKind::LeftBrace) {
+            self.error("Expected '{' after the 'match' value".to_string());
+            return None;
+        }
+        // The arms nest statements, so they count towards the nesting limit like a block
+        if !self.enter_nesting() {
+            return None;
+        }
+        self.consume();
+        let arms = self.parse_match_arms();
+        self.depth -= 1;
+        Some(ASTStatement::match_statement(ASTMatchStatement::new(subject, arms?)))
+    }
+
+    /// Parses the arms of a `match` up to and including its '}'
+    fn parse_match_arms(&mut self) -> Option<Vec<ASTMatchArm>> {
+        let mut arms = Vec::new();
+        loop {
+            match self.current().map(|t| &t.kind) {
+                Some(TokenKind::RightBrace) => {
+                    self.consume();
+                    return Some(arms);
+                }
+                Some(TokenKind::Semicolon) => {
+                    self.consume();
+                    continue;
+                }
+                None | Some(TokenKind::EOF) => {
+                    self.error("Expected '}' to close the 'match'".to_string());
+                    return None;
+                }
+                Some(_) => {}
+            }
+            let pattern = self.parse_pattern()?;
+            let names = pattern.names();
+            if let Some(name) = names.iter().enumerate().find_map(|(i, name)| names[..i].contains(name).then_some(name)) {
+                self.error(format!("'{}' is bound more than once in the pattern", name));
+                return None;
+            }
+            let guard = if self.current().map(|t| &t.kind) == Some(&TokenKind::If) {
+                self.consume();
+                Some(self.parse_expression()?)
+            } else {
+                None
+            };
+            if self.current().map(|t| &t.kind) != Some(&TokenKind::FatArrow) {
+                self.error("Expected '=>' after the 'match' pattern".to_string());
+                return None;
+            }
             self.consume();
+            let body = if self.current().map(|t| &t.kind) == Some(&TokenKind::LeftBrace) {
+                let body = self.parse_block()?;
+                if !self.end_match_arm() {
+                    return None;
+                }
+                body
+            } else {
+                vec![self.next_statement_ending(Self::end_match_arm)?]
+            };
+            arms.push(ASTMatchArm::new(pattern, guard, body));
         }
-        
-        Some(ASTStatement::variable_declaration(
-            ASTVariableDeclaration::new(name, initializer, is_mutable)
-        ))
+    }
+
+    /// Parses a `match` pattern: `_`, a name, a literal, or `(p, ...)` or `[p, ...]` to
+    /// unpack an array
+    fn parse_pattern(&mut self) -> Option<ASTPattern> {
+        let kind = self.current()?.kind.clone();
+        let pattern = match kind {
+            TokenKind::Identifier(name) if name.as_str() == "_" => ASTPattern::Wildcard,
+            TokenKind::Identifier(name) => ASTPattern::Binding(name),
+            TokenKind::Number(number) => ASTPattern::Literal(Value::Integer(number)),
+            TokenKind::Float(float) => ASTPattern::Literal(Value::Float(float)),
+            TokenKind::Boolean(boolean) => ASTPattern::Literal(Value::Boolean(boolean)),
+            TokenKind::String(string) => ASTPattern::Literal(Value::String(self.literals.string(string))),
+            TokenKind::Bytes(bytes) => ASTPattern::Literal(Value::Bytes(self.literals.bytes(bytes))),
+            TokenKind::Minus => {
+                self.consume();
+                let value = match self.current().map(|t| &t.kind) {
+                    Some(TokenKind::Number(number)) => Value::Integer(-number),
+                    Some(TokenKind::Float(float)) => Value::Float(-float),
+                    _ => {
+                        self.error("Expected a number after '-' in the pattern".to_string());
+                        return None;
+                    }
+                };
+                ASTPattern::Literal(value)
+            }
+            TokenKind::LeftParen | TokenKind::LeftBracket => {
+                if !self.enter_nesting() {
+                    return None;
+                }
+                self.consume();
+                let close = if kind == TokenKind::LeftParen { TokenKind::RightParen } else { TokenKind::RightBracket };
+                let items = self.parse_tuple_pattern(close);
+                self.depth -= 1;
+                return items.map(ASTPattern::Tuple);
+            }
+            _ => {
+                self.error("Expected a pattern: '_', a name, a literal, '(' or '['".to_string());
+                return None;
+            }
+        };
+        self.consume();
+        Some(pattern)
+    }
+
+    /// Parses the items of a `(p, ...)` or `[p, ...]` pattern whose opening bracket has
+    /// been consumed, up to and including `close`
+    fn parse_tuple_pattern(&mut self, close: TokenKind) -> Option<Vec<ASTPattern>> {
+        let mut items = Vec::new();
+        loop {
+            items.push(self.parse_pattern()?);
+            match self.current().map(|t| &t.kind) {
+                Some(TokenKind::Comma) => {
+                    self.consume();
+                }
+                Some(kind) if *kind == close => {
+                    self.consume();
+                    return Some(items);
+                }
+                _ => {
+                    self.error(format!("Expected ',' or {} in the pattern", close));
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Parses `defer expr`, which is only meaningful inside a block
+    fn parse_defer(&mut self) -> Option<ASTStatement> {
+        if self.block_depth == 0 {
+            self.error("'defer' outside of a block; it runs when its enclosing block exits".to_string());
+            return None;
+        }
+        self.consume(); // consume 'defer'
+        let expr = self.parse_expression()?;
+        Some(ASTStatement::defer(expr))
+    }
+
+    /// Parses `label: for ...` or `label: while ...`, the only statements that can carry a label
+    fn parse_labeled_loop(&mut self, label: Atom) -> Option<ASTStatement> {
+        self.consume(); // consume the label
+        self.consume(); // consume ':'
+        match self.current().map(|t| &t.kind) {
+            Some(TokenKind::For) => self.parse_for(Some(label)),
+            Some(TokenKind::While) => self.parse_while(Some(label)),
+            _ => {
+                self.error(format!("Expected a 'for' or 'while' loop after the label '{}:'", label));
+                None
+            }
+        }
+    }
+
+    /// Parses `break` or `continue` with an optional loop label on the same line, checking
+    /// that a loop, or the labeled loop, encloses it
+    fn parse_loop_control(&mut self) -> Option<ASTStatement> {
+        let keyword = self.consume()?.clone();
+        let kind = if keyword.kind == TokenKind::Break { LoopControlKind::Break } else { LoopControlKind::Continue };
+        let label = match self.current() {
+            Some(Token { kind: TokenKind::Identifier(label), newline_before: false, .. }) => Some(*label),
+            _ => None,
+        };
+        if self.loop_labels.is_empty() {
+            self.error(format!("'{}' outside of a loop", keyword.span.literal));
+            return None;
+        }
+        if let Some(label) = label {
+            if !self.loop_labels.contains(&Some(label)) {
+                self.error(format!("No enclosing loop is labeled '{}'", label));
+                return None;
+            }
+            self.consume();
+        }
+        Some(ASTStatement::loop_control(ASTLoopControl::new(kind, label)))
+    }
+
+    /// Parses `for x in iterable { ... }` and `for (k, v) in iterable { ... }`
+    pub fn parse_for(&mut self, label: Option<Atom>) -> Option<ASTStatement> {
+        self.consume()?; // consume 'for'
+
+        let pattern = match self.current().map(|t| t.kind.clone()) {
+            Some(TokenKind::Identifier(name)) => {
+                self.consume();
+                ASTForPattern::Name(name)
+            }
+            Some(TokenKind::LeftParen) => {
+                self.consume();
+                let mut names = Vec::new();
+                loop {
+                    match self.current().map(|t| t.kind.clone()) {
+                        Some(TokenKind::Identifier(name)) => names.push(name),
+                        _ => {
+                            self.error("Expected a variable name in the loop pattern".to_string());
+                            return None;
+                        }
+                    }
+                    self.consume();
+                    match self.current().map(|t| &t.kind) {
+                        Some(TokenKind::Comma) => {
+                            self.consume();
+                        }
+                        Some(TokenKind::RightParen) => break,
+                        _ => {
+                            self.error("Expected ',' or ')' in the loop pattern".to_string());
+                            return None;
+                        }
+                    }
+                }
+                self.consume(); // consume ')'
+                ASTForPattern::Tuple(names)
+            }
+            _ => {
+                self.error("Expected a loop variable or '(' after 'for'".to_string());
+                return None;
+            }
+        };
+
+        if self.current().map(|t| &t.kind) != Some(&TokenKind::In) {
+            self.error("Expected 'in' after the loop variable".to_string());
+            return None;
+        }
+        self.consume();
+
+        let iterable = self.parse_expression()?;
+        self.loop_labels.push(label);
+        let body = self.parse_block();
+        self.loop_labels.pop();
+        let mut for_statement = ASTForStatement::new(pattern, iterable, body?);
+        for_statement.label = label;
+        Some(ASTStatement::for_statement(for_statement))
+    }
+
+    /// Parses `while condition { ... }`
+    fn parse_while(&mut self, label: Option<Atom>) -> Option<ASTStatement> {
+        self.consume()?; // consume 'while'
+        let condition = self.parse_expression()?;
+        self.loop_labels.push(label);
+        let body = self.parse_block();
+        self.loop_labels.pop();
+        let mut while_statement = ASTWhileStatement::new(condition, body?);
+        while_statement.label = label;
+        Some(ASTStatement::while_statement(while_statement))
+    }
+
+    /// Parses `{ statements }`. Statements inside are separated like top-level ones.
+    fn parse_block(&mut self) -> Option<Vec<ASTStatement>> {
+        if self.current().map(|t| &t.kind) != Some(&TokenKind::LeftBrace) {
+            self.error("Expected '{' to start a block".to_string());
+            return None;
+        }
+        // Blocks nest statements, so they count towards the nesting limit like expressions
+        if !self.enter_nesting() {
+            return None;
+        }
+        self.consume();
+        let result = self.parse_block_rest(Vec::new());
+        self.depth -= 1;
+        result
+    }
+
+    /// Parses the rest of a block whose '{' and first `statements` have been consumed,
+    /// up to and including its '}'
+    fn parse_block_rest(&mut self, mut statements: Vec<ASTStatement>) -> Option<Vec<ASTStatement>> {
+        self.block_depth += 1;
+        let result = loop {
+            match self.current().map(|t| &t.kind) {
+                Some(TokenKind::RightBrace) => {
+                    self.consume();
+                    break Some(statements);
+                }
+                Some(TokenKind::Semicolon) => {
+                    self.consume();
+                }
+                None | Some(TokenKind::EOF) => {
+                    self.error("Expected '}' to close the block".to_string());
+                    break None;
+                }
+                Some(_) => match self.next_statement() {
+                    Some(statement) => statements.push(statement),
+                    None => break None,
+                },
+            }
+        };
+        self.block_depth -= 1;
+        result
+    }
+
+    /// Parses the rest of a block used as a value, such as `{ let t = a + b; t * 2 }`.
+    /// Loops outside the block cannot be left from inside it, so a `break` or `continue`
+    /// in it must target a loop that is also in it.
+    fn parse_block_expression(&mut self, statements: Vec<ASTStatement>) -> Option<ASTExpression> {
+        let loop_labels = std::mem::take(&mut self.loop_labels);
+        let statements = self.parse_block_rest(statements);
+        self.loop_labels = loop_labels;
+        let statements = statements?;
+        if !matches!(statements.last().map(|statement| &statement.kind), Some(ASTStatementKind::Expression(_))) {
+            self.error("A block used as a value must end with an expression".to_string());
+            return None;
+        }
+        Some(ASTExpression::block(statements))
     }
 
     /// Parses assignment statements (identifier = expression)
     pub fn parse_assignment(&mut self) -> Option<ASTStatement> {
         let name_token = self.consume()?;
         let name = match &name_token.kind {
-            TokenKind::Identifier(n) => n.clone(),
+            TokenKind::Identifier(n) => *n,
             _ => return None,
         };
         
         // Consume '='
-        if self.consume()?.kind != TokenKind::Equal {
-            eprintln!("Expected '=' in assignment");
+        if self.current()?.kind != TokenKind::Equal {
+            self.error("Expected '=' in assignment".to_string());
             return None;
         }
+        self.consume();
         
         // Parse value expression
         let value = self.parse_expression()?;
         
-        // Consume optional semicolon
-        if self.current().map(|t| &t.kind) == Some(&TokenKind::Semicolon) {
-            self.consume();
-        }
-        
         Some(ASTStatement::assignment(ASTAssignment::new(name, value)))
     }
 
+    /// Parses `= value` after an indexed variable, which the statement has already parsed
+    fn parse_element_assignment(&mut self, target: ASTExpression) -> Option<ASTStatement> {
+        let mut indices = Vec::new();
+        let mut target = target;
+        let name = loop {
+            match target.kind {
+                ASTExpressionKind::Index(index) => {
+                    indices.push(*index.index);
+                    target = *index.object;
+                }
+                ASTExpressionKind::Identifier(ident) if !indices.is_empty() => break ident.name,
+                _ => {
+                    self.error("Assignment cannot be used as a value; did you mean '==' to compare?".to_string());
+                    return None;
+                }
+            }
+        };
+        indices.reverse();
+        self.consume(); // consume '='
+
+        let value = self.parse_expression()?;
+        Some(ASTStatement::assignment(ASTAssignment::element(name, indices, value)))
+    }
+
+    /// Parses a full expression. Assignment is a statement, not an expression, so an `=`
+    /// right after one (`a = b = 5`, `print(x = 5)`) is reported as a likely `==` typo.
     pub fn parse_expression(&mut self) -> Option<ASTExpression> {
-        return self.parse_binary_expression(0);
+        let expression = self.parse_binary_expression(0)?;
+        if self.current().map(|t| &t.kind) == Some(&TokenKind::Equal) {
+            self.error("Assignment cannot be used as a value; did you mean '==' to compare?".to_string());
+            return None;
+        }
+        Some(expression)
     }
 
     /// Parses binary expressions using operator precedence climbing
     pub fn parse_binary_expression(&mut self, precedence: u8) -> Option<ASTExpression> {
+        if !self.enter_nesting() {
+            return None;
+        }
+        let result = self.parse_binary_expression_inner(precedence);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_binary_expression_inner(&mut self, precedence: u8) -> Option<ASTExpression> {
         let mut left: ASTExpression = self.parse_primary_expression()?;
+        // Each operator in a chain deepens the (left-leaning) tree the evaluator walks
+        let mut chain_length = 0;
 
+        // Check if next token is an operator
         loop {
-            // Check if next token is an operator
-            let operator = self.parse_binary_operator();
-            let operator_precedence = match operator.as_ref().map(|op| op.precedence()) {
-                Some(op_prec) => op_prec,
-                None => break, // Not an operator, we're done
+            if precedence == PIPE_PRECEDENCE && self.current().is_some_and(|t| t.kind == TokenKind::PipeGreater) {
+                chain_length += 1;
+                if self.depth + chain_length >= MAX_NESTING_DEPTH {
+                    self.error(format!("Expression nested too deeply (limit is {})", MAX_NESTING_DEPTH));
+                    return None;
+                }
+                self.consume();
+                let target = self.parse_binary_expression(PIPE_PRECEDENCE + 1)?;
+                left = ASTExpression::pipe(left, target);
+                continue;
+            }
+            let Some(operator) = self.parse_binary_operator() else {
+                break;
             };
-            // Only consume operators with higher precedence than current level
+            let operator_precedence = operator.precedence();
+            // Only consume operators that bind at least as tightly as the current level
             if operator_precedence < precedence {
                 break;
             }
+            chain_length += 1;
+            if self.depth + chain_length >= MAX_NESTING_DEPTH {
+                self.error(format!("Expression nested too deeply (limit is {})", MAX_NESTING_DEPTH));
+                return None;
+            }
             self.consume(); // Consume the operator token
-            // Recursively parse right side with current operator's precedence
-            let right: ASTExpression = self.parse_binary_expression(operator_precedence)?;
-            left = ASTExpression::binary(operator.unwrap(), left, right);
+            // Operators of equal precedence associate left, so the right side must bind tighter;
+            // exponentiation is the one right-associative operator
+            let right_precedence = if operator.is_right_associative() {
+                operator_precedence
+            } else {
+                operator_precedence + 1
+            };
+            let right: ASTExpression = self.parse_binary_expression(right_precedence)?;
+            left = ASTExpression::binary(operator, left, right);
         }
 
-        return Some(left);
+        Some(left)
     }
 
-    /// Parses primary expressions (literals, identifiers, function calls, parenthesized expressions)
+    /// Tracks recursion depth, reporting an error once input nests too deeply
+    fn enter_nesting(&mut self) -> bool {
+        if self.depth >= MAX_NESTING_DEPTH {
+            self.error(format!("Expression nested too deeply (limit is {})", MAX_NESTING_DEPTH));
+            return false;
+        }
+        self.depth += 1;
+        true
+    }
+
+    /// Parses primary expressions (literals, identifiers, function calls, parenthesized
+    /// expressions), each followed by any member accesses and method calls
     pub fn parse_primary_expression(&mut self) -> Option<ASTExpression> {
+        if !self.enter_nesting() {
+            return None;
+        }
+        let result = self.parse_primary_expression_inner();
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_primary_expression_inner(&mut self) -> Option<ASTExpression> {
         let token: &Token = self.current()?;
         let token_kind = token.kind.clone();
         
         match token_kind {
             TokenKind::Number(number) => {
                 self.consume();
-                return Some(ASTExpression::number(number));
+                self.parse_postfix(ASTExpression::number(number))
             },
             TokenKind::Float(float) => {
                 self.consume();
-                return Some(ASTExpression::float(float));
+                self.parse_postfix(ASTExpression::float(float))
             },
             TokenKind::Boolean(boolean) => {
                 self.consume();
-                return Some(ASTExpression::boolean(boolean));
+                self.parse_postfix(ASTExpression::boolean(boolean))
             },
             TokenKind::String(string) => {
                 self.consume();
-                return Some(ASTExpression::string(string));
+                let string = self.literals.string(string);
+                self.parse_postfix(ASTExpression::literal(Value::String(string)))
+            },
+            TokenKind::Bytes(bytes) => {
+                self.consume();
+                let bytes = self.literals.bytes(bytes);
+                self.parse_postfix(ASTExpression::literal(Value::Bytes(bytes)))
             },
             TokenKind::Identifier(name) => {
                 self.consume();
-                // Check if this is a function call (identifier followed by '(')
-                if self.current().map(|t| &t.kind) == Some(&TokenKind::LeftParen) {
-                    self.consume(); // consume '('
-                    let mut arguments = Vec::new();
-                    
-                    // Parse comma-separated argument list
-                    if self.current().map(|t| &t.kind) != Some(&TokenKind::RightParen) {
-                        loop {
-                            let arg = self.parse_expression()?;
-                            arguments.push(arg);
-                            
-                            // Continue if comma found, otherwise done with arguments
-                            if self.current().map(|t| &t.kind) == Some(&TokenKind::Comma) {
-                                self.consume(); // consume ','
-                            } else {
-                                break;
-                            }
-                        }
-                    }
-                    
-                    if self.consume()?.kind != TokenKind::RightParen {
-                        panic!("Expected closing parenthesis after function arguments");
-                    }
-                    
-                    return Some(ASTExpression::function_call(name, arguments));
-                } else {
-                    return Some(ASTExpression::identifier(name));
-                }
+                self.parse_postfix(ASTExpression::identifier(name))
             },
             TokenKind::LeftParen => {
                 self.consume();
                 let expression: ASTExpression = self.parse_expression()?;
-                if self.consume()?.kind != TokenKind::RightParen {
-                    panic!("Expected right parenthesis");
+                if self.current().map(|t| &t.kind) != Some(&TokenKind::RightParen) {
+                    self.error("Expected closing parenthesis ')'".to_string());
+                    return None;
                 }
-                return Some(ASTExpression::paranthesized(expression));
+                self.consume();
+                self.parse_postfix(ASTExpression::paranthesized(expression))
+            },
+            TokenKind::LeftBracket => {
+                self.consume();
+                let elements = self.parse_list(TokenKind::RightBracket, "Expected ']' after array elements")?;
+                self.parse_postfix(ASTExpression::array(elements))
+            },
+            TokenKind::LeftBrace => {
+                self.consume();
+                let literal = self.parse_brace_literal()?;
+                self.parse_postfix(literal)
             },
             TokenKind::Plus | TokenKind::Minus | TokenKind::Bang => {
                 let operator_token = self.consume()?.clone();
@@ -226,10 +1145,221 @@ impl Parser {
                 };
                 let operator = ASTUnaryOperator::new(kind, operator_token);
                 let operand = self.parse_primary_expression()?;
-                return Some(ASTExpression::unary(operator, operand));
+                Some(ASTExpression::unary(operator, operand))
             },
-            _ => None,
+            TokenKind::EOF => {
+                let location = self.location(token.span.start);
+                self.error(format!("Unexpected end of input{}, expected expression", location));
+                None
+            },
+            _ => {
+                self.error(format!("Expected an expression, found {}", token.kind));
+                None
+            },
+        }
+    }
+
+    /// Parses a parenthesized, comma-separated argument list
+    fn parse_arguments(&mut self) -> Option<Vec<ASTExpression>> {
+        self.consume(); // consume '('
+        self.parse_list(TokenKind::RightParen, "Expected closing parenthesis after function arguments")
+    }
+
+    /// Parses comma-separated expressions up to and including `close`, whose opening
+    /// token has already been consumed. Any of them may be spread with `...`, and the last
+    /// may be followed by a comma.
+    fn parse_list(&mut self, close: TokenKind, message: &str) -> Option<Vec<ASTExpression>> {
+        let mut items = Vec::new();
+        
+        // Parse comma-separated list
+        if self.current().map(|t| &t.kind) != Some(&close) {
+            loop {
+                let item = if self.current().map(|t| &t.kind) == Some(&TokenKind::Ellipsis) {
+                    self.consume(); // consume '...'
+                    ASTExpression::spread(self.parse_expression()?)
+                } else {
+                    self.parse_expression()?
+                };
+                items.push(item);
+                
+                // Continue if comma found, otherwise done with the list
+                if self.current().map(|t| &t.kind) == Some(&TokenKind::Comma) {
+                    self.consume(); // consume ','
+                    if self.current().map(|t| &t.kind) == Some(&close) {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+            }
+        }
+        
+        if self.current().map(|t| &t.kind) != Some(&close) {
+            self.error(message.to_string());
+            return None;
+        }
+        self.consume();
+        Some(items)
+    }
+
+    /// Parses a map `{key: value, ...}`, a set `{a, b, ...}` or a block expression after
+    /// its '{'. The first entry decides which it is: a block starts with a statement that is
+    /// not an expression, or has a `;` or line break after its first expression. `{}` is an
+    /// empty map and `{x}` a set.
+    fn parse_brace_literal(&mut self) -> Option<ASTExpression> {
+        const MESSAGE: &str = "Expected '}' after set elements";
+        match self.current().map(|t| &t.kind) {
+            Some(TokenKind::RightBrace) => {
+                self.consume();
+                return Some(ASTExpression::map(Vec::new()));
+            }
+            Some(TokenKind::Ellipsis) => return Some(ASTExpression::set(self.parse_list(TokenKind::RightBrace, MESSAGE)?)),
+            Some(
+                TokenKind::Let
+                | TokenKind::Const
+                | TokenKind::Import
+                | TokenKind::For
+                | TokenKind::While
+                | TokenKind::If
+                | TokenKind::Defer
+                | TokenKind::Guard
+                | TokenKind::With
+                | TokenKind::Match
+                | TokenKind::Break
+                | TokenKind::Continue
+                | TokenKind::Semicolon,
+            ) => return self.parse_block_expression(Vec::new()),
+            Some(TokenKind::Identifier(_)) if self.peek(1).map(|t| &t.kind) == Some(&TokenKind::Equal) => {
+                return self.parse_block_expression(Vec::new());
+            }
+            _ => {}
+        }
+
+        let start = self.current().map(|token| token.span.start);
+        let first = self.parse_binary_expression(0)?;
+        let ends_statement = self.current().is_some_and(|t| {
+            matches!(t.kind, TokenKind::Semicolon | TokenKind::Equal)
+                || (t.newline_before && !matches!(t.kind, TokenKind::RightBrace | TokenKind::Comma | TokenKind::Colon))
+        });
+        if ends_statement {
+            let mut statement = if self.current().map(|t| &t.kind) == Some(&TokenKind::Equal) {
+                self.parse_element_assignment(first)?
+            } else {
+                ASTStatement::expression(first)
+            };
+            if !self.end_statement() {
+                return None;
+            }
+            let end = self.previous().map(|token| token.span.end);
+            if let (Some(start), Some(end)) = (start, end) {
+                statement.range = Some(start..end);
+            }
+            return self.parse_block_expression(vec![statement]);
+        }
+        match self.current().map(|t| &t.kind) {
+            Some(TokenKind::Colon) => Some(ASTExpression::map(self.parse_map_entries(first)?)),
+            Some(TokenKind::Comma) => {
+                self.consume(); // consume ','
+                let rest = self.parse_list(TokenKind::RightBrace, MESSAGE)?;
+                Some(ASTExpression::set(std::iter::once(first).chain(rest).collect()))
+            }
+            Some(TokenKind::RightBrace) => {
+                self.consume();
+                Some(ASTExpression::set(vec![first]))
+            }
+            _ => {
+                self.error(MESSAGE.to_string());
+                None
+            }
+        }
+    }
+
+    /// Parses `key: value` pairs, starting at the ':' after the first key, up to and
+    /// including the closing '}'
+    fn parse_map_entries(&mut self, first_key: ASTExpression) -> Option<Vec<(ASTExpression, ASTExpression)>> {
+        let mut entries = Vec::new();
+        let mut key = first_key;
+        loop {
+            if self.current().map(|t| &t.kind) != Some(&TokenKind::Colon) {
+                self.error("Expected ':' after map key".to_string());
+                return None;
+            }
+            self.consume();
+            let value = self.parse_expression()?;
+            entries.push((key, value));
+
+            if self.current().map(|t| &t.kind) == Some(&TokenKind::Comma) {
+                self.consume(); // consume ','
+                if self.current().map(|t| &t.kind) == Some(&TokenKind::RightBrace) {
+                    break;
+                }
+                key = self.parse_expression()?;
+            } else {
+                break;
+            }
+        }
+
+        if self.current().map(|t| &t.kind) != Some(&TokenKind::RightBrace) {
+            self.error("Expected '}' after map entries".to_string());
+            return None;
+        }
+        self.consume();
+        Some(entries)
+    }
+
+    /// Parses `(args)`, `[index]`, `.name` and `.name(args)` suffixes. They bind tighter
+    /// than unary operators, so `-x.abs()` negates the result of `x.abs()`.
+    ///
+    /// A `(` or `[` that starts a new line begins a new statement rather than applying to
+    /// the expression before it.
+    fn parse_postfix(&mut self, mut expression: ASTExpression) -> Option<ASTExpression> {
+        // Each suffix deepens the (left-leaning) tree the evaluator walks
+        let mut chain_length = 0;
+        loop {
+            let suffix = match self.current() {
+                Some(token) if token.newline_before && token.kind != TokenKind::Dot => break,
+                Some(token) if matches!(token.kind, TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::Dot) => {
+                    token.kind.clone()
+                }
+                _ => break,
+            };
+            chain_length += 1;
+            if self.depth + chain_length >= MAX_NESTING_DEPTH {
+                self.error(format!("Expression nested too deeply (limit is {})", MAX_NESTING_DEPTH));
+                return None;
+            }
+            if suffix == TokenKind::LeftParen {
+                expression = ASTExpression::function_call(expression, self.parse_arguments()?);
+                continue;
+            }
+            if suffix == TokenKind::LeftBracket {
+                self.consume(); // consume '['
+                let index = self.parse_expression()?;
+                if self.current().map(|t| &t.kind) != Some(&TokenKind::RightBracket) {
+                    self.error("Expected ']' after index".to_string());
+                    return None;
+                }
+                self.consume();
+                expression = ASTExpression::index(expression, index);
+                continue;
+            }
+            self.consume(); // consume '.'
+            let name = match self.current().map(|t| &t.kind) {
+                Some(TokenKind::Identifier(name)) => *name,
+                _ => {
+                    self.error("Expected a member name after '.'".to_string());
+                    return None;
+                }
+            };
+            self.consume();
+            let call = self.current().is_some_and(|t| t.kind == TokenKind::LeftParen && !t.newline_before);
+            expression = if call {
+                ASTExpression::method_call(expression, name, self.parse_arguments()?)
+            } else {
+                ASTExpression::member_access(expression, name)
+            };
         }
+        Some(expression)
     }
 
     /// Identifies binary operators and returns with precedence info
@@ -257,22 +1387,377 @@ impl Parser {
             // Logical operators
             TokenKind::DoubleAmpersand => Some(ASTBinaryOperatorKind::LogicalAnd),
             TokenKind::DoublePipe => Some(ASTBinaryOperatorKind::LogicalOr),
+            TokenKind::DotDot => Some(ASTBinaryOperatorKind::Range),
+            TokenKind::In => Some(ASTBinaryOperatorKind::In),
             _ => None,
         };
-        return kind.map(|kind| ASTBinaryOperator::new(kind, token.clone()));
+        kind.map(|kind| ASTBinaryOperator::new(kind, token.clone()))
     }
 
+    /// The token `offset` places from the current one; -1 is the last consumed token and
+    /// at most `LOOKAHEAD - 1` tokens ahead are visible
     pub fn peek(&self, offset: isize) -> Option<&Token> {
-        self.tokens.get((self.current as isize + offset) as usize)
+        if offset < 0 {
+            return if offset == -1 { self.previous() } else { None };
+        }
+        self.lookahead.get(offset as usize).map(|buffered| &buffered.token)
     }
 
     pub fn current(&self) -> Option<&Token> {
         self.peek(0)
     }
 
+    /// The last consumed token
+    fn previous(&self) -> Option<&Token> {
+        self.previous.as_ref()
+    }
+
+    /// Moves past the current token. The `EOF` token is never used up, so consuming it
+    /// leaves it current.
     pub fn consume(&mut self) -> Option<&Token> {
-        self.current += 1;
-        let token: &Token = self.peek(-1)?;
-        return Some(token);
+        let buffered = if self.is_at_end() {
+            self.lookahead.front()?.clone()
+        } else {
+            self.lookahead.pop_front()?
+        };
+        if self.checkpoints > 0 && buffered.token.kind != TokenKind::EOF {
+            self.replay.push(buffered.clone());
+        }
+        self.previous = Some(buffered.token);
+        self.fill_lookahead();
+        self.previous.as_ref()
+    }
+
+    /// Saves the current position. Every checkpoint must be passed to `rollback` or
+    /// `commit`, innermost first.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoints += 1;
+        Checkpoint {
+            consumed: self.replay.len(),
+            lexed: self.lexed.len(),
+            diagnostics: self.diagnostics.len(),
+            previous: self.previous.clone(),
+            depth: self.depth,
+            ran_out_of_input: self.ran_out_of_input,
+            loop_labels: self.loop_labels.len(),
+            block_depth: self.block_depth,
+        }
+    }
+
+    /// Returns to `checkpoint`: the tokens consumed since are read again, and the parse
+    /// errors reported since are dropped
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        for buffered in self.replay.drain(checkpoint.consumed..).rev() {
+            self.lookahead.push_front(buffered);
+        }
+        self.diagnostics.truncate(checkpoint.diagnostics);
+        self.diagnostics.extend_from_slice(&self.lexed[checkpoint.lexed..]);
+        self.previous = checkpoint.previous;
+        self.depth = checkpoint.depth;
+        self.ran_out_of_input = checkpoint.ran_out_of_input;
+        self.loop_labels.truncate(checkpoint.loop_labels);
+        self.block_depth = checkpoint.block_depth;
+        self.release_checkpoint();
     }
-}
\ No newline at end of file
+
+    /// Keeps everything parsed since `checkpoint`
+    pub fn commit(&mut self, _checkpoint: Checkpoint) {
+        self.release_checkpoint();
+    }
+
+    fn release_checkpoint(&mut self) {
+        self.checkpoints -= 1;
+        if self.checkpoints == 0 {
+            self.replay.clear();
+            self.lexed.clear();
+        }
+    }
+}
+
+/// True if running `statements` always ends in a `break` or `continue`: the last one is
+/// either of those, or an `if` with an `else` whose every branch diverges
+fn diverges(statements: &[ASTStatement]) -> bool {
+    match statements.last().map(|statement| &statement.kind) {
+        Some(ASTStatementKind::LoopControl(_)) => true,
+        Some(ASTStatementKind::If(if_statement)) => {
+            if_statement.else_body.as_deref().is_some_and(diverges)
+                && if_statement.arms.iter().all(|arm| diverges(&arm.body))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ASTStatementKind;
+
+    fn parse(source: &str) -> Ast {
+        Parser::parse_region(source, 0..source.len()).0
+    }
+
+    fn ranges(ast: &Ast) -> Vec<Range<usize>> {
+        ast.statements.iter().filter_map(|statement| statement.range.clone()).collect()
+    }
+
+    /// Every statement range and operator span in the tree, nested ones included
+    fn spans(ast: &mut Ast) -> Vec<Range<usize>> {
+        #[derive(Default)]
+        struct Spans(Vec<Range<usize>>);
+        impl AstTransformer for Spans {
+            fn transform_statement(&mut self, statement: &mut ASTStatement) {
+                self.0.extend(statement.range.clone());
+                if let ASTStatementKind::Guard(guard) = &statement.kind {
+                    self.0.push(guard.keyword.span.start..guard.keyword.span.end);
+                }
+                walk_statement(self, statement);
+            }
+
+            fn transform_expression(&mut self, expression: &mut ASTExpression) {
+                match &expression.kind {
+                    ASTExpressionKind::Binary(binary) => self.0.push(binary.operator.token.span.start..binary.operator.token.span.end),
+                    ASTExpressionKind::Unary(unary) => self.0.push(unary.operator.token.span.start..unary.operator.token.span.end),
+                    _ => {}
+                }
+                walk_expression(self, expression);
+            }
+        }
+        let mut spans = Spans::default();
+        spans.transform(ast);
+        spans.0
+    }
+
+    #[test]
+    fn test_reparse_matches_full_parse() {
+        let old = "let a = 1\nlet b = a + 2\nprint(b)\n";
+        let edits = [(14..15, "bb"), (9..9, " * 4"), (32..32, "\n+ 1"), (0..0, "/// doc\n")];
+        for (range, text) in edits {
+            let mut source = old.to_string();
+            source.replace_range(range.clone(), text);
+            let (ast, diagnostics) = Parser::reparse(parse(old), range, text, &source);
+            assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+            assert_eq!(ranges(&ast), ranges(&parse(&source)), "edit {:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_reparse_later_statements_match_full_parse() {
+        let old = "let a = 1\nlet b = a + 2\nif b > 1 {\n    print(-b)\n}\nprint(b * 2)\nfor i in [b] {\n    guard i > 0 else { break }\n}\n";
+        // Inside the second statement, with nested spans after it; and a new statement later on
+        let edits = [(22..23, "22"), (10..10, "let z = 0\n"), (64..64, "let d = 4 +\n")];
+        for (range, text) in edits {
+            let mut source = old.to_string();
+            source.replace_range(range.clone(), text);
+            let (mut ast, diagnostics) = Parser::reparse(parse(old), range, text, &source);
+            let mut full = Parser::from_lexer(Lexer::new(&source));
+            let mut expected = Ast { statements: full.parse_all() };
+            assert_eq!(spans(&mut ast), spans(&mut expected), "edit {:?}", text);
+            assert_eq!(diagnostics, full.diagnostics, "edit {:?}", text);
+        }
+
+        // Retyping the last value of a file as an unfinished sum
+        let old = "let a = 1\nlet b = 2\nlet c = 3\nlet d = 4\n";
+        let source = "let a = 1\nlet b = 2\nlet c = 3\nlet d = 4 +\n";
+        let (_, diagnostics) = Parser::reparse(parse(old), 38..39, "4 +", source);
+        let errors: Vec<_> = diagnostics.iter().map(|d| (d.message.as_str(), d.span.as_ref().map(|s| s.start))).collect();
+        assert_eq!(errors, [("Unexpected end of input at line 5, column 1, expected expression", Some(42))]);
+    }
+
+    #[test]
+    fn test_tokens_are_pulled_on_demand() {
+        let pulled = std::cell::Cell::new(0);
+        let lexer = Lexer::new("/// first\nlet a = 1\nlet b = 2\nlet c = 3\n");
+        let mut parser = Parser::from_tokens(lexer.inspect(|_| pulled.set(pulled.get() + 1)));
+
+        let first = parser.next_statement().unwrap();
+        let ASTStatementKind::VariableDeclaration(decl) = &first.kind else {
+            panic!("expected a declaration");
+        };
+        assert_eq!(decl.doc.as_deref(), Some("first"));
+        let after_first = pulled.get();
+        assert_eq!(parser.parse_all().len(), 2);
+        assert!(after_first < pulled.get(), "the whole input was lexed up front");
+    }
+
+    #[test]
+    fn test_end_of_input_is_located() {
+        let mut parser = Parser::from_lexer_at_line(Lexer::new("print(1)\nlet x =  "), 4);
+        assert_eq!(parser.parse_all().len(), 1);
+        let errors: Vec<_> = parser.diagnostics.iter().map(|d| (d.message.as_str(), d.span.as_ref().map(|s| s.start))).collect();
+        assert_eq!(errors, [("Unexpected end of input at line 5, column 10, expected expression", Some(18))]);
+        assert!(parser.is_incomplete());
+
+        // A token list without an EOF ends where its last token does, and stays ended
+        let tokens = Lexer::new("1 +  ").filter(|token| token.kind != TokenKind::EOF);
+        let mut parser = Parser::from_tokens(tokens);
+        assert!(parser.parse_all().is_empty());
+        assert_eq!(parser.diagnostics[0].message, "Unexpected end of input, expected expression");
+        assert_eq!(parser.diagnostics[0].span.as_ref().map(|span| span.start), Some(3));
+        parser.consume();
+        assert!(parser.is_at_end());
+        assert_eq!(parser.peek(-1).map(|token| &token.kind), Some(&TokenKind::EOF));
+    }
+
+    #[test]
+    fn test_trailing_commas() {
+        let source = "f(1, 2,)\n[1,]\n{\"a\": 1,}\n{1, 2,}\nf(,)";
+        let mut parser = Parser::from_lexer(Lexer::new(source));
+        let statements = parser.parse_all();
+        let printed: Vec<String> = statements.iter().map(crate::ast::unparse::statement_source).collect();
+        assert_eq!(printed, ["f(1, 2)", "[1]", "{\"a\": 1}", "{1, 2}"]);
+        assert_eq!(parser.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_rollback_rereads_tokens() {
+        let mut parser = Parser::from_lexer(Lexer::new("(a, (b, 2)) => a\n"));
+        // Read as an expression, `(a, ...` fails at the ','
+        let checkpoint = parser.checkpoint();
+        assert!(parser.parse_expression().is_none());
+        assert!(!parser.diagnostics.is_empty());
+        parser.rollback(checkpoint);
+        assert!(parser.diagnostics.is_empty());
+
+        let checkpoint = parser.checkpoint();
+        let pattern = parser.parse_pattern();
+        parser.commit(checkpoint);
+        assert_eq!(pattern.as_ref().map(crate::ast::unparse::pattern_source).as_deref(), Some("(a, (b, 2))"));
+        assert_eq!(parser.current().map(|token| &token.kind), Some(&TokenKind::FatArrow));
+        assert_eq!(parser.peek(-1).map(|token| &token.kind), Some(&TokenKind::RightParen));
+
+        // A bad character found while speculating is still reported after a rollback
+        let mut parser = Parser::from_lexer(Lexer::new("x y @"));
+        let checkpoint = parser.checkpoint();
+        parser.consume();
+        parser.rollback(checkpoint);
+        assert_eq!(parser.current().map(|token| &token.kind), Some(&TokenKind::Identifier(Atom::intern("x"))));
+        assert_eq!(parser.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_statement_separators() {
+        let mut parser = Parser::from_lexer(Lexer::new("let x = 1; print(x)\n1 + 1 garbage\nx = 2;"));
+        let statements = parser.parse_all();
+        assert_eq!(statements.len(), 3);
+        assert_eq!(parser.diagnostics.len(), 1);
+        assert!(parser.diagnostics[0].message.contains("'garbage'"));
+
+        // A '(' starting a line is a new statement, even after a member name
+        let mut parser = Parser::from_lexer(Lexer::new("x.len\n(1)"));
+        assert_eq!(parser.parse_all().len(), 2);
+    }
+
+    #[test]
+    fn test_assignment_is_not_an_expression() {
+        for source in ["a = b = 5", "print(x = 5)", "let y = (x = 1)"] {
+            let mut parser = Parser::from_lexer(Lexer::new(source));
+            assert!(parser.parse_all().is_empty(), "{}", source);
+            assert!(parser.diagnostics[0].message.contains("did you mean '=='"), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_else_if_chains_stay_flat() {
+        // Far more arms than the nesting limit allows nested blocks
+        let mut source = "if x == 0 { }".to_string();
+        for i in 1..1000 {
+            source.push_str(&format!(" else if x == {} {{ }}", i));
+        }
+        source.push_str(" else { }");
+        let mut parser = Parser::from_lexer(Lexer::new(&source));
+        let statements = parser.parse_all();
+        assert!(parser.diagnostics.is_empty(), "{:?}", parser.diagnostics);
+        let ASTStatementKind::If(if_statement) = &statements[0].kind else {
+            panic!("expected an if statement");
+        };
+        assert_eq!(if_statement.arms.len(), 1000);
+        assert!(if_statement.else_body.is_some());
+
+        let mut parser = Parser::from_lexer(Lexer::new("let a = 1\nelse { }"));
+        assert_eq!(parser.parse_all().len(), 1);
+        assert!(parser.diagnostics[0].message.starts_with("'else' without a preceding 'if'"));
+    }
+
+    #[test]
+    fn test_pipelines_bind_loosest() {
+        let ast = parse("let out = [3, 1, 2]\n    |> sort\n    |> join(\", \")\nx + 1 |> f |> g(2) | h\nf(a |> b) + (c |> d)");
+        // Every binary operator binds tighter, so `g(2) | h` is the target, not `g(2)`
+        assert_eq!(
+            ast.to_source(),
+            "let out = [3, 1, 2] |> sort |> join(\", \")\nx + 1 |> f |> g(2) | h\nf(a |> b) + (c |> d)\n"
+        );
+    }
+
+    #[test]
+    fn test_match_arms_take_patterns_and_guards() {
+        let source = "match item {\n    0 => print(\"zero\")\n    n if n > -1.5 => {\n        let twice = n * 2\n        print(twice)\n    }\n    (_, (\"k\", v)) => print(v)\n}\n";
+        let mut parser = Parser::from_lexer(Lexer::new(source));
+        let statements = parser.parse_all();
+        assert!(parser.diagnostics.is_empty(), "{:?}", parser.diagnostics);
+        assert_eq!(Ast { statements }.to_source(), source);
+
+        let mut parser = Parser::from_lexer(Lexer::new("match p { (a, a) => print(a) }"));
+        assert!(parser.parse_all().is_empty());
+        assert_eq!(parser.diagnostics[0].message, "'a' is bound more than once in the pattern");
+
+        // `[...]` unpacks an array like `(...)`, and arms may also be separated by commas
+        let mut parser = Parser::from_lexer(Lexer::new("match p { [a, [b]] => a, 1 => { b }, _ => c }"));
+        let statements = parser.parse_all();
+        assert!(parser.diagnostics.is_empty(), "{:?}", parser.diagnostics);
+        assert_eq!(Ast { statements }.to_source(), "match p {\n    (a, (b)) => a\n    1 => b\n    _ => c\n}\n");
+
+        let mut parser = Parser::from_lexer(Lexer::new("match p { [a, b) => a }"));
+        parser.parse_all();
+        assert_eq!(parser.diagnostics[0].message, "Expected ',' or ']' in the pattern");
+        let mut parser = Parser::from_lexer(Lexer::new("match p { 1 => a 2 => b }"));
+        parser.parse_all();
+        assert_eq!(parser.diagnostics.len(), 1);
+        assert_eq!(parser.diagnostics[0].message, "Unexpected number 2 after the 'match' arm, expected ',', ';' or a new line");
+    }
+
+    #[test]
+    fn test_repeated_literals_share_storage() {
+        let mut parser = Parser::from_lexer(Lexer::new("let a = [\"row\", \"row\", b\"\\x00\", b\"\\x00\", \"other\"]"));
+        let statements = parser.parse_all();
+        let ASTStatementKind::VariableDeclaration(declaration) = &statements[0].kind else {
+            panic!("expected a declaration");
+        };
+        let ASTExpressionKind::Array(array) = &declaration.initializer.kind else {
+            panic!("expected an array");
+        };
+        let values: Vec<&Value> = array
+            .elements
+            .iter()
+            .map(|element| match &element.kind {
+                ASTExpressionKind::Number(literal) => &literal.value,
+                _ => panic!("expected a literal"),
+            })
+            .collect();
+        let (Value::String(first), Value::String(second), Value::String(other)) = (values[0], values[1], values[4]) else {
+            panic!("expected strings");
+        };
+        assert!(std::sync::Arc::ptr_eq(first, second));
+        assert!(!std::sync::Arc::ptr_eq(first, other));
+        let (Value::Bytes(first), Value::Bytes(second)) = (values[2], values[3]) else {
+            panic!("expected bytes");
+        };
+        assert!(std::sync::Arc::ptr_eq(first, second));
+        assert_eq!(parser.literals.len(), 3);
+    }
+
+    #[test]
+    fn test_bad_characters_are_located() {
+        let mut parser = Parser::from_lexer(Lexer::new("let a = 1\nlet b = @ + $\nprint(a)"));
+        let statements = parser.parse_all();
+        assert_eq!(statements.len(), 2);
+        let messages: Vec<&str> = parser.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, ["unexpected character '@' at line 2, column 9", "unexpected character '$' at line 2, column 13"]);
+        assert_eq!(parser.diagnostics[0].span.as_ref().map(|span| span.start), Some(18));
+
+        // Tokens lexed ahead of time are located the same way when given their source
+        let source = "let b = @";
+        let mut parser = Parser::from_lexed(Lexer::new(source).collect(), source);
+        parser.parse_all();
+        assert_eq!(parser.diagnostics[0].message, "unexpected character '@' at line 1, column 9");
+    }
+}
@@ -0,0 +1,131 @@
+//! Pretty-printing - multi-line rendering of nested values for the REPL
+//!
+//! An array, map or set that fits in the width stays on one line; one that does not is
+//! broken up with one element per line, indented under its brackets. Values nested deeper
+//! than the depth limit are elided as `[...]` or `{...}`, and only the first
+//! `MAX_PRETTY_ITEMS` elements of a collection are shown. Strings are quoted at every level,
+//! so the output reads like the literal that produced it.
+
+use crate::ast::types::Value;
+use crate::ast::unparse::string_literal;
+use std::fmt::Write;
+
+/// Elements of one collection shown before the rest are summarized as `... N more`
+pub const MAX_PRETTY_ITEMS: usize = 100;
+
+/// Spaces added per nesting level
+const INDENT: usize = 2;
+
+impl Value {
+    /// Renders the value within `width` columns where it can, showing `depth` levels of
+    /// nested collections; `depth` 0 elides even the outermost one
+    pub fn pretty(&self, depth: usize, width: usize) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, depth, Some(width), 0);
+        out
+    }
+
+    /// Writes the value at column `indent`; a `width` of `None` keeps it on one line
+    fn write_pretty(&self, out: &mut String, depth: usize, width: Option<usize>, indent: usize) {
+        let (open, close, items) = match self {
+            Value::Array(items) => ("[", "]", items.iter().map(|item| (None, item.clone())).collect::<Vec<_>>()),
+            Value::Map(entries) => (
+                "{",
+                "}",
+                entries.iter().map(|(key, value)| (Some(key), value.clone())).collect(),
+            ),
+            Value::Set(elements) if !elements.is_empty() => (
+                "{",
+                "}",
+                elements.iter().map(|element| (None, Value::from(element.clone()))).collect(),
+            ),
+            Value::String(s) => {
+                out.push_str(&string_literal(s));
+                return;
+            }
+            other => {
+                let _ = write!(out, "{}", other);
+                return;
+            }
+        };
+        if items.is_empty() {
+            let _ = write!(out, "{}{}", open, close);
+            return;
+        }
+        if depth == 0 {
+            let _ = write!(out, "{}...{}", open, close);
+            return;
+        }
+
+        let hidden = items.len().saturating_sub(MAX_PRETTY_ITEMS);
+        let shown = &items[..items.len() - hidden];
+        if let Some(width) = width {
+            let mut flat = String::new();
+            self.write_pretty(&mut flat, depth, None, indent);
+            if indent + flat.chars().count() <= width {
+                out.push_str(&flat);
+                return;
+            }
+        }
+
+        out.push_str(open);
+        for (i, (key, value)) in shown.iter().enumerate() {
+            match width {
+                Some(_) => {
+                    out.push('\n');
+                    out.extend(std::iter::repeat_n(' ', indent + INDENT));
+                }
+                None if i > 0 => out.push_str(", "),
+                None => {}
+            }
+            if let Some(key) = key {
+                let _ = write!(out, "{}: ", string_literal(key));
+            }
+            value.write_pretty(out, depth - 1, width, indent + INDENT);
+            if width.is_some() {
+                out.push(',');
+            }
+        }
+        if hidden > 0 {
+            match width {
+                Some(_) => {
+                    out.push('\n');
+                    out.extend(std::iter::repeat_n(' ', indent + INDENT));
+                }
+                None => out.push_str(", "),
+            }
+            let _ = write!(out, "... {} more", hidden);
+        }
+        if width.is_some() {
+            out.push('\n');
+            out.extend(std::iter::repeat_n(' ', indent));
+        }
+        out.push_str(close);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_pretty_breaks_wide_values_and_elides_deep_ones() {
+        let row = |name: &str| Value::from(BTreeMap::from([(Arc::from("name"), Value::from(name)), (Arc::from("tags"), Value::from(vec![Value::Integer(1), Value::Integer(2)]))]));
+        let table = Value::from(vec![row("first"), row("second")]);
+
+        assert_eq!(table.pretty(4, 80), "[{\"name\": \"first\", \"tags\": [1, 2]}, {\"name\": \"second\", \"tags\": [1, 2]}]");
+        assert_eq!(
+            table.pretty(4, 40),
+            "[\n  {\"name\": \"first\", \"tags\": [1, 2]},\n  {\"name\": \"second\", \"tags\": [1, 2]},\n]"
+        );
+        assert_eq!(table.pretty(2, 80), "[{\"name\": \"first\", \"tags\": [...]}, {\"name\": \"second\", \"tags\": [...]}]");
+        assert_eq!(table.pretty(0, 80), "[...]");
+        assert_eq!(Value::from("hi").pretty(4, 80), "\"hi\"");
+
+        let long = Value::from((0..250).map(Value::Integer).collect::<Vec<_>>());
+        assert!(long.pretty(4, 10_000).ends_with(", 99, ... 150 more]"));
+        assert!(long.pretty(4, 80).ends_with("  99,\n  ... 150 more\n]"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Resolver - assigns each variable reference its slot before evaluation
+//!
+//! Runs over freshly parsed statements against the current symbol table, mirroring the
+//! declarations the statements will make, so the evaluator can read and write variables by
+//! `Slot` instead of hashing names. References it cannot place (host globals, or names that
+//! are not declared yet) keep `slot: None` and are looked up by name at run time.
+
+use crate::ast::interner::Atom;
+use crate::ast::symbol_table::{Slot, SymbolTable};
+use crate::ast::{ASTExpression, ASTExpressionKind, ASTStatement, ASTStatementKind};
+use std::collections::HashMap;
+
+pub struct Resolver<'a> {
+    table: &'a SymbolTable,
+    /// Declarations made by the resolved code, from the table's current scope inward
+    scopes: Vec<HashMap<Atom, usize>>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(table: &'a SymbolTable) -> Self {
+        Resolver {
+            table,
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    pub fn resolve_statement(&mut self, statement: &mut ASTStatement) {
+        match &mut statement.kind {
+            ASTStatementKind::Expression(expr) | ASTStatementKind::Defer(expr) => self.resolve_expression(expr),
+            ASTStatementKind::VariableDeclaration(decl) => {
+                // The initializer cannot see the variable it declares
+                self.resolve_expression(&mut decl.initializer);
+                self.declare(decl.name);
+            }
+            ASTStatementKind::Assignment(assign) => {
+                for index in &mut assign.indices {
+                    self.resolve_expression(index);
+                }
+                self.resolve_expression(&mut assign.value);
+                assign.slot = self.lookup(assign.name);
+            }
+            // Imported modules are resolved when they are loaded
+            ASTStatementKind::Import(_) | ASTStatementKind::LoopControl(_) => {}
+            ASTStatementKind::For(for_statement) => {
+                self.resolve_expression(&mut for_statement.iterable);
+                // Mirrors the scope the evaluator enters for each item
+                self.scopes.push(HashMap::new());
+                for name in for_statement.pattern.names() {
+                    self.declare(*name);
+                }
+                for statement in &mut for_statement.body {
+                    self.resolve_statement(statement);
+                }
+                self.scopes.pop();
+            }
+            ASTStatementKind::While(while_statement) => {
+                self.resolve_expression(&mut while_statement.condition);
+                self.resolve_block(&mut while_statement.body);
+            }
+            ASTStatementKind::With(with) => {
+                self.resolve_expression(&mut with.resource);
+                self.scopes.push(HashMap::new());
+                self.declare(with.name);
+                for statement in &mut with.body {
+                    self.resolve_statement(statement);
+                }
+                self.scopes.pop();
+            }
+            ASTStatementKind::If(if_statement) => {
+                for arm in &mut if_statement.arms {
+                    self.resolve_expression(&mut arm.condition);
+                    self.resolve_block(&mut arm.body);
+                }
+                if let Some(else_body) = &mut if_statement.else_body {
+                    self.resolve_block(else_body);
+                }
+            }
+            // Desugared before resolution; resolved as the `if` it becomes in case it was not
+            ASTStatementKind::Guard(guard) => {
+                self.resolve_expression(&mut guard.condition);
+                self.resolve_block(&mut guard.body);
+            }
+            ASTStatementKind::Match(match_statement) => {
+                self.resolve_expression(&mut match_statement.subject);
+                for arm in &mut match_statement.arms {
+                    // The guard and the body share the scope holding the pattern's names
+                    self.scopes.push(HashMap::new());
+                    for name in arm.pattern.names() {
+                        self.declare(name);
+                    }
+                    if let Some(guard) = &mut arm.guard {
+                        self.resolve_expression(guard);
+                    }
+                    for statement in &mut arm.body {
+                        self.resolve_statement(statement);
+                    }
+                    self.scopes.pop();
+                }
+            }
+        }
+    }
+
+    /// Resolves a block that runs in its own scope, as each branch of an `if` and each block
+    /// expression does
+    fn resolve_block(&mut self, statements: &mut [ASTStatement]) {
+        self.scopes.push(HashMap::new());
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn resolve_expression(&mut self, expression: &mut ASTExpression) {
+        match &mut expression.kind {
+            ASTExpressionKind::Number(_) => {}
+            ASTExpressionKind::Binary(expr) => {
+                self.resolve_expression(&mut expr.left);
+                self.resolve_expression(&mut expr.right);
+            }
+            ASTExpressionKind::Paranthesized(paren_expr) => self.resolve_expression(&mut paren_expr.expression),
+            ASTExpressionKind::Unary(unary_expr) => self.resolve_expression(&mut unary_expr.operand),
+            ASTExpressionKind::Identifier(ident) => ident.slot = self.lookup(ident.name),
+            ASTExpressionKind::FunctionCall(func_call) => {
+                self.resolve_expression(&mut func_call.callee);
+                for arg in &mut func_call.arguments {
+                    self.resolve_expression(arg);
+                }
+            }
+            ASTExpressionKind::MemberAccess(member) => self.resolve_expression(&mut member.object),
+            ASTExpressionKind::MethodCall(method_call) => {
+                self.resolve_expression(&mut method_call.receiver);
+                for arg in &mut method_call.arguments {
+                    self.resolve_expression(arg);
+                }
+            }
+            ASTExpressionKind::Array(array) => {
+                for element in &mut array.elements {
+                    self.resolve_expression(element);
+                }
+            }
+            ASTExpressionKind::Set(set) => {
+                for element in &mut set.elements {
+                    self.resolve_expression(element);
+                }
+            }
+            ASTExpressionKind::Map(map) => {
+                for (key, value) in &mut map.entries {
+                    self.resolve_expression(key);
+                    self.resolve_expression(value);
+                }
+            }
+            ASTExpressionKind::Index(index) => {
+                self.resolve_expression(&mut index.object);
+                self.resolve_expression(&mut index.index);
+            }
+            ASTExpressionKind::Spread(spread) => self.resolve_expression(&mut spread.expression),
+            ASTExpressionKind::Pipe(pipe) => {
+                self.resolve_expression(&mut pipe.value);
+                self.resolve_expression(&mut pipe.target);
+            }
+            ASTExpressionKind::Block(block) => self.resolve_block(&mut block.statements),
+        }
+    }
+
+    /// Depth of the table's current scope; the resolver's own scopes nest below it
+    fn base_depth(&self) -> usize {
+        self.table.scope_depth() - 1
+    }
+
+    fn declare(&mut self, name: Atom) {
+        let depth = self.base_depth() + self.scopes.len() - 1;
+        let existing = if self.scopes.len() == 1 { self.table.scope_len(depth) } else { 0 };
+        let already_declared = self.scopes.len() == 1
+            && self.table.resolve(name).is_some_and(|slot| slot.depth == depth);
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        // A redeclaration fails at run time without taking a slot
+        if already_declared || scope.contains_key(&name) {
+            return;
+        }
+        let index = existing + scope.len();
+        scope.insert(name, index);
+    }
+
+    fn lookup(&self, name: Atom) -> Option<Slot> {
+        let base = self.base_depth();
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(offset, scope)| scope.get(&name).map(|&index| Slot { depth: base + offset, index }))
+            .or_else(|| self.table.resolve(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+    use crate::ast::types::Value;
+
+    #[test]
+    fn test_resolves_against_table_and_new_declarations() {
+        let mut table = SymbolTable::new();
+        table.define("a", Value::Integer(1), true).unwrap();
+
+        let mut statements = Parser::from_lexer(Lexer::new("let b = a\nb = a + b\nmissing")).parse_all();
+        let mut resolver = Resolver::new(&table);
+        for statement in &mut statements {
+            resolver.resolve_statement(statement);
+        }
+
+        let ASTStatementKind::Assignment(assign) = &statements[1].kind else {
+            panic!("expected an assignment");
+        };
+        assert_eq!(assign.slot, Some(Slot { depth: 0, index: 1 }));
+        let ASTExpressionKind::Binary(sum) = &assign.value.kind else {
+            panic!("expected a binary expression");
+        };
+        let ASTExpressionKind::Identifier(a) = &sum.left.kind else {
+            panic!("expected an identifier");
+        };
+        assert_eq!(a.slot, Some(Slot { depth: 0, index: 0 }));
+        let ASTStatementKind::Expression(missing) = &statements[2].kind else {
+            panic!("expected an expression");
+        };
+        assert!(matches!(&missing.kind, ASTExpressionKind::Identifier(ident) if ident.slot.is_none()));
+    }
+}
@@ -0,0 +1,153 @@
+//! String builtins - building strings from many pieces, and converting numbers to and from text
+//!
+//! `join` and `concat_all` size their result once and copy each piece into it, so
+//! collecting pieces in an array and joining them costs time linear in the output, where
+//! growing a string with `+` in a loop copies it on every step.
+//!
+//! `format` writes a value with its numbers in a `NumberFormat` spec (see
+//! `ast::number_format`). `parse_int` and `parse_float` fail on any text that is not exactly a number, instead of
+//! producing 0. `repr` writes a value as the literal that produces it (see
+//! `unparse::value_source`), so a string comes back quoted with its escapes.
+
+use crate::ast::iteration::ValueIter;
+use crate::ast::number_format::{NumberFormat, MAX_PRECISION};
+use crate::ast::types::Value;
+use crate::ast::unparse::{string_literal, value_source};
+use std::fmt::Write;
+use std::num::IntErrorKind;
+
+/// Names of the string builtins
+pub const STRING_BUILTINS: &[&str] = &["join", "concat_all", "parse_int", "parse_float", "to_fixed", "format", "repr"];
+
+pub fn is_string_builtin(name: &str) -> bool {
+    STRING_BUILTINS.contains(&name)
+}
+
+/// Calls a string builtin
+pub fn call(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    let (items, separator) = match (name, args) {
+        ("parse_int", [Value::String(text)]) => return parse_int(text, 10).map(Some),
+        ("parse_int", [Value::String(text), Value::Integer(base)]) => return parse_int(text, *base).map(Some),
+        ("parse_int", [Value::String(_), other]) => return Err(format!("base must be an Integer, found {}", other.get_type())),
+        ("parse_float", [Value::String(text)]) => {
+            return match text.parse::<f64>() {
+                Ok(number) => Ok(Some(Value::Float(number))),
+                Err(_) => Err(format!("{} is not a valid number", string_literal(text))),
+            };
+        }
+        ("parse_int" | "parse_float", [other, ..]) if args.len() <= 2 && !matches!(other, Value::String(_)) => {
+            return Err(format!("expected a String to parse, found {}", other.get_type()));
+        }
+        ("parse_int", _) => return Err(format!("expected 1 or 2 argument(s), found {}", args.len())),
+        ("parse_float", _) => return Err(format!("expected 1 argument(s), found {}", args.len())),
+        ("to_fixed", [number, Value::Integer(digits)]) => {
+            let number = f64::try_from(number.clone())?;
+            if !(0..=MAX_PRECISION as i64).contains(digits) {
+                return Err(format!("digits must be from 0 to {}, found {}", MAX_PRECISION, digits));
+            }
+            return Ok(Some(Value::from(format!("{:.*}", *digits as usize, number))));
+        }
+        ("to_fixed", [_, other]) => return Err(format!("digits must be an Integer, found {}", other.get_type())),
+        ("to_fixed", _) => return Err(format!("expected 2 argument(s), found {}", args.len())),
+        ("format", [value, Value::String(spec)]) => {
+            let format = NumberFormat::parse(spec)?;
+            return Ok(Some(Value::from(value.display_with(&format).to_string())));
+        }
+        ("format", [_, other]) => return Err(format!("format spec must be a String, found {}", other.get_type())),
+        ("format", _) => return Err(format!("expected 2 argument(s), found {}", args.len())),
+        ("repr", [value]) => return Ok(Some(Value::from(value_source(value)))),
+        ("repr", _) => return Err(format!("expected 1 argument(s), found {}", args.len())),
+        ("join", [items, Value::String(separator)]) => (items, &**separator),
+        ("join", [_, other]) => return Err(format!("separator must be a String, found {}", other.get_type())),
+        ("join", _) => return Err(format!("expected 2 argument(s), found {}", args.len())),
+        ("concat_all", [items]) => (items, ""),
+        ("concat_all", _) => return Err(format!("expected 1 argument(s), found {}", args.len())),
+        _ => return Err(format!("unknown string function '{}'", name)),
+    };
+    Ok(Some(Value::from(join(ValueIter::new(items)?, separator))))
+}
+
+/// Parses a whole string as an integer in `base` (2 to 36), with an optional sign
+fn parse_int(text: &str, base: i64) -> Result<Value, String> {
+    let radix = match u32::try_from(base) {
+        Ok(radix @ 2..=36) => radix,
+        _ => return Err(format!("base must be from 2 to 36, found {}", base)),
+    };
+    i64::from_str_radix(text, radix).map(Value::Integer).map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => format!("{} is out of range for an Integer", string_literal(text)),
+        _ if base == 10 => format!("{} is not a valid integer", string_literal(text)),
+        _ => format!("{} is not a valid base {} integer", string_literal(text), base),
+    })
+}
+
+/// Joins the items with `separator` between them. Strings are copied as they are and
+/// other values as they print, like the operands of `+`.
+fn join(items: ValueIter, separator: &str) -> String {
+    let items: Vec<Value> = items.collect();
+    let length: usize = items
+        .iter()
+        .map(|item| match item {
+            Value::String(s) => s.len(),
+            _ => 0, // grown as needed
+        })
+        .sum::<usize>()
+        + separator.len() * items.len().saturating_sub(1);
+
+    let mut joined = String::with_capacity(length);
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            joined.push_str(separator);
+        }
+        match item {
+            Value::String(s) => joined.push_str(s),
+            // Writing to a String cannot fail
+            other => {
+                let _ = write!(joined, "{}", other);
+            }
+        }
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_and_concat_all() {
+        let items = Value::from(vec![Value::from("a"), Value::Integer(1), Value::Boolean(true)]);
+        assert_eq!(call("join", &[items.clone(), Value::from(", ")]), Ok(Some(Value::from("a, 1, true"))));
+        assert_eq!(call("concat_all", &[items]), Ok(Some(Value::from("a1true"))));
+        assert_eq!(call("join", &[Value::Range(1, 4), Value::from("-")]), Ok(Some(Value::from("1-2-3"))));
+        assert_eq!(call("concat_all", &[Value::from(Vec::new())]), Ok(Some(Value::from(""))));
+        assert_eq!(call("join", &[Value::Integer(1), Value::from("")]), Err("Integer is not iterable".to_string()));
+    }
+
+    #[test]
+    fn test_number_parsing_and_formatting() {
+        assert_eq!(call("parse_int", &[Value::from("-42")]), Ok(Some(Value::Integer(-42))));
+        assert_eq!(call("parse_int", &[Value::from("ff"), Value::Integer(16)]), Ok(Some(Value::Integer(255))));
+        assert_eq!(call("parse_int", &[Value::from("12a")]), Err("\"12a\" is not a valid integer".to_string()));
+        assert_eq!(call("parse_int", &[Value::from("2"), Value::Integer(2)]), Err("\"2\" is not a valid base 2 integer".to_string()));
+        assert_eq!(call("parse_int", &[Value::from("99999999999999999999")]), Err("\"99999999999999999999\" is out of range for an Integer".to_string()));
+        assert_eq!(call("parse_int", &[Value::from("1"), Value::Integer(37)]), Err("base must be from 2 to 36, found 37".to_string()));
+        assert_eq!(call("parse_float", &[Value::from("2.5e3")]), Ok(Some(Value::Float(2500.0))));
+        assert_eq!(call("parse_float", &[Value::from(" 1")]), Err("\" 1\" is not a valid number".to_string()));
+        assert_eq!(call("parse_float", &[Value::Integer(1)]), Err("expected a String to parse, found Integer".to_string()));
+        assert_eq!(call("to_fixed", &[Value::Float(0.1 + 0.2), Value::Integer(2)]), Ok(Some(Value::from("0.30"))));
+        assert_eq!(call("to_fixed", &[Value::Integer(3), Value::Integer(1)]), Ok(Some(Value::from("3.0"))));
+        assert_eq!(call("to_fixed", &[Value::Float(1.0), Value::Integer(-1)]), Err("digits must be from 0 to 100, found -1".to_string()));
+        let prices = Value::from(vec![Value::Float(1234.5), Value::Integer(7)]);
+        assert_eq!(call("format", &[prices, Value::from(",.2")]), Ok(Some(Value::from("[1,234.50, 7.00]"))));
+    }
+
+    #[test]
+    fn test_repr_writes_literals() {
+        let repr = |value: Value| call("repr", &[value]).unwrap().unwrap().to_string();
+        assert_eq!(repr(Value::from("line1\nline2")), "\"line1\\nline2\"");
+        assert_eq!(repr(Value::from("say \"hi\"\t\u{1}")), "\"say \\\"hi\\\"\\t\\u{1}\"");
+        assert_eq!(repr(Value::Float(2.0)), "2.0");
+        assert_eq!(repr(Value::from(vec![Value::from("a\\b"), Value::Integer(1)])), "[\"a\\\\b\", 1]");
+        assert_eq!(call("repr", &[]), Err("expected 1 argument(s), found 0".to_string()));
+    }
+}
@@ -0,0 +1,68 @@
+//! Suggestions - "did you mean" hints for misspelled names
+//!
+//! Only names within a small edit distance of the misspelling are suggested, scaled by its
+//! length, so short names are not matched against unrelated ones.
+
+/// Levenshtein distance between `a` and `b`, also counting a swap of two adjacent characters,
+/// the commonest typo, as a single edit
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Rows i - 1 and i - 2 of the distance matrix, and the row being filled in
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut before_previous = vec![0; b.len() + 1];
+    let mut current = vec![0; b.len() + 1];
+    for i in 0..a.len() {
+        current[0] = i + 1;
+        for j in 0..b.len() {
+            let substitution = previous[j] + usize::from(a[i] != b[j]);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            if i > 0 && j > 0 && a[i] == b[j - 1] && a[i - 1] == b[j] {
+                current[j + 1] = current[j + 1].min(before_previous[j - 1] + 1);
+            }
+        }
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// The candidate closest to `name`, if any is close enough to be a likely typo. Ties go to
+/// the alphabetically first candidate, so the hint does not depend on iteration order.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (name.chars().count() + 1) / 3;
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// `"; did you mean 'x'?"` for the closest candidate, or nothing, to append to an error
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    closest(name, candidates)
+        .map(|candidate| format!("; did you mean '{}'?", candidate))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_name_within_typo_distance() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("héllo", "hello"), 1);
+        assert_eq!(edit_distance("pritn", "print"), 1);
+
+        let names = ["print", "println", "sqrt", "count"];
+        assert_eq!(closest("pritn", names), Some("print"));
+        assert_eq!(closest("sqtr", names), Some("sqrt"));
+        assert_eq!(closest("x", names), None);
+        assert_eq!(closest("total", names), None);
+        assert_eq!(closest("ab", ["ac", "aa"]), Some("aa"));
+        assert_eq!(did_you_mean("cont", names), "; did you mean 'count'?");
+    }
+}
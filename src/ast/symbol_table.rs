@@ -1,12 +1,14 @@
 //! Symbol table - manages variables and scopes
 
+use crate::ast::interner::Atom;
 use crate::ast::types::{DataType, Value};
 use std::collections::HashMap;
 
 /// Variable storage with type and mutability info
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Symbol {
-    pub name: String,
+    pub name: Atom,
     pub value: Value,
     pub data_type: DataType,
     pub is_mutable: bool,
@@ -14,9 +16,9 @@ pub struct Symbol {
 }
 
 impl Symbol {
-    pub fn new(name: String, value: Value, data_type: DataType, is_mutable: bool) -> Self {
+    pub fn new(name: impl Into<Atom>, value: Value, data_type: DataType, is_mutable: bool) -> Self {
         Symbol {
-            name,
+            name: name.into(),
             value,
             data_type,
             is_mutable,
@@ -25,137 +27,243 @@ impl Symbol {
     }
 }
 
-/// Single scope level containing variables
+/// Resolved location of a variable: the scope it lives in and its index within that scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+/// Single scope level: maps names to slot indices; the values live in the table's flat stack
 #[derive(Debug, Clone)]
 pub struct Scope {
-    symbols: HashMap<String, Symbol>,
+    start: usize,
+    slots: HashMap<Atom, usize>,
 }
 
 impl Scope {
-    pub fn new() -> Self {
+    pub fn new(start: usize) -> Self {
         Scope {
-            symbols: HashMap::new(),
+            start,
+            slots: HashMap::new(),
         }
     }
 
-    pub fn define(&mut self, name: String, symbol: Symbol) -> Result<(), String> {
-        if self.symbols.contains_key(&name) {
-            return Err(format!("Variable '{}' already declared in this scope", name));
-        }
-        self.symbols.insert(name, symbol);
-        Ok(())
+    /// Index of `name` within this scope
+    pub fn slot_of(&self, name: Atom) -> Option<usize> {
+        self.slots.get(&name).copied()
     }
 
-    pub fn get(&self, name: &str) -> Option<&Symbol> {
-        self.symbols.get(name)
+    pub fn contains(&self, name: Atom) -> bool {
+        self.slots.contains_key(&name)
     }
 
-    pub fn get_mut(&mut self, name: &str) -> Option<&mut Symbol> {
-        self.symbols.get_mut(name)
+    /// Number of variables declared in this scope
+    pub fn len(&self) -> usize {
+        self.slots.len()
     }
 
-    pub fn contains(&self, name: &str) -> bool {
-        self.symbols.contains_key(name)
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
     }
 }
 
-/// Manages nested scopes for variable lookup and assignment
-#[derive(Debug)]
+/// Manages nested scopes as one flat stack of symbols.
+///
+/// Names are only hashed when a variable is declared or resolved; code that carries a
+/// `Slot` (assigned by `ast::resolver::Resolver`) reads and writes by index.
+#[derive(Debug, Clone)]
 pub struct SymbolTable {
+    symbols: Vec<Symbol>,
     scopes: Vec<Scope>,
 }
 
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SymbolTable {
     pub fn new() -> Self {
         SymbolTable {
-            scopes: vec![Scope::new()], // Start with global scope
+            symbols: Vec::new(),
+            scopes: vec![Scope::new(0)], // Start with global scope
         }
     }
 
     /// Enter a new scope
     pub fn enter_scope(&mut self) {
-        self.scopes.push(Scope::new());
+        self.scopes.push(Scope::new(self.symbols.len()));
     }
 
-    /// Exit current scope
+    /// Exit current scope, dropping its variables
     pub fn exit_scope(&mut self) -> Result<(), String> {
         if self.scopes.len() <= 1 {
             return Err("Cannot exit global scope".to_string());
         }
-        self.scopes.pop();
+        if let Some(scope) = self.scopes.pop() {
+            self.symbols.truncate(scope.start);
+        }
         Ok(())
     }
 
+    /// Exits scopes until `depth` remain, dropping their variables; the global scope is
+    /// never exited
+    pub fn exit_to_depth(&mut self, depth: usize) {
+        if let Some(scope) = self.scopes.get(depth.max(1)) {
+            self.symbols.truncate(scope.start);
+            self.scopes.truncate(depth.max(1));
+        }
+    }
+
+    /// Runs `f` in a new scope, which is exited when `f` returns, however it returns. Scopes
+    /// `f` entered and did not exit are dropped with it.
+    pub fn scoped<R>(&mut self, f: impl FnOnce(&mut SymbolTable) -> R) -> R {
+        let depth = self.scope_depth();
+        self.enter_scope();
+        let result = f(self);
+        self.exit_to_depth(depth);
+        result
+    }
+
     /// Get current scope depth
     pub fn scope_depth(&self) -> usize {
         self.scopes.len()
     }
 
+    /// Number of variables declared in the scope at `depth` (0 is global)
+    pub fn scope_len(&self, depth: usize) -> usize {
+        self.scopes.get(depth).map_or(0, Scope::len)
+    }
+
     /// Define a new variable in the current scope
-    pub fn define(&mut self, name: String, value: Value, is_mutable: bool) -> Result<(), String> {
+    pub fn define(&mut self, name: impl Into<Atom>, value: Value, is_mutable: bool) -> Result<(), String> {
+        let name = name.into();
         let data_type = value.get_type();
-        let symbol = Symbol::new(name.clone(), value, data_type, is_mutable);
-        
-        if let Some(current_scope) = self.scopes.last_mut() {
-            current_scope.define(name, symbol)
-        } else {
-            Err("No active scope".to_string())
+        let Some(current_scope) = self.scopes.last_mut() else {
+            return Err("No active scope".to_string());
+        };
+        if current_scope.contains(name) {
+            return Err(format!("Variable '{}' already declared in this scope", name));
         }
+        current_scope.slots.insert(name, self.symbols.len() - current_scope.start);
+        self.symbols.push(Symbol::new(name, value, data_type, is_mutable));
+        Ok(())
     }
 
-    /// Look up a variable by name (searches from current scope up to global)
-    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+    /// Find the slot of a variable by name (searches from current scope up to global)
+    pub fn resolve(&self, name: impl Into<Atom>) -> Option<Slot> {
+        let name = name.into();
         // Search from innermost to outermost scope (lexical scoping)
-        for scope in self.scopes.iter().rev() {
-            if let Some(symbol) = scope.get(name) {
-                return Some(symbol); // Return first match found
-            }
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, scope)| scope.slot_of(name).map(|index| Slot { depth, index }))
+    }
+
+    /// Returns `slot` if it still holds `name`, otherwise resolves `name` afresh
+    pub fn locate(&self, name: Atom, slot: Option<Slot>) -> Option<Slot> {
+        match slot {
+            Some(slot) if self.get(slot).is_some_and(|symbol| symbol.name == name) => Some(slot),
+            _ => self.resolve(name),
         }
-        None // Variable not found in any scope
+    }
+
+    fn position(&self, slot: Slot) -> Option<usize> {
+        let scope = self.scopes.get(slot.depth)?;
+        (slot.index < scope.len()).then_some(scope.start + slot.index)
+    }
+
+    /// The symbol stored at `slot`
+    pub fn get(&self, slot: Slot) -> Option<&Symbol> {
+        self.symbols.get(self.position(slot)?)
+    }
+
+    /// Look up a variable by name
+    pub fn lookup(&self, name: impl Into<Atom>) -> Option<&Symbol> {
+        self.get(self.resolve(name)?)
     }
 
     /// Assign a value to an existing variable
-    pub fn assign(&mut self, name: &str, value: Value) -> Result<(), String> {
-        // Search from innermost to outermost scope
-        for scope in self.scopes.iter_mut().rev() {
-            if let Some(symbol) = scope.get_mut(name) {
-                // Enforce immutability for const variables
-                if !symbol.is_mutable {
-                    return Err(format!("Cannot assign to immutable variable '{}'", name));
-                }
-                
-                // Type checking: ensure assigned value matches variable's declared type
-                let new_type = value.get_type();
-                if symbol.data_type != new_type {
-                    // Special case: allow int to float widening conversion
-                    if !(symbol.data_type == DataType::Float && new_type == DataType::Integer) {
-                        return Err(format!(
-                            "Type mismatch: variable '{}' has type {:?}, cannot assign value of type {:?}",
-                            name, symbol.data_type, new_type
-                        ));
-                    }
-                    // Perform the coercion
-                    if let Value::Integer(i) = value {
-                        symbol.value = Value::Float(i as f64);
-                        return Ok(());
-                    }
-                }
-                
-                symbol.value = value;
+    pub fn assign(&mut self, name: impl Into<Atom>, value: Value) -> Result<(), String> {
+        let name = name.into();
+        match self.resolve(name) {
+            Some(slot) => self.assign_slot(slot, value),
+            None => Err(format!("Variable '{}' not found", name)),
+        }
+    }
+
+    /// Assign a value to the variable stored at `slot`
+    pub fn assign_slot(&mut self, slot: Slot, value: Value) -> Result<(), String> {
+        let Some(symbol) = self.position(slot).and_then(|position| self.symbols.get_mut(position)) else {
+            return Err("Invalid variable slot".to_string());
+        };
+        // Enforce immutability for const variables
+        if !symbol.is_mutable {
+            return Err(format!("Cannot assign to immutable variable '{}'", symbol.name));
+        }
+        
+        // Type checking: ensure assigned value matches variable's declared type
+        let new_type = value.get_type();
+        if symbol.data_type != new_type {
+            // Special case: allow int to float widening conversion
+            if !(symbol.data_type == DataType::Float && new_type == DataType::Integer) {
+                return Err(format!(
+                    "Type mismatch: variable '{}' has type {:?}, cannot assign value of type {:?}",
+                    symbol.name, symbol.data_type, new_type
+                ));
+            }
+            // Perform the coercion
+            if let Value::Integer(i) = value {
+                symbol.value = Value::Float(i as f64);
                 return Ok(());
             }
         }
         
-        Err(format!("Variable '{}' not found", name))
+        symbol.value = value;
+        Ok(())
+    }
+
+    /// The value of the mutable variable at `slot`, for changing it in place; unlike
+    /// `assign_slot` this cannot change its type
+    pub fn value_mut(&mut self, slot: Slot) -> Result<&mut Value, String> {
+        let Some(symbol) = self.position(slot).and_then(|position| self.symbols.get_mut(position)) else {
+            return Err("Invalid variable slot".to_string());
+        };
+        if !symbol.is_mutable {
+            return Err(format!("Cannot assign to immutable variable '{}'", symbol.name));
+        }
+        Ok(&mut symbol.value)
+    }
+
+    /// Every variable, outermost scope first, shadowed ones included
+    pub fn iter_symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    /// The variables declared in the scope at `depth` (0 is global), in declaration order;
+    /// empty past the innermost scope
+    pub fn symbols_in_scope(&self, depth: usize) -> &[Symbol] {
+        self.scopes
+            .get(depth)
+            .map_or(&[], |scope| &self.symbols[scope.start..scope.start + scope.len()])
+    }
+
+    /// The variables of the global scope
+    pub fn globals(&self) -> &[Symbol] {
+        self.symbols_in_scope(0)
     }
 
     /// Check if a variable exists in any scope
-    pub fn exists(&self, name: &str) -> bool {
+    pub fn exists(&self, name: impl Into<Atom>) -> bool {
         self.lookup(name).is_some()
     }
 
     /// Get the value of a variable
-    pub fn get_value(&self, name: &str) -> Result<Value, String> {
+    pub fn get_value(&self, name: impl Into<Atom>) -> Result<Value, String> {
+        let name = name.into();
         match self.lookup(name) {
             Some(symbol) => Ok(symbol.value.clone()),
             None => Err(format!("Variable '{}' not found", name)),
@@ -163,7 +271,8 @@ impl SymbolTable {
     }
 
     /// Check if a variable is mutable
-    pub fn is_mutable(&self, name: &str) -> Result<bool, String> {
+    pub fn is_mutable(&self, name: impl Into<Atom>) -> Result<bool, String> {
+        let name = name.into();
         match self.lookup(name) {
             Some(symbol) => Ok(symbol.is_mutable),
             None => Err(format!("Variable '{}' not found", name)),
@@ -217,4 +326,59 @@ mod tests {
         assert!(table.exists("x"));
         assert!(!table.exists("y"));
     }
+
+    #[test]
+    fn test_scoped_always_exits() {
+        let mut table = SymbolTable::new();
+        let result: Result<(), String> = table.scoped(|table| {
+            table.define("a", Value::Integer(1), true)?;
+            table.enter_scope();
+            table.define("b", Value::Integer(2), true)?;
+            table.define("b", Value::Integer(3), true)?;
+            Ok(())
+        });
+        assert_eq!(result, Err("Variable 'b' already declared in this scope".to_string()));
+        assert_eq!(table.scope_depth(), 1);
+        assert_eq!(table.iter_symbols().count(), 0);
+
+        table.exit_to_depth(0);
+        assert_eq!(table.scope_depth(), 1);
+    }
+
+    #[test]
+    fn test_symbol_introspection() {
+        let mut table = SymbolTable::new();
+        table.define("x", Value::Integer(1), false).unwrap();
+        table.enter_scope();
+        table.define("x", Value::from("inner"), true).unwrap();
+        table.define("y", Value::Boolean(true), true).unwrap();
+
+        let all: Vec<_> = table.iter_symbols().map(|symbol| (symbol.name.as_str(), symbol.data_type)).collect();
+        assert_eq!(all, [("x", DataType::Integer), ("x", DataType::String), ("y", DataType::Boolean)]);
+        assert_eq!(table.globals().len(), 1);
+        assert!(!table.globals()[0].is_mutable);
+        let inner: Vec<_> = table.symbols_in_scope(1).iter().map(|symbol| symbol.value.clone()).collect();
+        assert_eq!(inner, [Value::from("inner"), Value::Boolean(true)]);
+        assert!(table.symbols_in_scope(2).is_empty());
+    }
+
+    #[test]
+    fn test_slot_access() {
+        let mut table = SymbolTable::new();
+        table.define("x", Value::Integer(1), true).unwrap();
+        table.enter_scope();
+        table.define("y", Value::Integer(2), true).unwrap();
+        table.define("x", Value::Integer(3), true).unwrap();
+
+        let inner_x = table.resolve("x").unwrap();
+        assert_eq!(inner_x, Slot { depth: 1, index: 1 });
+        table.assign_slot(inner_x, Value::Integer(30)).unwrap();
+        assert_eq!(table.get(inner_x).unwrap().value, Value::Integer(30));
+
+        // A stale slot is rejected and the name is resolved again
+        table.exit_scope().unwrap();
+        assert!(table.get(inner_x).is_none());
+        assert_eq!(table.locate(Atom::intern("x"), Some(inner_x)), Some(Slot { depth: 0, index: 0 }));
+        assert_eq!(table.get_value("x").unwrap(), Value::Integer(1));
+    }
 }
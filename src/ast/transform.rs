@@ -0,0 +1,291 @@
+//! Transformations - rewriting the tree in place between parsing and evaluation
+//!
+//! An `AstTransformer` overrides the hooks for the nodes it rewrites and leaves the rest
+//! to the default walk, which recurses into every child. Hooks see a node before its
+//! children are walked; calling `walk_expression` or `walk_statement` first instead
+//! rewrites bottom-up, as constant folding does so that `sqrt(abs(-16))` folds completely.
+
+use crate::ast::types::Value;
+use crate::ast::{Ast, ASTExpression, ASTExpressionKind, ASTStatement, ASTStatementKind};
+use std::mem;
+
+/// A pass that rewrites statements and expressions in place
+pub trait AstTransformer {
+    fn transform_statement(&mut self, statement: &mut ASTStatement) {
+        walk_statement(self, statement);
+    }
+
+    fn transform_expression(&mut self, expression: &mut ASTExpression) {
+        walk_expression(self, expression);
+    }
+
+    /// Runs the pass over every statement of `ast`
+    fn transform(&mut self, ast: &mut Ast) {
+        for statement in &mut ast.statements {
+            self.transform_statement(statement);
+        }
+    }
+}
+
+/// Transforms each expression and nested statement of `statement`, in evaluation order
+pub fn walk_statement<T: AstTransformer + ?Sized>(transformer: &mut T, statement: &mut ASTStatement) {
+    match &mut statement.kind {
+        ASTStatementKind::Expression(expr) | ASTStatementKind::Defer(expr) => transformer.transform_expression(expr),
+        ASTStatementKind::VariableDeclaration(decl) => transformer.transform_expression(&mut decl.initializer),
+        ASTStatementKind::Assignment(assign) => {
+            for index in &mut assign.indices {
+                transformer.transform_expression(index);
+            }
+            transformer.transform_expression(&mut assign.value);
+        }
+        ASTStatementKind::Import(_) | ASTStatementKind::LoopControl(_) => {}
+        ASTStatementKind::For(for_statement) => {
+            transformer.transform_expression(&mut for_statement.iterable);
+            for statement in &mut for_statement.body {
+                transformer.transform_statement(statement);
+            }
+        }
+        ASTStatementKind::While(while_statement) => {
+            transformer.transform_expression(&mut while_statement.condition);
+            for statement in &mut while_statement.body {
+                transformer.transform_statement(statement);
+            }
+        }
+        ASTStatementKind::With(with) => {
+            transformer.transform_expression(&mut with.resource);
+            for statement in &mut with.body {
+                transformer.transform_statement(statement);
+            }
+        }
+        ASTStatementKind::Guard(guard) => {
+            transformer.transform_expression(&mut guard.condition);
+            for statement in &mut guard.body {
+                transformer.transform_statement(statement);
+            }
+        }
+        ASTStatementKind::If(if_statement) => {
+            for arm in &mut if_statement.arms {
+                transformer.transform_expression(&mut arm.condition);
+                for statement in &mut arm.body {
+                    transformer.transform_statement(statement);
+                }
+            }
+            for statement in if_statement.else_body.iter_mut().flatten() {
+                transformer.transform_statement(statement);
+            }
+        }
+        ASTStatementKind::Match(match_statement) => {
+            transformer.transform_expression(&mut match_statement.subject);
+            for arm in &mut match_statement.arms {
+                if let Some(guard) = &mut arm.guard {
+                    transformer.transform_expression(guard);
+                }
+                for statement in &mut arm.body {
+                    transformer.transform_statement(statement);
+                }
+            }
+        }
+    }
+}
+
+/// Transforms each direct sub-expression of `expression`, in evaluation order
+pub fn walk_expression<T: AstTransformer + ?Sized>(transformer: &mut T, expression: &mut ASTExpression) {
+    // A block's statements are transformed as statements, not just for their expressions
+    if let ASTExpressionKind::Block(block) = &mut expression.kind {
+        for statement in &mut block.statements {
+            transformer.transform_statement(statement);
+        }
+        return;
+    }
+    for child in children_mut(expression) {
+        transformer.transform_expression(child);
+    }
+}
+
+/// Replaces `expression` with what `rewrite` builds from it, handing over the old node by
+/// value so its parts can be moved into the new one without cloning
+pub fn replace_expression(expression: &mut ASTExpression, rewrite: impl FnOnce(ASTExpression) -> ASTExpression) {
+    let old = mem::replace(expression, ASTExpression::literal(Value::Boolean(false)));
+    *expression = rewrite(old);
+}
+
+/// The expressions a statement evaluates, including those of statements nested in it
+pub fn statement_expressions(statement: &ASTStatement) -> Vec<&ASTExpression> {
+    match &statement.kind {
+        ASTStatementKind::Expression(expr) | ASTStatementKind::Defer(expr) => vec![expr],
+        ASTStatementKind::VariableDeclaration(decl) => vec![&decl.initializer],
+        ASTStatementKind::Assignment(assign) => assign.indices.iter().chain(std::iter::once(&*assign.value)).collect(),
+        ASTStatementKind::Import(_) | ASTStatementKind::LoopControl(_) => Vec::new(),
+        ASTStatementKind::For(for_statement) => std::iter::once(&*for_statement.iterable)
+            .chain(for_statement.body.iter().flat_map(statement_expressions))
+            .collect(),
+        ASTStatementKind::While(while_statement) => std::iter::once(&*while_statement.condition)
+            .chain(while_statement.body.iter().flat_map(statement_expressions))
+            .collect(),
+        ASTStatementKind::With(with) => std::iter::once(&*with.resource)
+            .chain(with.body.iter().flat_map(statement_expressions))
+            .collect(),
+        ASTStatementKind::Guard(guard) => std::iter::once(&*guard.condition)
+            .chain(guard.body.iter().flat_map(statement_expressions))
+            .collect(),
+        ASTStatementKind::If(if_statement) => {
+            let mut expressions = Vec::new();
+            for arm in &if_statement.arms {
+                expressions.push(&arm.condition);
+                expressions.extend(arm.body.iter().flat_map(statement_expressions));
+            }
+            expressions.extend(if_statement.else_body.iter().flatten().flat_map(statement_expressions));
+            expressions
+        }
+        ASTStatementKind::Match(match_statement) => {
+            let mut expressions = vec![&*match_statement.subject];
+            for arm in &match_statement.arms {
+                expressions.extend(&arm.guard);
+                expressions.extend(arm.body.iter().flat_map(statement_expressions));
+            }
+            expressions
+        }
+    }
+}
+
+/// Mutable `statement_expressions`, in the same order
+pub fn statement_expressions_mut(statement: &mut ASTStatement) -> Vec<&mut ASTExpression> {
+    match &mut statement.kind {
+        ASTStatementKind::Expression(expr) | ASTStatementKind::Defer(expr) => vec![expr],
+        ASTStatementKind::VariableDeclaration(decl) => vec![&mut decl.initializer],
+        ASTStatementKind::Assignment(assign) => {
+            assign.indices.iter_mut().chain(std::iter::once(&mut *assign.value)).collect()
+        }
+        ASTStatementKind::Import(_) | ASTStatementKind::LoopControl(_) => Vec::new(),
+        ASTStatementKind::For(for_statement) => std::iter::once(&mut *for_statement.iterable)
+            .chain(for_statement.body.iter_mut().flat_map(statement_expressions_mut))
+            .collect(),
+        ASTStatementKind::While(while_statement) => std::iter::once(&mut *while_statement.condition)
+            .chain(while_statement.body.iter_mut().flat_map(statement_expressions_mut))
+            .collect(),
+        ASTStatementKind::With(with) => std::iter::once(&mut *with.resource)
+            .chain(with.body.iter_mut().flat_map(statement_expressions_mut))
+            .collect(),
+        ASTStatementKind::Guard(guard) => std::iter::once(&mut *guard.condition)
+            .chain(guard.body.iter_mut().flat_map(statement_expressions_mut))
+            .collect(),
+        ASTStatementKind::If(if_statement) => {
+            let mut expressions = Vec::new();
+            for arm in &mut if_statement.arms {
+                expressions.push(&mut arm.condition);
+                expressions.extend(arm.body.iter_mut().flat_map(statement_expressions_mut));
+            }
+            expressions.extend(if_statement.else_body.iter_mut().flatten().flat_map(statement_expressions_mut));
+            expressions
+        }
+        ASTStatementKind::Match(match_statement) => {
+            let mut expressions = vec![&mut *match_statement.subject];
+            for arm in &mut match_statement.arms {
+                expressions.extend(&mut arm.guard);
+                expressions.extend(arm.body.iter_mut().flat_map(statement_expressions_mut));
+            }
+            expressions
+        }
+    }
+}
+
+/// The direct sub-expressions of an expression, in evaluation order; for a block, the
+/// expressions of its statements
+pub fn children(expression: &ASTExpression) -> Vec<&ASTExpression> {
+    match &expression.kind {
+        ASTExpressionKind::Number(_) | ASTExpressionKind::Identifier(_) => Vec::new(),
+        ASTExpressionKind::Binary(expr) => vec![&expr.left, &expr.right],
+        ASTExpressionKind::Paranthesized(paren_expr) => vec![&paren_expr.expression],
+        ASTExpressionKind::Unary(unary_expr) => vec![&unary_expr.operand],
+        ASTExpressionKind::FunctionCall(func_call) => {
+            std::iter::once(&*func_call.callee).chain(&func_call.arguments).collect()
+        }
+        ASTExpressionKind::MemberAccess(member) => vec![&member.object],
+        ASTExpressionKind::MethodCall(method_call) => {
+            std::iter::once(&*method_call.receiver).chain(&method_call.arguments).collect()
+        }
+        ASTExpressionKind::Array(array) => array.elements.iter().collect(),
+        ASTExpressionKind::Map(map) => map.entries.iter().flat_map(|(key, value)| [key, value]).collect(),
+        ASTExpressionKind::Set(set) => set.elements.iter().collect(),
+        ASTExpressionKind::Index(index) => vec![&index.object, &index.index],
+        ASTExpressionKind::Spread(spread) => vec![&spread.expression],
+        ASTExpressionKind::Pipe(pipe) => vec![&pipe.value, &pipe.target],
+        ASTExpressionKind::Block(block) => block.statements.iter().flat_map(statement_expressions).collect(),
+    }
+}
+
+/// Mutable `children`, in the same order
+pub fn children_mut(expression: &mut ASTExpression) -> Vec<&mut ASTExpression> {
+    match &mut expression.kind {
+        ASTExpressionKind::Number(_) | ASTExpressionKind::Identifier(_) => Vec::new(),
+        ASTExpressionKind::Binary(expr) => vec![&mut expr.left, &mut expr.right],
+        ASTExpressionKind::Paranthesized(paren_expr) => vec![&mut paren_expr.expression],
+        ASTExpressionKind::Unary(unary_expr) => vec![&mut unary_expr.operand],
+        ASTExpressionKind::FunctionCall(func_call) => {
+            std::iter::once(&mut *func_call.callee).chain(&mut func_call.arguments).collect()
+        }
+        ASTExpressionKind::MemberAccess(member) => vec![&mut member.object],
+        ASTExpressionKind::MethodCall(method_call) => {
+            std::iter::once(&mut *method_call.receiver).chain(&mut method_call.arguments).collect()
+        }
+        ASTExpressionKind::Array(array) => array.elements.iter_mut().collect(),
+        ASTExpressionKind::Map(map) => map.entries.iter_mut().flat_map(|(key, value)| [key, value]).collect(),
+        ASTExpressionKind::Set(set) => set.elements.iter_mut().collect(),
+        ASTExpressionKind::Index(index) => vec![&mut index.object, &mut index.index],
+        ASTExpressionKind::Spread(spread) => vec![&mut spread.expression],
+        ASTExpressionKind::Pipe(pipe) => vec![&mut pipe.value, &mut pipe.target],
+        ASTExpressionKind::Block(block) => block.statements.iter_mut().flat_map(statement_expressions_mut).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::lexer::Lexer;
+    use crate::ast::parser::Parser;
+
+    /// Unwraps every parenthesized expression, including those nested in loops and branches
+    struct Unparenthesize;
+
+    impl AstTransformer for Unparenthesize {
+        fn transform_expression(&mut self, expression: &mut ASTExpression) {
+            if matches!(expression.kind, ASTExpressionKind::Paranthesized(_)) {
+                replace_expression(expression, |old| match old.kind {
+                    ASTExpressionKind::Paranthesized(paren_expr) => *paren_expr.expression,
+                    _ => old,
+                });
+                self.transform_expression(expression);
+            } else {
+                walk_expression(self, expression);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transformer_rewrites_nested_nodes() {
+        let mut ast = Ast::new();
+        ast.statements = Parser::from_lexer(Lexer::new("for i in ((xs)) { if (i) { f(((i)), (1)) } }")).parse_all();
+        Unparenthesize.transform(&mut ast);
+
+        let mut remaining = 0;
+        let mut pending: Vec<&ASTExpression> = Vec::new();
+        let ASTStatementKind::For(for_statement) = &ast.statements[0].kind else {
+            panic!("expected a for loop");
+        };
+        pending.push(&for_statement.iterable);
+        let ASTStatementKind::If(if_statement) = &for_statement.body[0].kind else {
+            panic!("expected an if statement");
+        };
+        pending.push(&if_statement.arms[0].condition);
+        let ASTStatementKind::Expression(call) = &if_statement.arms[0].body[0].kind else {
+            panic!("expected an expression");
+        };
+        pending.push(call);
+        while let Some(expression) = pending.pop() {
+            remaining += usize::from(matches!(expression.kind, ASTExpressionKind::Paranthesized(_)));
+            pending.extend(children(expression));
+        }
+        assert_eq!(remaining, 0);
+        assert!(matches!(for_statement.iterable.kind, ASTExpressionKind::Identifier(_)));
+    }
+}
@@ -1,24 +1,135 @@
 //! Type system - defines data types and values with operations
 
+use crate::ast::channels::Channel;
+use crate::ast::interner::Atom;
+use crate::ast::number_format::NumberFormat;
+use crate::ast::unparse::string_literal;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
 
 /// Data types supported by Arc language
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DataType {
     Integer,
     Float,
     Boolean,
     String,
+    Bytes,
+    Function,
+    Array,
+    Map,
+    Set,
+    Range,
+    Channel,
     Unknown,
 }
 
 /// Runtime value with type information
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Value {
     Integer(i64),
     Float(f64),
     Boolean(bool),
-    String(String),
+    String(Arc<str>),
+    /// Immutable binary data, written `b"..."`
+    Bytes(Arc<[u8]>),
+    /// A builtin or host function, or one built from others (see `ast::functions`)
+    Function(Function),
+    /// Arrays and maps are values: copies share storage until one of them is changed,
+    /// which copies it first (see `Value::set_element`)
+    Array(Arc<Aggregate<Vec<Value>>>),
+    /// String keys, iterated in key order
+    Map(Arc<Aggregate<BTreeMap<Arc<str>, Value>>>),
+    /// Distinct elements, iterated in order
+    Set(Arc<Aggregate<BTreeSet<SetElement>>>),
+    /// The integers from `start` up to but not including `end`
+    Range(i64, i64),
+    /// A queue shared by every copy of the value (see `ast::channels`)
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Channel(Arc<Channel>),
+}
+
+/// A function value
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Function {
+    /// A builtin or host function, referred to by name
+    Named(Atom),
+    /// A function with its first arguments already supplied (see `Function::bind`)
+    Bound(Arc<BoundFunction>),
+    /// `compose(f, g)`: calls `g`, then `f` with its result
+    Composed(Arc<(Function, Function)>),
+    /// `constantly(v)`: returns `v` whatever it is called with
+    Constant(Arc<Value>),
+}
+
+/// `bind(function, args)`: calling it calls `function` with `args` followed by the call's
+/// own arguments
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoundFunction {
+    pub function: Function,
+    pub args: Vec<Value>,
+}
+
+impl Function {
+    /// Supplies `function`'s first arguments. Binding a bound function adds to its arguments
+    /// rather than wrapping it again.
+    pub fn bind(function: Function, args: Vec<Value>) -> Function {
+        if args.is_empty() {
+            return function;
+        }
+        let (function, args) = match function {
+            Function::Bound(bound) => (bound.function.clone(), bound.args.iter().cloned().chain(args).collect()),
+            function => (function, args),
+        };
+        Function::Bound(Arc::new(BoundFunction { function, args }))
+    }
+
+    /// The name a call is charged to in profiles: the builtin or host function a bound
+    /// function runs, and the builtin that made a composed or constant one
+    pub fn name(&self) -> Atom {
+        match self {
+            Function::Named(name) => *name,
+            Function::Bound(bound) => bound.function.name(),
+            Function::Composed(_) => Atom::intern("compose"),
+            Function::Constant(_) => Atom::intern("constantly"),
+        }
+    }
+}
+
+impl From<Atom> for Function {
+    fn from(name: Atom) -> Self {
+        Function::Named(name)
+    }
+}
+
+/// The expression that built the function, e.g. `sqrt`, `bind(pow, 2)` or `compose(abs, f)`
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Function::Named(name) => write!(f, "{}", name),
+            Function::Bound(bound) => {
+                write!(f, "bind({}", bound.function)?;
+                for arg in &bound.args {
+                    write!(f, ", ")?;
+                    arg.write_element(f, &NumberFormat::default())?;
+                }
+                write!(f, ")")
+            }
+            Function::Composed(pair) => write!(f, "compose({}, {})", pair.0, pair.1),
+            Function::Constant(value) => {
+                write!(f, "constantly(")?;
+                value.write_element(f, &NumberFormat::default())?;
+                write!(f, ")")
+            }
+        }
+    }
 }
 
 impl Value {
@@ -28,6 +139,13 @@ impl Value {
             Value::Float(_) => DataType::Float,
             Value::Boolean(_) => DataType::Boolean,
             Value::String(_) => DataType::String,
+            Value::Bytes(_) => DataType::Bytes,
+            Value::Function(_) => DataType::Function,
+            Value::Array(_) => DataType::Array,
+            Value::Map(_) => DataType::Map,
+            Value::Set(_) => DataType::Set,
+            Value::Range(..) => DataType::Range,
+            Value::Channel(_) => DataType::Channel,
         }
     }
 
@@ -45,8 +163,8 @@ impl Value {
             (Value::Float(f), Value::Integer(i)) => Ok((Value::Float(*f), Value::Float(*i as f64))),
             
             // String concatenation with any type
-            (Value::String(s), other) => Ok((Value::String(s.clone()), Value::String(other.to_string()))),
-            (other, Value::String(s)) => Ok((Value::String(other.to_string()), Value::String(s.clone()))),
+            (Value::String(s), other) => Ok((Value::String(s.clone()), Value::from(other.to_string()))),
+            (other, Value::String(s)) => Ok((Value::from(other.to_string()), Value::String(s.clone()))),
             
             _ => Err(format!("Cannot coerce {:?} and {:?} to a common type", left.get_type(), right.get_type())),
         }
@@ -59,6 +177,12 @@ impl Value {
             Value::Integer(i) => *i != 0,
             Value::Float(f) => *f != 0.0,
             Value::String(s) => !s.is_empty(),
+            Value::Bytes(b) => !b.is_empty(),
+            Value::Function(_) | Value::Channel(_) => true,
+            Value::Array(items) => !items.is_empty(),
+            Value::Map(entries) => !entries.is_empty(),
+            Value::Set(elements) => !elements.is_empty(),
+            Value::Range(start, end) => start < end,
         }
     }
 
@@ -69,62 +193,434 @@ impl Value {
             Value::Float(f) => Ok(*f as i64),
             Value::Boolean(b) => Ok(if *b { 1 } else { 0 }),
             Value::String(_) => Err("Cannot convert string to integer for bitwise operations".to_string()),
+            other => Err(format!("Cannot convert {} to integer for bitwise operations", other.get_type().to_string().to_lowercase())),
         }
     }
 
-    /// Compare two values for equality
+    /// Compare two values for equality. Floats compare exactly, as IEEE 754 defines:
+    /// `0.1 + 0.2 == 0.3` is false and NaN equals nothing, itself included. Use the
+    /// `approx_eq` builtin for tolerant comparisons.
     pub fn equals(&self, other: &Value) -> Result<bool, String> {
         match (self, other) {
             (Value::Integer(a), Value::Integer(b)) => Ok(a == b),
-            (Value::Float(a), Value::Float(b)) => Ok((a - b).abs() < f64::EPSILON),
+            (Value::Float(a), Value::Float(b)) => Ok(a == b),
             (Value::Boolean(a), Value::Boolean(b)) => Ok(a == b),
             (Value::String(a), Value::String(b)) => Ok(a == b),
+            (Value::Bytes(a), Value::Bytes(b)) => Ok(a == b),
+            (Value::Function(a), Value::Function(b)) => Ok(a == b),
+            (Value::Range(a_start, a_end), Value::Range(b_start, b_end)) => Ok(a_start == b_start && a_end == b_end),
+            (Value::Channel(a), Value::Channel(b)) => Ok(Arc::ptr_eq(a, b)),
+            // Aggregates are equal when their elements are; elements that cannot be compared differ
+            (Value::Array(a), Value::Array(b)) => {
+                Ok(a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.equals(y) == Ok(true)))
+            }
+            (Value::Map(a), Value::Map(b)) => Ok(a.len() == b.len()
+                && a.iter().all(|(key, x)| b.get(key).is_some_and(|y| x.equals(y) == Ok(true)))),
+            (Value::Set(a), Value::Set(b)) => Ok(a.items == b.items),
             // Allow comparison between int and float
             (Value::Integer(i), Value::Float(f)) | (Value::Float(f), Value::Integer(i)) => {
-                Ok((*i as f64 - f).abs() < f64::EPSILON)
+                Ok(compare_integer_float(*i, *f) == Some(Ordering::Equal))
             },
             _ => Err(format!("Cannot compare {:?} and {:?} for equality", self.get_type(), other.get_type())),
         }
     }
 
-    /// Compare two values with ordering
-    pub fn compare(&self, other: &Value) -> Result<std::cmp::Ordering, String> {
-        use std::cmp::Ordering;
-        
+    /// Compare two values with ordering. `None` means the values are unordered, which
+    /// happens only when a NaN is involved: every `<`, `<=`, `>` and `>=` with a NaN is false.
+    ///
+    /// Strings compare by Unicode code point, not by any locale's collation, so `"Z" < "a"`
+    /// and `"é" > "z"`. Arrays compare lexicographically.
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, String> {
         match (self, other) {
-            (Value::Integer(a), Value::Integer(b)) => Ok(a.cmp(b)),
-            (Value::Float(a), Value::Float(b)) => {
-                if a < b { Ok(Ordering::Less) }
-                else if a > b { Ok(Ordering::Greater) }
-                else { Ok(Ordering::Equal) }
-            },
-            (Value::Boolean(a), Value::Boolean(b)) => Ok(a.cmp(b)),
-            (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+            (Value::Integer(a), Value::Integer(b)) => Ok(Some(a.cmp(b))),
+            (Value::Float(a), Value::Float(b)) => Ok(a.partial_cmp(b)),
+            (Value::Boolean(a), Value::Boolean(b)) => Ok(Some(a.cmp(b))),
+            (Value::String(a), Value::String(b)) => Ok(Some(a.cmp(b))),
+            (Value::Bytes(a), Value::Bytes(b)) => Ok(Some(a.cmp(b))),
+            // Element by element, then by length, like strings
+            (Value::Array(a), Value::Array(b)) => {
+                for (x, y) in a.iter().zip(b.iter()) {
+                    match x.compare(y)? {
+                        Some(Ordering::Equal) => {}
+                        ordering => return Ok(ordering),
+                    }
+                }
+                Ok(Some(a.len().cmp(&b.len())))
+            }
             // Allow comparison between int and float
-            (Value::Integer(i), Value::Float(f)) => {
-                let i_float = *i as f64;
-                if i_float < *f { Ok(Ordering::Less) }
-                else if i_float > *f { Ok(Ordering::Greater) }
-                else { Ok(Ordering::Equal) }
-            },
-            (Value::Float(f), Value::Integer(i)) => {
-                let i_float = *i as f64;
-                if f < &i_float { Ok(Ordering::Less) }
-                else if f > &i_float { Ok(Ordering::Greater) }
-                else { Ok(Ordering::Equal) }
-            },
+            (Value::Integer(i), Value::Float(f)) => Ok(compare_integer_float(*i, *f)),
+            (Value::Float(f), Value::Integer(i)) => Ok(compare_integer_float(*i, *f).map(Ordering::reverse)),
             _ => Err(format!("Cannot compare {:?} and {:?}", self.get_type(), other.get_type())),
         }
     }
 }
 
+/// An element of a set. Only values that compare exactly can be elements, so a set never
+/// has to decide whether `1` and `1.0` are the same element.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SetElement {
+    Boolean(bool),
+    Integer(i64),
+    String(Arc<str>),
+}
+
+impl TryFrom<&Value> for SetElement {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(SetElement::Boolean(*b)),
+            Value::Integer(i) => Ok(SetElement::Integer(*i)),
+            Value::String(s) => Ok(SetElement::String(s.clone())),
+            other => Err(format!("Set elements must be Integer, String or Boolean, found {}", other.get_type())),
+        }
+    }
+}
+
+impl From<SetElement> for Value {
+    fn from(element: SetElement) -> Self {
+        match element {
+            SetElement::Boolean(b) => Value::Boolean(b),
+            SetElement::Integer(i) => Value::Integer(i),
+            SetElement::String(s) => Value::String(s),
+        }
+    }
+}
+
+/// The contents of an array, map or set, and whether `freeze` has made them read-only
+///
+/// This is the heap for aggregate values: each `Aggregate` is reference counted through the
+/// `Arc` that holds it and freed when the last value sharing it goes away. Storing into a
+/// shared aggregate copies it first, so a value only ever contains values that already
+/// existed when it was built. No aggregate can reach itself, and reference counting alone
+/// reclaims everything, without a cycle collector.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Aggregate<T> {
+    items: T,
+    frozen: bool,
+}
+
+impl<T> Aggregate<T> {
+    pub fn new(items: T) -> Self {
+        Aggregate { items, frozen: false }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+}
+
+impl<T> Deref for Aggregate<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.items
+    }
+}
+
+impl Value {
+    /// Stores `value` at `path` inside this array or map, where each step is an array
+    /// position or a map key. Shared storage is copied before it is changed, so other
+    /// copies of the value never see the change.
+    pub fn set_element(&mut self, path: &[Value], value: Value) -> Result<(), String> {
+        let Some((last, path)) = path.split_last() else {
+            *self = value;
+            return Ok(());
+        };
+        let mut target = self;
+        for step in path {
+            target = target.element_mut(step)?;
+        }
+        match (target, last) {
+            (Value::Map(entries), Value::String(key)) => {
+                let entries = writable(entries)?;
+                entries.items.insert(key.clone(), value);
+                Ok(())
+            }
+            (target, last) => {
+                *target.element_mut(last)? = value;
+                Ok(())
+            }
+        }
+    }
+
+    /// The existing element at `step`, made writable
+    fn element_mut(&mut self, step: &Value) -> Result<&mut Value, String> {
+        let type_name = self.get_type();
+        match (self, step) {
+            (Value::Array(items), Value::Integer(i)) => {
+                let items = writable(items)?;
+                let length = items.items.len();
+                usize::try_from(*i)
+                    .ok()
+                    .and_then(|i| items.items.get_mut(i))
+                    .ok_or_else(|| format!("Index {} is out of bounds for length {}", i, length))
+            }
+            (Value::Map(entries), Value::String(key)) => writable(entries)?
+                .items
+                .get_mut(key)
+                .ok_or_else(|| format!("Key {} not found in map", string_literal(key))),
+            (Value::String(_), _) => Err("Cannot assign into a String; strings are immutable".to_string()),
+            (Value::Bytes(_), _) => Err("Cannot assign into Bytes; bytes are immutable".to_string()),
+            (_, step) => Err(format!("Cannot assign into {} with {}", type_name, step.get_type())),
+        }
+    }
+
+    /// Whether `item` is in this collection: an element of a set or array, a key of a map,
+    /// a substring of a string, a byte of bytes or an integer of a range
+    pub fn contains(&self, item: &Value) -> Result<bool, String> {
+        match (self, item) {
+            (Value::Set(elements), item) => Ok(SetElement::try_from(item).is_ok_and(|element| elements.contains(&element))),
+            (Value::Array(items), item) => Ok(items.iter().any(|element| element.equals(item) == Ok(true))),
+            (Value::Map(entries), Value::String(key)) => Ok(entries.contains_key(key)),
+            (Value::Map(_), _) => Ok(false),
+            (Value::String(text), Value::String(part)) => Ok(text.contains(&**part)),
+            (Value::Bytes(bytes), Value::Integer(byte)) => Ok(u8::try_from(*byte).is_ok_and(|byte| bytes.contains(&byte))),
+            (Value::Range(start, end), Value::Integer(i)) => Ok(start <= i && i < end),
+            (Value::Range(..), _) => Ok(false),
+            (collection, item) => Err(format!("Cannot test whether {} is in {}", item.get_type(), collection.get_type())),
+        }
+    }
+
+    /// A deep copy sharing no storage with this value, with every aggregate in it unfrozen
+    pub fn deep_copy(&self) -> Value {
+        self.with_frozen(false)
+    }
+
+    /// This value with it and every aggregate inside it frozen
+    pub fn frozen(&self) -> Value {
+        self.with_frozen(true)
+    }
+
+    fn with_frozen(&self, frozen: bool) -> Value {
+        match self {
+            Value::Array(items) => Value::Array(Arc::new(Aggregate {
+                items: items.iter().map(|item| item.with_frozen(frozen)).collect(),
+                frozen,
+            })),
+            Value::Map(entries) => Value::Map(Arc::new(Aggregate {
+                items: entries.iter().map(|(key, value)| (key.clone(), value.with_frozen(frozen))).collect(),
+                frozen,
+            })),
+            Value::Set(elements) => Value::Set(Arc::new(Aggregate { items: elements.items.clone(), frozen })),
+            other => other.clone(),
+        }
+    }
+}
+
+/// Unshares an aggregate for writing, refusing if it is frozen
+fn writable<T: Clone>(aggregate: &mut Arc<Aggregate<T>>) -> Result<&mut Aggregate<T>, String> {
+    if aggregate.frozen {
+        return Err("Cannot modify a frozen value".to_string());
+    }
+    Ok(Arc::make_mut(aggregate))
+}
+
+/// Orders an integer against a float without the rounding of `i as f64`, which would
+/// make `9007199254740993 == 9007199254740992.0` true
+fn compare_integer_float(i: i64, f: f64) -> Option<Ordering> {
+    match (i as f64).partial_cmp(&f)? {
+        // Rounded equal, so `f` is a whole number in [-2^63, 2^63]; compare exactly
+        Ordering::Equal if f >= 9_223_372_036_854_775_808.0 => Some(Ordering::Less),
+        Ordering::Equal => Some(i.cmp(&(f as i64))),
+        ordering => Some(ordering),
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(Arc::from(value))
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(Arc::from(value))
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::Array(Arc::new(Aggregate::new(value)))
+    }
+}
+
+impl From<BTreeMap<Arc<str>, Value>> for Value {
+    fn from(value: BTreeMap<Arc<str>, Value>) -> Self {
+        Value::Map(Arc::new(Aggregate::new(value)))
+    }
+}
+
+impl From<BTreeSet<SetElement>> for Value {
+    fn from(value: BTreeSet<SetElement>) -> Self {
+        Value::Set(Arc::new(Aggregate::new(value)))
+    }
+}
+
+/// Error message for a failed `TryFrom<Value>` conversion
+fn conversion_error(value: &Value, expected: DataType) -> String {
+    format!("Expected {} value, found {}", expected, value.get_type())
+}
+
+impl TryFrom<Value> for i64 {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Integer(i) => Ok(i),
+            other => Err(conversion_error(&other, DataType::Integer)),
+        }
+    }
+}
+
+/// Integers widen to floats, matching the interpreter's own coercion
+impl TryFrom<Value> for f64 {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Float(f) => Ok(f),
+            Value::Integer(i) => Ok(i as f64),
+            other => Err(conversion_error(&other, DataType::Float)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            other => Err(conversion_error(&other, DataType::Boolean)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s.to_string()),
+            other => Err(conversion_error(&other, DataType::String)),
+        }
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, &NumberFormat::default())
+    }
+}
+
+/// A value displayed with its numbers, nested ones included, written in a chosen format
+pub struct FormattedValue<'a> {
+    value: &'a Value,
+    format: &'a NumberFormat,
+}
+
+impl fmt::Display for FormattedValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.write(f, self.format)
+    }
+}
+
+impl Value {
+    /// Displays the value like `Display` does, but with numbers written in `format`
+    pub fn display_with<'a>(&'a self, format: &'a NumberFormat) -> FormattedValue<'a> {
+        FormattedValue { value: self, format }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, format: &NumberFormat) -> fmt::Result {
         match self {
-            Value::Integer(i) => write!(f, "{}", i),
-            Value::Float(fl) => write!(f, "{}", fl),
+            // The default format skips building a string per number
+            Value::Integer(i) if format.is_default() => write!(f, "{}", i),
+            Value::Float(fl) if format.is_default() => write!(f, "{}", fl),
+            Value::Integer(i) => write!(f, "{}", format.format_integer(*i)),
+            Value::Float(fl) => write!(f, "{}", format.format_float(*fl)),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::String(s) => write!(f, "{}", s),
+            Value::Bytes(bytes) => {
+                // Printable ASCII as is, everything else escaped, so the output reads back as a literal
+                write!(f, "b\"")?;
+                for &byte in bytes.iter() {
+                    match byte {
+                        b'"' => write!(f, "\\\"")?,
+                        b'\\' => write!(f, "\\\\")?,
+                        b'\n' => write!(f, "\\n")?,
+                        b'\t' => write!(f, "\\t")?,
+                        b'\r' => write!(f, "\\r")?,
+                        b' '..=b'~' => write!(f, "{}", byte as char)?,
+                        _ => write!(f, "\\x{:02x}", byte)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Function(function) => write!(f, "<function {}>", function),
+            Value::Channel(_) => write!(f, "<channel>"),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.write_element(f, format)?;
+                }
+                write!(f, "]")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", string_literal(key))?;
+                    value.write_element(f, format)?;
+                }
+                write!(f, "}}")
+            }
+            // `{}` would read back as an empty map
+            Value::Set(elements) if elements.is_empty() => write!(f, "set()"),
+            Value::Set(elements) => {
+                write!(f, "{{")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    Value::from(element.clone()).write_element(f, format)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Range(start, end) => write!(f, "{}..{}", start, end),
+        }
+    }
+
+    /// Formats a value inside an array or map, where strings are quoted
+    fn write_element(&self, f: &mut fmt::Formatter<'_>, format: &NumberFormat) -> fmt::Result {
+        match self {
+            Value::String(s) => f.write_str(&string_literal(s)),
+            other => other.write(f, format),
         }
     }
 }
@@ -136,7 +632,63 @@ impl fmt::Display for DataType {
             DataType::Float => write!(f, "Float"),
             DataType::Boolean => write!(f, "Boolean"),
             DataType::String => write!(f, "String"),
+            DataType::Bytes => write!(f, "Bytes"),
+            DataType::Function => write!(f, "Function"),
+            DataType::Array => write!(f, "Array"),
+            DataType::Map => write!(f, "Map"),
+            DataType::Set => write!(f, "Set"),
+            DataType::Range => write!(f, "Range"),
+            DataType::Channel => write!(f, "Channel"),
             DataType::Unknown => write!(f, "Unknown"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_conversions() {
+        assert_eq!(Value::from(5), Value::Integer(5));
+        assert_eq!(Value::from("hi"), Value::String("hi".into()));
+        assert_eq!(i64::try_from(Value::Integer(7)), Ok(7));
+        assert_eq!(f64::try_from(Value::Integer(2)), Ok(2.0));
+        assert_eq!(bool::try_from(Value::Integer(1)), Err("Expected Boolean value, found Integer".to_string()));
+    }
+
+    #[test]
+    fn test_float_equality_and_nan_ordering() {
+        assert_eq!(Value::Float(0.1 + 0.2).equals(&Value::Float(0.3)), Ok(false));
+        assert_eq!(Value::Float(1e20).equals(&Value::Float(1e20 + 16384.0)), Ok(false));
+        assert_eq!(Value::Integer(9_007_199_254_740_993).equals(&Value::Float(9_007_199_254_740_992.0)), Ok(false));
+        assert_eq!(Value::Integer(3).equals(&Value::Float(3.0)), Ok(true));
+
+        let nan = Value::Float(f64::NAN);
+        assert_eq!(nan.equals(&nan), Ok(false));
+        assert_eq!(nan.compare(&Value::Integer(1)), Ok(None));
+        assert_eq!(Value::Integer(i64::MAX).compare(&Value::Float(9.3e18)), Ok(Some(Ordering::Less)));
+    }
+
+    #[test]
+    fn test_aggregates_cannot_form_cycles() {
+        // `a[0] = a` stores the old array, not a reference back to `a`
+        let mut a = Value::from(vec![Value::Integer(1)]);
+        let before = a.clone();
+        a.set_element(&[Value::Integer(0)], before).unwrap();
+        assert_eq!(a.to_string(), "[[1]]");
+
+        let Value::Array(items) = &a else { unreachable!() };
+        let Value::Array(inner) = &items[0] else { unreachable!() };
+        assert_eq!((Arc::strong_count(items), Arc::strong_count(inner)), (1, 1));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let json = serde_json::to_string(&Value::Float(2.5)).unwrap();
+        assert_eq!(json, r#"{"Float":2.5}"#);
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), Value::Float(2.5));
+        assert_eq!(serde_json::to_string(&DataType::String).unwrap(), r#""String""#);
+    }
+}
@@ -0,0 +1,676 @@
+//! Unparsing - turning a tree back into source that parses to the same tree
+//!
+//! Parentheses are written where the source had them and wherever precedence or
+//! associativity needs them, so a tree built by hand or rewritten by a pass prints as
+//! source that means the same thing. Blocks are laid out one statement per line with four
+//! spaces per level. A literal the language cannot spell, such as a channel or an infinite
+//! float, is written as it displays and will not parse back.
+
+use crate::ast::types::Value;
+use crate::ast::{Ast, ASTExpression, ASTExpressionKind, ASTPattern, ASTStatement, ASTStatementKind};
+use std::fmt::Write;
+
+const INDENT: &str = "    ";
+
+impl Ast {
+    /// The program as source, one top-level statement per line
+    pub fn to_source(&self) -> String {
+        let mut unparser = Unparser::default();
+        for statement in &self.statements {
+            unparser.statement(statement);
+            unparser.out.push('\n');
+        }
+        unparser.out
+    }
+}
+
+/// A statement as source; nested blocks span several lines
+pub fn statement_source(statement: &ASTStatement) -> String {
+    let mut unparser = Unparser::default();
+    unparser.statement(statement);
+    unparser.out
+}
+
+/// An expression as source
+pub fn expression_source(expression: &ASTExpression) -> String {
+    let mut unparser = Unparser::default();
+    unparser.expression(expression);
+    unparser.out
+}
+
+/// A value as the literal that evaluates to it, such as `"line1\nline2"` for a string
+/// holding a newline; this is what the `repr` builtin returns
+pub fn value_source(value: &Value) -> String {
+    let mut unparser = Unparser::default();
+    unparser.literal(value);
+    unparser.out
+}
+
+/// A string as a quoted literal, with the escapes the lexer reads back to the same text
+pub fn string_literal(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    write_string(&mut out, text);
+    out
+}
+
+/// A `match` pattern as source
+pub fn pattern_source(pattern: &ASTPattern) -> String {
+    let mut unparser = Unparser::default();
+    unparser.pattern(pattern);
+    unparser.out
+}
+
+#[derive(Default)]
+struct Unparser {
+    out: String,
+    /// Nesting level of the block being written
+    depth: usize,
+}
+
+impl Unparser {
+    /// Writes a statement starting at the current column
+    fn statement(&mut self, statement: &ASTStatement) {
+        match &statement.kind {
+            ASTStatementKind::Expression(expr) => {
+                // A leading sign on a new line would continue the previous statement as a
+                // binary operator
+                let text = expression_source_at(expr, self.depth);
+                if text.starts_with(['+', '-']) {
+                    let _ = write!(self.out, "({})", text);
+                } else {
+                    self.out.push_str(&text);
+                }
+            }
+            ASTStatementKind::VariableDeclaration(decl) => {
+                for line in decl.doc.iter().flat_map(|doc| doc.lines()) {
+                    self.out.push_str("///");
+                    if !line.is_empty() {
+                        let _ = write!(self.out, " {}", line);
+                    }
+                    self.newline();
+                }
+                let keyword = if decl.is_mutable { "let" } else { "const" };
+                let _ = write!(self.out, "{} {} = ", keyword, decl.name);
+                self.expression(&decl.initializer);
+            }
+            ASTStatementKind::Assignment(assign) => {
+                self.out.push_str(assign.name.as_str());
+                for index in &assign.indices {
+                    self.out.push('[');
+                    self.expression(index);
+                    self.out.push(']');
+                }
+                self.out.push_str(" = ");
+                self.expression(&assign.value);
+            }
+            ASTStatementKind::Import(import) => {
+                self.out.push_str("import ");
+                write_string(&mut self.out, &import.path);
+            }
+            ASTStatementKind::For(for_statement) => {
+                if let Some(label) = for_statement.label {
+                    let _ = write!(self.out, "{}: ", label);
+                }
+                let _ = write!(self.out, "for {} in ", for_statement.pattern);
+                self.expression(&for_statement.iterable);
+                self.out.push(' ');
+                self.block(&for_statement.body);
+            }
+            ASTStatementKind::While(while_statement) => {
+                if let Some(label) = while_statement.label {
+                    let _ = write!(self.out, "{}: ", label);
+                }
+                self.out.push_str("while ");
+                self.expression(&while_statement.condition);
+                self.out.push(' ');
+                self.block(&while_statement.body);
+            }
+            ASTStatementKind::LoopControl(control) => {
+                let _ = write!(self.out, "{}", control);
+            }
+            ASTStatementKind::If(if_statement) => {
+                for (i, arm) in if_statement.arms.iter().enumerate() {
+                    self.out.push_str(if i == 0 { "if " } else { " else if " });
+                    self.expression(&arm.condition);
+                    self.out.push(' ');
+                    self.block(&arm.body);
+                }
+                if let Some(else_body) = &if_statement.else_body {
+                    self.out.push_str(" else ");
+                    self.block(else_body);
+                }
+            }
+            ASTStatementKind::Defer(expr) => {
+                self.out.push_str("defer ");
+                self.expression(expr);
+            }
+            ASTStatementKind::With(with) => {
+                self.out.push_str("with ");
+                self.expression(&with.resource);
+                let _ = write!(self.out, " as {} ", with.name);
+                self.block(&with.body);
+            }
+            ASTStatementKind::Guard(guard) => {
+                self.out.push_str("guard ");
+                self.expression(&guard.condition);
+                self.out.push_str(" else ");
+                self.block(&guard.body);
+            }
+            ASTStatementKind::Match(match_statement) => {
+                self.out.push_str("match ");
+                self.expression(&match_statement.subject);
+                self.out.push_str(" {");
+                self.depth += 1;
+                for arm in &match_statement.arms {
+                    self.newline();
+                    self.pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.out.push_str(" if ");
+                        self.expression(guard);
+                    }
+                    self.out.push_str(" => ");
+                    // A lone statement stays on the arm's line unless it would read as a block
+                    let text = match &arm.body[..] {
+                        [statement] => Some(statement_source_at(statement, self.depth)),
+                        _ => None,
+                    };
+                    match text {
+                        Some(text) if !text.contains('\n') && !text.starts_with('{') => self.out.push_str(&text),
+                        _ => self.block(&arm.body),
+                    }
+                }
+                self.depth -= 1;
+                self.newline();
+                self.out.push('}');
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &ASTPattern) {
+        match pattern {
+            ASTPattern::Wildcard => self.out.push('_'),
+            ASTPattern::Binding(name) => self.out.push_str(name.as_str()),
+            ASTPattern::Literal(value) => self.literal(value),
+            ASTPattern::Tuple(items) => {
+                self.out.push('(');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.pattern(item);
+                }
+                self.out.push(')');
+            }
+        }
+    }
+
+    /// Writes `{`, the statements one per line one level deeper, and `}`
+    fn block(&mut self, statements: &[ASTStatement]) {
+        self.out.push('{');
+        self.depth += 1;
+        for statement in statements {
+            self.newline();
+            self.statement(statement);
+        }
+        self.depth -= 1;
+        self.newline();
+        self.out.push('}');
+    }
+
+    /// Starts a new line indented to the current depth
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn expression(&mut self, expression: &ASTExpression) {
+        match &expression.kind {
+            ASTExpressionKind::Number(number) => self.literal(&number.value),
+            ASTExpressionKind::Binary(binary) => {
+                let precedence = binary.operator.precedence();
+                let right_associative = binary.operator.is_right_associative();
+                // Equal precedence needs parentheses on the side the operator does not group
+                let left_parens = binding(&binary.left)
+                    .is_some_and(|left| left < precedence || (left == precedence && right_associative));
+                let right_parens = binding(&binary.right)
+                    .is_some_and(|right| right < precedence || (right == precedence && !right_associative));
+                self.operand(&binary.left, left_parens);
+                let _ = write!(self.out, " {} ", binary.operator.kind);
+                self.operand(&binary.right, right_parens);
+            }
+            ASTExpressionKind::Paranthesized(paren_expr) => {
+                self.out.push('(');
+                self.expression(&paren_expr.expression);
+                self.out.push(')');
+            }
+            ASTExpressionKind::Unary(unary) => {
+                let _ = write!(self.out, "{}", unary.operator.kind);
+                self.operand(&unary.operand, !is_primary(&unary.operand));
+            }
+            ASTExpressionKind::Identifier(ident) => self.out.push_str(ident.name.as_str()),
+            ASTExpressionKind::FunctionCall(call) => {
+                self.operand(&call.callee, !is_primary(&call.callee));
+                self.list("(", &call.arguments, ")");
+            }
+            ASTExpressionKind::MemberAccess(member) => {
+                self.operand(&member.object, !is_primary(&member.object));
+                let _ = write!(self.out, ".{}", member.name);
+            }
+            ASTExpressionKind::MethodCall(method_call) => {
+                self.operand(&method_call.receiver, !is_primary(&method_call.receiver));
+                let _ = write!(self.out, ".{}", method_call.name);
+                self.list("(", &method_call.arguments, ")");
+            }
+            ASTExpressionKind::Array(array) => self.list("[", &array.elements, "]"),
+            ASTExpressionKind::Map(map) => {
+                self.out.push('{');
+                for (i, (key, value)) in map.entries.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(key);
+                    self.out.push_str(": ");
+                    self.expression(value);
+                }
+                self.out.push('}');
+            }
+            // `{}` is an empty map, so an empty set spreads an empty array
+            ASTExpressionKind::Set(set) if set.elements.is_empty() => self.out.push_str("{...[]}"),
+            ASTExpressionKind::Set(set) => self.list("{", &set.elements, "}"),
+            ASTExpressionKind::Index(index) => {
+                self.operand(&index.object, !is_primary(&index.object));
+                self.out.push('[');
+                self.expression(&index.index);
+                self.out.push(']');
+            }
+            ASTExpressionKind::Spread(spread) => {
+                self.out.push_str("...");
+                self.expression(&spread.expression);
+            }
+            // Pipelines group to the left, so only a pipeline on the right needs parentheses
+            ASTExpressionKind::Pipe(pipe) => {
+                self.expression(&pipe.value);
+                self.out.push_str(" |> ");
+                self.operand(&pipe.target, matches!(pipe.target.kind, ASTExpressionKind::Pipe(_)));
+            }
+            // `{ value }` would be a set; a leading `;` marks it as a block
+            ASTExpressionKind::Block(block) if block.statements.len() == 1 => {
+                self.out.push_str("{;");
+                self.depth += 1;
+                self.newline();
+                self.statement(&block.statements[0]);
+                self.depth -= 1;
+                self.newline();
+                self.out.push('}');
+            }
+            ASTExpressionKind::Block(block) => self.block(&block.statements),
+        }
+    }
+
+    fn operand(&mut self, expression: &ASTExpression, parens: bool) {
+        if parens {
+            self.out.push('(');
+        }
+        self.expression(expression);
+        if parens {
+            self.out.push(')');
+        }
+    }
+
+    fn list(&mut self, open: &str, items: &[ASTExpression], close: &str) {
+        self.out.push_str(open);
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expression(item);
+        }
+        self.out.push_str(close);
+    }
+
+    fn literal(&mut self, value: &Value) {
+        match value {
+            // Its magnitude is one more than the largest integer literal
+            Value::Integer(i64::MIN) => self.out.push_str("(-9223372036854775807 - 1)"),
+            Value::Integer(n) => {
+                let _ = write!(self.out, "{}", n);
+            }
+            Value::Float(f) if f.is_finite() => {
+                if f.is_sign_negative() {
+                    self.out.push('-');
+                }
+                // Floats are written without exponents, and need a '.' to lex as floats
+                let digits = f.abs().to_string();
+                self.out.push_str(&digits);
+                if !digits.contains('.') {
+                    self.out.push_str(".0");
+                }
+            }
+            Value::String(s) => write_string(&mut self.out, s),
+            Value::Bytes(bytes) => {
+                self.out.push_str("b\"");
+                for &byte in bytes.iter() {
+                    match byte {
+                        b'"' => self.out.push_str("\\\""),
+                        b'\\' => self.out.push_str("\\\\"),
+                        b'\n' => self.out.push_str("\\n"),
+                        b'\t' => self.out.push_str("\\t"),
+                        b'\r' => self.out.push_str("\\r"),
+                        b' '..=b'~' => self.out.push(byte as char),
+                        _ => {
+                            let _ = write!(self.out, "\\x{:02x}", byte);
+                        }
+                    }
+                }
+                self.out.push('"');
+            }
+            Value::Range(start, end) => {
+                self.literal(&Value::Integer(*start));
+                self.out.push_str("..");
+                self.literal(&Value::Integer(*end));
+            }
+            Value::Array(items) => {
+                self.out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.literal(item);
+                }
+                self.out.push(']');
+            }
+            Value::Map(entries) => {
+                self.out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    write_string(&mut self.out, key);
+                    self.out.push_str(": ");
+                    self.literal(value);
+                }
+                self.out.push('}');
+            }
+            Value::Set(elements) if elements.is_empty() => self.out.push_str("{...[]}"),
+            Value::Set(elements) => {
+                self.out.push('{');
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.literal(&Value::from(element.clone()));
+                }
+                self.out.push('}');
+            }
+            other => {
+                let _ = write!(self.out, "{}", other);
+            }
+        }
+    }
+}
+
+/// Source for an expression inside a block `depth` levels deep
+fn expression_source_at(expression: &ASTExpression, depth: usize) -> String {
+    let mut unparser = Unparser { out: String::new(), depth };
+    unparser.expression(expression);
+    unparser.out
+}
+
+fn statement_source_at(statement: &ASTStatement, depth: usize) -> String {
+    let mut unparser = Unparser { out: String::new(), depth };
+    unparser.statement(statement);
+    unparser.out
+}
+
+/// How tightly an operand written without parentheses binds, when that could regroup it;
+/// `None` for one that binds as tightly as a unary operand
+fn binding(expression: &ASTExpression) -> Option<u8> {
+    match &expression.kind {
+        ASTExpressionKind::Binary(binary) => Some(binary.operator.precedence()),
+        ASTExpressionKind::Pipe(_) => Some(0),
+        // Written as `start..end` or `(-9223372036854775807 - 1)`
+        ASTExpressionKind::Number(number) if matches!(number.value, Value::Range(..)) => Some(0),
+        _ => None,
+    }
+}
+
+/// Whether an expression is written as a primary that postfix and unary operators can be
+/// applied to without parentheses
+fn is_primary(expression: &ASTExpression) -> bool {
+    match &expression.kind {
+        ASTExpressionKind::Number(number) => match number.value {
+            Value::Integer(n) => n >= 0,
+            Value::Float(f) => f.is_finite() && f.is_sign_positive(),
+            Value::Range(..) => false,
+            _ => true,
+        },
+        ASTExpressionKind::Binary(_)
+        | ASTExpressionKind::Unary(_)
+        | ASTExpressionKind::Spread(_)
+        | ASTExpressionKind::Pipe(_) => false,
+        _ => true,
+    }
+}
+
+/// Writes `text` as a string literal, escaping what the lexer would not read back as is
+fn write_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::interner::Atom;
+    use crate::ast::lexer::{Lexer, TextSpan, Token, TokenKind};
+    use crate::ast::parser::Parser;
+    use crate::ast::{ASTBinaryOperator, ASTBinaryOperatorKind, ASTUnaryOperator, ASTUnaryOperatorKind, ASTVariableDeclaration};
+    use proptest::prelude::*;
+
+    /// A tree to generate, built into an AST afterwards since AST nodes are neither `Clone`
+    /// nor `Debug`
+    #[derive(Debug, Clone)]
+    enum Node {
+        Integer(i64),
+        Float(f64),
+        Boolean(bool),
+        String(String),
+        Name(&'static str),
+        Binary(usize, Box<Node>, Box<Node>),
+        Unary(usize, Box<Node>),
+        Paren(Box<Node>),
+        Call(&'static str, Vec<Node>),
+        Method(Box<Node>, &'static str, Vec<Node>),
+        Member(Box<Node>, &'static str),
+        Index(Box<Node>, Box<Node>),
+        Array(Vec<Node>),
+        Map(Vec<(String, Node)>),
+        Set(Vec<Node>),
+        Pipe(Box<Node>, Box<Node>),
+        /// `let` declarations, then the block's value
+        Block(Vec<(&'static str, Node)>, Box<Node>),
+    }
+
+    const NAMES: [&str; 4] = ["a", "b", "xs", "total"];
+    const BINARY_OPERATORS: usize = 11;
+
+    fn binary_operator(op: usize) -> ASTBinaryOperator {
+        let (kind, token_kind) = match op {
+            0 => (ASTBinaryOperatorKind::Plus, TokenKind::Plus),
+            1 => (ASTBinaryOperatorKind::Minus, TokenKind::Minus),
+            2 => (ASTBinaryOperatorKind::Multiply, TokenKind::Asterisk),
+            3 => (ASTBinaryOperatorKind::Modulo, TokenKind::Percent),
+            4 => (ASTBinaryOperatorKind::Exponentiation, TokenKind::DoubleStar),
+            5 => (ASTBinaryOperatorKind::BitwiseOr, TokenKind::Pipe),
+            6 => (ASTBinaryOperatorKind::LeftShift, TokenKind::LeftShift),
+            7 => (ASTBinaryOperatorKind::Less, TokenKind::Less),
+            8 => (ASTBinaryOperatorKind::Equal, TokenKind::EqualEqual),
+            9 => (ASTBinaryOperatorKind::In, TokenKind::In),
+            _ => (ASTBinaryOperatorKind::LogicalOr, TokenKind::DoublePipe),
+        };
+        ASTBinaryOperator::new(kind, token(token_kind))
+    }
+
+    fn token(kind: TokenKind) -> Token {
+        Token::new(kind, TextSpan::new(0, 0, String::new()))
+    }
+
+    fn build(node: &Node) -> ASTExpression {
+        let all = |nodes: &[Node]| nodes.iter().map(build).collect::<Vec<_>>();
+        match node {
+            Node::Integer(n) => ASTExpression::number(*n),
+            Node::Float(f) => ASTExpression::float(*f),
+            Node::Boolean(b) => ASTExpression::boolean(*b),
+            Node::String(s) => ASTExpression::string(s.clone()),
+            Node::Name(name) => ASTExpression::identifier(Atom::intern(name)),
+            Node::Binary(op, left, right) => ASTExpression::binary(binary_operator(*op), build(left), build(right)),
+            Node::Unary(op, operand) => {
+                let (kind, token_kind) = match op {
+                    0 => (ASTUnaryOperatorKind::Minus, TokenKind::Minus),
+                    1 => (ASTUnaryOperatorKind::Plus, TokenKind::Plus),
+                    _ => (ASTUnaryOperatorKind::LogicalNot, TokenKind::Bang),
+                };
+                ASTExpression::unary(ASTUnaryOperator::new(kind, token(token_kind)), build(operand))
+            }
+            Node::Paren(inner) => ASTExpression::paranthesized(build(inner)),
+            Node::Call(name, args) => ASTExpression::function_call(ASTExpression::identifier(Atom::intern(name)), all(args)),
+            Node::Method(receiver, name, args) => ASTExpression::method_call(build(receiver), Atom::intern(name), all(args)),
+            Node::Member(object, name) => ASTExpression::member_access(build(object), Atom::intern(name)),
+            Node::Index(object, index) => ASTExpression::index(build(object), build(index)),
+            Node::Array(elements) => ASTExpression::array(all(elements)),
+            Node::Map(entries) => ASTExpression::map(
+                entries.iter().map(|(key, value)| (ASTExpression::string(key.clone()), build(value))).collect(),
+            ),
+            Node::Set(elements) => ASTExpression::set(all(elements)),
+            Node::Pipe(value, target) => ASTExpression::pipe(build(value), build(target)),
+            Node::Block(declarations, value) => {
+                let mut statements: Vec<ASTStatement> = declarations
+                    .iter()
+                    .map(|(name, init)| {
+                        ASTStatement::variable_declaration(ASTVariableDeclaration::new(Atom::intern(name), build(init), true))
+                    })
+                    .collect();
+                statements.push(ASTStatement::expression(build(value)));
+                ASTExpression::block(statements)
+            }
+        }
+    }
+
+    fn node() -> impl Strategy<Value = Node> {
+        let name = prop::sample::select(&NAMES[..]);
+        let leaf = prop_oneof![
+            (0i64..1000).prop_map(Node::Integer),
+            prop::sample::select(&[0.5, 2.25, 10.0, 0.1][..]).prop_map(Node::Float),
+            any::<bool>().prop_map(Node::Boolean),
+            "[a-z \"\\\\\n\t\u{1}é]{0,6}".prop_map(Node::String),
+            name.clone().prop_map(Node::Name),
+        ];
+        leaf.prop_recursive(5, 48, 4, move |inner| {
+            let list = prop::collection::vec(inner.clone(), 0..3);
+            prop_oneof![
+                (0..BINARY_OPERATORS, inner.clone(), inner.clone()).prop_map(|(op, l, r)| Node::Binary(op, l.into(), r.into())),
+                (0..3usize, inner.clone()).prop_map(|(op, operand)| Node::Unary(op, operand.into())),
+                inner.clone().prop_map(|inner| Node::Paren(inner.into())),
+                (name.clone(), list.clone()).prop_map(|(name, args)| Node::Call(name, args)),
+                (inner.clone(), name.clone(), list.clone()).prop_map(|(r, name, args)| Node::Method(r.into(), name, args)),
+                (inner.clone(), name.clone()).prop_map(|(object, name)| Node::Member(object.into(), name)),
+                (inner.clone(), inner.clone()).prop_map(|(object, index)| Node::Index(object.into(), index.into())),
+                list.clone().prop_map(Node::Array),
+                prop::collection::vec(("[a-z]{1,3}", inner.clone()), 0..3).prop_map(Node::Map),
+                prop::collection::vec(inner.clone(), 1..3).prop_map(Node::Set),
+                (inner.clone(), inner.clone()).prop_map(|(value, target)| Node::Pipe(value.into(), target.into())),
+                (prop::collection::vec((name.clone(), inner.clone()), 0..2), inner.clone())
+                    .prop_map(|(declarations, value)| Node::Block(declarations, value.into())),
+            ]
+        })
+    }
+
+    /// The tree's structure with parentheses left out, which the unparser may add
+    fn shape(expression: &ASTExpression) -> String {
+        let all = |items: &[ASTExpression]| items.iter().map(shape).collect::<Vec<_>>().join(" ");
+        match &expression.kind {
+            ASTExpressionKind::Number(number) => format!("{:?}", number.value),
+            ASTExpressionKind::Identifier(ident) => ident.name.to_string(),
+            ASTExpressionKind::Paranthesized(paren_expr) => shape(&paren_expr.expression),
+            ASTExpressionKind::Binary(b) => format!("({} {} {})", b.operator.kind, shape(&b.left), shape(&b.right)),
+            ASTExpressionKind::Unary(u) => format!("({} {})", u.operator.kind, shape(&u.operand)),
+            ASTExpressionKind::FunctionCall(call) => format!("(call {} {})", shape(&call.callee), all(&call.arguments)),
+            ASTExpressionKind::MethodCall(m) => format!("(.{} {} {})", m.name, shape(&m.receiver), all(&m.arguments)),
+            ASTExpressionKind::MemberAccess(m) => format!("(.{} {})", m.name, shape(&m.object)),
+            ASTExpressionKind::Index(index) => format!("(index {} {})", shape(&index.object), shape(&index.index)),
+            ASTExpressionKind::Array(array) => format!("[{}]", all(&array.elements)),
+            ASTExpressionKind::Set(set) => format!("#{{{}}}", all(&set.elements)),
+            ASTExpressionKind::Spread(spread) => format!("(... {})", shape(&spread.expression)),
+            ASTExpressionKind::Pipe(pipe) => format!("(|> {} {})", shape(&pipe.value), shape(&pipe.target)),
+            ASTExpressionKind::Map(map) => {
+                let entries: Vec<String> = map.entries.iter().map(|(k, v)| format!("{}: {}", shape(k), shape(v))).collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            ASTExpressionKind::Block(block) => {
+                let statements: Vec<String> = block
+                    .statements
+                    .iter()
+                    .map(|statement| match &statement.kind {
+                        ASTStatementKind::VariableDeclaration(decl) => format!("(let {} {})", decl.name, shape(&decl.initializer)),
+                        ASTStatementKind::Expression(expr) => shape(expr),
+                        _ => statement_source(statement),
+                    })
+                    .collect();
+                format!("(block {})", statements.join(" "))
+            }
+        }
+    }
+
+    fn parse(source: &str) -> Ast {
+        let mut parser = Parser::from_lexer(Lexer::new(source));
+        let mut ast = Ast::new();
+        ast.statements = parser.parse_all();
+        assert!(parser.diagnostics.is_empty(), "{:?} in:\n{}", parser.diagnostics, source);
+        ast
+    }
+
+    proptest! {
+        #[test]
+        fn test_printed_expressions_parse_back(nodes in prop::collection::vec(node(), 1..4)) {
+            let mut ast = Ast::new();
+            ast.statements = nodes.iter().map(|node| ASTStatement::expression(build(node))).collect();
+            let source = ast.to_source();
+            let reparsed = parse(&source);
+
+            let shapes = |ast: &Ast| -> Vec<String> {
+                ast.statements
+                    .iter()
+                    .map(|statement| match &statement.kind {
+                        ASTStatementKind::Expression(expr) => shape(expr),
+                        _ => statement_source(statement),
+                    })
+                    .collect()
+            };
+            prop_assert_eq!(shapes(&reparsed), shapes(&ast), "source:\n{}", source);
+            prop_assert_eq!(reparsed.to_source(), source);
+        }
+    }
+
+    #[test]
+    fn test_statements_print_as_source() {
+        let source = "/// Tallies\nlet total = 0\nouter: for (k, v) in {\"a\": [1, -2.5]} {\n    if k in xs && !done {\n        break outer\n    } else if v[0] == 1 {\n        grid[k][0] = (2 + 3) * 4 ** (1 ** 2)\n    } else {\n        continue\n    }\n    (-v).abs()\n    defer print(b\"\\x00hi\\n\")\n}\nwith open(\"f\") as file {\n    import \"lib/util.arc\"\n}\ncount: while n > 0 {\n    n = n - 1\n    guard n != 3 else {\n        break\n    }\n}\nlet out = xs |> sort |> join(\", \")\n";
+        assert_eq!(parse(source).to_source(), source);
+    }
+}
@@ -0,0 +1,185 @@
+//! Async host functions - letting scripts await futures without blocking the host's runtime
+//!
+//! The evaluator walks the tree recursively, so it cannot return to the caller's executor
+//! halfway through a statement. `Interpreter::eval_async` instead runs it on a worker
+//! thread. When the script calls an async host function, the worker hands the function's
+//! future to the `EvalFuture` and waits; the host's executor polls that future like any
+//! other, and its output is sent back to the worker, which carries on. Polling never
+//! blocks the executor thread; only dropping an unfinished `EvalFuture` does (see its
+//! `Drop`).
+
+use crate::ast::evaluator::ASTEvaluator;
+use crate::ast::types::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+
+/// The future an async host function returns
+pub type HostFuture = Pin<Box<dyn Future<Output = Result<Option<Value>, String>> + Send>>;
+
+/// A registered async host function taking evaluated arguments
+pub type AsyncHostFunction = Arc<dyn Fn(Vec<Value>) -> HostFuture + Send + Sync>;
+
+/// Wraps an `async` closure as an `AsyncHostFunction`
+pub fn async_host_function<F, Fut>(function: F) -> AsyncHostFunction
+where
+    F: Fn(Vec<Value>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Option<Value>, String>> + Send + 'static,
+{
+    Arc::new(move |args| Box::pin(function(args)))
+}
+
+/// A future waiting to be polled for the worker, and where to send its output
+struct AsyncCall {
+    future: HostFuture,
+    reply: Sender<Result<Option<Value>, String>>,
+}
+
+/// The worker's side of an `eval_async`: how the evaluator hands out futures
+pub struct AsyncBridge {
+    calls: Sender<AsyncCall>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl AsyncBridge {
+    /// Has the host poll `future`, blocking this worker thread until it completes
+    pub fn call(&self, future: HostFuture) -> Result<Option<Value>, String> {
+        let (reply, result) = mpsc::channel();
+        self.calls
+            .send(AsyncCall { future, reply })
+            .map_err(|_| "evaluation was cancelled".to_string())?;
+        wake(&self.waker);
+        result.recv().unwrap_or_else(|_| Err("evaluation was cancelled".to_string()))
+    }
+}
+
+fn wake(waker: &Mutex<Option<Waker>>) {
+    if let Some(waker) = waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).as_ref() {
+        waker.wake_by_ref();
+    }
+}
+
+/// What the worker thread hands back when it finishes
+type WorkerOutput = (ASTEvaluator, Result<Option<Value>, String>);
+
+/// The future `Interpreter::eval_async` returns. The evaluator is moved to a worker thread
+/// on the first poll and put back when the program finishes. Dropping the future before
+/// then cancels any pending async call, waits for the worker to stop, and puts the
+/// evaluator back.
+pub struct EvalFuture<'a> {
+    evaluator: &'a mut ASTEvaluator,
+    state: EvalState,
+}
+
+enum EvalState {
+    /// Not polled yet: the program to run, and how the worker runs it
+    Start(String, fn(ASTEvaluator, &str) -> WorkerOutput),
+    Running(Worker),
+    Finished,
+}
+
+struct Worker {
+    thread: JoinHandle<WorkerOutput>,
+    calls: Receiver<AsyncCall>,
+    /// Signalled by the worker when it sends a call or finishes
+    waker: Arc<Mutex<Option<Waker>>>,
+    pending: Option<AsyncCall>,
+}
+
+impl<'a> EvalFuture<'a> {
+    pub(crate) fn new(evaluator: &'a mut ASTEvaluator, source: String, run: fn(ASTEvaluator, &str) -> WorkerOutput) -> Self {
+        EvalFuture { evaluator, state: EvalState::Start(source, run) }
+    }
+
+    /// Waits for the worker and takes back the evaluator
+    fn finish(&mut self, worker: Worker) -> Result<Option<Value>, String> {
+        drop(worker.calls);
+        drop(worker.pending);
+        match worker.thread.join() {
+            Ok((evaluator, result)) => {
+                *self.evaluator = evaluator;
+                result
+            }
+            Err(_) => Err("evaluation thread panicked".to_string()),
+        }
+    }
+}
+
+/// Wakes the `EvalFuture` when the worker thread ends, however it ends
+struct WakeOnDrop(Arc<Mutex<Option<Waker>>>);
+
+impl Drop for WakeOnDrop {
+    fn drop(&mut self) {
+        wake(&self.0);
+    }
+}
+
+impl Future for EvalFuture<'_> {
+    type Output = Result<Option<Value>, String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let EvalState::Start(source, run) = &mut this.state {
+            let (source, run) = (std::mem::take(source), *run);
+            let evaluator = std::mem::take(this.evaluator);
+            let (calls_sender, calls) = mpsc::channel();
+            let waker = Arc::new(Mutex::new(None));
+            let bridge = AsyncBridge { calls: calls_sender, waker: waker.clone() };
+            let wake_when_done = WakeOnDrop(waker.clone());
+            let thread = std::thread::spawn(move || {
+                // Dropped last, after the bridge, so the future sees the calls channel closed
+                let _wake_when_done = wake_when_done;
+                let mut evaluator = evaluator;
+                evaluator.async_bridge = Some(bridge);
+                let (mut evaluator, result) = run(evaluator, &source);
+                evaluator.async_bridge = None;
+                (evaluator, result)
+            });
+            this.state = EvalState::Running(Worker { thread, calls, waker, pending: None });
+        }
+
+        let finished = match &mut this.state {
+            EvalState::Running(worker) => {
+                // Registered before checking for work, so a call sent after the check still wakes us
+                *worker.waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(cx.waker().clone());
+                loop {
+                    if let Some(call) = &mut worker.pending {
+                        match call.future.as_mut().poll(cx) {
+                            Poll::Ready(result) => {
+                                let _ = call.reply.send(result);
+                                worker.pending = None;
+                            }
+                            Poll::Pending => return Poll::Pending,
+                        }
+                    }
+                    match worker.calls.try_recv() {
+                        Ok(call) => worker.pending = Some(call),
+                        Err(TryRecvError::Empty) => return Poll::Pending,
+                        // The worker dropped its end: the program has finished
+                        Err(TryRecvError::Disconnected) => break true,
+                    }
+                }
+            }
+            _ => false,
+        };
+        match std::mem::replace(&mut this.state, EvalState::Finished) {
+            EvalState::Running(worker) if finished => Poll::Ready(this.finish(worker)),
+            _ => Poll::Ready(Err("eval_async polled after it finished".to_string())),
+        }
+    }
+}
+
+/// Joins the worker on the dropping thread. The worker owns the evaluator until it stops,
+/// and only a join hands it back, so this blocks until the program reaches its next async
+/// call (which fails once the calls channel is gone) or ends. A program that computes for
+/// a long time without an async call holds up the drop for as long.
+impl Drop for EvalFuture<'_> {
+    fn drop(&mut self) {
+        if let EvalState::Running(worker) = std::mem::replace(&mut self.state, EvalState::Finished) {
+            let _ = self.finish(worker);
+        }
+    }
+}
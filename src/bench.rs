@@ -0,0 +1,204 @@
+//! Benchmarks - representative programs and the timing harness behind `bench`
+//!
+//! The same programs drive the Criterion suite in `benches/programs.rs`; the `bench`
+//! subcommand times them without extra dependencies and can compare against a saved run.
+
+use crate::ast::evaluator::ASTEvaluator;
+use crate::ast::lexer::{Lexer, Token};
+use crate::ast::parser::Parser;
+use crate::ast::Ast;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// A generated Arc program exercising one part of the pipeline
+pub struct BenchProgram {
+    pub name: &'static str,
+    pub source: String,
+}
+
+/// The benchmark programs. Repeated work is unrolled into statements, so the lexer and
+/// parser see as much source as the evaluator runs.
+pub fn programs() -> Vec<BenchProgram> {
+    let mut arithmetic = String::from("let x = 0\nlet y = 1.5\n");
+    for _ in 0..2_000 {
+        arithmetic.push_str("x = x + 3 * 2 - 1\ny = y * 1.0001 + x % 7\n");
+    }
+
+    let mut strings = String::from("let s = \"\"\n");
+    for i in 0..500 {
+        writeln!(strings, "s = s + \"item{}, \"", i).ok();
+    }
+
+    let mut deep = String::new();
+    for _ in 0..50 {
+        deep.push_str(&"(1 + ".repeat(100));
+        deep.push('1');
+        deep.push_str(&")".repeat(100));
+        deep.push('\n');
+    }
+
+    let mut symbols = String::from("let v0 = 1\n");
+    for i in 1..500 {
+        writeln!(symbols, "let v{} = (v{} + v{}) % 1000", i, i - 1, i / 2).ok();
+    }
+    for i in 0..500 {
+        writeln!(symbols, "v{} = v{} - v{}", i, 499 - i, i).ok();
+    }
+
+    let mut math = String::from("let r = 2.5\nlet area = 0.0\n");
+    for i in 0..1_000 {
+        writeln!(math, "area = area + pow(r, 2) * sqrt(abs(r * r)) / sqrt(abs(r * r)) + max(sin(1.5), cos({}))", i).ok();
+    }
+
+    vec![
+        BenchProgram { name: "arithmetic", source: arithmetic },
+        BenchProgram { name: "string_building", source: strings },
+        BenchProgram { name: "deep_expressions", source: deep },
+        BenchProgram { name: "symbol_heavy", source: symbols },
+        BenchProgram { name: "math_heavy", source: math },
+    ]
+}
+
+/// Tokenizes a whole program
+pub fn lex(source: &str) -> Vec<Token> {
+    Lexer::new(source).collect()
+}
+
+/// Parses pre-lexed tokens
+pub fn parse(tokens: Vec<Token>) -> Ast {
+    let mut ast = Ast::new();
+    ast.statements = Parser::new(tokens).parse_all();
+    ast
+}
+
+/// Runs a parsed program in a fresh evaluator, with `-O` optimizations when `optimize` is set
+pub fn evaluate(mut ast: Ast, optimize: bool) -> ASTEvaluator {
+    let mut evaluator = ASTEvaluator::new();
+    evaluator.optimize = optimize;
+    evaluator.resolve(&mut ast);
+    ast.visit(&mut evaluator);
+    evaluator
+}
+
+/// Mean time per phase for one program
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub lex: Duration,
+    pub parse: Duration,
+    pub eval: Duration,
+}
+
+impl BenchResult {
+    pub fn total(&self) -> Duration {
+        self.lex + self.parse + self.eval
+    }
+}
+
+/// Times each phase of `program`, averaged over `iterations` runs
+pub fn run(program: &BenchProgram, iterations: u32, optimize: bool) -> BenchResult {
+    let iterations = iterations.max(1);
+    let (mut lex_time, mut parse_time, mut eval_time) = (Duration::ZERO, Duration::ZERO, Duration::ZERO);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let tokens = lex(&program.source);
+        let lexed = Instant::now();
+        let ast = parse(tokens);
+        let parsed = Instant::now();
+        evaluate(ast, optimize);
+        eval_time += parsed.elapsed();
+        parse_time += parsed - lexed;
+        lex_time += lexed - start;
+    }
+    BenchResult {
+        name: program.name.to_string(),
+        lex: lex_time / iterations,
+        parse: parse_time / iterations,
+        eval: eval_time / iterations,
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Formats results as a table, with a change column when a baseline is given
+pub fn report(results: &[BenchResult], baseline: Option<&HashMap<String, Duration>>) -> String {
+    let mut out = String::new();
+    write!(out, "{:<18} {:>12} {:>12} {:>12} {:>12}", "Program", "Lex", "Parse", "Eval", "Total").ok();
+    if baseline.is_some() {
+        write!(out, " {:>12} {:>9}", "Baseline", "Change").ok();
+    }
+    out.push('\n');
+    for result in results {
+        write!(
+            out,
+            "{:<18} {:>12} {:>12} {:>12} {:>12}",
+            result.name,
+            format_duration(result.lex),
+            format_duration(result.parse),
+            format_duration(result.eval),
+            format_duration(result.total())
+        )
+        .ok();
+        if let Some(baseline) = baseline {
+            let (before, change) = match baseline.get(&result.name) {
+                Some(before) if !before.is_zero() => {
+                    let change = (result.total().as_secs_f64() / before.as_secs_f64() - 1.0) * 100.0;
+                    (format_duration(*before), format!("{:+.1}%", change))
+                }
+                _ => ("-".to_string(), "-".to_string()),
+            };
+            write!(out, " {:>12} {:>9}", before, change).ok();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Serializes total times as `name nanoseconds` lines, for a later `--baseline`
+pub fn save_baseline(results: &[BenchResult]) -> String {
+    results.iter().map(|result| format!("{} {}\n", result.name, result.total().as_nanos())).collect()
+}
+
+/// Reads a file written by `save_baseline`
+pub fn parse_baseline(text: &str) -> Result<HashMap<String, Duration>, String> {
+    let mut baseline = HashMap::new();
+    for (index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let parsed = line
+            .split_once(' ')
+            .and_then(|(name, nanos)| Some((name, nanos.trim().parse::<u64>().ok()?)));
+        match parsed {
+            Some((name, nanos)) => baseline.insert(name.to_string(), Duration::from_nanos(nanos)),
+            None => return Err(format!("Invalid baseline entry on line {}: '{}'", index + 1, line)),
+        };
+    }
+    Ok(baseline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_programs_run_cleanly() {
+        for program in programs() {
+            let evaluator = evaluate(parse(lex(&program.source)), false);
+            assert!(evaluator.errors.is_empty(), "{}: {:?}", program.name, evaluator.errors);
+        }
+    }
+
+    #[test]
+    fn test_baseline_roundtrip() {
+        let results = vec![BenchResult {
+            name: "arithmetic".to_string(),
+            lex: Duration::from_micros(1),
+            parse: Duration::from_micros(2),
+            eval: Duration::from_micros(3),
+        }];
+        let baseline = parse_baseline(&save_baseline(&results)).unwrap();
+        assert_eq!(baseline["arithmetic"], Duration::from_micros(6));
+        assert!(report(&results, Some(&baseline)).contains("+0.0%"));
+    }
+}
@@ -0,0 +1,62 @@
+//! Capabilities - which host resources builtins may touch
+
+/// Host resources a script is allowed to use.
+///
+/// Builtins that need a disabled capability fail with a "Capability denied" runtime
+/// error instead of running, so untrusted snippets can be evaluated safely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalCapabilities {
+    /// `read_file` and `import`
+    pub filesystem: bool,
+    /// `env`
+    pub env: bool,
+    /// `http_get`, `http_post`, and host functions that check it
+    pub network: bool,
+    /// `clock`
+    pub time: bool,
+    /// `print`
+    pub stdout: bool,
+    /// `exec`; off unless granted explicitly, since a command can do anything the host can
+    pub process: bool,
+}
+
+impl Default for EvalCapabilities {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl EvalCapabilities {
+    /// Everything but `process` - the default for scripts run from the command line
+    pub fn standard() -> Self {
+        EvalCapabilities { process: false, ..Self::all() }
+    }
+
+    /// Everything enabled, including running other programs
+    pub fn all() -> Self {
+        EvalCapabilities { filesystem: true, env: true, network: true, time: true, stdout: true, process: true }
+    }
+
+    /// Everything disabled - pure computation only
+    pub fn none() -> Self {
+        EvalCapabilities { filesystem: false, env: false, network: false, time: false, stdout: false, process: false }
+    }
+
+    /// Checks a capability by name, returning the runtime error for a denied one
+    pub fn check(&self, capability: &str, operation: &str) -> Result<(), String> {
+        let allowed = match capability {
+            "filesystem" => self.filesystem,
+            "env" => self.env,
+            "network" => self.network,
+            "time" => self.time,
+            "stdout" => self.stdout,
+            "process" => self.process,
+            _ => false,
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!("Capability denied: {} requires '{}'", operation, capability))
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! C ABI - lets C, C++, Swift and other languages embed the interpreter
+//!
+//! Built with the `capi` feature. Every function takes the handle returned by
+//! `arc_new`; strings handed back stay owned by the handle and remain valid until
+//! the next call to `arc_eval` or `arc_free`. The matching header is `include/arc.h`.
+
+use crate::ast::types::Value;
+use crate::interpreter::Interpreter;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Opaque interpreter handle
+pub struct ArcInterpreter {
+    interpreter: Interpreter,
+    result: Option<Value>,
+    result_string: Option<CString>,
+    error: Option<CString>,
+}
+
+pub const ARC_OK: c_int = 0;
+pub const ARC_ERROR: c_int = 1;
+pub const ARC_INVALID_ARGUMENT: c_int = -1;
+
+/// Converts text to a C string, dropping interior NULs rather than failing
+fn to_c_string(text: String) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+/// Creates an interpreter. Free it with `arc_free`.
+#[no_mangle]
+pub extern "C" fn arc_new() -> *mut ArcInterpreter {
+    Box::into_raw(Box::new(ArcInterpreter {
+        interpreter: Interpreter::new(),
+        result: None,
+        result_string: None,
+        error: None,
+    }))
+}
+
+/// Destroys an interpreter.
+///
+/// # Safety
+/// `handle` must come from `arc_new` and not have been freed; NULL is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn arc_free(handle: *mut ArcInterpreter) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Evaluates a NUL-terminated UTF-8 program. Returns `ARC_OK`, `ARC_ERROR` (see
+/// `arc_last_error`) or `ARC_INVALID_ARGUMENT`.
+///
+/// # Safety
+/// `handle` must be a live handle and `source` a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn arc_eval(handle: *mut ArcInterpreter, source: *const c_char) -> c_int {
+    let Some(arc) = handle.as_mut() else {
+        return ARC_INVALID_ARGUMENT;
+    };
+    arc.result = None;
+    arc.result_string = None;
+    arc.error = None;
+    if source.is_null() {
+        arc.error = Some(to_c_string("source is NULL".to_string()));
+        return ARC_INVALID_ARGUMENT;
+    }
+    let source = match CStr::from_ptr(source).to_str() {
+        Ok(source) => source,
+        Err(_) => {
+            arc.error = Some(to_c_string("source is not valid UTF-8".to_string()));
+            return ARC_INVALID_ARGUMENT;
+        }
+    };
+
+    // Never unwind across the FFI boundary
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| arc.interpreter.eval(source)));
+    match outcome {
+        Ok(Ok(value)) => {
+            arc.result_string = value.as_ref().map(|value| to_c_string(value.to_string()));
+            arc.result = value;
+            ARC_OK
+        }
+        Ok(Err(message)) => {
+            arc.error = Some(to_c_string(message));
+            ARC_ERROR
+        }
+        Err(_) => {
+            arc.error = Some(to_c_string("internal error: evaluation panicked".to_string()));
+            ARC_ERROR
+        }
+    }
+}
+
+/// The last result formatted as text, or NULL if the program produced no value.
+///
+/// # Safety
+/// `handle` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn arc_get_string_result(handle: *const ArcInterpreter) -> *const c_char {
+    match handle.as_ref().and_then(|arc| arc.result_string.as_ref()) {
+        Some(text) => text.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Writes the last result to `out` if it is an integer; returns `ARC_OK` or `ARC_ERROR`.
+///
+/// # Safety
+/// `handle` must be a live handle and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn arc_get_int_result(handle: *const ArcInterpreter, out: *mut i64) -> c_int {
+    match (handle.as_ref().and_then(|arc| arc.result.as_ref()), out.as_mut()) {
+        (Some(Value::Integer(value)), Some(out)) => {
+            *out = *value;
+            ARC_OK
+        }
+        (_, None) => ARC_INVALID_ARGUMENT,
+        _ => ARC_ERROR,
+    }
+}
+
+/// Writes the last result to `out` if it is a number; returns `ARC_OK` or `ARC_ERROR`.
+///
+/// # Safety
+/// `handle` must be a live handle and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn arc_get_float_result(handle: *const ArcInterpreter, out: *mut f64) -> c_int {
+    match (handle.as_ref().and_then(|arc| arc.result.clone()), out.as_mut()) {
+        (_, None) => ARC_INVALID_ARGUMENT,
+        (Some(value), Some(out)) => match f64::try_from(value) {
+            Ok(value) => {
+                *out = value;
+                ARC_OK
+            }
+            Err(_) => ARC_ERROR,
+        },
+        (None, Some(_)) => ARC_ERROR,
+    }
+}
+
+/// The error from the last failed `arc_eval`, or NULL.
+///
+/// # Safety
+/// `handle` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn arc_last_error(handle: *const ArcInterpreter) -> *const c_char {
+    match handle.as_ref().and_then(|arc| arc.error.as_ref()) {
+        Some(text) => text.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_through_c_api() {
+        unsafe {
+            let handle = arc_new();
+            let program = CString::new("let x = 20\nx * 2 + 2").unwrap();
+            assert_eq!(arc_eval(handle, program.as_ptr()), ARC_OK);
+
+            let mut value = 0;
+            assert_eq!(arc_get_int_result(handle, &mut value), ARC_OK);
+            assert_eq!(value, 42);
+            assert_eq!(CStr::from_ptr(arc_get_string_result(handle)).to_str(), Ok("42"));
+
+            let bad = CString::new("missing").unwrap();
+            assert_eq!(arc_eval(handle, bad.as_ptr()), ARC_ERROR);
+            assert_eq!(CStr::from_ptr(arc_last_error(handle)).to_str(), Ok("Variable 'missing' not found"));
+            assert!(arc_get_string_result(handle).is_null());
+            arc_free(handle);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Coverage - records executed statement lines and renders summaries and lcov reports
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Executable and executed lines for a single source file
+#[derive(Debug, Default, Clone)]
+pub struct FileCoverage {
+    executable: BTreeSet<usize>,
+    hits: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+    /// Number of lines that contain a statement
+    pub fn lines_found(&self) -> usize {
+        self.executable.len()
+    }
+
+    /// Number of statement lines executed at least once
+    pub fn lines_hit(&self) -> usize {
+        self.executable.iter().filter(|line| self.hits.contains_key(line)).count()
+    }
+
+    /// Statement lines that never executed
+    pub fn missed_lines(&self) -> Vec<usize> {
+        self.executable.iter().copied().filter(|line| !self.hits.contains_key(line)).collect()
+    }
+
+    /// Percentage of statement lines executed (100% for files without statements)
+    pub fn percent(&self) -> f64 {
+        if self.executable.is_empty() {
+            100.0
+        } else {
+            self.lines_hit() as f64 * 100.0 / self.lines_found() as f64
+        }
+    }
+}
+
+/// Coverage data for every file executed in a run
+#[derive(Debug, Default)]
+pub struct Coverage {
+    files: BTreeMap<String, FileCoverage>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage { files: BTreeMap::new() }
+    }
+
+    /// Registers a line that contains a statement
+    pub fn add_executable_line(&mut self, file: &str, line: usize) {
+        self.files.entry(file.to_string()).or_default().executable.insert(line);
+    }
+
+    /// Records one execution of the statement on a line
+    pub fn record_hit(&mut self, file: &str, line: usize) {
+        let coverage = self.files.entry(file.to_string()).or_default();
+        coverage.executable.insert(line);
+        *coverage.hits.entry(line).or_insert(0) += 1;
+    }
+
+    pub fn file(&self, file: &str) -> Option<&FileCoverage> {
+        self.files.get(file)
+    }
+
+    /// Renders a per-file table of statement coverage
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "=== Coverage ===");
+        let _ = writeln!(out, "{:>8} {:>8} {:>8}  File", "Lines", "Hit", "Cover");
+        for (file, coverage) in &self.files {
+            let _ = writeln!(
+                out,
+                "{:>8} {:>8} {:>7.1}%  {}",
+                coverage.lines_found(),
+                coverage.lines_hit(),
+                coverage.percent(),
+                file
+            );
+            let missed = coverage.missed_lines();
+            if !missed.is_empty() {
+                let missed: Vec<String> = missed.iter().map(|line| line.to_string()).collect();
+                let _ = writeln!(out, "{:>27} missed lines: {}", "", missed.join(", "));
+            }
+        }
+        out
+    }
+
+    /// Renders the report in lcov tracefile format
+    pub fn to_lcov(&self) -> String {
+        let mut out = String::new();
+        for (file, coverage) in &self.files {
+            let _ = writeln!(out, "TN:");
+            let _ = writeln!(out, "SF:{}", file);
+            for line in &coverage.executable {
+                let hits = coverage.hits.get(line).copied().unwrap_or(0);
+                let _ = writeln!(out, "DA:{},{}", line, hits);
+            }
+            let _ = writeln!(out, "LF:{}", coverage.lines_found());
+            let _ = writeln!(out, "LH:{}", coverage.lines_hit());
+            let _ = writeln!(out, "end_of_record");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lcov_report_lists_hit_and_missed_lines() {
+        let mut coverage = Coverage::new();
+        coverage.add_executable_line("a.arc", 1);
+        coverage.add_executable_line("a.arc", 2);
+        coverage.record_hit("a.arc", 1);
+        coverage.record_hit("a.arc", 1);
+
+        let lcov = coverage.to_lcov();
+        assert!(lcov.contains("SF:a.arc\nDA:1,2\nDA:2,0\nLF:2\nLH:1\nend_of_record"));
+        assert_eq!(coverage.file("a.arc").unwrap().missed_lines(), vec![2]);
+    }
+}
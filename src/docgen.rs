@@ -0,0 +1,123 @@
+//! Documentation generator - extracts `///` comments and renders Markdown or HTML
+
+use crate::ast::lexer::Lexer;
+use crate::ast::parser::Parser;
+use crate::ast::ASTStatementKind;
+
+/// A documented declaration found in a script
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocItem {
+    pub name: String,
+    pub is_mutable: bool,
+    pub doc: Option<String>,
+}
+
+impl DocItem {
+    /// Source-like signature, e.g. `const PI`
+    pub fn signature(&self) -> String {
+        format!("{} {}", if self.is_mutable { "let" } else { "const" }, self.name)
+    }
+}
+
+/// Documentation for a single script
+#[derive(Debug, Clone)]
+pub struct ModuleDoc {
+    pub name: String,
+    pub items: Vec<DocItem>,
+}
+
+/// Parses a whole script and collects its constants plus any documented variables
+pub fn extract_docs(name: &str, source: &str) -> ModuleDoc {
+    let mut parser = Parser::from_lexer(Lexer::new(source));
+    let mut items = Vec::new();
+    for statement in parser.parse_all() {
+        if let ASTStatementKind::VariableDeclaration(decl) = &statement.kind {
+            // Constants are the public surface of a script; variables only when documented
+            if !decl.is_mutable || decl.doc.is_some() {
+                items.push(DocItem {
+                    name: decl.name.to_string(),
+                    is_mutable: decl.is_mutable,
+                    doc: decl.doc.clone(),
+                });
+            }
+        }
+    }
+
+    ModuleDoc { name: name.to_string(), items }
+}
+
+/// Renders module documentation as Markdown
+pub fn render_markdown(modules: &[ModuleDoc]) -> String {
+    let mut out = String::new();
+    for module in modules {
+        out.push_str(&format!("# {}\n\n", module.name));
+        if module.items.is_empty() {
+            out.push_str("_No documented items._\n\n");
+        }
+        for item in &module.items {
+            out.push_str(&format!("## `{}`\n\n", item.signature()));
+            if let Some(doc) = &item.doc {
+                out.push_str(doc);
+                out.push_str("\n\n");
+            }
+        }
+    }
+    out
+}
+
+/// Renders module documentation as a standalone HTML page
+pub fn render_html(modules: &[ModuleDoc]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Arc Documentation</title>\n</head>\n<body>\n",
+    );
+    for module in modules {
+        out.push_str(&format!("<h1>{}</h1>\n", escape_html(&module.name)));
+        for item in &module.items {
+            out.push_str(&format!("<h2><code>{}</code></h2>\n", escape_html(&item.signature())));
+            if let Some(doc) = &item.doc {
+                for paragraph in doc.split("\n\n") {
+                    out.push_str(&format!("<p>{}</p>\n", escape_html(paragraph)));
+                }
+            }
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Escapes text for inclusion in HTML
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doc_comments_attach_to_declarations() {
+        let source = "/// Circle constant\n/// Used for area\nconst PI = 3.14\n// plain comment\nlet r = 2\n";
+        let module = extract_docs("circle", source);
+
+        assert_eq!(module.items.len(), 1);
+        assert_eq!(module.items[0].name, "PI");
+        assert_eq!(module.items[0].doc.as_deref(), Some("Circle constant\nUsed for area"));
+    }
+
+    #[test]
+    fn test_documented_let_is_included() {
+        let module = extract_docs("m", "/// Counter\nlet count = 0\nlet other = 1\n");
+        assert_eq!(module.items.len(), 1);
+        assert_eq!(module.items[0].signature(), "let count");
+    }
+}
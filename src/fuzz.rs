@@ -0,0 +1,117 @@
+//! Fuzzing entry points - drive the pipeline on arbitrary bytes for cargo-fuzz targets
+//!
+//! Every function here must return normally for any input; malformed source is
+//! reported through diagnostics and evaluator errors, never a panic.
+
+use crate::ast::diagnostics::Diagnostic;
+use crate::ast::evaluator::ASTEvaluator;
+use crate::ast::lexer::{Lexer, Token};
+use crate::ast::parser::Parser;
+use crate::ast::{ASTStatement, Ast};
+
+/// Interprets fuzzer bytes as source text, replacing invalid UTF-8
+fn source_from_bytes(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).into_owned()
+}
+
+/// Lexes the input to completion and returns the tokens
+pub fn fuzz_lexer(data: &[u8]) -> Vec<Token> {
+    let source = source_from_bytes(data);
+    let mut lexer = Lexer::new(&source);
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.next_token() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Parses every statement in the input, skipping past tokens that fail to parse
+fn parse_all(data: &[u8]) -> (Vec<ASTStatement>, Vec<Diagnostic>) {
+    let mut parser = Parser::new(fuzz_lexer(data));
+    let statements = parser.parse_all();
+    (statements, parser.diagnostics)
+}
+
+/// Lexes and parses the input, returning the parser diagnostics
+pub fn fuzz_parser(data: &[u8]) -> Vec<Diagnostic> {
+    parse_all(data).1
+}
+
+/// Runs the whole pipeline (lex, parse, evaluate) and returns all diagnostics
+pub fn fuzz_roundtrip(data: &[u8]) -> Vec<Diagnostic> {
+    let (statements, mut diagnostics) = parse_all(data);
+
+    let mut evaluator = ASTEvaluator::new();
+    for statement in statements {
+        let mut ast = Ast::new();
+        ast.add_statement(statement);
+        evaluator.resolve(&mut ast);
+        ast.visit(&mut evaluator);
+    }
+    diagnostics.extend(evaluator.errors.into_iter().map(|error| Diagnostic::error(error, None)));
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_inputs_do_not_panic() {
+        let inputs: Vec<Vec<u8>> = vec![
+            b"".to_vec(),
+            b"(5 + 3".to_vec(),
+            b"print(1, 2".to_vec(),
+            b"let = 5".to_vec(),
+            b"let x 5".to_vec(),
+            b"\"unterminated".to_vec(),
+            b"/* unterminated".to_vec(),
+            "let \u{3c0} = \"\u{1f600}\" + 1".as_bytes().to_vec(),
+            vec![0xff, 0xfe, b'(', 0x80],
+            b"9223372036854775807 + 1".to_vec(),
+            b"-9223372036854775807 - 2".to_vec(),
+            b"(0 - 9223372036854775807 - 1) / -1".to_vec(),
+            b"(0 - 9223372036854775807 - 1) % -1".to_vec(),
+            b"2 ** 64".to_vec(),
+            b"2 ** 9999999999".to_vec(),
+            b"1 << 64".to_vec(),
+            b"1 >> -1".to_vec(),
+            "(".repeat(10_000).into_bytes(),
+            "-".repeat(10_000).into_bytes(),
+            "2 ** ".repeat(10_000).into_bytes(),
+        ];
+        for input in inputs {
+            fuzz_roundtrip(&input);
+        }
+    }
+
+    #[test]
+    fn test_operator_chains_are_left_associative() {
+        let source = format!("let x = 1000{}\nx - 900", " - 1".repeat(100));
+        let (statements, diagnostics) = parse_all(source.as_bytes());
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        let mut evaluator = ASTEvaluator::new();
+        let mut ast = Ast::new();
+        for statement in statements {
+            ast.add_statement(statement);
+        }
+        evaluator.resolve(&mut ast);
+        ast.visit(&mut evaluator);
+        assert_eq!(evaluator.last_value, Some(crate::ast::types::Value::Integer(0)));
+    }
+
+    #[test]
+    fn test_overlong_chain_is_rejected() {
+        let source = format!("1{}", " + 1".repeat(5_000));
+        let diagnostics = fuzz_parser(source.as_bytes());
+        assert!(diagnostics[0].message.contains("nested too deeply"));
+    }
+
+    #[test]
+    fn test_overflow_becomes_error() {
+        let diagnostics = fuzz_roundtrip(b"9223372036854775807 * 2");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("overflow"));
+    }
+}
@@ -0,0 +1,50 @@
+//! Shared globals - read-only constants and host functions shared by many interpreters
+
+use crate::ast::types::Value;
+use crate::host::{HostFunction, IntoHostFunction};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A global scope built once and shared, read-only, between interpreters.
+///
+/// Each interpreter still has its own variables; a script can shadow a shared name
+/// with its own declaration but cannot assign to it.
+#[derive(Default, Clone)]
+pub struct SharedGlobals {
+    values: HashMap<String, Value>,
+    functions: HashMap<String, HostFunction>,
+}
+
+impl SharedGlobals {
+    pub fn new() -> Self {
+        SharedGlobals::default()
+    }
+
+    /// Adds a constant
+    pub fn define(&mut self, name: &str, value: impl Into<Value>) {
+        self.values.insert(name.to_string(), value.into());
+    }
+
+    /// Adds a host function, converting arguments like `Interpreter::register`
+    pub fn register<Args>(&mut self, name: &str, function: impl IntoHostFunction<Args>) {
+        self.functions.insert(name.to_string(), function.into_host_function());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&HostFunction> {
+        self.functions.get(name)
+    }
+
+    /// Names of the constants and functions
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().chain(self.functions.keys()).map(String::as_str)
+    }
+
+    /// Freezes the globals so they can be handed to any number of interpreters
+    pub fn into_shared(self) -> Arc<SharedGlobals> {
+        Arc::new(self)
+    }
+}
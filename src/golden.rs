@@ -0,0 +1,171 @@
+//! Golden files - language behaviour locked in by scripts and their expected output
+//!
+//! Every `.arc` script under a directory is run and its transcript is compared with the
+//! `.expected` file next to it. A transcript is the script's printed output with each error
+//! on its own `error:` line, in the order they happened. Blessing writes the transcripts
+//! out as the new expectations instead. `tests/lang.rs` checks `tests/lang/` this way
+//! under `cargo test`.
+
+use crate::ast::diagnostics::line_column;
+use crate::ast::evaluator::ASTEvaluator;
+use crate::ast::lexer::Lexer;
+use crate::ast::parser::Parser;
+use crate::ast::Ast;
+use crate::output::callback_sink;
+use crate::package::ModuleLoader;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A script whose transcript did not match its `.expected` file
+#[derive(Debug)]
+pub struct GoldenFailure {
+    pub script: PathBuf,
+    /// `None` when the script has no `.expected` file yet
+    pub expected: Option<String>,
+    pub actual: String,
+}
+
+impl fmt::Display for GoldenFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(expected) = &self.expected else {
+            return write!(f, "{}: no .expected file, run with --bless to create it", self.script.display());
+        };
+        let mut expected_lines = expected.lines();
+        let mut actual_lines = self.actual.lines();
+        for line in 1.. {
+            match (expected_lines.next(), actual_lines.next()) {
+                (Some(e), Some(a)) if e == a => {}
+                (None, None) => break,
+                (e, a) => {
+                    return write!(
+                        f,
+                        "{}: line {} differs\n  expected: {}\n  actual:   {}",
+                        self.script.display(),
+                        line,
+                        e.unwrap_or("<end of output>"),
+                        a.unwrap_or("<end of output>")
+                    );
+                }
+            }
+        }
+        // Only trailing newlines differ
+        write!(f, "{}: output differs at the end", self.script.display())
+    }
+}
+
+/// The outcome of checking a directory of scripts
+#[derive(Debug, Default)]
+pub struct GoldenRun {
+    /// Number of scripts run
+    pub scripts: usize,
+    /// `.expected` files written or rewritten by blessing
+    pub blessed: Vec<PathBuf>,
+    pub failures: Vec<GoldenFailure>,
+}
+
+/// Runs every script under `dir`, comparing each transcript with its `.expected` file, or
+/// writing the transcripts that differ when `bless` is set
+pub fn check(dir: &Path, bless: bool) -> io::Result<GoldenRun> {
+    let mut run = GoldenRun::default();
+    for script in scripts(dir)? {
+        let actual = transcript(&script, &fs::read_to_string(&script)?);
+        let expected_path = script.with_extension("expected");
+        let expected = fs::read_to_string(&expected_path).ok();
+        run.scripts += 1;
+        if expected.as_deref() == Some(actual.as_str()) {
+            continue;
+        }
+        if bless {
+            fs::write(&expected_path, &actual)?;
+            run.blessed.push(expected_path);
+        } else {
+            run.failures.push(GoldenFailure { script, expected, actual });
+        }
+    }
+    Ok(run)
+}
+
+/// The `.arc` files under `dir`, including subdirectories, in path order
+pub fn scripts(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            found.extend(scripts(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "arc") {
+            found.push(path);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Runs `source` as the script at `path` and returns its transcript. A script with parse
+/// errors is not run; its transcript lists the errors with their lines.
+pub fn transcript(path: &Path, source: &str) -> String {
+    let mut parser = Parser::from_lexer(Lexer::new(source));
+    let mut ast = Ast::new();
+    ast.statements = parser.parse_all();
+    let parse_errors: Vec<String> = parser
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.is_error())
+        .map(|diagnostic| match &diagnostic.span {
+            Some(span) => format!("{} (line {})\n", diagnostic, line_column(source, span.start()).0),
+            None => format!("{}\n", diagnostic),
+        })
+        .collect();
+    if !parse_errors.is_empty() {
+        return parse_errors.concat();
+    }
+
+    let log = Arc::new(Mutex::new(String::new()));
+    let mut evaluator = ASTEvaluator::new();
+    if let Ok(loader) = ModuleLoader::for_script(path) {
+        evaluator.modules = loader;
+    }
+    // Traces name the file alone, so transcripts do not depend on where the tree is
+    evaluator.source_name = path.file_name().map(|name| name.to_string_lossy().into_owned());
+    evaluator.output_sink = Some({
+        let log = log.clone();
+        callback_sink(move |text| log.lock().unwrap().push_str(text))
+    });
+    evaluator.diagnostic_sink = Some({
+        let log = log.clone();
+        callback_sink(move |text| log.lock().unwrap().push_str(&format!("error: {}", text)))
+    });
+    evaluator.resolve(&mut ast);
+    ast.visit(&mut evaluator);
+    drop(evaluator);
+    let log = Arc::try_unwrap(log).expect("the sinks are dropped with the evaluator");
+    log.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_compares_and_blesses_transcripts() {
+        let dir = std::env::temp_dir().join(format!("arc-golden-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("order.arc"), "print(1)\nmissing\nprint(2)\n").unwrap();
+
+        let run = check(&dir, false).unwrap();
+        assert_eq!(run.scripts, 1);
+        assert!(run.failures[0].to_string().ends_with("no .expected file, run with --bless to create it"));
+
+        assert_eq!(check(&dir, true).unwrap().blessed, [dir.join("order.expected")]);
+        let expected = fs::read_to_string(dir.join("order.expected")).unwrap();
+        assert_eq!(expected, "1\nerror: Variable 'missing' not found\n2\n");
+        assert!(check(&dir, false).unwrap().failures.is_empty());
+
+        fs::write(dir.join("order.expected"), "1\nerror: something else\n2\n").unwrap();
+        let failure = check(&dir, false).unwrap().failures.remove(0);
+        assert!(failure.to_string().ends_with("line 2 differs\n  expected: error: something else\n  actual:   error: Variable 'missing' not found"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
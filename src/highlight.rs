@@ -0,0 +1,136 @@
+//! Syntax highlighter - renders Arc source as standalone HTML using the lexer's tokens
+
+use crate::ast::lexer::{Lexer, Token, TokenCategory, TokenKind};
+use crate::docgen::escape_html;
+
+const STYLESHEET: &str = "\
+body { background: #fafafa; }
+pre.arc { font-family: monospace; font-size: 14px; line-height: 1.4; padding: 1em; background: #fff; border: 1px solid #ddd; }
+.arc .kw { color: #a626a4; font-weight: bold; }
+.arc .num { color: #986801; }
+.arc .str { color: #50a14f; }
+.arc .bool { color: #0184bc; }
+.arc .ident { color: #383a42; }
+.arc .fn { color: #4078f2; }
+.arc .op { color: #0184bc; }
+.arc .punct { color: #383a42; }
+.arc .comment { color: #a0a1a7; font-style: italic; }
+.arc .doc { color: #7c8594; font-style: italic; }
+.arc .bad { color: #e45649; text-decoration: wavy underline; }
+";
+
+/// CSS class used for a token, or None for plain whitespace
+pub fn css_class(token: &Token, next: Option<&Token>) -> Option<&'static str> {
+    let class = match &token.kind {
+        TokenKind::Number(_) | TokenKind::Float(_) => "num",
+        TokenKind::String(_) | TokenKind::Bytes(_) => "str",
+        TokenKind::Boolean(_) => "bool",
+        TokenKind::Identifier(_) => {
+            // Identifiers directly followed by '(' are function calls
+            if next.map(|t| t.kind == TokenKind::LeftParen).unwrap_or(false) {
+                "fn"
+            } else {
+                "ident"
+            }
+        }
+        TokenKind::DocComment(_) => "doc",
+        TokenKind::Whitespace => {
+            if token.span.literal.starts_with("//") || token.span.literal.starts_with("/*") {
+                "comment"
+            } else {
+                return None;
+            }
+        }
+        kind => match kind.category() {
+            TokenCategory::Keyword => "kw",
+            TokenCategory::Punctuation => "punct",
+            TokenCategory::Invalid => "bad",
+            TokenCategory::End => return None,
+            _ => "op",
+        },
+    };
+    Some(class)
+}
+
+/// Renders source code as a `<pre>` block with one `<span>` per highlighted token
+pub fn highlight_fragment(source: &str) -> String {
+    let mut lexer = Lexer::raw(source);
+    let mut tokens: Vec<Token> = Vec::new();
+    while let Some(token) = lexer.next_token() {
+        tokens.push(token);
+    }
+
+    let mut out = String::from("<pre class=\"arc\"><code>");
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind == TokenKind::EOF {
+            continue;
+        }
+        // The next significant token decides whether an identifier is a call
+        let next = tokens[i + 1..].iter().find(|t| t.kind != TokenKind::Whitespace);
+        let text = escape_html(&token.span.literal);
+        match css_class(token, next) {
+            Some(class) => out.push_str(&format!("<span class=\"{}\">{}</span>", class, text)),
+            None => out.push_str(&text),
+        }
+    }
+    out.push_str("</code></pre>");
+    out
+}
+
+/// Renders source code as a standalone HTML document with an embedded stylesheet
+pub fn highlight_html(source: &str, title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escape_html(title),
+        STYLESHEET,
+        highlight_fragment(source)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_get_their_category_class() {
+        let html = highlight_fragment("let x = 42 + 1.5\nif true { print(\"hi\") } // done");
+
+        assert!(html.starts_with("<pre class=\"arc\"><code>"));
+        assert!(html.ends_with("</code></pre>"));
+        assert!(html.contains("<span class=\"kw\">let</span>"));
+        assert!(html.contains("<span class=\"ident\">x</span>"));
+        assert!(html.contains("<span class=\"op\">=</span>"));
+        assert!(html.contains("<span class=\"num\">42</span>"));
+        assert!(html.contains("<span class=\"num\">1.5</span>"));
+        assert!(html.contains("<span class=\"kw\">if</span>"));
+        assert!(html.contains("<span class=\"bool\">true</span>"));
+        assert!(html.contains("<span class=\"punct\">{</span>"));
+        assert!(html.contains("<span class=\"fn\">print</span>"));
+        assert!(html.contains("<span class=\"str\">&quot;hi&quot;</span>"));
+        assert!(html.contains("<span class=\"comment\">// done</span>"));
+    }
+
+    #[test]
+    fn test_doc_comments_and_bad_characters() {
+        let html = highlight_fragment("/// Counter\nlet n = 1 @ 2");
+        assert!(html.contains("<span class=\"doc\">/// Counter</span>"));
+        assert!(html.contains("<span class=\"bad\">@</span>"));
+    }
+
+    #[test]
+    fn test_call_needs_a_following_paren() {
+        // Whitespace between the name and '(' still makes a call
+        let html = highlight_fragment("f (1)\nf + 1");
+        assert_eq!(html.matches("<span class=\"fn\">f</span>").count(), 1);
+        assert_eq!(html.matches("<span class=\"ident\">f</span>").count(), 1);
+    }
+
+    #[test]
+    fn test_html_is_escaped_and_standalone() {
+        let html = highlight_html("print(1 < 2)", "a <b> & c");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>a &lt;b&gt; &amp; c</title>"));
+        assert!(html.contains("<span class=\"op\">&lt;</span>"));
+        assert!(html.contains(".arc .kw"));
+    }
+}
@@ -0,0 +1,141 @@
+//! Host functions - Rust closures callable from Arc scripts
+//!
+//! Closures with typed parameters are wrapped automatically: arity is checked and each
+//! argument is converted from a `Value` before the closure runs.
+
+use crate::ast::types::Value;
+use std::sync::Arc;
+
+/// A registered host function taking evaluated arguments
+pub type HostFunction = Arc<dyn Fn(&[Value]) -> Result<Option<Value>, String> + Send + Sync>;
+
+/// Rust types that can be received as host-function arguments
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, String>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, String> {
+        Ok(value)
+    }
+}
+
+macro_rules! impl_from_value {
+    ($($ty:ty),*) => {
+        $(
+            impl FromValue for $ty {
+                fn from_value(value: Value) -> Result<Self, String> {
+                    <$ty>::try_from(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_value!(i64, f64, bool, String);
+
+/// Rust types that can be returned from host functions
+pub trait HostReturn {
+    fn into_result(self) -> Result<Option<Value>, String>;
+}
+
+impl HostReturn for () {
+    fn into_result(self) -> Result<Option<Value>, String> {
+        Ok(None)
+    }
+}
+
+impl HostReturn for Value {
+    fn into_result(self) -> Result<Option<Value>, String> {
+        Ok(Some(self))
+    }
+}
+
+macro_rules! impl_host_return {
+    ($($ty:ty),*) => {
+        $(
+            impl HostReturn for $ty {
+                fn into_result(self) -> Result<Option<Value>, String> {
+                    Ok(Some(Value::from(self)))
+                }
+            }
+        )*
+    };
+}
+
+impl_host_return!(i64, f64, bool, String, &str);
+
+/// `Err` becomes a runtime error in the calling script
+impl<T: HostReturn> HostReturn for Result<T, String> {
+    fn into_result(self) -> Result<Option<Value>, String> {
+        self.and_then(HostReturn::into_result)
+    }
+}
+
+/// Closures that can be registered as host functions; `Args` is the parameter tuple
+pub trait IntoHostFunction<Args> {
+    fn into_host_function(self) -> HostFunction;
+}
+
+macro_rules! impl_into_host_function {
+    ($($arg:ident $name:ident),*) => {
+        impl<F, R, $($arg),*> IntoHostFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + Send + Sync + 'static,
+            R: HostReturn,
+            $($arg: FromValue,)*
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn into_host_function(self) -> HostFunction {
+                Arc::new(move |args: &[Value]| {
+                    let arity = <[&str]>::len(&[$(stringify!($name)),*]);
+                    if args.len() != arity {
+                        return Err(format!("expected {} argument(s), found {}", arity, args.len()));
+                    }
+                    let mut args = args.iter().cloned().enumerate();
+                    $(
+                        let $name = match args.next() {
+                            Some((index, value)) => $arg::from_value(value)
+                                .map_err(|e| format!("argument {}: {}", index + 1, e))?,
+                            None => return Err(format!("expected {} argument(s)", arity)),
+                        };
+                    )*
+                    self($($name),*).into_result()
+                })
+            }
+        }
+    };
+}
+
+impl_into_host_function!();
+impl_into_host_function!(A a);
+impl_into_host_function!(A a, B b);
+impl_into_host_function!(A a, B b, C c);
+impl_into_host_function!(A a, B b, C c, D d);
+impl_into_host_function!(A a, B b, C c, D d, E e);
+impl_into_host_function!(A a, B b, C c, D d, E e, G g);
+
+/// Wraps a closure that receives the raw argument list, for variadic functions
+pub fn raw_host_function<F>(function: F) -> HostFunction
+where
+    F: Fn(&[Value]) -> Result<Option<Value>, String> + Send + Sync + 'static,
+{
+    Arc::new(function)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_function_checks_arity_and_types() {
+        let clamp = (|x: f64, lo: f64, hi: f64| x.max(lo).min(hi)).into_host_function();
+
+        assert_eq!(clamp(&[Value::Integer(12), Value::Float(0.0), Value::Float(10.0)]), Ok(Some(Value::Float(10.0))));
+        assert_eq!(clamp(&[Value::Float(1.0)]), Err("expected 3 argument(s), found 1".to_string()));
+        assert_eq!(
+            clamp(&[Value::Float(1.0), Value::Boolean(true), Value::Float(2.0)]),
+            Err("argument 2: Expected Float value, found Boolean".to_string())
+        );
+    }
+}
@@ -0,0 +1,734 @@
+//! Interpreter - high-level embedding API over the lexer, parser, and evaluator
+
+use crate::ast::evaluator::ASTEvaluator;
+use crate::async_host::{async_host_function, EvalFuture};
+use crate::ast::lexer::Lexer;
+use crate::ast::parser::Parser;
+use crate::ast::types::Value;
+use crate::ast::Ast;
+use crate::capabilities::EvalCapabilities;
+use crate::globals::SharedGlobals;
+use crate::ast::ASTStatement;
+use crate::host::{raw_host_function, IntoHostFunction};
+use crate::limits::SizeLimits;
+use crate::stream::StatementStream;
+use std::future::Future;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+/// An Arc interpreter that keeps variables and host functions between `eval` calls.
+///
+/// Interpreters are `Send` and `Sync`: one can be moved to a worker thread or held
+/// across `.await` points, but evaluation needs `&mut self`, so sharing one between
+/// threads requires a lock. Host functions must be `Send + Sync` for the same reason.
+#[derive(Default)]
+pub struct Interpreter {
+    pub evaluator: ASTEvaluator,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter { evaluator: ASTEvaluator::new() }
+    }
+
+    /// Creates an interpreter whose builtins may only use the given capabilities
+    pub fn with_capabilities(capabilities: EvalCapabilities) -> Self {
+        let mut interp = Interpreter::new();
+        interp.evaluator.capabilities = capabilities;
+        interp
+    }
+
+    /// Creates an interpreter that can read a shared global scope.
+    ///
+    /// Every interpreter built from the same `Arc` sees the same constants and functions,
+    /// while its own variables stay private.
+    pub fn with_globals(globals: Arc<SharedGlobals>) -> Self {
+        let mut interp = Interpreter::new();
+        interp.evaluator.globals = Some(globals);
+        interp
+    }
+
+    /// Registers a Rust closure as a function callable from scripts.
+    ///
+    /// Arguments are converted from `Value` by their parameter types, e.g.
+    /// `interp.register("clamp", |x: f64, lo: f64, hi: f64| x.max(lo).min(hi))`.
+    pub fn register<Args>(&mut self, name: &str, function: impl IntoHostFunction<Args>) {
+        self.evaluator.host_functions.insert(name.to_string(), function.into_host_function());
+    }
+
+    /// Registers a function that receives the raw argument list, for variadic functions
+    pub fn register_raw<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&[Value]) -> Result<Option<Value>, String> + Send + Sync + 'static,
+    {
+        self.evaluator.host_functions.insert(name.to_string(), raw_host_function(function));
+    }
+
+    /// Registers an async function, such as a database query, callable from scripts run
+    /// with `eval_async`. It receives the raw argument list, like `register_raw`.
+    pub fn register_async<F, Fut>(&mut self, name: &str, function: F)
+    where
+        F: Fn(Vec<Value>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Option<Value>, String>> + Send + 'static,
+    {
+        self.evaluator.async_functions.insert(name.to_string(), async_host_function(function));
+    }
+
+    /// Sends `print` output to `sink` instead of stdout
+    pub fn set_output(&mut self, sink: impl Write + Send + Sync + 'static) {
+        self.evaluator.output_sink = Some(Box::new(sink));
+    }
+
+    /// Writes each runtime error to `sink`, one per line, as it is raised. `eval` still
+    /// returns the errors as well.
+    pub fn set_diagnostics(&mut self, sink: impl Write + Send + Sync + 'static) {
+        self.evaluator.diagnostic_sink = Some(Box::new(sink));
+    }
+
+    /// Sets the largest strings and collections scripts may build; larger ones are runtime
+    /// errors
+    pub fn set_size_limits(&mut self, limits: SizeLimits) {
+        self.evaluator.size_limits = limits;
+    }
+
+    /// Runs a whole program, returning the value of its last expression
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, String> {
+        let mut parser = Parser::from_lexer(Lexer::new(source));
+        let statements = parser.parse_all();
+        let parse_errors: Vec<String> = parser
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect();
+        if !parse_errors.is_empty() {
+            return Err(parse_errors.join("\n"));
+        }
+
+        self.evaluator.last_value = None;
+        for statement in statements {
+            self.run_statement(statement)?;
+        }
+        Ok(self.evaluator.last_value.clone())
+    }
+
+    /// Runs a whole program like `eval`, awaiting async functions on the caller's executor.
+    ///
+    /// The program runs on its own thread, which waits whenever it calls an async function
+    /// while the returned future awaits that function's future, so polling never blocks
+    /// the executor. Dropping the future early cancels the program at its next async call,
+    /// and does block: the drop waits for the worker to reach that call (or the end of the
+    /// program) so it can hand the evaluator back.
+    pub fn eval_async(&mut self, source: &str) -> EvalFuture<'_> {
+        EvalFuture::new(&mut self.evaluator, source.to_string(), |evaluator, source| {
+            let mut interp = Interpreter { evaluator };
+            let result = interp.eval(source);
+            (interp.evaluator, result)
+        })
+    }
+
+    /// Runs a program from a reader, evaluating each statement as soon as it is parsed.
+    ///
+    /// Unlike `eval`, statements before a parse error have already run when it is reported.
+    pub fn eval_stream<R: BufRead>(&mut self, reader: R) -> Result<Option<Value>, String> {
+        self.evaluator.last_value = None;
+        for statement in StatementStream::new(reader) {
+            self.run_statement(statement.map_err(|diagnostic| diagnostic.message)?)?;
+        }
+        Ok(self.evaluator.last_value.clone())
+    }
+
+    /// Evaluates one statement, returning the errors it raised
+    fn run_statement(&mut self, statement: ASTStatement) -> Result<(), String> {
+        let error_count = self.evaluator.errors.len();
+        let mut ast = Ast::new();
+        ast.add_statement(statement);
+        self.evaluator.resolve(&mut ast);
+        ast.visit(&mut self.evaluator);
+        if self.evaluator.errors.len() > error_count {
+            return Err(self.evaluator.errors.split_off(error_count).join("\n"));
+        }
+        Ok(())
+    }
+
+    /// Reads a variable
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.evaluator.lookup_variable(name).ok()
+    }
+
+    /// Defines or updates a mutable global variable
+    pub fn set(&mut self, name: &str, value: impl Into<Value>) -> Result<(), String> {
+        let value = value.into();
+        if self.evaluator.symbol_table.exists(name) {
+            self.evaluator.symbol_table.assign(name, value)
+        } else {
+            self.evaluator.symbol_table.define(name.to_string(), value, true)
+        }
+    }
+}
+
+// Compile-time check of the threading contract; a non-thread-safe field fails the build here
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Interpreter>();
+    assert_send_sync::<Value>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::channels::Channel;
+    use crate::ast::types::Function;
+
+    #[test]
+    fn test_registered_functions_are_callable() {
+        let mut interp = Interpreter::new();
+        interp.register("clamp", |x: f64, lo: f64, hi: f64| x.max(lo).min(hi));
+        interp.register("shout", |s: String| s.to_uppercase());
+        interp.set("limit", 5).unwrap();
+
+        assert_eq!(interp.eval("clamp(12, 0, limit)"), Ok(Some(Value::Float(5.0))));
+        assert_eq!(interp.eval("shout(\"hi\")"), Ok(Some(Value::String("HI".into()))));
+        assert_eq!(interp.eval("clamp(1)"), Err("clamp: expected 3 argument(s), found 1".to_string()));
+    }
+
+    #[test]
+    fn test_eval_stream_matches_eval() {
+        let source = "let a = 1\nlet b = 2\nlet c = 3\nlet d = (1 +\n";
+        let streamed = Interpreter::new().eval_stream(source.as_bytes());
+        assert_eq!(streamed, Interpreter::new().eval(source));
+        assert_eq!(streamed, Err("Unexpected end of input at line 5, column 1, expected expression".to_string()));
+
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.eval_stream("let x = [\n  1,\n  2,\n]\nx[1]\n".as_bytes()), Ok(Some(Value::Integer(2))));
+    }
+
+    #[test]
+    fn test_interpreter_runs_on_worker_thread() {
+        let mut interp = Interpreter::new();
+        interp.register("double", |x: i64| x * 2);
+        interp.eval("let x = 20").unwrap();
+
+        let handle = std::thread::spawn(move || {
+            let result = interp.eval("double(x) + 2");
+            (interp, result)
+        });
+        let (interp, result) = handle.join().unwrap();
+        assert_eq!(result, Ok(Some(Value::Integer(42))));
+        assert_eq!(interp.get("x"), Some(Value::Integer(20)));
+    }
+
+    #[test]
+    fn test_eval_async_awaits_host_futures() {
+        use std::task::{Context, Poll, Wake, Waker};
+
+        /// Yields once before completing, like a future waiting on I/O
+        struct YieldOnce(bool);
+        impl Future for YieldOnce {
+            type Output = ();
+            fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+                if self.0 {
+                    return Poll::Ready(());
+                }
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let mut future = std::pin::pin!(future);
+            let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+            let mut cx = Context::from_waker(&waker);
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+                std::thread::park();
+            }
+        }
+
+        let mut interp = Interpreter::new();
+        interp.register_async("fetch", |args: Vec<Value>| async move {
+            YieldOnce(false).await;
+            match args.as_slice() {
+                [Value::Integer(id)] => Ok(Some(Value::from(format!("row {}", id)))),
+                _ => Err("expected an id".to_string()),
+            }
+        });
+        assert_eq!(
+            block_on(interp.eval_async("let a = fetch(1)\nlet b = fetch(2)\na + \", \" + b")),
+            Ok(Some(Value::from("row 1, row 2")))
+        );
+        // The evaluator comes back with the program's variables
+        assert_eq!(interp.get("b"), Some(Value::from("row 2")));
+        assert_eq!(block_on(interp.eval_async("fetch()")), Err("fetch: expected an id".to_string()));
+        assert_eq!(
+            interp.eval("fetch(1)"),
+            Err("fetch: async functions can only be called under Interpreter::eval_async".to_string())
+        );
+    }
+
+    #[test]
+    fn test_failure_scopes_keep_block_locals() {
+        let mut interp = Interpreter::new();
+        interp.evaluator.keep_failure_scopes = true;
+        assert!(interp.eval("let total = 0\nfor i in 1..5 { total = total + i\nlet bad = i / (3 - i) }").is_err());
+        // The loop's scope is gone from the live table but kept in the snapshot
+        assert_eq!(interp.get("i"), None);
+        let scopes = interp.evaluator.failure_scopes.take().unwrap();
+        assert_eq!(scopes.get_value("i"), Ok(Value::Integer(3)));
+        assert_eq!(scopes.get_value("total"), Ok(Value::Integer(6)));
+    }
+
+    #[test]
+    fn test_statements_return_their_values() {
+        use crate::ast::StatementEvaluator;
+        let statements = Parser::from_lexer(Lexer::new("let x = 2 * 3\nx = x + 1\nfor i in [1] { x }\nx")).parse_all();
+        let mut evaluator = ASTEvaluator::new();
+        let values: Vec<_> = statements.iter().map(|statement| evaluator.evaluate_statement(statement)).collect();
+        assert_eq!(values, [Some(Value::Integer(6)), Some(Value::Integer(7)), None, Some(Value::Integer(7))]);
+        assert!(evaluator.errors.is_empty());
+        assert_eq!(evaluator.last_value, None);
+    }
+
+    #[test]
+    fn test_errors_in_imports_carry_a_trace() {
+        let dir = std::env::temp_dir().join(format!("arc-trace-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("inner.arc"), "let a = 1\nlet b = missing\nlet c = also_missing\n").unwrap();
+        std::fs::write(dir.join("outer.arc"), "// helpers\n\nimport \"inner\"\nlet d = a\n").unwrap();
+
+        let mut interp = Interpreter::new();
+        interp.evaluator.modules = crate::package::ModuleLoader::for_script(&dir.join("main.arc")).unwrap();
+        interp.evaluator.source_name = Some("<repl>".to_string());
+        let result = interp.eval("import \"outer\"");
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            result,
+            Err([
+                "Variable 'missing' not found\n  at module 'inner', line 2\n  at module 'outer', line 3\n  at <repl>",
+                "Failed to evaluate initializer for variable 'b'",
+                "Variable 'also_missing' not found\n  at module 'inner', line 3\n  at module 'outer', line 3\n  at <repl>",
+                "Failed to evaluate initializer for variable 'c'",
+            ]
+            .join("\n"))
+        );
+    }
+
+    #[test]
+    fn test_instances_share_globals_but_not_variables() {
+        let mut globals = SharedGlobals::new();
+        globals.define("RATE", 2);
+        globals.register("scale", |x: i64| x * 10);
+        let globals = globals.into_shared();
+
+        let mut first = Interpreter::with_globals(Arc::clone(&globals));
+        let mut second = Interpreter::with_globals(globals);
+        first.eval("let total = scale(RATE)").unwrap();
+
+        assert_eq!(first.get("total"), Some(Value::Integer(20)));
+        assert_eq!(second.get("total"), None);
+        assert_eq!(second.eval("RATE = 3"), Err("Cannot assign to shared global 'RATE'".to_string()));
+        assert_eq!(second.eval("let RATE = 5\nRATE"), Ok(Some(Value::Integer(5))));
+        assert_eq!(first.get("RATE"), Some(Value::Integer(2)));
+    }
+
+    #[test]
+    fn test_channels_connect_interpreters_on_threads() {
+        let jobs = Value::from(Channel::new(Some(2)));
+        let mut consumer = Interpreter::new();
+        consumer.set("jobs", jobs.clone()).unwrap();
+
+        let producer = std::thread::spawn(move || {
+            let mut producer = Interpreter::new();
+            producer.set("jobs", jobs).unwrap();
+            producer.eval("for i in 1..6 { send(jobs, {\"id\": i}) }\nclose(jobs)")
+        });
+        consumer.eval("let total = 0\nfor i in 1..6 { total = total + recv(jobs)[\"id\"] }").unwrap();
+        assert_eq!(producer.join().unwrap(), Ok(None));
+        assert_eq!(consumer.eval("total"), Ok(Some(Value::Integer(15))));
+        assert_eq!(consumer.eval("recv(jobs)"), Err("recv: channel is closed and empty".to_string()));
+        assert_eq!(consumer.eval("jobs == jobs"), Ok(Some(Value::Boolean(true))));
+    }
+
+    #[test]
+    fn test_denied_capabilities_raise_errors() {
+        let mut interp = Interpreter::with_capabilities(EvalCapabilities { time: true, ..EvalCapabilities::none() });
+
+        assert!(matches!(interp.eval("clock()"), Ok(Some(Value::Float(_)))));
+        assert_eq!(interp.eval("read_file(\"/etc/passwd\")"), Err("Capability denied: read_file() requires 'filesystem'".to_string()));
+        assert_eq!(interp.eval("print(1)"), Err("Capability denied: print() requires 'stdout'".to_string()));
+        assert_eq!(interp.eval("import \"util\""), Err("Capability denied: import requires 'filesystem'".to_string()));
+        assert_eq!(interp.eval("http_get(\"http://localhost\")"), Err("Capability denied: http_get() requires 'network'".to_string()));
+        // Not even the default set lets a script run other programs
+        assert_eq!(Interpreter::new().eval("exec(\"ls\")"), Err("Capability denied: exec() requires 'process'".to_string()));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_exec_reports_code_and_output() {
+        let mut interp = Interpreter::with_capabilities(EvalCapabilities::all());
+        interp.eval("let result = exec(\"sh\", [\"-c\", \"echo out; echo err >&2; exit 3\"])").unwrap();
+
+        assert_eq!(interp.eval("result[\"code\"]"), Ok(Some(Value::Integer(3))));
+        assert_eq!(interp.eval("result[\"stdout\"]"), Ok(Some(Value::from("out\n"))));
+        assert_eq!(interp.eval("result[\"stderr\"]"), Ok(Some(Value::from("err\n"))));
+        assert_eq!(interp.eval("exec(\"sh\", [1])"), Err("exec: arguments must be Strings, found Integer".to_string()));
+        assert!(interp.eval("exec(\"/no/such/program\")").unwrap_err().starts_with("exec: cannot run '/no/such/program'"));
+    }
+
+    #[test]
+    fn test_method_calls_pass_the_receiver_first() {
+        let mut interp = Interpreter::new();
+        interp.register("shout", |s: String| s.to_uppercase());
+        interp.eval("let x = -16").unwrap();
+
+        assert_eq!(interp.eval("x.abs().sqrt()"), Ok(Some(Value::Float(4.0))));
+        assert_eq!(interp.eval("-x.abs()"), Ok(Some(Value::Integer(-16))));
+        assert_eq!(interp.eval("\"a\".shout().len"), Err("String has no field 'len'".to_string()));
+    }
+
+    #[test]
+    fn test_any_expression_can_be_called() {
+        let mut interp = Interpreter::new();
+        interp.register("shout", |s: String| s.to_uppercase());
+        interp.eval("let f = sqrt").unwrap();
+
+        assert_eq!(interp.eval("f(16)"), Ok(Some(Value::Float(4.0))));
+        assert_eq!(interp.eval("(f)(9)"), Ok(Some(Value::Float(3.0))));
+        assert_eq!(interp.eval("let s = shout\ns(\"hi\")"), Ok(Some(Value::from("HI"))));
+        assert_eq!(interp.eval("f"), Ok(Some(Value::Function(Function::Named("sqrt".into())))));
+        assert_eq!(interp.eval("5(1)"), Err("Integer is not callable".to_string()));
+        assert_eq!(interp.eval("nope(1)"), Err("Unknown function: 'nope'".to_string()));
+    }
+
+    #[test]
+    fn test_bound_functions_supply_leading_arguments() {
+        let mut interp = Interpreter::new();
+        interp.register("greet", |greeting: String, name: String| format!("{}, {}", greeting, name));
+        interp.eval("let hello = bind(greet, \"Hello\")").unwrap();
+
+        assert_eq!(interp.eval("hello(\"Ada\")"), Ok(Some(Value::from("Hello, Ada"))));
+        assert_eq!(interp.eval("bind(pow, 2)(10)"), Ok(Some(Value::Float(1024.0))));
+        assert_eq!(interp.eval("sort_by([1, 3, 2], bind(cmp))"), Ok(Some(Value::from(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)]))));
+        assert_eq!(interp.eval("\"Ada\" |> hello"), Ok(Some(Value::from("Hello, Ada"))));
+        assert_eq!(interp.eval("hello").map(|value| value.unwrap().to_string()), Ok("<function bind(greet, \"Hello\")>".to_string()));
+        assert_eq!(interp.eval("bind(sqrt, 4)(9)"), Err("sqrt expects 1 numeric argument, got 2 arguments (Integer, Integer)".to_string()));
+    }
+
+    #[test]
+    fn test_functions_combine_into_new_functions() {
+        let mut interp = Interpreter::new();
+        interp.eval("let root_of_sum = compose(sqrt, sum)").unwrap();
+
+        assert_eq!(interp.eval("root_of_sum([9, 16])"), Ok(Some(Value::Float(5.0))));
+        assert_eq!(interp.eval("apply(pow, [2, 3])"), Ok(Some(Value::Float(8.0))));
+        assert_eq!(interp.eval("apply(compose(identity, constantly(7)), [1, 2, 3])"), Ok(Some(Value::Integer(7))));
+        assert_eq!(interp.eval("[arity(pow), arity(root_of_sum), arity(bind(pow, 2)), arity(print)]").map(|value| value.unwrap().to_string()), Ok("[2, 1, 1, 0]".to_string()));
+        assert_eq!(interp.eval("compose(abs, flush)()"), Err("flush returned no value to pass to abs".to_string()));
+        assert_eq!(interp.eval("apply(sqrt, [])"), Err("sqrt expects 1 numeric argument, got no arguments".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_names_suggest_close_matches() {
+        let mut interp = Interpreter::new();
+        interp.register("fetch_rows", |n: i64| n);
+        assert_eq!(interp.eval("let count = 1\nlet total = 2"), Ok(Some(Value::Integer(2))));
+        assert_eq!(interp.eval("pritn(count)"), Err("Unknown function: 'pritn'; did you mean 'print'?".to_string()));
+        assert_eq!(interp.eval("fetch_row(1)"), Err("Unknown function: 'fetch_row'; did you mean 'fetch_rows'?".to_string()));
+        assert_eq!(interp.eval("coutn"), Err("Variable 'coutn' not found; did you mean 'count'?".to_string()));
+        assert_eq!(interp.eval("sqtr"), Err("Variable 'sqtr' not found; did you mean 'sqrt'?".to_string()));
+        // Only variables can be assigned to
+        assert_eq!(interp.eval("totl = 3"), Err("Variable 'totl' not found; did you mean 'total'?".to_string()));
+        assert_eq!(interp.eval("prnt = 3"), Err("Variable 'prnt' not found".to_string()));
+    }
+
+    #[test]
+    fn test_for_in_walks_every_iterable() {
+        let mut interp = Interpreter::new();
+        interp.eval("let total = 0\nlet text = \"\"").unwrap();
+
+        interp.eval("for i in 1..5 {\n total = total + i\n}").unwrap();
+        assert_eq!(interp.eval("total"), Ok(Some(Value::Integer(10))));
+        interp.eval("for (k, v) in {\"b\": 2, \"a\": 1} { text = text + k + v }").unwrap();
+        interp.eval("for ch in \"xy\" { text = text + ch }").unwrap();
+        interp.eval("for item in [true, 2.5] { text = text + item }").unwrap();
+        assert_eq!(interp.eval("text"), Ok(Some(Value::from("a1b2xytrue2.5"))));
+
+        assert_eq!(interp.eval("for x in 3 { }"), Err("Integer is not iterable".to_string()));
+        assert_eq!(interp.eval("for (a, b) in [1] { }"), Err("Cannot unpack Integer into 2 variables".to_string()));
+        assert_eq!(interp.eval("i"), Err("Variable 'i' not found".to_string()));
+    }
+
+    #[test]
+    fn test_labeled_break_and_continue() {
+        let mut interp = Interpreter::new();
+        interp.eval("let seen = \"\"").unwrap();
+
+        interp.eval("outer: for y in 0..3 {\n for x in 0..3 {\n  seen = seen + x + y\n  continue outer\n }\n seen = seen + \"!\"\n}").unwrap();
+        assert_eq!(interp.eval("seen"), Ok(Some(Value::from("000102"))));
+        interp.eval("seen = \"\"; rows: for y in 0..3 { for x in 0..3 { seen = seen + x; break rows } }").unwrap();
+        assert_eq!(interp.eval("seen"), Ok(Some(Value::from("0"))));
+        interp.eval("seen = \"\"; for y in 0..2 { for x in 0..3 { seen = seen + x; break; seen = \"?\" } }").unwrap();
+        assert_eq!(interp.eval("seen"), Ok(Some(Value::from("00"))));
+        interp.eval("seen = \"\"; for c in \"ab\" { continue; seen = c }").unwrap();
+        assert_eq!(interp.eval("seen"), Ok(Some(Value::from(""))));
+
+        assert!(interp.eval("break").unwrap_err().contains("'break' outside of a loop"));
+        assert!(interp.eval("for i in 0..2 { continue inner }").unwrap_err().contains("No enclosing loop is labeled 'inner'"));
+        assert!(interp.eval("done: print(1)").unwrap_err().contains("Expected a 'for' or 'while' loop after the label 'done:'"));
+    }
+
+    #[test]
+    fn test_while_loops() {
+        let mut interp = Interpreter::new();
+        interp.eval("let n = 10\nlet steps = 0\nwhile n != 1 {\n    if n % 2 == 0 { n = n / 2 } else { n = 3 * n + 1 }\n    steps = steps + 1\n}").unwrap();
+        assert_eq!(interp.eval("steps"), Ok(Some(Value::Integer(6))));
+        interp.eval("let seen = \"\"; let i = 0\nouter: while true {\n    i = i + 1\n    if i % 2 == 0 { continue }\n    for c in \"ab\" { seen = seen + c + i; if i > 4 { break outer } }\n}").unwrap();
+        assert_eq!(interp.eval("seen"), Ok(Some(Value::from("a1b1a3b3a5"))));
+        // The condition's scope is outside the body's, which starts afresh each time
+        assert!(interp.eval("while false { let x = 1 }\nx").unwrap_err().contains("Variable 'x' not found"));
+
+        // An endless loop stops under the default limit without any setup
+        let error = interp.eval("let spins = 0\nwhile true { spins = spins + 1 }").unwrap_err();
+        assert!(error.starts_with("'while' loop exceeded the limit of 1000000 iterations"), "{}", error);
+        assert_eq!(interp.eval("spins"), Ok(Some(Value::Integer(1_000_000))));
+
+        interp.set_size_limits(SizeLimits { max_loop_iterations: 100, ..SizeLimits::default() });
+        let error = interp.eval("spins = 0\nwhile true { spins = spins + 1 }").unwrap_err();
+        assert!(error.starts_with("'while' loop exceeded the limit of 100 iterations"), "{}", error);
+        assert_eq!(interp.eval("spins"), Ok(Some(Value::Integer(100))));
+    }
+
+    #[test]
+    fn test_if_else_if_chains() {
+        let mut interp = Interpreter::new();
+        interp.eval("let grade = \"\"").unwrap();
+        let classify = "for score in [95, 75, 40] {\n if score >= 90 { grade = grade + \"A\" }\n else if score >= 70 { grade = grade + \"C\" } else { grade = grade + \"F\" }\n}";
+        interp.eval(classify).unwrap();
+        assert_eq!(interp.eval("grade"), Ok(Some(Value::from("ACF"))));
+
+        // Branches are scoped, and a failing condition stops the chain
+        interp.eval("if 0 { grade = 1 } else if [1] { let inner = 2 }").unwrap();
+        assert_eq!(interp.eval("inner"), Err("Variable 'inner' not found".to_string()));
+        assert_eq!(interp.eval("if missing { } else { grade = 1 }"), Err("Variable 'missing' not found".to_string()));
+        assert_eq!(interp.eval("grade"), Ok(Some(Value::from("ACF"))));
+    }
+
+    #[test]
+    fn test_guard_leaves_the_iteration() {
+        let mut interp = Interpreter::new();
+        interp.eval("let total = 0").unwrap();
+        let program = "for x in [4, -1, 0, 5, 99, 6] {\n guard x > 0 else { continue }\n guard x < 50 else { break }\n total = total + x\n}";
+        interp.eval(program).unwrap();
+        assert_eq!(interp.eval("total"), Ok(Some(Value::Integer(9))));
+        interp.eval("for x in [1, 2] { guard x == 1 else { if x > 5 { break } else { continue } }\n total = 0 }").unwrap();
+        assert_eq!(interp.eval("total"), Ok(Some(Value::Integer(0))));
+
+        let not_diverging = "for x in [1] { guard x else { print(x) } }";
+        assert!(interp.eval(not_diverging).unwrap_err().contains("The 'else' block of a 'guard' must end with 'break' or 'continue'"));
+        assert!(interp.eval("for x in [1] { guard x else { if x { break } } }").is_err());
+        assert!(interp.eval("guard true else { break }").unwrap_err().contains("'break' outside of a loop"));
+    }
+
+    #[test]
+    fn test_with_closes_the_resource_on_every_exit() {
+        let mut interp = Interpreter::new();
+        interp.eval("let jobs = channel()").unwrap();
+        interp.eval("with jobs as out { send(out, 1); send(out, 2) }").unwrap();
+        assert_eq!(interp.eval("[recv(jobs), recv(jobs)]"), interp.eval("[1, 2]"));
+        assert_eq!(interp.eval("send(jobs, 3)"), Err("send: cannot send on a closed channel".to_string()));
+
+        // Closed after an error and after leaving a loop early
+        interp.eval("let a = channel()\nlet b = channel()").unwrap();
+        assert!(interp.eval("with a as out { missing }").is_err());
+        interp.eval("for i in 0..3 { with b as out { break } }").unwrap();
+        assert!(interp.eval("send(a, 1)").is_err());
+        assert!(interp.eval("send(b, 1)").is_err());
+
+        assert_eq!(interp.eval("out"), Err("Variable 'out' not found".to_string()));
+        assert_eq!(interp.eval("with 5 as n { n }"), Err("'with' needs a resource that can be closed, found Integer".to_string()));
+    }
+
+    #[test]
+    fn test_defer_runs_at_block_exit_in_reverse() {
+        let mut interp = Interpreter::new();
+        interp.evaluator.captured_output = Some(String::new());
+        let output = |interp: &mut Interpreter| interp.evaluator.captured_output.replace(String::new()).unwrap_or_default();
+
+        interp.eval("for i in 1..3 {\n let name = \"f\" + i\n defer print(\"close\", name)\n defer print(\"flush\", i)\n print(\"use\", name)\n}").unwrap();
+        assert_eq!(output(&mut interp), "use f1\nflush 1\nclose f1\nuse f2\nflush 2\nclose f2\n");
+
+        // Deferred expressions still run when the block ends early
+        interp.eval("for i in 0..5 { defer print(i); if i == 1 { break } }").unwrap();
+        assert_eq!(output(&mut interp), "0\n1\n");
+        assert!(interp.eval("if true { defer print(\"cleanup\"); missing }").is_err());
+        assert_eq!(output(&mut interp), "cleanup\n");
+
+        assert!(interp.eval("defer print(1)").unwrap_err().contains("'defer' outside of a block"));
+    }
+
+    #[test]
+    fn test_blocks_are_expressions() {
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.eval("let x = { let t = 2 + 3; t * 2 }\nx"), Ok(Some(Value::Integer(10))));
+        assert_eq!(interp.eval("t"), Err("Variable 't' not found".to_string()));
+        assert_eq!(interp.eval("let y = {\n x = x + 1\n x\n}\ny"), Ok(Some(Value::Integer(11))));
+        interp.evaluator.captured_output = Some(String::new());
+        assert_eq!(interp.eval("{ defer print(\"done\"); print(x); x }"), Ok(Some(Value::Integer(11))));
+        assert_eq!(interp.evaluator.captured_output.take(), Some("11\ndone\n".to_string()));
+        assert_eq!(interp.eval("{x}"), interp.eval("{11}"));
+
+        assert!(interp.eval("let z = { let t = 1 }").unwrap_err().contains("A block used as a value must end with an expression"));
+        assert!(interp.eval("for i in 0..2 { let z = { break; 1 } }").unwrap_err().contains("'break' outside of a loop"));
+    }
+
+    #[test]
+    fn test_spread_flattens_arrays_in_place() {
+        let mut interp = Interpreter::new();
+        interp.eval("let rest = [2, 3]").unwrap();
+
+        assert_eq!(interp.eval("[1, ...rest, 9]"), Ok(Some(Value::from(vec![1.into(), 2.into(), 3.into(), 9.into()]))));
+        assert_eq!(interp.eval("max(...rest, 1)"), Ok(Some(Value::Integer(3))));
+        assert_eq!(interp.eval("[...[], ...rest]"), Ok(Some(Value::from(vec![2.into(), 3.into()]))));
+        assert_eq!(interp.eval("max(...5)"), Err("Cannot spread Integer; only arrays can be spread".to_string()));
+    }
+
+    #[test]
+    fn test_aggregates_copy_on_write_and_freeze() {
+        let mut interp = Interpreter::new();
+        interp.eval("let a = [1, [2, 3]]; let b = a; b[1][0] = 9").unwrap();
+        assert_eq!(interp.eval("a").map(|v| v.unwrap().to_string()), Ok("[1, [2, 3]]".to_string()));
+        assert_eq!(interp.eval("b").map(|v| v.unwrap().to_string()), Ok("[1, [9, 3]]".to_string()));
+
+        interp.eval("let m = {\"x\": 1}; m[\"y\"] = 2").unwrap();
+        assert_eq!(interp.eval("m").map(|v| v.unwrap().to_string()), Ok("{\"x\": 1, \"y\": 2}".to_string()));
+
+        interp.eval("let f = freeze(a)").unwrap();
+        assert_eq!(interp.eval("f[1][0] = 7"), Err("Cannot modify a frozen value".to_string()));
+        interp.eval("let g = copy(f); g[1][0] = 7").unwrap();
+        assert_eq!(interp.eval("g[1]").map(|v| v.unwrap().to_string()), Ok("[7, 3]".to_string()));
+        assert_eq!(interp.eval("a[5] = 0"), Err("Index 5 is out of bounds for length 2".to_string()));
+        assert_eq!(interp.eval("let s = \"hi\"; s[0] = \"y\""), Err("Cannot assign into a String; strings are immutable".to_string()));
+    }
+
+    #[test]
+    fn test_bytes_index_slice_and_convert() {
+        let mut interp = Interpreter::new();
+        interp.eval("let data = b\"\\x89PNG\\r\\n\"").unwrap();
+        assert_eq!(interp.eval("data[0]"), Ok(Some(Value::Integer(0x89))));
+        assert_eq!(interp.eval("data[1..4].decode()"), Ok(Some(Value::from("PNG"))));
+        assert_eq!(interp.eval("data[4..6]").map(|v| v.unwrap().to_string()), Ok("b\"\\r\\n\"".to_string()));
+        assert_eq!(interp.eval("bytes([104, 105]) == \"hi\".encode(\"ascii\")"), Ok(Some(Value::Boolean(true))));
+        assert_eq!(interp.eval("\"héllo\"[1..3]"), Ok(Some(Value::from("él"))));
+        assert_eq!(interp.eval("data.decode()"), Err("decode: invalid utf-8 at byte 0".to_string()));
+        assert_eq!(interp.eval("data[2..9]"), Err("Slice 2..9 is out of bounds for length 6".to_string()));
+    }
+
+    #[test]
+    fn test_json_builtins() {
+        let mut interp = Interpreter::new();
+        interp.eval("let config = json_parse(\"{\\\"port\\\": 8080, \\\"hosts\\\": [\\\"a\\\", \\\"b\\\"]}\")").unwrap();
+        assert_eq!(interp.eval("config[\"hosts\"][1]"), Ok(Some(Value::from("b"))));
+        assert_eq!(interp.eval("config[\"port\"] + 1"), Ok(Some(Value::Integer(8081))));
+        assert_eq!(interp.eval("json_stringify(config)"), Ok(Some(Value::from("{\"hosts\":[\"a\",\"b\"],\"port\":8080}"))));
+        assert_eq!(interp.eval("json_parse(\"[1\")"), Err("json_parse: expected ',' or ']' at byte 2".to_string()));
+    }
+
+    #[test]
+    fn test_sets_and_membership() {
+        let mut interp = Interpreter::new();
+        interp.eval("let a = {3, 1, 2, 1}; let b = set([2, 3, 4])").unwrap();
+        assert_eq!(interp.eval("a").map(|v| v.unwrap().to_string()), Ok("{1, 2, 3}".to_string()));
+        assert_eq!(interp.eval("a | b").map(|v| v.unwrap().to_string()), Ok("{1, 2, 3, 4}".to_string()));
+        assert_eq!(interp.eval("a & b").map(|v| v.unwrap().to_string()), Ok("{2, 3}".to_string()));
+        assert_eq!(interp.eval("a - b").map(|v| v.unwrap().to_string()), Ok("{1}".to_string()));
+        assert_eq!(interp.eval("(b - a - {4}) == set()"), Ok(Some(Value::Boolean(true))));
+
+        assert_eq!(interp.eval("2 in a && !(5 in a)"), Ok(Some(Value::Boolean(true))));
+        assert_eq!(interp.eval("\"ell\" in \"hello\" && 3 in 0..4 && \"k\" in {\"k\": 1} && 2 in [1, 2]"), Ok(Some(Value::Boolean(true))));
+        assert_eq!(interp.eval("1 + 1 in a == 3 - 2 in [true]"), Ok(Some(Value::Boolean(false))));
+        assert_eq!(interp.eval("1 in 5"), Err("Cannot test whether Integer is in Integer".to_string()));
+        assert_eq!(interp.eval("{1.5}"), Err("Set elements must be Integer, String or Boolean, found Float".to_string()));
+        assert_eq!(interp.eval("{}").map(|v| v.unwrap().to_string()), Ok("{}".to_string()));
+    }
+
+    #[test]
+    fn test_sort_by_calls_back_into_functions() {
+        let mut interp = Interpreter::new();
+        interp.register("by_length", |a: String, b: String| a.chars().count() as i64 - b.chars().count() as i64);
+        assert_eq!(
+            interp.eval("sort_by([\"ccc\", \"a\", \"bb\"], by_length)").map(|v| v.unwrap().to_string()),
+            Ok("[\"a\", \"bb\", \"ccc\"]".to_string())
+        );
+        assert_eq!(interp.eval("[3, 1, 2].sort().reverse()[0] + sum(0..4)"), Ok(Some(Value::Integer(9))));
+        assert_eq!(interp.eval("sort_by([1, 2], by_length)"), Err("sort_by: by_length: argument 1: Expected String value, found Integer".to_string()));
+        assert_eq!(interp.eval("sort_by([\"b\", \"B\", \"a\"], cmp)"), interp.eval("[\"B\", \"a\", \"b\"]"));
+        assert_eq!(interp.eval("\"apple\" < \"apples\" && [1, 2] < [1, 3] && [2] >= [1, 9]"), Ok(Some(Value::Boolean(true))));
+    }
+
+    #[test]
+    fn test_output_and_diagnostics_go_to_sinks() {
+        use crate::output::callback_sink;
+        use std::sync::Mutex;
+
+        let log = Arc::new(Mutex::new(String::new()));
+        let sink = |prefix: &'static str| {
+            let log = log.clone();
+            callback_sink(move |text| log.lock().unwrap().push_str(&format!("{}{}", prefix, text)))
+        };
+        let mut interp = Interpreter::new();
+        interp.set_output(sink("out: "));
+        interp.set_diagnostics(sink("err: "));
+
+        assert!(interp.eval("print(1)").is_ok());
+        assert!(interp.eval("missing").is_err());
+        assert_eq!(*log.lock().unwrap(), "out: 1\nerr: Variable 'missing' not found\n");
+    }
+
+    #[test]
+    fn test_size_limits_stop_runaway_values() {
+        let mut interp = Interpreter::new();
+        interp.set_size_limits(SizeLimits { max_string_len: 1000, max_collection_len: 10, ..SizeLimits::default() });
+        let error = interp.eval("let s = \"x\"\nfor i in 0..64 { s = s + s }").unwrap_err();
+        assert!(error.starts_with("String of 1024 bytes exceeds the size limit of 1000\n"));
+        assert!(matches!(interp.get("s"), Some(Value::String(text)) if text.len() == 512));
+        assert_eq!(interp.eval("join([s, s], \"\")"), Err("join: String of 1024 bytes exceeds the size limit of 1000".to_string()));
+        assert_eq!(
+            interp.eval("let a = [1, 2, 3, 4, 5, 6]\n[...a, ...a]"),
+            Err("Collection of 12 items exceeds the size limit of 10".to_string())
+        );
+    }
+
+    #[test]
+    fn test_output_is_flushed_per_statement_and_before_errors() {
+        use crate::output::callback_sink;
+        use std::sync::Mutex;
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let sink = |prefix: &'static str| {
+            let log = log.clone();
+            callback_sink(move |text| log.lock().unwrap().push(format!("{}{}", prefix, text)))
+        };
+        let mut interp = Interpreter::new();
+        interp.set_output(sink("out: "));
+        interp.set_diagnostics(sink("err: "));
+        let take = |log: &Arc<Mutex<Vec<String>>>| std::mem::take(&mut *log.lock().unwrap());
+
+        assert!(interp.eval("for i in 1..3 { print(i) }").is_ok());
+        assert_eq!(take(&log), ["out: 1\n2\n"]);
+        assert!(interp.eval("for i in 1..3 { print(i); flush() }").is_ok());
+        assert_eq!(take(&log), ["out: 1\n", "out: 2\n"]);
+        assert!(interp.eval("{ print(1); missing }").is_err());
+        assert_eq!(take(&log), ["out: 1\n", "err: Variable 'missing' not found\n"]);
+        assert_eq!(interp.eval("flush(1)"), Err("flush expects no arguments, got 1 argument (Integer)".to_string()));
+    }
+}
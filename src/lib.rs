@@ -1,3 +1,27 @@
 //! Arc Compiler Library - Core components for lexing, parsing, and evaluation
 
 pub mod ast;
+pub mod async_host;
+pub mod bench;
+pub mod capabilities;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod coverage;
+pub mod docgen;
+pub mod fuzz;
+pub mod globals;
+pub mod golden;
+pub mod highlight;
+pub mod host;
+pub mod interpreter;
+pub mod limits;
+pub mod output;
+pub mod package;
+pub mod prelude;
+pub mod profiler;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod session;
+pub mod stream;
+#[cfg(feature = "wasm")]
+pub mod wasm;
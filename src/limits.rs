@@ -0,0 +1,116 @@
+//! Size limits - how large a string or collection a script may build, how long a `while`
+//! loop may run, and how long a script may wait on a channel
+//!
+//! A few characters of script can ask for gigabytes, for example by doubling a string in a
+//! loop. The evaluator checks string concatenation and array building against these limits
+//! before it allocates, as do the collection builtins that collect an iterable, and every
+//! builtin's result after it returns, so a runaway value ends
+//! the statement with a runtime error instead of exhausting the host's memory. A `while`
+//! loop whose condition never turns false is stopped the same way once it has run too many
+//! times, and a `send` or `recv` that nothing else answers gives up after a while.
+
+use crate::ast::types::Value;
+use std::time::Duration;
+
+/// The largest values a script may build
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeLimits {
+    /// Bytes in a string or bytes value
+    pub max_string_len: usize,
+    /// Items in an array, map or set
+    pub max_collection_len: usize,
+    /// Runs of one `while` loop's body
+    pub max_loop_iterations: usize,
+    /// Time one `send` or `recv` may wait on a channel
+    pub max_channel_wait: Duration,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        SizeLimits {
+            max_string_len: 64 * 1024 * 1024,
+            max_collection_len: 16 * 1024 * 1024,
+            max_loop_iterations: 1_000_000,
+            max_channel_wait: Duration::from_secs(30),
+        }
+    }
+}
+
+impl SizeLimits {
+    /// No limits beyond what the host can allocate
+    pub fn unlimited() -> Self {
+        SizeLimits {
+            max_string_len: usize::MAX,
+            max_collection_len: usize::MAX,
+            max_loop_iterations: usize::MAX,
+            max_channel_wait: Duration::MAX,
+        }
+    }
+
+    /// Checks the length of a string about to be built, returning the runtime error for one
+    /// that is too long
+    pub fn check_string(&self, len: usize) -> Result<(), String> {
+        if len <= self.max_string_len {
+            Ok(())
+        } else {
+            Err(format!("String of {} bytes exceeds the size limit of {}", len, self.max_string_len))
+        }
+    }
+
+    /// Checks the length of an array, map or set about to be built
+    pub fn check_collection(&self, len: usize) -> Result<(), String> {
+        if len <= self.max_collection_len {
+            Ok(())
+        } else {
+            Err(format!("Collection of {} items exceeds the size limit of {}", len, self.max_collection_len))
+        }
+    }
+
+    /// Checks that a `while` loop may run its body for the `count`th time
+    pub fn check_iterations(&self, count: usize) -> Result<(), String> {
+        if count <= self.max_loop_iterations {
+            Ok(())
+        } else {
+            Err(format!("'while' loop exceeded the limit of {} iterations", self.max_loop_iterations))
+        }
+    }
+
+    /// The runtime error for a channel `send` or `recv` that waited as long as it may
+    pub fn channel_wait_error(&self, operation: &str) -> String {
+        let outcome = if operation == "send" { "the channel stayed full" } else { "nothing was sent" };
+        format!("waited the limit of {} seconds and {}", self.max_channel_wait.as_secs_f64(), outcome)
+    }
+
+    /// Checks a value that has already been built. Only the value itself is measured, not
+    /// the values inside it.
+    pub fn check(&self, value: &Value) -> Result<(), String> {
+        match value {
+            Value::String(text) => self.check_string(text.len()),
+            Value::Bytes(bytes) => self.check_string(bytes.len()),
+            Value::Array(items) => self.check_collection(items.len()),
+            Value::Map(entries) => self.check_collection(entries.len()),
+            Value::Set(elements) => self.check_collection(elements.len()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_are_measured_against_the_limits() {
+        let limits = SizeLimits { max_string_len: 3, max_collection_len: 2, max_loop_iterations: 5, ..SizeLimits::default() };
+        assert!(limits.check(&Value::from("abc")).is_ok());
+        assert_eq!(limits.check(&Value::from("abcd")), Err("String of 4 bytes exceeds the size limit of 3".to_string()));
+        assert_eq!(
+            limits.check(&Value::from(vec![Value::Integer(1); 3])),
+            Err("Collection of 3 items exceeds the size limit of 2".to_string())
+        );
+        assert!(SizeLimits::unlimited().check(&Value::from("abcd")).is_ok());
+        assert!(limits.check_iterations(5).is_ok());
+        assert_eq!(limits.check_iterations(6), Err("'while' loop exceeded the limit of 5 iterations".to_string()));
+        assert_eq!(limits.channel_wait_error("recv"), "waited the limit of 30 seconds and nothing was sent");
+    }
+}
@@ -1,93 +1,904 @@
 //! Arc Compiler - Supports REPL mode and file execution
 
-mod ast;
-use ast::lexer::Token;
-use ast::Ast;
+use arc_compiler::ast;
+use ast::{Ast, ASTImportStatement, ASTStatement, ASTStatementKind};
 use ast::parser::Parser;
 use ast::evaluator::ASTEvaluator;
-use ast::ASTVisitor;
-use std::io::{self, Write, BufRead};
+use ast::{diagnostics, lint};
+use ast::number_format::NumberFormat;
+use arc_compiler::bench;
+use arc_compiler::capabilities::EvalCapabilities;
+use arc_compiler::coverage::Coverage;
+use arc_compiler::docgen;
+use arc_compiler::highlight;
+use arc_compiler::limits::SizeLimits;
+use arc_compiler::package::{self, Manifest, ModuleLoader};
+use arc_compiler::profiler::Profiler;
+use arc_compiler::session::Session;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
 use std::env;
 use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Options controlling file execution
+#[derive(Default)]
+struct RunOptions {
+    profile: bool,
+    profile_folded: Option<String>,
+    coverage: bool,
+    coverage_lcov: Option<String>,
+    optimize: bool,
+    /// Report unknown escape sequences in strings as errors
+    strict: bool,
+    /// Run each file in its own interpreter; required for more than one file
+    isolated: bool,
+    /// Worker threads for an isolated batch
+    jobs: usize,
+    /// How `print` writes numbers
+    number_format: NumberFormat,
+    /// Grant the `process` capability, letting scripts run other programs with `exec`
+    allow_process: bool,
+    /// Report how long each REPL input took to lex, parse and evaluate
+    repl_timing: bool,
+    /// Stop at the first runtime error and open an inspector on the state it left
+    inspect: bool,
+    /// Script the REPL runs at startup instead of `~/.arc/prelude.arc`
+    prelude: Option<String>,
+    /// The largest strings and collections scripts may build, and the longest `while` loop
+    size_limits: SizeLimits,
+}
 
 /// Entry point - runs REPL or executes file from command line
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() > 1 {
-        // File execution mode
-        let filename = &args[1];
-        execute_file(filename);
-    } else {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Subcommands
+    match args.first().map(String::as_str) {
+        Some("doc") => {
+            run_doc(&args[1..]);
+            return;
+        }
+        Some("highlight") => {
+            run_highlight(&args[1..]);
+            return;
+        }
+        Some("new") => {
+            run_new(&args[1..]);
+            return;
+        }
+        Some("bench") => {
+            run_bench(&args[1..]);
+            return;
+        }
+        _ => {}
+    }
+
+    // `run` executes a file, or the current package's entry script when none is given
+    let is_run = args.first().map(String::as_str) == Some("run");
+    let args = if is_run { args[1..].to_vec() } else { args };
+
+    let mut options = RunOptions { jobs: 1, ..RunOptions::default() };
+    let mut files: Vec<String> = Vec::new();
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--profile" => options.profile = true,
+            "--profile-folded" => {
+                options.profile = true;
+                options.profile_folded = iter.next();
+                if options.profile_folded.is_none() {
+                    eprintln!("--profile-folded requires an output file");
+                    return;
+                }
+            }
+            "-O" => options.optimize = true,
+            "--strict" => options.strict = true,
+            "--coverage" => options.coverage = true,
+            "--coverage-lcov" => {
+                options.coverage = true;
+                options.coverage_lcov = iter.next();
+                if options.coverage_lcov.is_none() {
+                    eprintln!("--coverage-lcov requires an output file");
+                    return;
+                }
+            }
+            "--isolated" => options.isolated = true,
+            "--allow-process" => options.allow_process = true,
+            "--repl-timing" => options.repl_timing = true,
+            "--inspect" => options.inspect = true,
+            "--prelude" => {
+                options.prelude = iter.next();
+                if options.prelude.is_none() {
+                    eprintln!("--prelude requires a script");
+                    return;
+                }
+            }
+            "--number-format" => match iter.next().map(|spec| NumberFormat::parse(&spec)) {
+                Some(Ok(format)) => options.number_format = format,
+                Some(Err(e)) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+                None => {
+                    eprintln!("--number-format requires a spec such as ,.2f");
+                    return;
+                }
+            },
+            "--max-string-len" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(len) => options.size_limits.max_string_len = len,
+                None => {
+                    eprintln!("--max-string-len requires a number of bytes");
+                    return;
+                }
+            },
+            "--max-collection-len" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(len) => options.size_limits.max_collection_len = len,
+                None => {
+                    eprintln!("--max-collection-len requires a number of items");
+                    return;
+                }
+            },
+            "--max-loop-iterations" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(count) => options.size_limits.max_loop_iterations = count,
+                None => {
+                    eprintln!("--max-loop-iterations requires a number of iterations");
+                    return;
+                }
+            },
+            "--max-channel-wait" => {
+                match iter.next().and_then(|n| n.parse().ok()).and_then(|n| Duration::try_from_secs_f64(n).ok()) {
+                    Some(wait) => options.size_limits.max_channel_wait = wait,
+                    None => {
+                        eprintln!("--max-channel-wait requires a number of seconds");
+                        return;
+                    }
+                }
+            }
+            "--jobs" | "-j" => match iter.next().and_then(|n| n.parse().ok()).filter(|&n| n > 0) {
+                Some(jobs) => options.jobs = jobs,
+                None => {
+                    eprintln!("--jobs requires a positive number of threads");
+                    return;
+                }
+            },
+            _ => files.push(arg),
+        }
+    }
+
+    if (files.len() > 1 || options.jobs > 1) && !options.isolated {
+        eprintln!("Running several files requires --isolated (each file gets its own interpreter)");
+        return;
+    }
+    if options.prelude.is_some() && (is_run || !files.is_empty()) {
+        eprintln!("--prelude only applies to the REPL");
+        return;
+    }
+    if files.is_empty() && !is_run && (options.profile || options.coverage || options.inspect || options.isolated) {
+        eprintln!("--profile, --coverage, --inspect and --isolated only apply to scripts");
+        return;
+    }
+    if files.len() > 1 && (options.profile_folded.is_some() || options.coverage_lcov.is_some() || options.inspect) {
+        eprintln!("--profile-folded, --coverage-lcov and --inspect take a single file");
+        return;
+    }
+
+    match files.as_slice() {
         // REPL mode
-        run_repl();
+        [] if !is_run => run_repl(&options),
+        [] => match package_entry() {
+            Ok(entry) => {
+                execute_file(&entry.display().to_string(), &options, &mut Console::default());
+            }
+            Err(e) => eprintln!("{}", e),
+        },
+        // File execution mode
+        [filename] => {
+            execute_file(filename, &options, &mut Console::default());
+        }
+        // Batch mode
+        files => execute_batch(files, &options),
+    }
+}
+
+/// Locates the entry script of the package containing the current directory
+fn package_entry() -> Result<PathBuf, String> {
+    let cwd = env::current_dir().map_err(|e| format!("Cannot read current directory: {}", e))?;
+    let manifest_path = package::find_manifest(&cwd)
+        .ok_or_else(|| format!("No {} found in '{}' or any parent directory", package::MANIFEST_NAME, cwd.display()))?;
+    let manifest = Manifest::load(&manifest_path)?;
+    let root = manifest_path.parent().map(Path::to_path_buf).unwrap_or_default();
+    Ok(root.join(manifest.entry))
+}
+
+/// Creates a new package skeleton
+fn run_new(args: &[String]) {
+    let name = match args.first() {
+        Some(name) => name,
+        None => {
+            eprintln!("Usage: rust-compiler new <package-name>");
+            return;
+        }
+    };
+    match package::create_package(Path::new(name)) {
+        Ok(()) => println!("Created package '{}'", name),
+        Err(e) => eprintln!("{}", e),
     }
 }
 
-/// Reads and executes Arc source file line by line
-fn execute_file(filename: &str) {
+/// Applies the options that change how statements run, for files and the REPL alike
+fn configure_evaluator(evaluator: &mut ASTEvaluator, options: &RunOptions) {
+    evaluator.optimize = options.optimize;
+    evaluator.number_format = options.number_format;
+    evaluator.size_limits = options.size_limits;
+    evaluator.capabilities.process = options.allow_process;
+}
+
+/// Reads and executes Arc source file line by line, returning whether it ran without errors
+fn execute_file(filename: &str, options: &RunOptions, console: &mut Console) -> bool {
     let contents = match fs::read_to_string(filename) {
         Ok(c) => c,
         Err(e) => {
-            eprintln!("Error reading file '{}': {}", filename, e);
-            return;
+            console.err(format!("Error reading file '{}': {}\n", filename, e));
+            return false;
         }
     };
     
-    println!("=== Executing {} ===", filename);
+    console.out(format!("=== Executing {} ===\n", filename));
+    let lints = match lint::LintConfig::for_script(Path::new(filename)) {
+        Ok(lints) => lints,
+        Err(e) => {
+            console.err(format!("{}\n", e));
+            return false;
+        }
+    };
+    let lint_diagnostics = lint::lint_with(&contents, &lints);
+    for diagnostic in &lint_diagnostics {
+        let line = diagnostic.span.as_ref().map_or(1, |span| diagnostics::line_column(&contents, span.start()).0);
+        console.err(format!("Line {}: {}\n", line, diagnostic));
+    }
+    // Warnings leave the script to run; an error means it cannot do what it says
+    if lint_diagnostics.iter().any(|diagnostic| diagnostic.is_error()) {
+        return false;
+    }
     let mut evaluator = ASTEvaluator::new();
+    match ModuleLoader::for_script(Path::new(filename)) {
+        Ok(loader) => evaluator.modules = loader,
+        Err(e) => {
+            console.err(format!("{}\n", e));
+            return false;
+        }
+    }
+    evaluator.source_name = Some(filename.to_string());
+    configure_evaluator(&mut evaluator, options);
+    evaluator.keep_failure_scopes = options.inspect;
+    if console.is_buffered() {
+        evaluator.captured_output = Some(String::new());
+    }
+    if options.profile {
+        evaluator.profiler = Some(Profiler::new(filename));
+    }
+    let mut coverage = if options.coverage { Some(Coverage::new()) } else { None };
     
-    for (line_num, line) in contents.lines().enumerate() {
-        let line = line.trim();
-        
-        // Skip empty lines and comments
-        if line.is_empty() || line.starts_with("//") {
+    // A statement that is still open at the end of a line (a block, a call's arguments)
+    // continues on the next; it is counted and reported under its first line
+    let lines: Vec<&str> = contents.lines().collect();
+    let mut pending = String::new();
+    let mut first_line = 0;
+    for (line_num, raw_line) in lines.iter().enumerate() {
+        let line = raw_line.trim();
+
+        if pending.is_empty() {
+            // Skip empty lines and comments
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            first_line = line_num + 1;
+            if let Some(coverage) = coverage.as_mut() {
+                coverage.add_executable_line(filename, first_line);
+            }
+            if let Some(profiler) = evaluator.profiler.as_mut() {
+                profiler.enter_line(first_line);
+            }
+        } else {
+            pending.push('\n');
+        }
+        // Parse untrimmed lines so reported columns match the file
+        pending.push_str(raw_line);
+
+        // No statement starts with `|>`, so a line that does carries on the pipeline above
+        if lines.get(line_num + 1).is_some_and(|next| next.trim_start().starts_with("|>")) {
             continue;
         }
-        
-        execute_line(line, &mut evaluator, line_num + 1);
+        let more_input = line_num + 1 < lines.len();
+        let outcome = execute_line(&pending, &mut evaluator, first_line, options, console, more_input);
+        if outcome == LineOutcome::Incomplete {
+            continue;
+        }
+        if let Some(profiler) = evaluator.profiler.as_mut() {
+            profiler.exit_line(first_line, pending.lines().next().unwrap_or_default().trim());
+        }
+        if outcome == LineOutcome::Executed {
+            if let Some(coverage) = coverage.as_mut() {
+                coverage.record_hit(filename, first_line);
+            }
+        }
+        pending.clear();
+        if options.inspect && !evaluator.errors.is_empty() {
+            break;
+        }
+    }
+
+    if let Some(profiler) = &evaluator.profiler {
+        console.err(format!("\n{}", profiler.report()));
+        if let Some(path) = &options.profile_folded {
+            match fs::write(path, profiler.folded_stacks()) {
+                Ok(()) => console.err(format!("\nFolded stacks written to {}\n", path)),
+                Err(e) => console.err(format!("Error writing folded stacks to '{}': {}\n", path, e)),
+            }
+        }
+    }
+
+    if let Some(coverage) = &coverage {
+        console.err(format!("\n{}", coverage.summary()));
+        if let Some(path) = &options.coverage_lcov {
+            match fs::write(path, coverage.to_lcov()) {
+                Ok(()) => console.err(format!("\nlcov report written to {}\n", path)),
+                Err(e) => console.err(format!("Error writing lcov report to '{}': {}\n", path, e)),
+            }
+        }
     }
     
     if !evaluator.errors.is_empty() {
-        println!("\n=== Errors ===");
+        console.out("\n=== Errors ===\n".to_string());
         for error in &evaluator.errors {
-            eprintln!("{}", error);
+            console.err(format!("{}\n", error));
+        }
+        if options.inspect {
+            run_inspector(&mut evaluator, first_line);
+            return false;
         }
     }
+    evaluator.errors.is_empty()
 }
 
-/// Tokenizes, parses, and evaluates a single line of code
-fn execute_line(input: &str, evaluator: &mut ASTEvaluator, line_num: usize) {
-    let mut lexer = ast::lexer::Lexer::new(input);
-    let mut tokens: Vec<Token> = Vec::new();
-    while let Some(token) = lexer.next_token() {
-        tokens.push(token);
+/// A restricted REPL over the state a failed script left: it evaluates expressions with
+/// the variables in scope at the error, block locals included, but runs no statements
+/// and lets builtins do nothing beyond printing
+fn run_inspector(evaluator: &mut ASTEvaluator, line: usize) {
+    if let Some(scopes) = evaluator.failure_scopes.take() {
+        evaluator.symbol_table = scopes;
     }
+    evaluator.errors.clear();
+    evaluator.captured_output = None;
+    evaluator.capabilities = EvalCapabilities { stdout: true, ..EvalCapabilities::none() };
 
-    let mut ast: Ast = Ast::new();
-    let mut parser = Parser::new(tokens);
-    
-    match parser.next_statement() {
-        Some(statement) => {
-            ast.add_statement(statement);
-            let error_count_before = evaluator.errors.len();
-            ast.visit(evaluator);
-            let error_count_after = evaluator.errors.len();
-            
-            if error_count_after > error_count_before {
-                eprintln!("Line {}: Error occurred", line_num);
+    println!("\n=== Inspecting the error at line {} ===", line);
+    println!("Evaluate expressions to look at the state; :vars lists the variables, exit leaves.\n");
+    let stdin = io::stdin();
+    loop {
+        print!("inspect>> ");
+        let _ = io::stdout().flush();
+        let mut input = String::new();
+        match stdin.read_line(&mut input) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let input = input.trim();
+        match input {
+            "" => continue,
+            "exit" | "quit" => break,
+            ":vars" => print_variables(evaluator),
+            _ => inspect_expression(evaluator, input),
+        }
+        println!();
+    }
+}
+
+/// Lists the visible variables, innermost scope first, with shadowed ones left out
+fn print_variables(evaluator: &ASTEvaluator) {
+    let table = &evaluator.symbol_table;
+    let mut seen = HashSet::new();
+    for depth in (0..table.scope_depth()).rev() {
+        for symbol in table.symbols_in_scope(depth).iter().filter(|symbol| seen.insert(symbol.name)) {
+            let constness = if symbol.is_mutable { "" } else { " (const)" };
+            println!("{}: {}{} = {}", symbol.name, symbol.data_type, constness, symbol.value.pretty(2, 80));
+        }
+    }
+}
+
+/// Evaluates one line in the inspector, refusing anything but expressions
+fn inspect_expression(evaluator: &mut ASTEvaluator, input: &str) {
+    let mut ast = Ast::new();
+    let mut parser = Parser::from_lexer(ast::lexer::Lexer::new(input));
+    ast.statements = parser.parse_all();
+    if let Some(diagnostic) = parser.diagnostics.first() {
+        println!("Parse error: {}", diagnostic.message);
+        return;
+    }
+    if !ast.statements.iter().all(|statement| matches!(statement.kind, ASTStatementKind::Expression(_))) {
+        println!("Only expressions can be evaluated while inspecting");
+        return;
+    }
+    evaluator.resolve(&mut ast);
+    ast.visit(evaluator);
+    if evaluator.errors.is_empty() {
+        if let Some(value) = &evaluator.last_value {
+            println!("{} : {}", value.pretty(4, 80), value.get_type());
+        }
+    } else {
+        println!("Error:");
+        for error in evaluator.errors.drain(..) {
+            println!("  {}", error);
+        }
+    }
+}
+
+/// Runs independent scripts, each in its own interpreter, on `options.jobs` worker threads.
+/// Each file's output is buffered and printed whole, in the order the files were given.
+fn execute_batch(files: &[String], options: &RunOptions) {
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    let mut failed = 0;
+
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.min(files.len()) {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(filename) = files.get(index) else { break };
+                let mut console = Console::buffered();
+                let ok = execute_file(filename, options, &mut console);
+                if sender.send((index, ok, console)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Print each file as soon as every file before it has been printed
+        let mut finished: HashMap<usize, (bool, Console)> = HashMap::new();
+        let mut printed = 0;
+        for (index, ok, console) in receiver {
+            finished.insert(index, (ok, console));
+            while let Some((ok, console)) = finished.remove(&printed) {
+                console.flush();
+                if !ok {
+                    failed += 1;
+                }
+                printed += 1;
+            }
+        }
+    });
+
+    println!("\n=== {} file(s) run, {} with errors ===", files.len(), failed);
+}
+
+/// Which stream a piece of console output belongs to
+enum Stream {
+    Out,
+    Err,
+}
+
+/// Destination for messages from a file run: the terminal, or a buffer that is
+/// written out in one piece so concurrent runs do not interleave
+#[derive(Default)]
+struct Console {
+    buffer: Option<Vec<(Stream, String)>>,
+}
+
+impl Console {
+    fn buffered() -> Self {
+        Console { buffer: Some(Vec::new()) }
+    }
+
+    fn is_buffered(&self) -> bool {
+        self.buffer.is_some()
+    }
+
+    /// Writes to stdout, or buffers it
+    fn out(&mut self, text: String) {
+        match self.buffer.as_mut() {
+            Some(buffer) => buffer.push((Stream::Out, text)),
+            None => print!("{}", text),
+        }
+    }
+
+    /// Writes to stderr, or buffers it
+    fn err(&mut self, text: String) {
+        match self.buffer.as_mut() {
+            Some(buffer) => buffer.push((Stream::Err, text)),
+            None => eprint!("{}", text),
+        }
+    }
+
+    /// Writes buffered output, holding both stream locks so nothing else interleaves
+    fn flush(self) {
+        let stdout = io::stdout();
+        let stderr = io::stderr();
+        let (mut out, mut err) = (stdout.lock(), stderr.lock());
+        for (stream, text) in self.buffer.unwrap_or_default() {
+            let _ = match stream {
+                Stream::Out => out.write_all(text.as_bytes()).and_then(|()| out.flush()),
+                Stream::Err => err.write_all(text.as_bytes()),
+            };
+        }
+    }
+}
+
+/// Generates Markdown or HTML documentation from `///` comments in scripts
+fn run_doc(args: &[String]) {
+    let mut html = false;
+    let mut output: Option<String> = None;
+    let mut inputs: Vec<String> = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--html" => html = true,
+            "-o" | "--output" => output = iter.next().cloned(),
+            _ => inputs.push(arg.clone()),
+        }
+    }
+    if inputs.is_empty() {
+        eprintln!("Usage: rust-compiler doc [--html] [-o <file>] <file.arc|dir>...");
+        return;
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for input in &inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            collect_arc_files(path, &mut files);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    files.sort();
+
+    let mut modules = Vec::new();
+    for file in &files {
+        match fs::read_to_string(file) {
+            Ok(source) => {
+                let name = file.with_extension("").display().to_string();
+                modules.push(docgen::extract_docs(&name, &source));
             }
+            Err(e) => eprintln!("Error reading file '{}': {}", file.display(), e),
         }
+    }
+
+    let rendered = if html {
+        docgen::render_html(&modules)
+    } else {
+        docgen::render_markdown(&modules)
+    };
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, rendered) {
+                eprintln!("Error writing '{}': {}", path, e);
+            }
+        }
+        None => print!("{}", rendered),
+    }
+}
+
+/// Times the built-in benchmark programs, optionally against a saved baseline
+fn run_bench(args: &[String]) {
+    let usage = "Usage: rust-compiler bench [-O] [--iterations <n>] [--save <file>] [--baseline <file>]";
+    let mut iterations: u32 = 20;
+    let mut optimize = false;
+    let mut save: Option<String> = None;
+    let mut baseline_path: Option<String> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--iterations" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => iterations = n,
+                None => {
+                    eprintln!("{}", usage);
+                    return;
+                }
+            },
+            "-O" => optimize = true,
+            "--save" => save = iter.next().cloned(),
+            "--baseline" => baseline_path = iter.next().cloned(),
+            _ => {
+                eprintln!("{}", usage);
+                return;
+            }
+        }
+    }
+
+    let baseline = match &baseline_path {
+        Some(path) => match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| bench::parse_baseline(&text)) {
+            Ok(baseline) => Some(baseline),
+            Err(e) => {
+                eprintln!("Error reading baseline '{}': {}", path, e);
+                return;
+            }
+        },
+        None => None,
+    };
+
+    println!("Running {} iterations per program...", iterations);
+    let results: Vec<_> = bench::programs().iter().map(|program| bench::run(program, iterations, optimize)).collect();
+    print!("{}", bench::report(&results, baseline.as_ref()));
+
+    if let Some(path) = save {
+        match fs::write(&path, bench::save_baseline(&results)) {
+            Ok(()) => println!("Baseline written to {}", path),
+            Err(e) => eprintln!("Error writing '{}': {}", path, e),
+        }
+    }
+}
+
+/// Exports a script as standalone syntax-highlighted HTML
+fn run_highlight(args: &[String]) {
+    let mut output: Option<String> = None;
+    let mut input: Option<String> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = iter.next().cloned(),
+            _ => input = Some(arg.clone()),
+        }
+    }
+    let input = match input {
+        Some(input) => input,
         None => {
-            if !input.is_empty() {
-                eprintln!("Line {}: Parse error", line_num);
+            eprintln!("Usage: rust-compiler highlight <file.arc> [-o <file.html>]");
+            return;
+        }
+    };
+
+    let source = match fs::read_to_string(&input) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading file '{}': {}", input, e);
+            return;
+        }
+    };
+    let html = highlight::highlight_html(&source, &input);
+    match output {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, html) {
+                eprintln!("Error writing '{}': {}", path, e);
             }
         }
+        None => print!("{}", html),
+    }
+}
+
+/// Recursively collects `.arc` files under a directory
+fn collect_arc_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading directory '{}': {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_arc_files(&path, files);
+        } else if path.extension().map(|ext| ext == "arc").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
+
+/// What `execute_line` did with its input
+#[derive(Debug, PartialEq)]
+enum LineOutcome {
+    Executed,
+    /// Parsing failed, so nothing ran
+    Failed,
+    /// The input ends mid-statement and `more_input` was set; nothing ran or was reported
+    Incomplete,
+}
+
+/// Tokenizes, parses, and evaluates every statement on a line, returning whether they ran.
+/// Nothing runs if any part of the line fails to parse.
+fn execute_line(
+    input: &str,
+    evaluator: &mut ASTEvaluator,
+    line_num: usize,
+    options: &RunOptions,
+    console: &mut Console,
+    more_input: bool,
+) -> LineOutcome {
+    let mut ast: Ast = Ast::new();
+    let mut lexer = ast::lexer::Lexer::new(input);
+    if options.strict {
+        lexer = lexer.strict();
+    }
+    let mut parser = Parser::from_lexer_at_line(lexer, line_num);
+    ast.statements = parser.parse_all();
+
+    if more_input && parser.is_incomplete() {
+        return LineOutcome::Incomplete;
+    }
+    if !parser.diagnostics.is_empty() || ast.statements.is_empty() {
+        if parser.diagnostics.is_empty() && !input.trim().is_empty() {
+            console.err(format!("Line {}: Parse error\n", line_num));
+        }
+        for diagnostic in &parser.diagnostics {
+            console.err(format!("Line {}: {}\n", line_num, diagnostic));
+        }
+        return LineOutcome::Failed;
+    }
+
+    let error_count_before = evaluator.errors.len();
+    evaluator.resolve(&mut ast);
+    ast.visit(evaluator);
+    if let Some(output) = evaluator.captured_output.as_mut() {
+        console.out(mem::take(output));
+    }
+    let error_count_after = evaluator.errors.len();
+    
+    if error_count_after > error_count_before {
+        console.err(format!("Line {}: Error occurred\n", line_num));
     }
+    LineOutcome::Executed
+}
+
+/// Writes the session's successful inputs as a script, or as a Markdown notebook for `.md` paths
+fn export_session(session: &Session, path: &str) {
+    if path.is_empty() {
+        println!("Usage: :export <file.arc|file.md>");
+        return;
+    }
+    let contents = if path.ends_with(".md") {
+        session.to_markdown()
+    } else {
+        session.to_script()
+    };
+    match fs::write(path, contents) {
+        Ok(()) => println!("Exported {} statement(s) to {}", session.entries().len(), path),
+        Err(e) => println!("Error writing '{}': {}", path, e),
+    }
+}
+
+/// How the REPL echoes results, changed with `:set display`
+struct ReplDisplay {
+    /// Levels of nested arrays, maps and sets shown before they are elided
+    depth: usize,
+    /// Columns a result may take before it is broken over several lines
+    width: usize,
+}
+
+impl Default for ReplDisplay {
+    fn default() -> Self {
+        ReplDisplay { depth: 4, width: 80 }
+    }
+}
+
+/// Applies `:set display depth=N width=N`, reporting the settings
+fn set_display(display: &mut ReplDisplay, settings: &str) {
+    for setting in settings.split_whitespace() {
+        let (target, value) = match setting.split_once('=') {
+            Some(("depth", value)) => (&mut display.depth, value),
+            Some(("width", value)) => (&mut display.width, value),
+            _ => {
+                println!("Unknown display setting '{}'; expected depth=N or width=N", setting);
+                return;
+            }
+        };
+        match value.parse() {
+            Ok(value) => *target = value,
+            Err(_) => {
+                println!("Invalid value in '{}'; expected a whole number", setting);
+                return;
+            }
+        }
+    }
+    println!("display depth={} width={}", display.depth, display.width);
+}
+
+/// How long each phase of a REPL input took; `eval` is `None` if it did not run
+struct Timing {
+    lex: Duration,
+    parse: Duration,
+    eval: Option<Duration>,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "time: lex {}µs, parse {}µs", self.lex.as_micros(), self.parse.as_micros())?;
+        if let Some(eval) = self.eval {
+            write!(f, ", eval {}µs", eval.as_micros())?;
+        }
+        Ok(())
+    }
+}
+
+/// Strips a `:time` prefix, returning the statement and whether to report its timing
+fn timed_input(input: &str, always: bool) -> (&str, bool) {
+    match input.strip_prefix(":time ") {
+        Some(rest) => (rest.trim(), true),
+        None => (input, always),
+    }
+}
+
+/// Lexes the whole input and then parses it, so the two can be timed apart.
+/// Lexer diagnostics come before the parser's.
+fn parse_input(input: &str, strict: bool) -> (Ast, Vec<diagnostics::Diagnostic>, Timing) {
+    let started = Instant::now();
+    let mut lexer = ast::lexer::Lexer::new(input);
+    if strict {
+        lexer = lexer.strict();
+    }
+    let tokens = lexer.by_ref().collect();
+    let lex = started.elapsed();
+
+    let started = Instant::now();
+    let mut ast: Ast = Ast::new();
+    let mut parser = Parser::from_lexed(tokens, input);
+    ast.statements = parser.parse_all();
+    let parse = started.elapsed();
+    parser.diagnostics.splice(0..0, lexer.diagnostics);
+    (ast, parser.diagnostics, Timing { lex, parse, eval: None })
+}
+
+/// Reads the input of `:paste`: raw lines up to one holding only `.`, or the end of input
+fn read_paste(input: &mut impl BufRead) -> String {
+    let mut buffer = String::new();
+    loop {
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) if line.trim() == "." => break,
+            Ok(_) => buffer.push_str(&line),
+        }
+    }
+    buffer
+}
+
+/// The script the REPL runs at startup: the `--prelude` file, or `~/.arc/prelude.arc` if
+/// there is one
+fn prelude_path(options: &RunOptions) -> Option<PathBuf> {
+    if let Some(path) = &options.prelude {
+        return Some(PathBuf::from(path));
+    }
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    let path = Path::new(&home).join(".arc").join("prelude.arc");
+    path.is_file().then_some(path)
+}
+
+/// Runs the prelude as an import, so what it defines is in scope for the whole session
+fn load_prelude(evaluator: &mut ASTEvaluator, path: &Path) {
+    if !path.is_file() {
+        println!("Cannot load prelude '{}': no such file\n", path.display());
+        return;
+    }
+    let mut ast = Ast::new();
+    ast.add_statement(ASTStatement::import(ASTImportStatement::new(path.display().to_string())));
+    evaluator.resolve(&mut ast);
+    ast.visit(evaluator);
+    if evaluator.errors.is_empty() {
+        println!("Loaded prelude {}\n", path.display());
+        return;
+    }
+    println!("Errors in prelude {}:", path.display());
+    for error in evaluator.errors.drain(..) {
+        println!("  {}", error);
+    }
+    println!();
 }
 
 /// Interactive Read-Eval-Print Loop for testing expressions
-fn run_repl() {
+fn run_repl(options: &RunOptions) {
     println!("=== Arc Compiler REPL ===");
     println!("Type expressions to evaluate them. Type 'exit' or 'quit' to exit.\n");
     println!("Examples:");
@@ -95,17 +906,31 @@ fn run_repl() {
     println!("  x + 5");
     println!("  print(x)");
     println!("  // This is a comment");
-    println!("  const pi = 3.14\n");
+    println!("  const pi = 3.14");
+    println!("  :export session.arc   (or session.md for a notebook)");
+    println!("  :set display depth=2 width=100");
+    println!("  :vars                 (list the variables)");
+    println!("  :time <statement>     (time lexing, parsing and evaluation)");
+    println!("  :paste                (run a multi-line block, ended by a lone '.')\n");
 
     let mut evaluator = ASTEvaluator::new();
+    evaluator.source_name = Some("<repl>".to_string());
+    configure_evaluator(&mut evaluator, options);
+    if let Some(path) = prelude_path(options) {
+        load_prelude(&mut evaluator, &path);
+    }
+    let mut session = Session::new();
+    let mut display = ReplDisplay::default();
     let stdin = io::stdin();
     
     loop {
         print!(">> ");
-        io::stdout().flush().unwrap();
+        let _ = io::stdout().flush();
         
         let mut input = String::new();
         match stdin.read_line(&mut input) {
+            // End of input (Ctrl-D)
+            Ok(0) => break,
             Ok(_) => {
                 let input = input.trim();
                 
@@ -119,47 +944,80 @@ fn run_repl() {
                 if input.is_empty() {
                     continue;
                 }
-                
-                // Tokenize
-                let mut lexer = ast::lexer::Lexer::new(input);
-                let mut tokens: Vec<Token> = Vec::new();
-                while let Some(token) = lexer.next_token() {
-                    tokens.push(token);
+
+                if let Some(path) = input.strip_prefix(":export") {
+                    export_session(&session, path.trim());
+                    println!();
+                    continue;
                 }
 
-                // Parse
-                let mut ast: Ast = Ast::new();
-                let mut parser = Parser::new(tokens);
+                if input == ":vars" {
+                    print_variables(&evaluator);
+                    println!();
+                    continue;
+                }
+
+                if let Some(settings) = input.strip_prefix(":set display") {
+                    set_display(&mut display, settings);
+                    println!();
+                    continue;
+                }
                 
-                match parser.next_statement() {
-                    Some(statement) => {
-                        ast.add_statement(statement);
-                        
-                        // Evaluate
-                        let error_count_before = evaluator.errors.len();
-                        ast.visit(&mut evaluator);
-                        let error_count_after = evaluator.errors.len();
-                        
-                        // Display result
-                        if error_count_after > error_count_before {
-                            println!("Error:");
-                            for i in error_count_before..error_count_after {
-                                println!("  {}", evaluator.errors[i]);
-                            }
-                        } else {
-                            match &evaluator.last_value {
-                                Some(value) => {
-                                    println!("{:?} : {:?}", value, value.get_type());
-                                }
-                                None => {
-                                    // Statement executed without producing a value
-                                }
-                            }
-                        }
+                let (input, timed) = timed_input(input, options.repl_timing);
+                let pasted;
+                let input = if input == ":paste" {
+                    println!("(paste mode: end with a line holding only '.', or Ctrl-D)");
+                    pasted = read_paste(&mut stdin.lock());
+                    if pasted.trim().is_empty() {
+                        println!();
+                        continue;
                     }
-                    None => {
+                    pasted.as_str()
+                } else {
+                    input
+                };
+
+                let (mut ast, diagnostics, mut timing) = parse_input(input, options.strict);
+                if !diagnostics.is_empty() || ast.statements.is_empty() {
+                    if diagnostics.is_empty() {
                         println!("Parse error: Invalid syntax");
                     }
+                    for diagnostic in &diagnostics {
+                        println!("Parse error: {}", diagnostic.message);
+                    }
+                    if timed {
+                        println!("{}", timing);
+                    }
+                } else {
+                    // Evaluate, capturing program output so it can be recorded
+                    let error_count_before = evaluator.errors.len();
+                    evaluator.captured_output = Some(String::new());
+                    let started = Instant::now();
+                    evaluator.resolve(&mut ast);
+                    ast.visit(&mut evaluator);
+                    timing.eval = Some(started.elapsed());
+                    let output = evaluator.captured_output.take().unwrap_or_default();
+                    print!("{}", output);
+                    let error_count_after = evaluator.errors.len();
+                    
+                    // Display result
+                    if error_count_after > error_count_before {
+                        println!("Error:");
+                        for i in error_count_before..error_count_after {
+                            println!("  {}", evaluator.errors[i]);
+                        }
+                    } else {
+                        let result = evaluator.last_value.as_ref()
+                            .map(|value| format!("{} : {}", value.pretty(display.depth, display.width), value.get_type()));
+                        // Statements that produce no value print nothing
+                        if let Some(result) = &result {
+                            println!("{}", result);
+                        }
+                        session.record(input, &output, result);
+                    }
+                    if timed {
+                        println!("{}", timing);
+                    }
                 }
             }
             Err(error) => {
@@ -170,3 +1028,72 @@ fn run_repl() {
         println!();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_prefix_is_stripped() {
+        assert_eq!(timed_input(":time  x + 1 ", false), ("x + 1", true));
+        assert_eq!(timed_input("x + 1", false), ("x + 1", false));
+        assert_eq!(timed_input("x + 1", true), ("x + 1", true));
+        // Only the command followed by a statement is special
+        assert_eq!(timed_input(":timer", false), (":timer", false));
+    }
+
+    #[test]
+    fn test_timing_report() {
+        let mut timing = Timing { lex: Duration::from_micros(12), parse: Duration::from_micros(340), eval: None };
+        assert_eq!(timing.to_string(), "time: lex 12µs, parse 340µs");
+        timing.eval = Some(Duration::from_millis(2));
+        assert_eq!(timing.to_string(), "time: lex 12µs, parse 340µs, eval 2000µs");
+    }
+
+    #[test]
+    fn test_parse_input_lexes_before_parsing() {
+        let (ast, diagnostics, timing) = parse_input("let x = 1\nx + 2", false);
+        assert_eq!(ast.statements.len(), 2);
+        assert!(diagnostics.is_empty());
+        assert_eq!(timing.eval, None);
+
+        // Lexer diagnostics come before the parser's
+        let (_, diagnostics, _) = parse_input("let s = \"\\q\"\nlet = 1", true);
+        assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+        assert!(diagnostics[0].message.contains("escape"), "{}", diagnostics[0].message);
+        assert!(diagnostics[1].message.contains("identifier"), "{}", diagnostics[1].message);
+    }
+
+    #[test]
+    fn test_paste_reads_raw_lines_up_to_a_lone_dot() {
+        let mut input = io::Cursor::new("for i in 0..3 {\n    print(i)\n}\n  .  \nafter\n");
+        assert_eq!(read_paste(&mut input), "for i in 0..3 {\n    print(i)\n}\n");
+
+        // The rest of the input is left for the REPL
+        let mut rest = String::new();
+        input.read_line(&mut rest).unwrap();
+        assert_eq!(rest, "after\n");
+    }
+
+    #[test]
+    fn test_paste_ends_at_end_of_input() {
+        assert_eq!(read_paste(&mut io::Cursor::new("let x = 1\nx . 2")), "let x = 1\nx . 2");
+        assert_eq!(read_paste(&mut io::Cursor::new("")), "");
+    }
+
+    #[test]
+    fn test_pasted_block_runs_as_one_input() {
+        let pasted = read_paste(&mut io::Cursor::new("let total = 0\nfor i in 0..10 {\n    total = total + i\n}\ntotal\n.\n"));
+        // Parsed a line at a time, the loop would be cut off after its header
+        let (_, diagnostics, _) = parse_input(pasted.lines().nth(1).unwrap(), false);
+        assert!(!diagnostics.is_empty());
+
+        let (mut ast, diagnostics, _) = parse_input(&pasted, false);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let mut evaluator = ASTEvaluator::new();
+        evaluator.resolve(&mut ast);
+        ast.visit(&mut evaluator);
+        assert!(evaluator.errors.is_empty(), "{:?}", evaluator.errors);
+        assert_eq!(evaluator.last_value.map(|value| value.to_string()), Some("45".to_string()));
+    }
+}
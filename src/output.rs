@@ -0,0 +1,66 @@
+//! Output sinks - where a script's output and runtime errors are written
+//!
+//! By default `print` writes to the process's stdout and runtime errors are only collected
+//! in `ASTEvaluator::errors` for the caller to report. An embedder can instead hand the
+//! evaluator any `Write` handle, or a callback wrapped with `callback_sink`, to receive
+//! output as it is printed and errors as they are raised.
+
+use std::io::{self, Write};
+
+/// A destination for script output or diagnostics
+pub type OutputSink = Box<dyn Write + Send + Sync>;
+
+/// A sink calling `callback` with each piece of text written to it. Text that is not valid
+/// UTF-8 on its own, such as half of a character, is held until the rest arrives.
+pub fn callback_sink(callback: impl FnMut(&str) + Send + Sync + 'static) -> OutputSink {
+    Box::new(CallbackSink { callback, pending: Vec::new() })
+}
+
+struct CallbackSink<F> {
+    callback: F,
+    pending: Vec<u8>,
+}
+
+impl<F: FnMut(&str)> Write for CallbackSink<F> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(bytes);
+        let complete = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => {
+                self.pending.clear();
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "output is not valid UTF-8"));
+            }
+        };
+        if complete > 0 {
+            let text = String::from_utf8_lossy(&self.pending[..complete]).into_owned();
+            (self.callback)(&text);
+            self.pending.drain(..complete);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_callback_sink_passes_whole_characters() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let mut sink = {
+            let received = received.clone();
+            callback_sink(move |text| received.lock().unwrap().push(text.to_string()))
+        };
+        let bytes = "hé".as_bytes();
+        sink.write_all(&bytes[..2]).unwrap();
+        sink.write_all(&bytes[2..]).unwrap();
+        assert_eq!(*received.lock().unwrap(), ["h", "é"]);
+        assert!(sink.write_all(&[0xff]).is_err());
+    }
+}
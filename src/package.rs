@@ -0,0 +1,288 @@
+//! Packages - `arc.toml` manifests, path dependencies, and import resolution
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_NAME: &str = "arc.toml";
+pub const DEFAULT_ENTRY: &str = "src/main.arc";
+
+/// A package dependency declared in `[dependencies]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Parsed contents of an `arc.toml` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    pub entry: String,
+    pub dependencies: Vec<Dependency>,
+    /// Raw `key = value` pairs of every other table, keyed by "table.key"
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Parses the supported subset of TOML: tables, string/number/bool values,
+    /// and `{ path = "..." }` inline tables for dependencies
+    pub fn parse(source: &str) -> Result<Manifest, String> {
+        let mut name: Option<String> = None;
+        let mut version = "0.1.0".to_string();
+        let mut entry = DEFAULT_ENTRY.to_string();
+        let mut dependencies = Vec::new();
+        let mut extra = BTreeMap::new();
+        let mut table = String::new();
+
+        for (index, raw_line) in source.lines().enumerate() {
+            let line_num = index + 1;
+            let line = strip_comment(raw_line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                match header.strip_suffix(']') {
+                    Some(header) => table = header.trim().to_string(),
+                    None => return Err(format!("arc.toml:{}: unterminated table header", line_num)),
+                }
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(format!("arc.toml:{}: expected 'key = value'", line_num)),
+            };
+
+            match table.as_str() {
+                "package" => {
+                    let value = parse_string(value)
+                        .ok_or_else(|| format!("arc.toml:{}: '{}' must be a string", line_num, key))?;
+                    match key {
+                        "name" => name = Some(value),
+                        "version" => version = value,
+                        "entry" => entry = value,
+                        _ => {
+                            extra.insert(format!("package.{}", key), value);
+                        }
+                    }
+                }
+                "dependencies" => {
+                    let path = parse_inline_path(value).ok_or_else(|| {
+                        format!("arc.toml:{}: dependency '{}' must be {{ path = \"...\" }}", line_num, key)
+                    })?;
+                    dependencies.push(Dependency { name: key.to_string(), path: PathBuf::from(path) });
+                }
+                _ => {
+                    let value = parse_string(value).unwrap_or_else(|| value.to_string());
+                    extra.insert(format!("{}.{}", table, key), value);
+                }
+            }
+        }
+
+        let name = name.ok_or_else(|| "arc.toml: missing 'name' in [package]".to_string())?;
+        Ok(Manifest { name, version, entry, dependencies, extra })
+    }
+
+    /// Reads and parses a manifest file
+    pub fn load(path: &Path) -> Result<Manifest, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading '{}': {}", path.display(), e))?;
+        Manifest::parse(&source)
+    }
+
+    /// Renders a manifest for a freshly created package
+    pub fn template(name: &str) -> String {
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nentry = \"{}\"\n\n[dependencies]\n",
+            name, DEFAULT_ENTRY
+        )
+    }
+}
+
+/// Removes a trailing `# comment` that is not inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    Some(inner.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+/// Parses `{ path = "..." }`
+fn parse_inline_path(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('{')?.strip_suffix('}')?;
+    for field in inner.split(',') {
+        if let Some((key, value)) = field.split_once('=') {
+            if key.trim() == "path" {
+                return parse_string(value.trim());
+            }
+        }
+    }
+    None
+}
+
+/// Finds the nearest `arc.toml` in `start` or any of its ancestors
+pub fn find_manifest(start: &Path) -> Option<PathBuf> {
+    let mut dir = Some(start);
+    while let Some(current) = dir {
+        let candidate = current.join(MANIFEST_NAME);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+/// Creates a new package directory with a manifest and an entry script
+pub fn create_package(dir: &Path) -> Result<(), String> {
+    if dir.exists() {
+        return Err(format!("Destination '{}' already exists", dir.display()));
+    }
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("Invalid package path '{}'", dir.display()))?;
+
+    let src = dir.join("src");
+    fs::create_dir_all(&src).map_err(|e| format!("Error creating '{}': {}", src.display(), e))?;
+    fs::write(dir.join(MANIFEST_NAME), Manifest::template(&name))
+        .map_err(|e| format!("Error writing manifest: {}", e))?;
+    fs::write(dir.join(DEFAULT_ENTRY), format!("print(\"Hello from {}!\")\n", name))
+        .map_err(|e| format!("Error writing entry script: {}", e))?;
+    Ok(())
+}
+
+/// Resolves `import` paths and tracks which modules have already run
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    /// Dependency name -> package root directory
+    packages: BTreeMap<String, PathBuf>,
+    /// Directories of the files currently executing, innermost last
+    dir_stack: Vec<PathBuf>,
+    loaded: HashSet<PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn new() -> Self {
+        ModuleLoader::default()
+    }
+
+    /// Creates a loader for a script, picking up dependencies from the nearest manifest
+    pub fn for_script(script: &Path) -> Result<Self, String> {
+        let mut loader = ModuleLoader::new();
+        let dir = script.parent().map(Path::to_path_buf).unwrap_or_default();
+        let lookup_dir = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir.clone() };
+
+        if let Some(manifest_path) = find_manifest(&lookup_dir) {
+            let manifest = Manifest::load(&manifest_path)?;
+            let root = manifest_path.parent().map(Path::to_path_buf).unwrap_or_default();
+            loader.add_dependencies(&root, &manifest);
+        }
+        if let Ok(canonical) = fs::canonicalize(script) {
+            loader.loaded.insert(canonical);
+        }
+        loader.dir_stack.push(dir);
+        Ok(loader)
+    }
+
+    /// Registers a manifest's dependencies, with paths relative to the package root
+    pub fn add_dependencies(&mut self, root: &Path, manifest: &Manifest) {
+        for dependency in &manifest.dependencies {
+            self.packages.insert(dependency.name.clone(), root.join(&dependency.path));
+        }
+    }
+
+    /// Maps an import specifier to a file.
+    ///
+    /// `"pkg"` loads a dependency's entry script and `"pkg/util"` loads `src/util.arc`
+    /// inside it; anything else is a path relative to the importing file.
+    pub fn resolve(&self, spec: &str) -> Result<PathBuf, String> {
+        let (head, rest) = match spec.split_once('/') {
+            Some((head, rest)) => (head, Some(rest)),
+            None => (spec, None),
+        };
+
+        if let Some(root) = self.packages.get(head) {
+            return match rest {
+                None => {
+                    let manifest_path = root.join(MANIFEST_NAME);
+                    let entry = if manifest_path.is_file() {
+                        Manifest::load(&manifest_path)?.entry
+                    } else {
+                        DEFAULT_ENTRY.to_string()
+                    };
+                    Ok(root.join(entry))
+                }
+                Some(rest) => Ok(with_arc_extension(root.join("src").join(rest))),
+            };
+        }
+
+        let base = self.dir_stack.last().cloned().unwrap_or_default();
+        let path = with_arc_extension(base.join(spec));
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!("Cannot resolve import '{}': no such package or file", spec))
+        }
+    }
+
+    /// Marks a module as loaded, returning false if it already ran
+    pub fn begin_module(&mut self, path: &Path) -> bool {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if !self.loaded.insert(canonical) {
+            return false;
+        }
+        self.dir_stack.push(path.parent().map(Path::to_path_buf).unwrap_or_default());
+        true
+    }
+
+    /// Leaves the module entered by `begin_module`
+    pub fn end_module(&mut self) {
+        self.dir_stack.pop();
+    }
+}
+
+fn with_arc_extension(path: PathBuf) -> PathBuf {
+    if path.extension().is_some() {
+        path
+    } else {
+        path.with_extension("arc")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest_with_dependencies() {
+        let manifest = Manifest::parse(
+            "# project\n[package]\nname = \"app\"\nversion = \"1.2.0\"\n\n[dependencies]\nmathlib = { path = \"../mathlib\" } # local\n",
+        )
+        .unwrap();
+
+        assert_eq!(manifest.name, "app");
+        assert_eq!(manifest.version, "1.2.0");
+        assert_eq!(manifest.entry, DEFAULT_ENTRY);
+        assert_eq!(manifest.dependencies, vec![Dependency { name: "mathlib".to_string(), path: PathBuf::from("../mathlib") }]);
+    }
+
+    #[test]
+    fn test_manifest_requires_name() {
+        assert!(Manifest::parse("[package]\nversion = \"1.0\"\n").is_err());
+        assert!(Manifest::parse("[dependencies]\nx = \"1.0\"\n").is_err());
+    }
+}
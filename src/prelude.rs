@@ -0,0 +1,54 @@
+//! Standard prelude - constants written in Arc that every script can read
+//!
+//! `prelude.arc` is compiled into the library and run once, as a module, the first time a
+//! script reads a name it does not define. What it declares becomes a reserved, read-only
+//! scope beneath the shared globals: a script may shadow a prelude name with its own
+//! declaration but cannot assign to it. Arc cannot define functions yet, so the prelude
+//! holds constants only.
+
+use crate::ast::evaluator::ASTEvaluator;
+use crate::globals::SharedGlobals;
+use std::sync::OnceLock;
+
+/// The prelude's source
+pub const PRELUDE_SOURCE: &str = include_str!("prelude.arc");
+
+/// The prelude's constants, built on first use
+pub fn prelude() -> &'static SharedGlobals {
+    static PRELUDE: OnceLock<SharedGlobals> = OnceLock::new();
+    PRELUDE.get_or_init(|| {
+        let mut evaluator = ASTEvaluator::new();
+        // The prelude must not read itself while it is being built
+        evaluator.use_prelude = false;
+        evaluator.run_module("<prelude>", PRELUDE_SOURCE);
+        let mut globals = SharedGlobals::new();
+        for symbol in evaluator.symbol_table.globals() {
+            globals.define(symbol.name.as_str(), symbol.value.clone());
+        }
+        globals
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::types::Value;
+    use crate::interpreter::Interpreter;
+
+    #[test]
+    fn test_prelude_is_a_read_only_scope() {
+        let mut evaluator = ASTEvaluator::new();
+        evaluator.use_prelude = false;
+        evaluator.run_module("<prelude>", PRELUDE_SOURCE);
+        assert_eq!(evaluator.errors, Vec::<String>::new());
+        assert_eq!(prelude().get("INT_MIN"), Some(&Value::Integer(i64::MIN)));
+
+        let mut interp = Interpreter::new();
+        assert_eq!(interp.eval("TAU == 2 * PI"), Ok(Some(Value::Boolean(true))));
+        assert_eq!(interp.eval("PI = 3"), Err("Cannot assign to prelude constant 'PI'".to_string()));
+        assert_eq!(interp.eval("let PI = 3\nPI"), Ok(Some(Value::Integer(3))));
+
+        interp.evaluator.use_prelude = false;
+        assert!(interp.eval("E").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Profiler - accumulates wall time and hit counts per statement and function
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Timing totals for a single source line
+#[derive(Debug, Clone)]
+pub struct LineStats {
+    pub line: usize,
+    pub source: String,
+    pub hits: u64,
+    pub total: Duration,
+}
+
+/// Timing totals for a single function
+#[derive(Debug, Clone)]
+pub struct FunctionStats {
+    pub name: String,
+    pub calls: u64,
+    pub total: Duration,
+}
+
+/// An active frame on the profiler stack
+struct Frame {
+    name: String,
+    start: Instant,
+    child_time: Duration,
+}
+
+/// Collects per-line and per-function timings plus folded call stacks
+pub struct Profiler {
+    root: String,
+    stack: Vec<Frame>,
+    lines: HashMap<usize, LineStats>,
+    functions: HashMap<String, FunctionStats>,
+    folded: HashMap<String, Duration>,
+}
+
+impl Profiler {
+    /// Creates a profiler whose folded stacks are rooted at `root` (usually the script name)
+    pub fn new(root: &str) -> Self {
+        Profiler {
+            root: root.replace(';', "_"),
+            stack: Vec::new(),
+            lines: HashMap::new(),
+            functions: HashMap::new(),
+            folded: HashMap::new(),
+        }
+    }
+
+    /// Marks the start of a statement on the given line
+    pub fn enter_line(&mut self, line: usize) {
+        self.enter(format!("line {}", line));
+    }
+
+    /// Marks the end of the statement started by `enter_line`
+    pub fn exit_line(&mut self, line: usize, source: &str) {
+        let elapsed = self.exit();
+        let stats = self.lines.entry(line).or_insert_with(|| LineStats {
+            line,
+            source: source.to_string(),
+            hits: 0,
+            total: Duration::ZERO,
+        });
+        stats.hits += 1;
+        stats.total += elapsed;
+    }
+
+    /// Marks the start of a function call
+    pub fn enter_function(&mut self, name: &str) {
+        self.enter(name.to_string());
+    }
+
+    /// Marks the end of the function call started by `enter_function`
+    pub fn exit_function(&mut self, name: &str) {
+        let elapsed = self.exit();
+        let stats = self.functions.entry(name.to_string()).or_insert_with(|| FunctionStats {
+            name: name.to_string(),
+            calls: 0,
+            total: Duration::ZERO,
+        });
+        stats.calls += 1;
+        stats.total += elapsed;
+    }
+
+    fn enter(&mut self, name: String) {
+        self.stack.push(Frame {
+            name: name.replace(';', "_"),
+            start: Instant::now(),
+            child_time: Duration::ZERO,
+        });
+    }
+
+    /// Pops the innermost frame, charging its self time to the folded stack path
+    fn exit(&mut self) -> Duration {
+        let path = self.stack_path();
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return Duration::ZERO,
+        };
+        let total = frame.start.elapsed();
+        let self_time = total.saturating_sub(frame.child_time);
+        *self.folded.entry(path).or_insert(Duration::ZERO) += self_time;
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.child_time += total;
+        }
+        total
+    }
+
+    fn stack_path(&self) -> String {
+        let mut path = self.root.clone();
+        for frame in &self.stack {
+            path.push(';');
+            path.push_str(&frame.name);
+        }
+        path
+    }
+
+    /// Lines sorted by total time, hottest first
+    pub fn hot_lines(&self) -> Vec<&LineStats> {
+        let mut lines: Vec<&LineStats> = self.lines.values().collect();
+        lines.sort_by(|a, b| b.total.cmp(&a.total).then(a.line.cmp(&b.line)));
+        lines
+    }
+
+    /// Functions sorted by total time, hottest first
+    pub fn hot_functions(&self) -> Vec<&FunctionStats> {
+        let mut functions: Vec<&FunctionStats> = self.functions.values().collect();
+        functions.sort_by(|a, b| b.total.cmp(&a.total).then(a.name.cmp(&b.name)));
+        functions
+    }
+
+    /// Renders a human-readable hot-spot report
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "=== Profile: hot lines ===");
+        let _ = writeln!(out, "{:>12} {:>8} {:>6}  Source", "Time (us)", "Hits", "Line");
+        for stats in self.hot_lines() {
+            let _ = writeln!(
+                out,
+                "{:>12} {:>8} {:>6}  {}",
+                stats.total.as_micros(),
+                stats.hits,
+                stats.line,
+                stats.source
+            );
+        }
+
+        if !self.functions.is_empty() {
+            let _ = writeln!(out, "\n=== Profile: functions ===");
+            let _ = writeln!(out, "{:>12} {:>8}  Function", "Time (us)", "Calls");
+            for stats in self.hot_functions() {
+                let _ = writeln!(out, "{:>12} {:>8}  {}", stats.total.as_micros(), stats.calls, stats.name);
+            }
+        }
+        out
+    }
+
+    /// Renders stacks in the folded format understood by flamegraph.pl and inferno
+    pub fn folded_stacks(&self) -> String {
+        let mut stacks: Vec<(&String, &Duration)> = self.folded.iter().collect();
+        stacks.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut out = String::new();
+        for (path, time) in stacks {
+            let _ = writeln!(out, "{} {}", path, time.as_micros());
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_hits_accumulate() {
+        let mut profiler = Profiler::new("script.arc");
+        for _ in 0..3 {
+            profiler.enter_line(2);
+            profiler.exit_line(2, "x = x + 1");
+        }
+
+        let lines = profiler.hot_lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].hits, 3);
+    }
+
+    #[test]
+    fn test_folded_stacks_nest_functions_under_lines() {
+        let mut profiler = Profiler::new("script.arc");
+        profiler.enter_line(1);
+        profiler.enter_function("print");
+        profiler.exit_function("print");
+        profiler.exit_line(1, "print(1)");
+
+        let folded = profiler.folded_stacks();
+        assert!(folded.contains("script.arc;line 1 "));
+        assert!(folded.contains("script.arc;line 1;print "));
+    }
+}
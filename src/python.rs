@@ -0,0 +1,161 @@
+//! Python bindings - exposes `arc.Interpreter` through PyO3
+//!
+//! Built with the `python` feature, usually via `maturin develop`, which also enables
+//! `pyo3/extension-module`.
+
+// The #[pymethods] expansion in pyo3 0.22 converts PyErr into itself
+#![allow(clippy::useless_conversion)]
+
+use crate::ast::types::Value;
+use crate::interpreter::Interpreter;
+use pyo3::exceptions::{PyRuntimeError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PySet, PyTuple};
+
+fn value_to_py(py: Python<'_>, value: &Value) -> PyObject {
+    match value {
+        Value::Integer(i) => i.into_py(py),
+        Value::Float(f) => f.into_py(py),
+        Value::Boolean(b) => b.into_py(py),
+        Value::String(s) => s.into_py(py),
+        Value::Bytes(b) => PyBytes::new_bound(py, b).into_py(py),
+        Value::Array(items) => PyList::new_bound(py, items.iter().map(|item| value_to_py(py, item))).into_py(py),
+        // Elements are booleans, integers and strings, which Python can always hash
+        Value::Set(elements) => match PySet::new_bound(py, &elements.iter().map(|element| value_to_py(py, &element.clone().into())).collect::<Vec<_>>()) {
+            Ok(set) => set.into_py(py),
+            Err(_) => py.None(),
+        },
+        Value::Map(entries) => {
+            let dict = PyDict::new_bound(py);
+            for (key, value) in entries.iter() {
+                // Setting a str key on a fresh dict cannot fail
+                let _ = dict.set_item(&**key, value_to_py(py, value));
+            }
+            dict.into_py(py)
+        }
+        Value::Function(_) | Value::Range(..) | Value::Channel(_) => value.to_string().into_py(py),
+    }
+}
+
+fn value_from_py(object: &Bound<'_, PyAny>) -> Result<Value, String> {
+    // bool first: Python booleans are also ints
+    if let Ok(b) = object.extract::<bool>() {
+        Ok(Value::Boolean(b))
+    } else if let Ok(i) = object.extract::<i64>() {
+        Ok(Value::Integer(i))
+    } else if let Ok(f) = object.extract::<f64>() {
+        Ok(Value::Float(f))
+    } else if let Ok(s) = object.extract::<String>() {
+        Ok(Value::from(s))
+    } else {
+        let type_name = object.get_type().name().map(|name| name.to_string()).unwrap_or_default();
+        Err(format!("cannot convert Python {} to an Arc value", type_name))
+    }
+}
+
+/// An Arc interpreter; variables and registered functions persist between `eval` calls
+#[pyclass(name = "Interpreter", module = "arc")]
+pub struct PyInterpreter {
+    inner: Interpreter,
+}
+
+#[pymethods]
+impl PyInterpreter {
+    #[new]
+    fn new() -> Self {
+        PyInterpreter { inner: Interpreter::new() }
+    }
+
+    /// Runs a program and returns the value of its last expression, or None
+    fn eval(&mut self, py: Python<'_>, source: &str) -> PyResult<Option<PyObject>> {
+        match self.inner.eval(source) {
+            Ok(value) => Ok(value.map(|value| value_to_py(py, &value))),
+            Err(message) => Err(PyRuntimeError::new_err(message)),
+        }
+    }
+
+    /// Reads a variable, returning None if it is not defined
+    fn get(&self, py: Python<'_>, name: &str) -> Option<PyObject> {
+        self.inner.get(name).map(|value| value_to_py(py, &value))
+    }
+
+    /// Defines or updates a variable from an int, float, bool or str
+    fn set(&mut self, name: &str, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        let value = value_from_py(value).map_err(PyTypeError::new_err)?;
+        self.inner.set(name, value).map_err(PyRuntimeError::new_err)
+    }
+
+    /// Makes a Python callable available to scripts; exceptions become runtime errors
+    fn register(&mut self, name: &str, function: PyObject) {
+        self.inner.register_raw(name, move |args: &[Value]| {
+            Python::with_gil(|py| {
+                let args = PyTuple::new_bound(py, args.iter().map(|arg| value_to_py(py, arg)));
+                let result = function.call1(py, args).map_err(|e| e.to_string())?;
+                let result = result.bind(py);
+                if result.is_none() {
+                    Ok(None)
+                } else {
+                    value_from_py(result).map(Some)
+                }
+            })
+        });
+    }
+}
+
+/// The `arc` Python module
+#[pymodule]
+#[pyo3(name = "arc")]
+fn arc_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyInterpreter>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_interpreter(test: impl FnOnce(Python<'_>, &mut PyInterpreter)) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| test(py, &mut PyInterpreter::new()));
+    }
+
+    #[test]
+    fn test_eval_and_variables() {
+        with_interpreter(|py, interpreter| {
+            interpreter.set("x", 7i64.into_py(py).bind(py)).unwrap();
+            let value = interpreter.eval(py, "let y = x * 6\ny").unwrap().unwrap();
+            assert_eq!(value.extract::<i64>(py).unwrap(), 42);
+            assert_eq!(interpreter.get(py, "y").unwrap().extract::<i64>(py).unwrap(), 42);
+            assert!(interpreter.get(py, "missing").is_none());
+        });
+    }
+
+    #[test]
+    fn test_values_convert_both_ways() {
+        with_interpreter(|py, interpreter| {
+            interpreter.set("flag", true.into_py(py).bind(py)).unwrap();
+            assert!(interpreter.eval(py, "flag").unwrap().unwrap().extract::<bool>(py).unwrap());
+
+            let list = interpreter.eval(py, "[1, \"a\", 2.5]").unwrap().unwrap();
+            assert_eq!(list.bind(py).repr().unwrap().to_string(), "[1, 'a', 2.5]");
+
+            let error = interpreter.set("xs", PyList::empty_bound(py).as_any()).unwrap_err();
+            assert!(error.is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    #[test]
+    fn test_registered_callables() {
+        with_interpreter(|py, interpreter| {
+            let add = py.eval_bound("lambda a, b: a + b", None, None).unwrap();
+            interpreter.register("add", add.unbind());
+            let value = interpreter.eval(py, "add(40, 2)").unwrap().unwrap();
+            assert_eq!(value.extract::<i64>(py).unwrap(), 42);
+
+            let fail = py.eval_bound("lambda: 1 // 0", None, None).unwrap();
+            interpreter.register("fail", fail.unbind());
+            let error = interpreter.eval(py, "fail()").unwrap_err();
+            assert!(error.is_instance_of::<PyRuntimeError>(py));
+            assert!(error.to_string().contains("ZeroDivisionError"), "{}", error);
+        });
+    }
+}
@@ -0,0 +1,83 @@
+//! REPL sessions - records successful inputs and exports them as scripts or notebooks
+
+use std::fmt::Write;
+
+/// One successfully evaluated REPL input
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionEntry {
+    pub input: String,
+    /// Text written by `print` while the input ran
+    pub output: String,
+    /// The echoed result, e.g. `15 : Integer`
+    pub result: Option<String>,
+}
+
+/// History of a REPL session
+#[derive(Debug, Default)]
+pub struct Session {
+    entries: Vec<SessionEntry>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session { entries: Vec::new() }
+    }
+
+    /// Records an input that evaluated without errors
+    pub fn record(&mut self, input: &str, output: &str, result: Option<String>) {
+        self.entries.push(SessionEntry {
+            input: input.to_string(),
+            output: output.to_string(),
+            result,
+        });
+    }
+
+    pub fn entries(&self) -> &[SessionEntry] {
+        &self.entries
+    }
+
+    /// Renders the session as a runnable Arc script
+    pub fn to_script(&self) -> String {
+        let mut out = String::from("// Exported from an Arc REPL session\n");
+        for entry in &self.entries {
+            out.push_str(&entry.input);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Renders the session as a Markdown notebook interleaving inputs and their results
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# Arc REPL session\n");
+        for entry in &self.entries {
+            let _ = write!(out, "\n```arc\n{}\n```\n", entry.input);
+            let mut shown = String::new();
+            shown.push_str(&entry.output);
+            if let Some(result) = &entry.result {
+                shown.push_str(result);
+                shown.push('\n');
+            }
+            if !shown.is_empty() {
+                let _ = write!(out, "\n```text\n{}```\n", shown);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exports() {
+        let mut session = Session::new();
+        session.record("let x = 10", "", Some("Integer(10) : Integer".to_string()));
+        session.record("print(x)", "10\n", None);
+
+        assert_eq!(session.to_script(), "// Exported from an Arc REPL session\nlet x = 10\nprint(x)\n");
+        let notebook = session.to_markdown();
+        assert!(notebook.contains("```arc\nprint(x)\n```\n\n```text\n10\n```"));
+        assert!(notebook.contains("```text\nInteger(10) : Integer\n```"));
+    }
+}
@@ -0,0 +1,211 @@
+//! Streaming - pulls statements from a reader one at a time
+//!
+//! One parser reads the whole program through a `ReaderLexer`, which lexes lines as they
+//! are read and drops the text of lines already parsed, so megabyte-scale generated
+//! scripts run without materializing their whole token vector or AST, and a statement
+//! spread over many lines is lexed and parsed once.
+
+use crate::ast::diagnostics::{self, Diagnostic};
+use crate::ast::lexer::{Lexer, Token, TokenKind};
+use crate::ast::parser::Parser;
+use crate::ast::ASTStatement;
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+/// Text dropped from the front of a `ReaderLexer`'s buffer at once; dropping less often
+/// keeps the copies it costs rare
+const DROP_THRESHOLD: usize = 64 * 1024;
+
+/// Lexes a program as it is read. Tokens carry offsets into the whole input.
+///
+/// A token that reaches the end of the text read so far may continue on the next line
+/// (an unclosed string or block comment), so it is lexed again once more text is in.
+/// Each refill at least doubles the text after the token's start, which keeps a
+/// long multi-line token from being lexed once per line.
+pub struct ReaderLexer<'a> {
+    reader: Box<dyn BufRead + 'a>,
+    /// Input from the start of the first line not yet fully lexed
+    buffer: String,
+    /// Offset in the whole input of the start of `buffer`
+    base: usize,
+    /// Line number of the start of `buffer`
+    line: usize,
+    /// Offset in `buffer` of the next token
+    position: usize,
+    /// Set once the reader has no more input
+    exhausted: bool,
+    /// Lexer diagnostics, and an error if reading failed
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> ReaderLexer<'a> {
+    pub fn new(reader: impl BufRead + 'a) -> Self {
+        ReaderLexer {
+            reader: Box::new(reader),
+            buffer: String::new(),
+            base: 0,
+            line: 1,
+            position: 0,
+            exhausted: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Returns the next token, ending with `EOF` where the input ends
+    pub fn next_token(&mut self) -> Option<Token> {
+        self.drop_lexed_lines();
+        loop {
+            let mut lexer = Lexer::new(&self.buffer);
+            lexer.current_pos = self.position;
+            let mut token = lexer.next_token()?;
+            let incomplete = token.kind == TokenKind::EOF || token.span.end == self.buffer.len();
+            if incomplete && !self.exhausted {
+                self.read_more();
+                continue;
+            }
+
+            token.newline_before = self.buffer[self.position..token.span.start].contains('\n');
+            self.position = token.span.end;
+            self.diagnostics.extend(lexer.diagnostics.into_iter().map(|mut diagnostic| {
+                if let Some(span) = &mut diagnostic.span {
+                    span.start += self.base;
+                    span.end += self.base;
+                }
+                diagnostic
+            }));
+            token.span.start += self.base;
+            token.span.end += self.base;
+            return Some(token);
+        }
+    }
+
+    /// 1-based line and column of an offset into the input. Offsets before the text still
+    /// held count from the start of the oldest line kept.
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let (line, column) = diagnostics::line_column(&self.buffer, offset.saturating_sub(self.base));
+        (line + self.line - 1, column)
+    }
+
+    /// Reads whole lines until the text after the next token's start has at least doubled,
+    /// or the input ends
+    fn read_more(&mut self) {
+        let wanted = self.buffer.len() + (self.buffer.len() - self.position).max(1);
+        while self.buffer.len() < wanted {
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => {
+                    self.exhausted = true;
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    self.exhausted = true;
+                    self.diagnostics.push(Diagnostic::error(format!("Error reading input: {}", e), None));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Drops the lines before the one holding the next token, once there is enough of them
+    fn drop_lexed_lines(&mut self) {
+        if self.position < DROP_THRESHOLD {
+            return;
+        }
+        let Some(newline) = self.buffer[..self.position].rfind('\n') else {
+            return;
+        };
+        let dropped = newline + 1;
+        self.line += self.buffer[..dropped].matches('\n').count();
+        self.buffer.drain(..dropped);
+        self.base += dropped;
+        self.position -= dropped;
+    }
+}
+
+/// Iterator over the statements of a program read from a reader.
+///
+/// A statement may span any number of lines (an open parenthesis, a trailing operator,
+/// an unclosed block comment); it is returned once its last token has been read.
+pub struct StatementStream<'a> {
+    parser: Parser<'a>,
+    ready: VecDeque<Result<ASTStatement, Diagnostic>>,
+}
+
+impl<'a> StatementStream<'a> {
+    pub fn new(reader: impl BufRead + 'a) -> Self {
+        StatementStream { parser: Parser::from_reader(reader), ready: VecDeque::new() }
+    }
+}
+
+impl Iterator for StatementStream<'_> {
+    type Item = Result<ASTStatement, Diagnostic>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.parser.is_at_end() {
+            let statement = self.parser.next_statement_or_skip();
+            self.parser.release_literals();
+            let diagnostics = std::mem::take(&mut self.parser.diagnostics);
+            self.ready.extend(diagnostics.into_iter().filter(Diagnostic::is_error).map(Err));
+            self.ready.extend(statement.map(Ok));
+        }
+        self.ready.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statements_span_lines_until_complete() {
+        let source = "let x = (1 +\n  2)\n/* a\n comment */ x *\n 10\nlet y = 1; y\n";
+        let statements: Vec<_> = StatementStream::new(source.as_bytes()).collect();
+
+        assert_eq!(statements.len(), 4);
+        assert!(statements.iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn test_unfinished_input_reports_error() {
+        let mut stream = StatementStream::new("let x = (1 +".as_bytes());
+        match stream.next() {
+            Some(Err(error)) => assert!(error.message.contains("end of input")),
+            _ => panic!("expected a diagnostic"),
+        }
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn test_long_statement_is_parsed_once() {
+        // Re-parsing the pending lines after each one made this take minutes
+        let mut source = String::from("let items = [\n");
+        for i in 0..20_000 {
+            source.push_str(&format!("    \"item {}\",\n", i));
+        }
+        source.push_str("]\nitems\n");
+        let statements: Vec<_> = StatementStream::new(source.as_bytes()).collect();
+
+        assert_eq!(statements.len(), 2);
+        let Some(Ok(last)) = statements.last() else {
+            panic!("expected a statement");
+        };
+        // Offsets are into the whole input, not the lines still buffered
+        assert_eq!(last.range, Some(source.len() - 6..source.len() - 1));
+    }
+
+    #[test]
+    fn test_errors_are_located_in_the_whole_input() {
+        let source = "let a = 1\nlet b = 2\nlet c = 3\nlet d = (1 +\n";
+        let messages: Vec<String> = StatementStream::new(source.as_bytes())
+            .filter_map(Result::err)
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(messages, ["Unexpected end of input at line 5, column 1, expected expression"]);
+
+        let messages: Vec<String> = StatementStream::new("let s = \"a\nb\"\nlet t = @\n".as_bytes())
+            .filter_map(Result::err)
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(messages, ["unexpected character '@' at line 3, column 9"]);
+    }
+}
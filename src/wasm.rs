@@ -0,0 +1,183 @@
+//! WebAssembly bindings - runs Arc client-side for the browser playground
+//!
+//! Built with the `wasm` feature for `wasm32-unknown-unknown`, e.g.
+//! `wasm-pack build --target web --features wasm`.
+
+use crate::ast::diagnostics::{Diagnostic, Severity};
+use crate::ast::evaluator::ASTEvaluator;
+use crate::ast::lexer::Lexer;
+use crate::ast::parser::Parser;
+use crate::ast::types::Value;
+use crate::ast::{ASTStatement, Ast};
+use crate::capabilities::EvalCapabilities;
+use js_sys::{Array, BigInt, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+fn set(object: &Object, key: &str, value: JsValue) {
+    // Setting a property on a plain object cannot fail
+    let _ = Reflect::set(object, &JsValue::from_str(key), &value);
+}
+
+/// `Number.MAX_SAFE_INTEGER`: larger integers are not all representable as a JS number
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// True when a JS number holds `i` exactly
+fn is_safe_integer(i: i64) -> bool {
+    i.unsigned_abs() <= MAX_SAFE_INTEGER
+}
+
+fn value_to_js(value: &Value) -> JsValue {
+    match value {
+        Value::Integer(i) if is_safe_integer(*i) => JsValue::from_f64(*i as f64),
+        Value::Integer(i) => BigInt::from(*i).into(),
+        Value::Float(f) => JsValue::from_f64(*f),
+        Value::Boolean(b) => JsValue::from_bool(*b),
+        Value::String(s) => JsValue::from_str(s),
+        Value::Bytes(b) => Uint8Array::from(&b[..]).into(),
+        Value::Array(items) => items.iter().map(value_to_js).collect::<Array>().into(),
+        Value::Set(elements) => elements.iter().map(|element| value_to_js(&element.clone().into())).collect::<Array>().into(),
+        Value::Map(entries) => {
+            let object = Object::new();
+            for (key, value) in entries.iter() {
+                set(&object, key, value_to_js(value));
+            }
+            object.into()
+        }
+        Value::Function(_) | Value::Range(..) | Value::Channel(_) => JsValue::from_str(&value.to_string()),
+    }
+}
+
+/// `{ severity, message, start, end }`, with null offsets for runtime errors
+fn diagnostic_to_js(diagnostic: &Diagnostic) -> JsValue {
+    let object = Object::new();
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    set(&object, "severity", JsValue::from_str(severity));
+    set(&object, "message", JsValue::from_str(&diagnostic.message));
+    let (start, end) = match &diagnostic.span {
+        Some(span) => (JsValue::from_f64(span.start as f64), JsValue::from_f64(span.end as f64)),
+        None => (JsValue::NULL, JsValue::NULL),
+    };
+    set(&object, "start", start);
+    set(&object, "end", end);
+    object.into()
+}
+
+fn diagnostics_to_js(diagnostics: &[Diagnostic]) -> JsValue {
+    diagnostics.iter().map(diagnostic_to_js).collect::<Array>().into()
+}
+
+fn parse(source: &str) -> (Vec<ASTStatement>, Vec<Diagnostic>) {
+    let mut parser = Parser::from_lexer(Lexer::new(source));
+    let statements = parser.parse_all();
+    (statements, parser.diagnostics)
+}
+
+/// Parses without running, for live editor markers
+#[wasm_bindgen]
+pub fn diagnostics(source: &str) -> JsValue {
+    diagnostics_to_js(&parse(source).1)
+}
+
+/// The result of running a program, before it is converted to JS
+struct Outcome {
+    value: Option<Value>,
+    output: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn run(source: &str) -> Outcome {
+    let (statements, mut diagnostics) = parse(source);
+    let mut evaluator = ASTEvaluator::new();
+    evaluator.capabilities = EvalCapabilities { stdout: true, ..EvalCapabilities::none() };
+    evaluator.captured_output = Some(String::new());
+
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        for statement in statements {
+            let mut ast = Ast::new();
+            ast.add_statement(statement);
+            evaluator.resolve(&mut ast);
+            ast.visit(&mut evaluator);
+            if !evaluator.errors.is_empty() {
+                break;
+            }
+        }
+        diagnostics.extend(evaluator.errors.drain(..).map(|error| Diagnostic::error(error, None)));
+    }
+
+    Outcome {
+        value: evaluator.last_value.take(),
+        output: evaluator.captured_output.take().unwrap_or_default(),
+        diagnostics,
+    }
+}
+
+/// Runs a program in a fresh sandboxed interpreter.
+///
+/// Returns `{ value, type, output, diagnostics }`: the last expression's value and type
+/// name (or null), everything printed, and all parse and runtime diagnostics. Integers
+/// are numbers, except those beyond `Number.MAX_SAFE_INTEGER` either way, which are
+/// `BigInt`s so they keep every digit.
+#[wasm_bindgen]
+pub fn eval(source: &str) -> JsValue {
+    let outcome = run(source);
+    let result = Object::new();
+    match &outcome.value {
+        Some(value) => {
+            set(&result, "value", value_to_js(value));
+            set(&result, "type", JsValue::from_str(&value.get_type().to_string()));
+        }
+        None => {
+            set(&result, "value", JsValue::NULL);
+            set(&result, "type", JsValue::NULL);
+        }
+    }
+    set(&result, "output", JsValue::from_str(&outcome.output));
+    set(&result, "diagnostics", diagnostics_to_js(&outcome.diagnostics));
+    result.into()
+}
+
+// JsValue only works on wasm32, so these check what the exports report before conversion
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_reports_value_and_output() {
+        let outcome = run("let x = 6\nprint(\"x is\", x)\nx * 7");
+        assert_eq!(outcome.value, Some(Value::Integer(42)));
+        assert_eq!(outcome.output, "x is 6\n");
+        assert!(outcome.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_integers_beyond_safe_range_need_bigint() {
+        assert!(is_safe_integer(0));
+        assert!(is_safe_integer(9_007_199_254_740_991));
+        assert!(is_safe_integer(-9_007_199_254_740_991));
+        assert!(!is_safe_integer(9_007_199_254_740_992));
+        assert!(!is_safe_integer(-9_007_199_254_740_992));
+        assert!(!is_safe_integer(i64::MIN));
+        assert_eq!(run("2 ** 62").value, Some(Value::Integer(1 << 62)));
+    }
+
+    #[test]
+    fn test_eval_is_sandboxed() {
+        let outcome = run("read_file(\"/etc/passwd\")");
+        assert_eq!(outcome.value, None);
+        assert_eq!(outcome.diagnostics.len(), 1);
+        assert!(outcome.diagnostics[0].is_error());
+        assert!(outcome.diagnostics[0].message.contains("filesystem"), "{}", outcome.diagnostics[0].message);
+        assert_eq!(outcome.diagnostics[0].span, None);
+    }
+
+    #[test]
+    fn test_parse_errors_stop_evaluation() {
+        let outcome = run("print(\"ran\")\nlet = 1");
+        assert_eq!(outcome.output, "");
+        assert!(outcome.diagnostics[0].is_error());
+        assert!(outcome.diagnostics[0].span.is_some());
+    }
+}
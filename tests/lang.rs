@@ -0,0 +1,31 @@
+//! Runs each script in `tests/lang/` and compares its output with the `.expected` file
+//! beside it. `cargo test --test lang -- --bless` rewrites the files that differ.
+
+use arc_compiler::golden;
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let bless = std::env::args().any(|arg| arg == "--bless");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lang");
+    let run = match golden::check(&dir, bless) {
+        Ok(run) => run,
+        Err(e) => {
+            eprintln!("cannot run {}: {}", dir.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for path in &run.blessed {
+        println!("blessed {}", path.display());
+    }
+    for failure in &run.failures {
+        eprintln!("{}\n", failure);
+    }
+    println!("lang: {} script(s), {} failed", run.scripts, run.failures.len());
+    if run.failures.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}